@@ -13,10 +13,19 @@
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 use static_web_server::{
-    Result, Settings,
+    Context, Result, Settings,
     settings::{Commands, cli::General},
 };
 
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+))]
+use static_web_server::precompress::PrecompressOpts;
+
 fn main() -> Result {
     let opts = Settings::get(true)?;
 
@@ -53,6 +62,52 @@ fn main() -> Result {
                 }
                 return Ok(());
             }
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-gzip",
+                feature = "compression-brotli",
+                feature = "compression-zstd",
+                feature = "compression-deflate"
+            ))]
+            Commands::Compress {
+                formats,
+                jobs,
+                force,
+                dir,
+            } => {
+                let jobs = jobs
+                    .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+                let summary = static_web_server::precompress::run(&PrecompressOpts {
+                    root: dir.unwrap_or(opts.general.root),
+                    formats,
+                    level: opts.general.compression_level,
+                    jobs,
+                    force,
+                    include_hidden: opts.general.include_hidden,
+                })?;
+                println!(
+                    "{} written, {} up to date, {} not smaller than the original ({} -> {} bytes)",
+                    summary.written,
+                    summary.up_to_date,
+                    summary.not_smaller,
+                    summary.bytes_in,
+                    summary.bytes_out
+                );
+                return Ok(());
+            }
+            Commands::Sri { output, dir } => {
+                let root = dir.unwrap_or(opts.general.root);
+                let manifest =
+                    static_web_server::digest::sri_manifest(&root, opts.general.include_hidden)?;
+                let json = serde_json::to_string_pretty(&manifest)?;
+                match output {
+                    Some(path) => std::fs::write(&path, json + "\n").with_context(|| {
+                        format!("unable to write the manifest to `{}`", path.display())
+                    })?,
+                    None => println!("{json}"),
+                }
+                return Ok(());
+            }
         }
     }
 
@@ -17,39 +17,53 @@ use async_compression::tokio::bufread::GzipEncoder;
 #[cfg(any(feature = "compression", feature = "compression-zstd"))]
 use async_compression::tokio::bufread::ZstdEncoder;
 
-use headers::{ContentType, HeaderMap, HeaderMapExt, HeaderValue};
+use globset::{Glob, GlobMatcher};
+use headers::{ContentType, HeaderMap, HeaderMapExt, HeaderValue, IfNoneMatch};
 use http_body_util::BodyExt as _;
 use hyper::{
-    Method, Request, Response, StatusCode,
-    header::{CONTENT_ENCODING, CONTENT_LENGTH},
+    Method, Response, StatusCode, Uri,
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, ETAG, LAST_MODIFIED, VARY},
 };
-use mime_guess::Mime;
+use mime_guess::{Mime, mime};
+use std::path::PathBuf;
+use std::sync::Arc;
 use tokio_util::io::{ReaderStream, StreamReader};
 
 use crate::body::Body;
-use crate::error_page;
 use crate::exts::headers::{AcceptEncoding, ContentCoding};
 use crate::exts::http::{MethodExt, append_vary_accept_encoding};
 use crate::exts::mime::MimeExt;
 use crate::handler::RequestHandlerOpts;
+#[cfg(feature = "mem-cache")]
+use crate::mem_cache::variants::{self, PendingVariant};
 use crate::settings::CompressionLevel;
-use crate::{Error, Result};
+use crate::settings::file::{CompressionLevels, CompressionRule};
+use crate::{Context, Error, Result, digest};
 
 /// Minimum response body size in bytes below which dynamic compression is skipped.
-const MIN_COMPRESS_SIZE: usize = 200;
+pub(crate) const MIN_COMPRESS_SIZE: usize = 200;
 
-/// List of encodings that can be handled given enabled features.
+/// List of encodings that can be handled given enabled features,
+/// in the default order of preference.
 const AVAILABLE_ENCODINGS: &[ContentCoding] = &[
-    #[cfg(any(feature = "compression", feature = "compression-deflate"))]
-    ContentCoding::DEFLATE,
-    #[cfg(any(feature = "compression", feature = "compression-gzip"))]
-    ContentCoding::GZIP,
-    #[cfg(any(feature = "compression", feature = "compression-brotli"))]
-    ContentCoding::BROTLI,
     #[cfg(any(feature = "compression", feature = "compression-zstd"))]
     ContentCoding::ZSTD,
+    #[cfg(any(feature = "compression", feature = "compression-brotli"))]
+    ContentCoding::BROTLI,
+    #[cfg(any(feature = "compression", feature = "compression-gzip"))]
+    ContentCoding::GZIP,
+    #[cfg(any(feature = "compression", feature = "compression-deflate"))]
+    ContentCoding::DEFLATE,
 ];
 
+/// The built-in policy used when `[advanced.compression]` is not configured.
+pub(crate) static DEFAULT_POLICY: CompressionPolicy = CompressionPolicy {
+    min_size: MIN_COMPRESS_SIZE as u64,
+    encodings: Vec::new(),
+    levels: Vec::new(),
+    rules: Vec::new(),
+};
+
 /// Initializes dynamic compression.
 pub fn init(enabled: bool, level: CompressionLevel, handler_opts: &mut RequestHandlerOpts) {
     handler_opts.compression = enabled;
@@ -73,11 +87,302 @@ pub fn init(enabled: bool, level: CompressionLevel, handler_opts: &mut RequestHa
     );
 }
 
+/// Initializes the dynamic compression policy.
+pub fn init_policy(handler_opts: &mut RequestHandlerOpts) -> Result {
+    let Some(opts) = handler_opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.compression.as_ref())
+    else {
+        return Ok(());
+    };
+
+    let encodings = match &opts.encodings {
+        Some(names) => parse_encodings(names)?,
+        None => Vec::new(),
+    };
+    let levels = match &opts.levels {
+        Some(levels) => parse_levels(levels)?,
+        None => Vec::new(),
+    };
+    let rules = opts
+        .rules
+        .iter()
+        .flatten()
+        .map(PolicyRule::new)
+        .collect::<Result<Vec<_>>>()?;
+    let policy = CompressionPolicy {
+        min_size: opts.min_size.unwrap_or(MIN_COMPRESS_SIZE as u64),
+        encodings,
+        levels,
+        rules,
+    };
+
+    tracing::info!(
+        min_size = policy.min_size,
+        encodings = %policy.encodings().iter().map(ContentCoding::as_str).collect::<Vec<_>>().join(","),
+        levels = policy.levels.len(),
+        rules = policy.rules.len(),
+        "auto compression policy"
+    );
+
+    handler_opts.compression_policy = Some(Arc::new(policy));
+
+    Ok(())
+}
+
+/// Parses a list of encoding names, e.g. `["zstd", "br", "gzip"]`.
+fn parse_encodings(names: &[String]) -> Result<Vec<ContentCoding>> {
+    let mut encodings = Vec::with_capacity(names.len());
+    for name in names {
+        let coding = match name.trim().to_ascii_lowercase().as_str() {
+            "gzip" => ContentCoding::GZIP,
+            "deflate" => ContentCoding::DEFLATE,
+            "br" | "brotli" => ContentCoding::BROTLI,
+            "zstd" => ContentCoding::ZSTD,
+            _ => bail!("unsupported compression encoding: {name}"),
+        };
+        if !AVAILABLE_ENCODINGS.contains(&coding) {
+            tracing::warn!("compression encoding `{name}` is not enabled in this build, ignoring");
+            continue;
+        }
+        if !encodings.contains(&coding) {
+            encodings.push(coding);
+        }
+    }
+    Ok(encodings)
+}
+
+/// Validates the compression levels per encoding.
+fn parse_levels(levels: &CompressionLevels) -> Result<Vec<(ContentCoding, i32)>> {
+    let mut parsed = Vec::new();
+    for (coding, level, range) in [
+        (ContentCoding::GZIP, levels.gzip, 0..=9),
+        (ContentCoding::DEFLATE, levels.deflate, 0..=9),
+        (ContentCoding::BROTLI, levels.brotli, 0..=11),
+        (ContentCoding::ZSTD, levels.zstd, 1..=22),
+    ] {
+        let Some(level) = level else { continue };
+        if !range.contains(&level) {
+            bail!(
+                "compression level {level} of `{}` is out of range {}-{}",
+                coding.as_str(),
+                range.start(),
+                range.end()
+            );
+        }
+        parsed.push((coding, level));
+    }
+    Ok(parsed)
+}
+
+/// Decides which responses are compressed on the fly and how.
+#[derive(Debug)]
+pub struct CompressionPolicy {
+    /// Minimum response body size in bytes.
+    min_size: u64,
+    /// Encodings in order of preference, all the available ones when empty.
+    encodings: Vec<ContentCoding>,
+    /// Levels overriding the compression level setting per encoding.
+    levels: Vec<(ContentCoding, i32)>,
+    /// Rules checked in order, the first matching one applies.
+    rules: Vec<PolicyRule>,
+}
+
+/// A rule of the compression policy.
+#[derive(Debug)]
+struct PolicyRule {
+    /// Request paths matched, all when `None`.
+    source: Option<GlobMatcher>,
+    /// MIME type matched, e.g. `application/wasm` or `image/*`. All when `None`.
+    mime: Option<Mime>,
+    /// Whether matching responses are compressed.
+    enabled: bool,
+    /// Encodings allowed for matching responses, the policy ones when `None`.
+    encodings: Option<Vec<ContentCoding>>,
+}
+
+impl PolicyRule {
+    fn new(rule: &CompressionRule) -> Result<Self> {
+        let source = match &rule.source {
+            Some(source) => Some(
+                Glob::new(source)
+                    .with_context(|| {
+                        format!(
+                            "can not compile glob pattern for compression rule source: {source}"
+                        )
+                    })?
+                    .compile_matcher(),
+            ),
+            None => None,
+        };
+        let mime = match &rule.mime {
+            Some(mime) => Some(
+                mime.trim()
+                    .to_ascii_lowercase()
+                    .parse::<Mime>()
+                    .with_context(|| format!("invalid MIME type for compression rule: {mime}"))?,
+            ),
+            None => None,
+        };
+        let encodings = match &rule.encodings {
+            Some(names) => Some(parse_encodings(names)?),
+            None => None,
+        };
+        Ok(Self {
+            source,
+            mime,
+            enabled: rule.enabled.unwrap_or(true),
+            encodings,
+        })
+    }
+
+    fn matches(&self, uri_path: &str, mime: Option<&Mime>) -> bool {
+        let source_matches = self
+            .source
+            .as_ref()
+            .is_none_or(|source| source.is_match(uri_path));
+        let mime_matches = self.mime.as_ref().is_none_or(|pattern| {
+            mime.is_some_and(|actual| {
+                (pattern.type_() == mime::STAR || pattern.type_() == actual.type_())
+                    && (pattern.subtype() == mime::STAR || pattern.subtype() == actual.subtype())
+            })
+        });
+        source_matches && mime_matches
+    }
+}
+
+impl CompressionPolicy {
+    fn encodings(&self) -> &[ContentCoding] {
+        if self.encodings.is_empty() {
+            AVAILABLE_ENCODINGS
+        } else {
+            &self.encodings
+        }
+    }
+
+    /// Returns the encodings allowed for a response of the given MIME type
+    /// and length in bytes, or `None` when it must not be compressed.
+    fn allowed(
+        &self,
+        uri_path: &str,
+        mime: Option<&Mime>,
+        len: Option<u64>,
+    ) -> Option<&[ContentCoding]> {
+        // Tiny payloads gain no benefit and the compression overhead can
+        // make them larger than the original.
+        if let Some(len) = len
+            && len < self.min_size
+        {
+            tracing::trace!(
+                "skipping compression: content-length ({len}) below minimum ({})",
+                self.min_size
+            );
+            return None;
+        }
+
+        match self.rules.iter().find(|rule| rule.matches(uri_path, mime)) {
+            Some(rule) if !rule.enabled => None,
+            Some(rule) => Some(rule.encodings.as_deref().unwrap_or(self.encodings())),
+            // Skip compression for non-text-based MIME types
+            None if mime.is_some_and(|mime| !mime.is_compressible()) => None,
+            None => Some(self.encodings()),
+        }
+    }
+
+    /// Whether a response of the given MIME type and length may be
+    /// compressed with `coding`, or its dictionary-based counterpart.
+    pub(crate) fn allows(
+        &self,
+        uri_path: &str,
+        mime: Option<&Mime>,
+        len: Option<u64>,
+        coding: ContentCoding,
+    ) -> bool {
+        let coding = base_encoding(coding);
+        self.allowed(uri_path, mime, len)
+            .is_some_and(|encodings| encodings.contains(&coding))
+    }
+
+    /// Selects the encoding of a response compressed on the fly given the
+    /// request headers, if any.
+    ///
+    /// Encodings accepted with the same quality are ordered by the policy
+    /// preference.
+    pub(crate) fn select(
+        &self,
+        uri_path: &str,
+        headers: &HeaderMap<HeaderValue>,
+        mime: Option<&Mime>,
+        len: Option<u64>,
+    ) -> Option<ContentCoding> {
+        let accept_encoding = headers.typed_get::<AcceptEncoding>()?;
+        tracing::trace!("request with accept-encoding header: {:?}", accept_encoding);
+        let allowed = self.allowed(uri_path, mime, len)?;
+        let preference = self.encodings();
+        let priority = |coding| {
+            preference
+                .iter()
+                .position(|&c| c == coding)
+                .map_or(0, |pos| (preference.len() - pos) as u8)
+        };
+        let coding = accept_encoding
+            .sorted_encodings_by(priority)
+            .find(|coding| AVAILABLE_ENCODINGS.contains(coding) && allowed.contains(coding))?;
+        tracing::trace!(
+            "preferred encoding selected from the accept-encoding header: {:?}",
+            coding
+        );
+        Some(coding)
+    }
+
+    /// Returns the level of the given encoding, the one of the policy or
+    /// else the one derived from the compression level setting.
+    pub(crate) fn level(
+        &self,
+        coding: ContentCoding,
+        level: CompressionLevel,
+    ) -> async_compression::Level {
+        let base = base_encoding(coding);
+        self.levels
+            .iter()
+            .find(|&&(c, _)| c == base)
+            .map(|&(_, level)| async_compression::Level::Precise(level))
+            .unwrap_or_else(|| algorithm_level(coding, level))
+    }
+}
+
+/// Maps a dictionary-based encoding to its underlying one.
+fn base_encoding(coding: ContentCoding) -> ContentCoding {
+    match coding {
+        ContentCoding::DCB => ContentCoding::BROTLI,
+        ContentCoding::DCZ => ContentCoding::ZSTD,
+        coding => coding,
+    }
+}
+
+/// Returns the MIME type and length of a response body, when known.
+pub(crate) fn body_traits(resp: &Response<Body>) -> (Option<Mime>, Option<u64>) {
+    let mime = resp.headers().typed_get::<ContentType>().map(Mime::from);
+    let len = resp
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    (mime, len)
+}
+
 /// Post-processing to dynamically compress the response if necessary.
-pub(crate) fn post_process<T>(
+///
+/// It takes the request parts it needs rather than the request itself,
+/// whose body may not be `Sync`, to hold them across the cache lookup.
+pub(crate) async fn post_process(
     opts: &RequestHandlerOpts,
-    req: &Request<T>,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap<HeaderValue>,
     mut resp: Response<Body>,
+    file_path: Option<&PathBuf>,
 ) -> Result<Response<Body>, Error> {
     if !opts.compression {
         return Ok(resp);
@@ -91,26 +396,140 @@ pub(crate) fn post_process<T>(
     // Compression content encoding varies so use a `Vary` header
     append_vary_accept_encoding(&mut resp);
 
-    // Auto compression based on the `Accept-Encoding` header
-    match auto(req.method(), req.headers(), opts.compression_level, resp) {
-        Ok(resp) => Ok(resp),
-        Err(err) => {
-            tracing::error!("error during body compression: {:?}", err);
-            error_page::error_response(
-                req.uri(),
-                req.method(),
-                &StatusCode::INTERNAL_SERVER_ERROR,
-                &opts.page404,
-                &opts.page50x,
-            )
+    // Skip compression for HEAD and OPTIONS request methods
+    if method.is_head() || method.is_options() {
+        return Ok(resp);
+    }
+
+    // In-memory cache hit whose encoded variant is not cached yet
+    #[cfg(feature = "mem-cache")]
+    let pending_variant = resp.extensions_mut().remove::<PendingVariant>();
+
+    #[cfg(feature = "mem-cache")]
+    let file_path = file_path.filter(|_| pending_variant.is_none());
+
+    // Encoding based on the `Accept-Encoding` header and the policy
+    let policy = opts
+        .compression_policy
+        .as_deref()
+        .unwrap_or(&DEFAULT_POLICY);
+    let (mime, len) = body_traits(&resp);
+    let Some(coding) = policy.select(uri.path(), headers, mime.as_ref(), len) else {
+        return Ok(resp);
+    };
+    let level = policy.level(coding, opts.compression_level);
+
+    // On-disk cache of previously compressed files
+    let disk_cache = match opts.compression_cache.as_ref().zip(file_path) {
+        Some((cache, path)) => cache
+            .key(method, coding, level, path, &resp)
+            .await
+            .map(|key| (cache, key)),
+        None => None,
+    };
+    if let Some((cache, key)) = disk_cache.as_ref()
+        && cache.serve(key, &mut resp)
+    {
+        return Ok(tag_encoded(headers, resp));
+    }
+
+    let (head, body) = resp.into_parts();
+    let resp = tag_encoded(headers, encode(head, body, coding, level));
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        return Ok(resp);
+    }
+
+    #[cfg(feature = "mem-cache")]
+    let resp = match (pending_variant, disk_cache) {
+        (Some(pending), _) => variants::tee(resp, pending),
+        (None, Some((cache, key))) => cache.tee(resp, key),
+        (None, None) => resp,
+    };
+    #[cfg(not(feature = "mem-cache"))]
+    let resp = match disk_cache {
+        Some((cache, key)) => cache.tee(resp, key),
+        None => resp,
+    };
+    Ok(resp)
+}
+
+/// Gives a response compressed on the fly a strong `ETag` distinct from
+/// the one of the identity representation, replacing it with a
+/// `304 Not Modified` when the client already holds that representation.
+///
+/// Weak `ETag`s are left as-is since they ignore content codings. The
+/// digests of the identity representation are dropped.
+fn tag_encoded(headers: &HeaderMap<HeaderValue>, resp: Response<Body>) -> Response<Body> {
+    let Some(coding) = resp.headers().get(CONTENT_ENCODING).cloned() else {
+        return resp;
+    };
+    tag_representation(headers, resp, coding.to_str().ok())
+}
+
+/// Same as [`tag_encoded`] for a representation identified by `label`
+/// in its strong `ETag`, e.g. an encoding along with its dictionary.
+pub(crate) fn tag_representation(
+    headers: &HeaderMap<HeaderValue>,
+    mut resp: Response<Body>,
+    label: Option<&str>,
+) -> Response<Body> {
+    resp.headers_mut().remove(&digest::REPR_DIGEST);
+    resp.headers_mut().remove(&digest::CONTENT_DIGEST);
+    if resp.status() != StatusCode::OK {
+        return resp;
+    }
+    let Some(etag) = resp
+        .headers()
+        .get(ETAG)
+        .filter(|etag| crate::etag::is_strong(etag))
+        .and_then(|etag| crate::etag::encoded(etag, label?))
+    else {
+        return resp;
+    };
+    resp.headers_mut().insert(ETAG, etag.clone());
+
+    let held = headers
+        .typed_get::<IfNoneMatch>()
+        .zip(
+            etag.to_str()
+                .ok()
+                .and_then(|s| s.parse::<headers::ETag>().ok()),
+        )
+        .is_some_and(|(if_none_match, etag)| !if_none_match.precondition_passes(&etag));
+    if !held {
+        return resp;
+    }
+    let mut not_modified = Response::new(crate::body::empty());
+    *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+    for name in [ETAG, LAST_MODIFIED, VARY] {
+        if let Some(value) = resp.headers().get(&name) {
+            not_modified.headers_mut().insert(name, value.clone());
         }
     }
+    not_modified
+}
+
+/// Maps a compression level setting to the level of the given algorithm.
+///
+/// The `default` setting uses a level balancing speed and size for each
+/// algorithm rather than the (often slow) library default.
+pub(crate) fn algorithm_level(
+    coding: ContentCoding,
+    level: CompressionLevel,
+) -> async_compression::Level {
+    let default = match coding {
+        ContentCoding::ZSTD | ContentCoding::DCZ => 3,
+        _ => 4,
+    };
+    level.into_algorithm_level(default)
 }
 
 /// Create a wrapping handler that compresses the Body of a [`hyper::Response`]
 /// using gzip, `deflate`, `brotli` or `zstd` if is specified in the `Accept-Encoding` header, adding
 /// `content-encoding: <coding>` to the Response's [`HeaderMap`].
 /// It also provides the ability to apply compression for text-based MIME types only.
+///
+/// The built-in compression policy applies.
 pub fn auto(
     method: &Method,
     headers: &HeaderMap<HeaderValue>,
@@ -123,65 +542,59 @@ pub fn auto(
     }
 
     // Compress response based on Accept-Encoding header
-    if let Some(encoding) = get_preferred_encoding(headers) {
-        tracing::trace!(
-            "preferred encoding selected from the accept-encoding header: {:?}",
-            encoding
-        );
-
-        // Skip compression for non-text-based MIME types
-        if let Some(content_type) = resp.headers().typed_get::<ContentType>()
-            && !Mime::from(content_type).is_compressible()
-        {
-            return Ok(resp);
-        }
+    let (mime, len) = body_traits(&resp);
+    let Some(coding) = DEFAULT_POLICY.select("", headers, mime.as_ref(), len) else {
+        return Ok(resp);
+    };
+    let level = DEFAULT_POLICY.level(coding, level);
+    let (head, body) = resp.into_parts();
+    Ok(encode(head, body, coding, level))
+}
 
-        // Skip compression for responses below the minimum size threshold.
-        // Tiny payloads gain no benefit and the compression overhead can
-        // make them larger than the original.
-        if let Some(content_length) = resp
-            .headers()
-            .get(CONTENT_LENGTH)
-            .and_then(|v| v.to_str().ok())
-            .and_then(|v| v.parse::<usize>().ok())
-            && content_length < MIN_COMPRESS_SIZE
-        {
-            tracing::trace!(
-                "skipping compression: content-length ({content_length}) below minimum ({MIN_COMPRESS_SIZE})",
-            );
-            return Ok(resp);
-        }
+/// Compresses the Body of a [`Response`] using the given encoding and level,
+/// adding `content-encoding: <coding>` to the Response's [`HeaderMap`].
+fn encode(
+    mut head: http::response::Parts,
+    body: Body,
+    coding: ContentCoding,
+    level: async_compression::Level,
+) -> Response<Body> {
+    tracing::trace!(
+        "compressing response body on the fly using {}",
+        coding.as_str()
+    );
 
+    let reader = |body: Body| StreamReader::new(body.into_data_stream());
+    let body = match coding {
         #[cfg(any(feature = "compression", feature = "compression-gzip"))]
-        if encoding == ContentCoding::GZIP {
-            let (head, body) = resp.into_parts();
-            return Ok(gzip(head, body, level));
-        }
-
+        ContentCoding::GZIP => crate::body::stream(ReaderStream::new(GzipEncoder::with_quality(
+            reader(body),
+            level,
+        ))),
         #[cfg(any(feature = "compression", feature = "compression-deflate"))]
-        if encoding == ContentCoding::DEFLATE {
-            let (head, body) = resp.into_parts();
-            return Ok(deflate(head, body, level));
-        }
-
+        ContentCoding::DEFLATE => crate::body::stream(ReaderStream::new(
+            DeflateEncoder::with_quality(reader(body), level),
+        )),
         #[cfg(any(feature = "compression", feature = "compression-brotli"))]
-        if encoding == ContentCoding::BROTLI {
-            let (head, body) = resp.into_parts();
-            return Ok(brotli(head, body, level));
-        }
-
+        ContentCoding::BROTLI => crate::body::stream(ReaderStream::new(
+            BrotliEncoder::with_quality(reader(body), level),
+        )),
         #[cfg(any(feature = "compression", feature = "compression-zstd"))]
-        if encoding == ContentCoding::ZSTD {
-            let (head, body) = resp.into_parts();
-            return Ok(zstd(head, body, level));
+        ContentCoding::ZSTD => crate::body::stream(ReaderStream::new(ZstdEncoder::with_quality(
+            reader(body),
+            level,
+        ))),
+        _ => {
+            tracing::trace!(
+                "no compression feature matched the preferred encoding, probably not enabled or unsupported"
+            );
+            return Response::from_parts(head, body);
         }
-
-        tracing::trace!(
-            "no compression feature matched the preferred encoding, probably not enabled or unsupported"
-        );
-    }
-
-    Ok(resp)
+    };
+    let header = create_encoding_header(head.headers.remove(CONTENT_ENCODING), coding);
+    head.headers.remove(CONTENT_LENGTH);
+    head.headers.insert(CONTENT_ENCODING, header);
+    Response::from_parts(head, body)
 }
 
 /// Create a wrapping handler that compresses the Body of a [`Response`].
@@ -191,24 +604,9 @@ pub fn auto(
     docsrs,
     doc(cfg(any(feature = "compression", feature = "compression-gzip")))
 )]
-pub fn gzip(
-    mut head: http::response::Parts,
-    body: Body,
-    level: CompressionLevel,
-) -> Response<Body> {
-    const DEFAULT_COMPRESSION_LEVEL: i32 = 4;
-
-    tracing::trace!("compressing response body on the fly using GZIP");
-
-    let level = level.into_algorithm_level(DEFAULT_COMPRESSION_LEVEL);
-    let body = crate::body::stream(ReaderStream::new(GzipEncoder::with_quality(
-        StreamReader::new(body.into_data_stream()),
-        level,
-    )));
-    let header = create_encoding_header(head.headers.remove(CONTENT_ENCODING), ContentCoding::GZIP);
-    head.headers.remove(CONTENT_LENGTH);
-    head.headers.insert(CONTENT_ENCODING, header);
-    Response::from_parts(head, body)
+pub fn gzip(head: http::response::Parts, body: Body, level: CompressionLevel) -> Response<Body> {
+    let level = algorithm_level(ContentCoding::GZIP, level);
+    encode(head, body, ContentCoding::GZIP, level)
 }
 
 /// Create a wrapping handler that compresses the Body of a [`Response`].
@@ -218,27 +616,9 @@ pub fn gzip(
     docsrs,
     doc(cfg(any(feature = "compression", feature = "compression-deflate")))
 )]
-pub fn deflate(
-    mut head: http::response::Parts,
-    body: Body,
-    level: CompressionLevel,
-) -> Response<Body> {
-    const DEFAULT_COMPRESSION_LEVEL: i32 = 4;
-
-    tracing::trace!("compressing response body on the fly using DEFLATE");
-
-    let level = level.into_algorithm_level(DEFAULT_COMPRESSION_LEVEL);
-    let body = crate::body::stream(ReaderStream::new(DeflateEncoder::with_quality(
-        StreamReader::new(body.into_data_stream()),
-        level,
-    )));
-    let header = create_encoding_header(
-        head.headers.remove(CONTENT_ENCODING),
-        ContentCoding::DEFLATE,
-    );
-    head.headers.remove(CONTENT_LENGTH);
-    head.headers.insert(CONTENT_ENCODING, header);
-    Response::from_parts(head, body)
+pub fn deflate(head: http::response::Parts, body: Body, level: CompressionLevel) -> Response<Body> {
+    let level = algorithm_level(ContentCoding::DEFLATE, level);
+    encode(head, body, ContentCoding::DEFLATE, level)
 }
 
 /// Create a wrapping handler that compresses the Body of a [`Response`].
@@ -248,25 +628,9 @@ pub fn deflate(
     docsrs,
     doc(cfg(any(feature = "compression", feature = "compression-brotli")))
 )]
-pub fn brotli(
-    mut head: http::response::Parts,
-    body: Body,
-    level: CompressionLevel,
-) -> Response<Body> {
-    const DEFAULT_COMPRESSION_LEVEL: i32 = 4;
-
-    tracing::trace!("compressing response body on the fly using BROTLI");
-
-    let level = level.into_algorithm_level(DEFAULT_COMPRESSION_LEVEL);
-    let body = crate::body::stream(ReaderStream::new(BrotliEncoder::with_quality(
-        StreamReader::new(body.into_data_stream()),
-        level,
-    )));
-    let header =
-        create_encoding_header(head.headers.remove(CONTENT_ENCODING), ContentCoding::BROTLI);
-    head.headers.remove(CONTENT_LENGTH);
-    head.headers.insert(CONTENT_ENCODING, header);
-    Response::from_parts(head, body)
+pub fn brotli(head: http::response::Parts, body: Body, level: CompressionLevel) -> Response<Body> {
+    let level = algorithm_level(ContentCoding::BROTLI, level);
+    encode(head, body, ContentCoding::BROTLI, level)
 }
 
 /// Create a wrapping handler that compresses the Body of a [`Response`].
@@ -276,24 +640,9 @@ pub fn brotli(
     docsrs,
     doc(cfg(any(feature = "compression", feature = "compression-zstd")))
 )]
-pub fn zstd(
-    mut head: http::response::Parts,
-    body: Body,
-    level: CompressionLevel,
-) -> Response<Body> {
-    const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
-
-    tracing::trace!("compressing response body on the fly using ZSTD");
-
-    let level = level.into_algorithm_level(DEFAULT_COMPRESSION_LEVEL);
-    let body = crate::body::stream(ReaderStream::new(ZstdEncoder::with_quality(
-        StreamReader::new(body.into_data_stream()),
-        level,
-    )));
-    let header = create_encoding_header(head.headers.remove(CONTENT_ENCODING), ContentCoding::ZSTD);
-    head.headers.remove(CONTENT_LENGTH);
-    head.headers.insert(CONTENT_ENCODING, header);
-    Response::from_parts(head, body)
+pub fn zstd(head: http::response::Parts, body: Body, level: CompressionLevel) -> Response<Body> {
+    let level = algorithm_level(ContentCoding::ZSTD, level);
+    encode(head, body, ContentCoding::ZSTD, level)
 }
 
 /// Given an optional existing encoding header, appends to the existing or creates a new one.
@@ -448,4 +797,109 @@ mod tests {
             "non-compressible content-types are never compressed"
         );
     }
+
+    // Compression policy tests
+
+    fn rule(source: Option<&str>, mime: Option<&str>, enabled: bool) -> CompressionRule {
+        CompressionRule {
+            source: source.map(str::to_owned),
+            mime: mime.map(str::to_owned),
+            enabled: Some(enabled),
+            encodings: None,
+        }
+    }
+
+    fn policy(rules: &[CompressionRule]) -> CompressionPolicy {
+        CompressionPolicy {
+            min_size: 1024,
+            encodings: Vec::new(),
+            levels: Vec::new(),
+            rules: rules.iter().map(|r| PolicyRule::new(r).unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn policy_rules_override_the_mime_heuristic() {
+        let policy = policy(&[
+            rule(Some("/api/**"), None, false),
+            rule(None, Some("application/wasm"), true),
+            rule(None, Some("image/*"), true),
+        ]);
+        let headers = accept_gzip_headers();
+        let select = |path, mime: &str, len| {
+            policy.select(path, &headers, Some(&mime.parse().unwrap()), Some(len))
+        };
+        assert_eq!(
+            select("/app.wasm", "application/wasm", 2048),
+            Some(ContentCoding::GZIP)
+        );
+        assert_eq!(
+            select("/logo.bmp", "image/bmp", 2048),
+            Some(ContentCoding::GZIP)
+        );
+        assert_eq!(select("/api/users", "application/json", 2048), None);
+        assert_eq!(select("/app.zip", "application/zip", 2048), None);
+        // Below the configured minimum size
+        assert_eq!(select("/index.html", "text/html", 512), None);
+        assert_eq!(
+            select("/index.html", "text/html", 1024),
+            Some(ContentCoding::GZIP)
+        );
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn policy_encodings_follow_the_preference_order() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, "gzip, br, zstd;q=0.5".parse().unwrap());
+        let html = "text/html".parse::<Mime>().unwrap();
+        let json = "application/json".parse::<Mime>().unwrap();
+
+        // Built-in preference among encodings of the same quality
+        let select =
+            |policy: &CompressionPolicy, mime| policy.select("/", &headers, Some(mime), None);
+        assert_eq!(select(&DEFAULT_POLICY, &html), Some(ContentCoding::BROTLI));
+
+        let mut no_brotli = rule(None, Some("application/json"), true);
+        no_brotli.encodings = Some(vec!["zstd".to_owned(), "gzip".to_owned()]);
+        let mut policy = policy(&[no_brotli]);
+        policy.encodings = parse_encodings(&["gzip".to_owned(), "br".to_owned()]).unwrap();
+        assert_eq!(select(&policy, &html), Some(ContentCoding::GZIP));
+        // Per-rule encodings: `zstd` has a lower quality than `gzip`
+        assert_eq!(select(&policy, &json), Some(ContentCoding::GZIP));
+
+        policy.encodings = parse_encodings(&["br".to_owned()]).unwrap();
+        assert_eq!(select(&policy, &html), Some(ContentCoding::BROTLI));
+        headers.insert(ACCEPT_ENCODING, "gzip".parse().unwrap());
+        assert_eq!(
+            policy.select("/", &headers, Some(&html), None),
+            None,
+            "unlisted encodings are not used"
+        );
+
+        assert!(parse_encodings(&["lzma".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn policy_levels_apply_per_encoding() {
+        let levels = CompressionLevels {
+            gzip: Some(6),
+            deflate: None,
+            brotli: Some(11),
+            zstd: None,
+        };
+        let mut policy = policy(&[]);
+        policy.levels = parse_levels(&levels).unwrap();
+        let level = |coding| format!("{:?}", policy.level(coding, CompressionLevel::Fastest));
+        assert_eq!(level(ContentCoding::GZIP), "Precise(6)");
+        assert_eq!(level(ContentCoding::BROTLI), "Precise(11)");
+        assert_eq!(level(ContentCoding::DCB), "Precise(11)");
+        assert_eq!(level(ContentCoding::ZSTD), "Fastest");
+
+        let out_of_range = CompressionLevels {
+            gzip: Some(12),
+            ..levels
+        };
+        assert!(parse_levels(&out_of_range).is_err());
+    }
 }
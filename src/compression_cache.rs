@@ -0,0 +1,711 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Persistent on-disk cache for the output of the on-the-fly compression.
+//!
+//! The first response compressed for a given file, `ETag`, encoding and
+//! compression level is written to the cache directory, and later requests
+//! get that file streamed back as-is, the same way a pre-compressed sidecar
+//! (`.br`, `.gz`, `.zst`) would be served. Changing a file changes its
+//! `ETag` and therefore its cache key, so stale entries are never served
+//! and simply age out.
+//!
+//! Entries are written to a temporary file and then renamed into place, so
+//! a crash or an aborted response never leaves a partial entry behind. The
+//! directory is bounded by size with LRU cleanup and survives restarts:
+//! the existing entries are indexed at startup, ordered by modification
+//! time. The directory can not be inside a served directory, which would
+//! expose the entries.
+
+use async_compression::Level;
+use bytes::{Bytes, BytesMut};
+use futures_util::Stream;
+use headers::HeaderValue;
+use http_body_util::BodyExt as _;
+use hyper::header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE};
+use hyper::{Method, Response, StatusCode};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context as TaskContext, Poll};
+use std::time::SystemTime;
+
+use crate::body::Body;
+use crate::exts::headers::ContentCoding;
+use crate::fs::stream::{FileStream, optimal_buf_size};
+use crate::handler::RequestHandlerOpts;
+use crate::{Context, Result};
+
+/// Default maximum total size in MiB of the cache directory.
+pub const DEFAULT_MAX_SIZE: u64 = 512;
+/// Default maximum size in KiB of a file to be cached (32 MiB).
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 32_768;
+
+/// Name of the directory holding the entries being written.
+const TMP_DIR: &str = ".tmp";
+
+/// Counter making temporary file names unique within the process.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Initializes the on-disk compression cache.
+///
+/// It requires dynamic compression, so it must be initialized after it.
+pub fn init(handler_opts: &mut RequestHandlerOpts) -> Result {
+    let Some(opts) = handler_opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.compression_cache.as_ref())
+    else {
+        tracing::info!(enabled = false, "compression cache");
+        return Ok(());
+    };
+
+    if !handler_opts.compression {
+        tracing::warn!("compression cache is configured but dynamic compression is disabled");
+        tracing::info!(enabled = false, "compression cache");
+        return Ok(());
+    }
+
+    let dir = resolve(&opts.dir);
+    for root in served_roots(handler_opts) {
+        let root = resolve(root);
+        if dir.starts_with(&root) {
+            bail!(
+                "compression cache directory `{}` is inside the served directory `{}`",
+                opts.dir.display(),
+                root.display()
+            );
+        }
+    }
+
+    let max_size = opts
+        .max_size
+        .unwrap_or(DEFAULT_MAX_SIZE)
+        .saturating_mul(1024 * 1024);
+    let max_file_size = opts
+        .max_file_size
+        .unwrap_or(DEFAULT_MAX_FILE_SIZE)
+        .saturating_mul(1024);
+    let cache = CompressionCache::open(&opts.dir, max_size, max_file_size)?;
+
+    tracing::info!(
+        enabled = true,
+        dir = %opts.dir.display(),
+        max_size,
+        max_file_size,
+        entries = cache.index().entries.len(),
+        "compression cache"
+    );
+
+    handler_opts.compression_cache = Some(Arc::new(cache));
+
+    Ok(())
+}
+
+/// Returns the directories served by the main site, the virtual hosts and
+/// the mounts.
+fn served_roots(handler_opts: &RequestHandlerOpts) -> Vec<&Path> {
+    let mut roots = vec![handler_opts.root_dir.as_path()];
+    if let Some(advanced) = &handler_opts.advanced_opts {
+        let vhosts = advanced.virtual_hosts.iter().flatten();
+        roots.extend(vhosts.map(|vhost| vhost.root.as_path()));
+        let mounts = advanced.mounts.iter().flatten();
+        roots.extend(mounts.map(|mount| mount.root.as_path()));
+        if let Some(mass) = &advanced.mass_virtual_hosts {
+            roots.push(&mass.root);
+            roots.extend(mass.fallback.as_deref());
+        }
+    }
+    roots
+}
+
+/// Resolves a path which may not exist yet from its closest existing
+/// ancestor, so that it can be compared with other resolved paths.
+fn resolve(path: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    loop {
+        let dir = match existing.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => existing,
+        };
+        if let Ok(mut resolved) = dir.canonicalize() {
+            for name in missing.iter().rev() {
+                match *name == ".." {
+                    true => _ = resolved.pop(),
+                    false => resolved.push(name),
+                }
+            }
+            return resolved;
+        }
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(last)) => {
+                missing.push(last.as_os_str());
+                existing = parent;
+            }
+            _ => return path.to_owned(),
+        }
+    }
+}
+
+/// An on-disk cache of compressed response bodies.
+#[derive(Debug)]
+pub struct CompressionCache {
+    /// Directory of the cache entries.
+    dir: PathBuf,
+    /// Maximum total size in bytes of the entries.
+    max_size: u64,
+    /// Maximum size in bytes of a file to be cached.
+    max_file_size: u64,
+    /// In-memory index of the entries.
+    index: Mutex<Index>,
+}
+
+/// LRU index of the cache entries.
+#[derive(Debug, Default)]
+struct Index {
+    /// Entries by file name.
+    entries: HashMap<String, Entry>,
+    /// Entry names by recency, least recently used first.
+    lru: BTreeMap<u64, String>,
+    /// Total size in bytes of the entries.
+    total: u64,
+    /// Recency clock.
+    tick: u64,
+    /// Entries currently being written.
+    filling: HashSet<String>,
+}
+
+/// A cache entry.
+#[derive(Debug)]
+struct Entry {
+    /// Size in bytes of the encoded file.
+    len: u64,
+    /// Last use, see [`Index::tick`].
+    tick: u64,
+}
+
+impl Index {
+    /// Marks an entry as used and returns its size.
+    fn touch(&mut self, name: &str) -> Option<u64> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(name)?;
+        self.lru.remove(&entry.tick);
+        entry.tick = self.tick;
+        self.lru.insert(self.tick, name.to_owned());
+        Some(entry.len)
+    }
+
+    /// Adds (or replaces) an entry as the most recently used one.
+    fn insert(&mut self, name: String, len: u64) {
+        self.remove(&name);
+        self.tick += 1;
+        self.total += len;
+        self.lru.insert(self.tick, name.clone());
+        self.entries.insert(
+            name,
+            Entry {
+                len,
+                tick: self.tick,
+            },
+        );
+    }
+
+    /// Drops an entry from the index.
+    fn remove(&mut self, name: &str) {
+        if let Some(entry) = self.entries.remove(name) {
+            self.lru.remove(&entry.tick);
+            self.total -= entry.len;
+        }
+    }
+
+    /// Drops the least recently used entries until the total size fits
+    /// within `max_size` and returns their names.
+    fn evict(&mut self, max_size: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total > max_size {
+            let Some((_, name)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&name) {
+                self.total -= entry.len;
+            }
+            evicted.push(name);
+        }
+        evicted
+    }
+}
+
+/// Identifies the cache entry of a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+    /// File name of the entry.
+    name: String,
+    /// Encoding of the entry.
+    coding: ContentCoding,
+}
+
+impl CompressionCache {
+    /// Opens (or creates) a cache directory and indexes its entries.
+    fn open(dir: &Path, max_size: u64, max_file_size: u64) -> Result<Self> {
+        let tmp_dir = dir.join(TMP_DIR);
+        fs::create_dir_all(&tmp_dir).with_context(|| {
+            format!(
+                "unable to create the compression cache directory `{}`",
+                dir.display()
+            )
+        })?;
+
+        // Leftovers of writes interrupted by a crash or a restart
+        for entry in fs::read_dir(&tmp_dir)?.flatten() {
+            let _ = fs::remove_file(entry.path());
+        }
+
+        let mut found = Vec::new();
+        for entry in fs::read_dir(dir)
+            .with_context(|| {
+                format!(
+                    "unable to read the compression cache directory `{}`",
+                    dir.display()
+                )
+            })?
+            .flatten()
+        {
+            let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            if !is_entry_name(&name) {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_file() {
+                let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                found.push((modified, name, meta.len()));
+            }
+        }
+        found.sort();
+
+        let cache = Self {
+            dir: dir.to_owned(),
+            max_size,
+            max_file_size,
+            index: Mutex::new(Index::default()),
+        };
+        {
+            let mut index = cache.index();
+            for (_, name, len) in found {
+                index.insert(name, len);
+            }
+        }
+        cache.evict();
+
+        Ok(cache)
+    }
+
+    fn index(&self) -> MutexGuard<'_, Index> {
+        self.index.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns the cache key of a response when it is eligible for the
+    /// cache: a full `200` response to a `GET` request whose body is the
+    /// unmodified content of a file, compressed with the given encoding
+    /// and level as selected by the compression policy.
+    pub(crate) async fn key(
+        &self,
+        method: &Method,
+        coding: ContentCoding,
+        level: Level,
+        file_path: &Path,
+        resp: &Response<Body>,
+    ) -> Option<CacheKey> {
+        if method != Method::GET
+            || resp.status() != StatusCode::OK
+            || resp.headers().contains_key(CONTENT_RANGE)
+        {
+            return None;
+        }
+        let len = resp
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())?;
+        if len > self.max_file_size {
+            return None;
+        }
+
+        // The body must be the file itself (not e.g. a directory listing)
+        let meta = tokio::fs::metadata(file_path).await.ok()?;
+        if !meta.is_file() || meta.len() != len {
+            return None;
+        }
+        let (_, etag) = crate::etag::build_from_meta(&meta)?;
+
+        Some(CacheKey {
+            name: entry_name(file_path, &etag, coding, level),
+            coding,
+        })
+    }
+
+    /// Replaces the body of a response with its cache entry, if any.
+    /// Returns whether the response is served from the cache.
+    pub(crate) fn serve(&self, key: &CacheKey, resp: &mut Response<Body>) -> bool {
+        let Some(len) = self.index().touch(&key.name) else {
+            return false;
+        };
+
+        let path = self.dir.join(&key.name);
+        let (file, meta) = match File::open(&path).and_then(|file| {
+            let meta = file.metadata()?;
+            Ok((file, meta))
+        }) {
+            Ok(opened) => opened,
+            Err(err) => {
+                tracing::debug!(
+                    "compression cache entry `{}` is unavailable: {err}",
+                    path.display()
+                );
+                self.index().remove(&key.name);
+                return false;
+            }
+        };
+        if meta.len() != len {
+            self.index().remove(&key.name);
+            return false;
+        }
+
+        tracing::trace!(
+            "serving {} encoded body from the compression cache",
+            key.coding.as_str()
+        );
+
+        let headers = resp.headers_mut();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from(key.coding));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+        *resp.body_mut() = crate::body::stream(FileStream::new(file, optimal_buf_size(&meta)));
+        true
+    }
+
+    /// Tees a response compressed on the fly into the cache entry of the
+    /// given key.
+    ///
+    /// The response is returned untouched when it was not compressed with
+    /// the key encoding or when the entry is already being written.
+    pub(crate) fn tee(self: &Arc<Self>, resp: Response<Body>, key: CacheKey) -> Response<Body> {
+        let is_encoded = resp
+            .headers()
+            .get(CONTENT_ENCODING)
+            .is_some_and(|v| v.as_bytes() == key.coding.as_str().as_bytes());
+        if !is_encoded || !self.index().filling.insert(key.name.clone()) {
+            return resp;
+        }
+
+        let (head, body) = resp.into_parts();
+        let body = crate::body::stream(DiskFillStream {
+            inner: body.into_data_stream(),
+            buf: Some(BytesMut::new()),
+            cache: self.clone(),
+            name: key.name,
+        });
+        Response::from_parts(head, body)
+    }
+
+    /// Writes an entry atomically and evicts the least recently used
+    /// entries when the cache grows beyond its size.
+    fn store(&self, name: &str, data: &[u8]) -> io::Result<()> {
+        let len = data.len() as u64;
+        if len > self.max_size {
+            return Ok(());
+        }
+
+        let tmp_path = self.dir.join(TMP_DIR).join(format!(
+            "{name}.{}.{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = File::create(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(data)?;
+                file.sync_data()
+            })
+            .and_then(|_| fs::rename(&tmp_path, self.dir.join(name)));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        written?;
+
+        self.index().insert(name.to_owned(), len);
+        self.evict();
+        tracing::debug!("{name} ({len} bytes) inserted into the compression cache");
+
+        Ok(())
+    }
+
+    /// Removes the entries evicted to fit within the cache size.
+    fn evict(&self) {
+        let evicted = self.index().evict(self.max_size);
+        for name in evicted {
+            if let Err(err) = fs::remove_file(self.dir.join(&name)) {
+                tracing::debug!("unable to remove compression cache entry `{name}`: {err}");
+            }
+        }
+    }
+
+    /// Stores a fully encoded body, off the async runtime when possible.
+    fn fill(self: Arc<Self>, name: String, data: Bytes) {
+        let write = move || {
+            if let Err(err) = self.store(&name, &data) {
+                tracing::warn!("unable to write compression cache entry `{name}`: {err}");
+            }
+            self.index().filling.remove(&name);
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(write);
+            }
+            Err(_) => write(),
+        }
+    }
+}
+
+/// Builds the file name of an entry from its identity: the path and
+/// `ETag` of the original file, the encoding and the compression level.
+fn entry_name(file_path: &Path, etag: &HeaderValue, coding: ContentCoding, level: Level) -> String {
+    let level = match level {
+        Level::Fastest => "fastest".to_owned(),
+        Level::Best => "best".to_owned(),
+        Level::Precise(level) => level.to_string(),
+        _ => "default".to_owned(),
+    };
+    let mut hash = Fnv128::default();
+    hash.write(file_path.as_os_str().as_encoded_bytes());
+    hash.write(etag.as_bytes());
+    hash.write(coding.as_str().as_bytes());
+    hash.write(level.as_bytes());
+    format!("{:032x}.{}", hash.0, coding.as_str())
+}
+
+/// Whether a file name looks like a cache entry, so that unrelated files
+/// living in the cache directory are left alone.
+fn is_entry_name(name: &str) -> bool {
+    name.split_once('.').is_some_and(|(hash, ext)| {
+        hash.len() == 32
+            && hash.bytes().all(|b| b.is_ascii_hexdigit())
+            && matches!(ext, "br" | "gzip" | "zstd" | "deflate")
+    })
+}
+
+/// 128-bit FNV-1a hasher. Unlike `DefaultHasher`, its output is stable
+/// across Rust releases, which keeps cache entries valid after upgrades.
+struct Fnv128(u128);
+
+impl Default for Fnv128 {
+    fn default() -> Self {
+        Self(0x6c62272e07bb014262b821756295c58d)
+    }
+}
+
+impl Fnv128 {
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    /// Hashes a field followed by a separator.
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes.iter().chain(&[0]) {
+            self.0 ^= u128::from(b);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+/// A stream that forwards an encoded body and, once it has been fully
+/// produced, writes it to the cache.
+struct DiskFillStream<S> {
+    inner: S,
+    /// Accumulator for the encoded body. Dropped on error.
+    buf: Option<BytesMut>,
+    cache: Arc<CompressionCache>,
+    /// File name of the entry.
+    name: String,
+}
+
+impl<S> Stream for DiskFillStream<S>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin,
+{
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let this = Pin::into_inner(self);
+        let item = match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(item) => item,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        match &item {
+            Some(Ok(chunk)) => {
+                if let Some(buf) = this.buf.as_mut() {
+                    buf.extend_from_slice(chunk);
+                }
+            }
+            Some(Err(_)) => this.buf = None,
+            None => {
+                if let Some(buf) = this.buf.take() {
+                    this.cache
+                        .clone()
+                        .fill(std::mem::take(&mut this.name), buf.freeze());
+                }
+            }
+        }
+
+        Poll::Ready(item)
+    }
+}
+
+impl<S> Drop for DiskFillStream<S> {
+    fn drop(&mut self) {
+        // Aborted before completion: release the entry for a later fill
+        if !self.name.is_empty() {
+            self.cache.index().filling.remove(&self.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(dir: &Path, max_size: u64) -> Arc<CompressionCache> {
+        Arc::new(CompressionCache::open(dir, max_size, 1024 * 1024).unwrap())
+    }
+
+    fn gzip_key(name: &str) -> CacheKey {
+        CacheKey {
+            name: format!("{name:0>32}.gzip"),
+            coding: ContentCoding::GZIP,
+        }
+    }
+
+    #[test]
+    fn entry_names_depend_on_every_key_part() {
+        let etag = HeaderValue::from_static("W/\"1b21dd2-2000\"");
+        let path = Path::new("/srv/public/app.js");
+        let name = entry_name(path, &etag, ContentCoding::BROTLI, Level::Precise(4));
+        assert!(is_entry_name(&name));
+        assert!(name.ends_with(".br"));
+        assert_eq!(
+            name,
+            entry_name(path, &etag, ContentCoding::BROTLI, Level::Precise(4))
+        );
+
+        let other_etag = HeaderValue::from_static("W/\"1b21dd3-2000\"");
+        for other in [
+            entry_name(
+                Path::new("/srv/public/b.js"),
+                &etag,
+                ContentCoding::BROTLI,
+                Level::Precise(4),
+            ),
+            entry_name(path, &other_etag, ContentCoding::BROTLI, Level::Precise(4)),
+            entry_name(path, &etag, ContentCoding::ZSTD, Level::Precise(4)),
+            entry_name(path, &etag, ContentCoding::BROTLI, Level::Best),
+            entry_name(path, &etag, ContentCoding::BROTLI, Level::Precise(5)),
+        ] {
+            assert_ne!(name, other);
+        }
+    }
+
+    #[test]
+    fn unrelated_files_are_not_entries() {
+        assert!(is_entry_name(&format!("{:032x}.zstd", 42)));
+        assert!(!is_entry_name("index.html"));
+        assert!(!is_entry_name(&format!("{:032x}.txt", 42)));
+        assert!(!is_entry_name(TMP_DIR));
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted() {
+        let mut index = Index::default();
+        index.insert("a".into(), 10);
+        index.insert("b".into(), 10);
+        index.insert("c".into(), 10);
+        assert_eq!(index.touch("a"), Some(10));
+
+        assert_eq!(index.evict(20), vec!["b".to_owned()]);
+        assert_eq!(index.total, 20);
+        assert!(index.touch("b").is_none());
+
+        index.insert("a".into(), 15);
+        assert_eq!(index.total, 25);
+    }
+
+    #[tokio::test]
+    async fn entries_are_served_once_filled_and_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = cache(dir.path(), 1024);
+        let key = gzip_key("1");
+
+        let mut resp = Response::new(crate::body::full("identity"));
+        assert!(!store.serve(&key, &mut resp));
+        resp.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        let body = store
+            .tee(resp, key.clone())
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(body, "identity");
+
+        // The write happens on a blocking thread
+        for _ in 0..100 {
+            if store.index().entries.contains_key(&key.name) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let reopened = cache(dir.path(), 1024);
+        let mut resp = Response::new(crate::body::empty());
+        assert!(reopened.serve(&key, &mut resp));
+        assert_eq!(resp.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(resp.headers()[CONTENT_LENGTH], "8");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "identity");
+    }
+
+    #[test]
+    fn store_evicts_over_budget_and_cleans_up_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = cache(dir.path(), 10);
+        let (first, second) = (gzip_key("1"), gzip_key("2"));
+
+        store.store(&first.name, b"123456").unwrap();
+        store.store(&second.name, b"123456").unwrap();
+        assert!(!dir.path().join(&first.name).exists());
+        assert!(dir.path().join(&second.name).exists());
+
+        // Entries larger than the whole cache are never written
+        store.store(&first.name, &[0; 11]).unwrap();
+        assert!(!dir.path().join(&first.name).exists());
+        assert_eq!(fs::read_dir(dir.path().join(TMP_DIR)).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn responses_of_other_encodings_are_not_teed() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = cache(dir.path(), 1024);
+        let key = gzip_key("1");
+
+        let resp = store.tee(Response::new(crate::body::full("plain")), key.clone());
+        drop(resp);
+        assert!(!store.index().filling.contains(&key.name));
+    }
+}
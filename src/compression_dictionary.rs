@@ -0,0 +1,548 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Compression Dictionary Transport support.
+//!
+//! Resources whose request path matches a configured `source` glob are
+//! served with a `Use-As-Dictionary` header, so browsers keep them as
+//! compression dictionaries for later requests of the URLs matching the
+//! `match` pattern. Those requests announce the SHA-256 hash of the
+//! dictionary held by the client with `Available-Dictionary` and, when the
+//! server knows a dictionary with that hash, responses are encoded with
+//! `dcb` (Brotli) or `dcz` (Zstandard) using it as a raw prefix dictionary.
+//!
+//! A precomputed `<file>.<hash>.dcb` or `<file>.<hash>.dcz` sidecar, where
+//! `<hash>` is the lowercase hexadecimal SHA-256 of the dictionary, is
+//! served when it is not older than the file. Otherwise responses are
+//! compressed on the fly when dynamic compression is enabled. Either way
+//! the dynamic compression policy must allow the underlying encoding.
+//!
+//! Dictionaries are indexed at startup by scanning the root directory, and
+//! whenever they are served.
+
+use globset::{Glob, GlobMatcher};
+use headers::HeaderMapExt;
+use http_body_util::BodyExt as _;
+use hyper::header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, HeaderName, HeaderValue};
+use hyper::{HeaderMap, Method, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read as _, Seek as _, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::body::Body;
+use crate::compression::{DEFAULT_POLICY, body_traits, tag_representation};
+use crate::exts::headers::{AcceptEncoding, ContentCoding};
+use crate::exts::http::{append_vary_accept_encoding, append_vary_available_dictionary};
+use crate::fs::hashes::{self, HashKind};
+use crate::fs::stream::{FileStream, optimal_buf_size};
+use crate::handler::RequestHandlerOpts;
+use crate::{Context, Error, Result};
+
+/// Maximum size of a response body compressed on the fly, which is done
+/// in memory.
+const MAX_COMPRESS_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Maximum size of a dictionary.
+const MAX_DICTIONARY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Maximum number of dictionaries kept in the index. It is cleared when
+/// full, dictionaries get indexed again once served.
+const MAX_DICTIONARIES: usize = 1024;
+
+/// Magic number heading `dcb` encoded bodies.
+const DCB_MAGIC: [u8; 4] = [0xff, 0x44, 0x43, 0x42];
+
+/// Magic number heading `dcz` encoded bodies, a Zstandard skippable frame.
+const DCZ_MAGIC: [u8; 8] = [0x5e, 0x2a, 0x4d, 0x18, 0x20, 0x00, 0x00, 0x00];
+
+static USE_AS_DICTIONARY: HeaderName = HeaderName::from_static("use-as-dictionary");
+static AVAILABLE_DICTIONARY: HeaderName = HeaderName::from_static("available-dictionary");
+
+/// SHA-256 hash of a dictionary.
+type Hash = [u8; 32];
+
+/// Encodings using a dictionary that can be handled given enabled features.
+const AVAILABLE_ENCODINGS: &[ContentCoding] = &[
+    #[cfg(any(feature = "compression", feature = "compression-brotli"))]
+    ContentCoding::DCB,
+    #[cfg(any(feature = "compression", feature = "compression-zstd"))]
+    ContentCoding::DCZ,
+];
+
+/// Initializes the compression dictionaries.
+pub fn init(handler_opts: &mut RequestHandlerOpts) -> Result {
+    let Some(entries) = handler_opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.compression_dictionaries.as_ref())
+        .filter(|entries| !entries.is_empty())
+    else {
+        tracing::info!(enabled = false, "compression dictionaries");
+        return Ok(());
+    };
+
+    let mut rules = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let source = Glob::new(&entry.source)
+            .with_context(|| {
+                format!(
+                    "can not compile glob pattern for compression dictionary source: {}",
+                    entry.source
+                )
+            })?
+            .compile_matcher();
+        let mut value = ["match=", &sf_string(&entry.url_match)].concat();
+        if let Some(id) = &entry.id {
+            value.push_str(", id=");
+            value.push_str(&sf_string(id));
+        }
+        let value = HeaderValue::from_str(&value).with_context(|| {
+            format!(
+                "invalid compression dictionary match pattern or id: {}",
+                entry.url_match
+            )
+        })?;
+        rules.push(Rule { source, value });
+    }
+
+    let dictionaries = CompressionDictionaries {
+        rules,
+        known: Mutex::new(HashMap::new()),
+    };
+    dictionaries.scan(&handler_opts.root_dir);
+
+    tracing::info!(
+        enabled = true,
+        rules = dictionaries.rules.len(),
+        dictionaries = dictionaries.known().len(),
+        "compression dictionaries"
+    );
+
+    handler_opts.compression_dictionaries = Some(Arc::new(dictionaries));
+
+    Ok(())
+}
+
+/// Formats a structured field string, RFC 8941 §3.3.3.
+fn sf_string(value: &str) -> String {
+    let mut s = String::with_capacity(value.len() + 2);
+    s.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\') {
+            s.push('\\');
+        }
+        s.push(c);
+    }
+    s.push('"');
+    s
+}
+
+/// A resource served as a dictionary.
+#[derive(Debug)]
+struct Rule {
+    /// Request paths of the resource.
+    source: GlobMatcher,
+    /// `Use-As-Dictionary` header value.
+    value: HeaderValue,
+}
+
+/// The configured dictionaries along with the ones seen so far.
+#[derive(Debug)]
+pub struct CompressionDictionaries {
+    rules: Vec<Rule>,
+    /// Dictionary files by hash.
+    known: Mutex<HashMap<Hash, PathBuf>>,
+}
+
+impl CompressionDictionaries {
+    fn known(&self) -> std::sync::MutexGuard<'_, HashMap<Hash, PathBuf>> {
+        self.known.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns the `Use-As-Dictionary` value of the given request path.
+    fn rule_for(&self, uri_path: &str) -> Option<&HeaderValue> {
+        self.rules
+            .iter()
+            .find(|rule| rule.source.is_match(uri_path))
+            .map(|rule| &rule.value)
+    }
+
+    /// Indexes the dictionaries found in the given root directory.
+    ///
+    /// Symbolic links are not followed and hidden files are skipped.
+    fn scan(&self, root: &Path) {
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                tracing::debug!("unable to read directory `{}`", dir.display());
+                continue;
+            };
+            for entry in entries.flatten() {
+                let is_hidden = entry
+                    .file_name()
+                    .to_str()
+                    .is_none_or(|n| n.starts_with('.'));
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                if is_hidden {
+                    continue;
+                }
+                let path = entry.path();
+                if file_type.is_dir() {
+                    pending.push(path);
+                } else if file_type.is_file()
+                    && crate::digest::uri_path_of(&path, root)
+                        .is_some_and(|uri_path| self.rule_for(&uri_path).is_some())
+                {
+                    self.register(&path);
+                }
+            }
+        }
+    }
+
+    /// Indexes the dictionary at the given path.
+    fn register(&self, path: &Path) {
+        let Some(hash) = dictionary_hash(path) else {
+            return;
+        };
+        let mut known = self.known();
+        if known
+            .get(&hash)
+            .is_some_and(|known_path| known_path == path)
+        {
+            return;
+        }
+        if known.len() >= MAX_DICTIONARIES {
+            known.clear();
+        }
+        tracing::debug!("compression dictionary `{}` indexed", path.display());
+        known.insert(hash, path.to_owned());
+    }
+
+    /// Returns the path of the dictionary with the given hash if it is
+    /// still available.
+    fn lookup(&self, hash: &Hash) -> Option<PathBuf> {
+        let path = self.known().get(hash).cloned()?;
+        if dictionary_hash(&path).as_ref() == Some(hash) {
+            return Some(path);
+        }
+        self.known().remove(hash);
+        None
+    }
+}
+
+/// Computes the hash of a dictionary file.
+fn dictionary_hash(path: &Path) -> Option<Hash> {
+    let mut file = File::open(path).ok()?;
+    let meta = file.metadata().ok()?;
+    if !meta.is_file() || meta.len() > MAX_DICTIONARY_SIZE {
+        return None;
+    }
+    let hash = hashes::hash_file(HashKind::Sha256, &mut file, path, &meta).ok()?;
+    hash[..].try_into().ok()
+}
+
+/// Marks dictionary resources and encodes responses with the dictionary
+/// available to the client if possible.
+pub(crate) fn post_process<T>(
+    opts: &RequestHandlerOpts,
+    req: &Request<T>,
+    mut resp: Response<Body>,
+    file_path: Option<&PathBuf>,
+) -> Result<Response<Body>, Error> {
+    let (Some(dictionaries), Some(file_path)) = (opts.compression_dictionaries.as_ref(), file_path)
+    else {
+        return Ok(resp);
+    };
+    if resp.status() != StatusCode::OK {
+        return Ok(resp);
+    }
+
+    if let Some(value) = dictionaries.rule_for(req.uri().path()) {
+        resp.headers_mut()
+            .insert(USE_AS_DICTIONARY.clone(), value.clone());
+        dictionaries.register(file_path);
+    }
+
+    if req.method() != Method::GET
+        || resp.headers().contains_key(CONTENT_ENCODING)
+        || resp.headers().contains_key(CONTENT_RANGE)
+    {
+        return Ok(resp);
+    }
+    let headers = req.headers();
+    let Some(hash) = headers.get(&AVAILABLE_DICTIONARY).and_then(parse_hash) else {
+        return Ok(resp);
+    };
+    let Some(coding) = preferred_encoding(headers) else {
+        return Ok(resp);
+    };
+    let policy = opts
+        .compression_policy
+        .as_deref()
+        .unwrap_or(&DEFAULT_POLICY);
+    let (mime, len) = body_traits(&resp);
+    if !policy.allows(req.uri().path(), mime.as_ref(), len, coding) {
+        return Ok(resp);
+    }
+    let Some(dictionary) = dictionaries.lookup(&hash) else {
+        tracing::trace!("available dictionary is unknown, skipping");
+        return Ok(resp);
+    };
+
+    if let Some((file, meta)) = precomputed(file_path, coding, &hash) {
+        tracing::trace!("serving precomputed {} encoded body", coding.as_str());
+        resp.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(meta.len()));
+        *resp.body_mut() = crate::body::stream(FileStream::new(file, optimal_buf_size(&meta)));
+    } else {
+        let in_bounds = len.is_some_and(|len| len <= MAX_COMPRESS_SIZE);
+        if !opts.compression || !in_bounds {
+            return Ok(resp);
+        }
+        tracing::trace!(
+            "compressing response body on the fly using {}",
+            coding.as_str()
+        );
+        let level = policy.level(coding, opts.compression_level);
+        resp = compress(resp, coding, level, dictionary, hash);
+    }
+
+    resp.headers_mut()
+        .insert(CONTENT_ENCODING, HeaderValue::from(coding));
+    append_vary_accept_encoding(&mut resp);
+    append_vary_available_dictionary(&mut resp);
+    let label = format!("{}-{}", coding.as_str(), hex(&hash[..4]));
+    Ok(tag_representation(headers, resp, Some(&label)))
+}
+
+/// Parses an `Available-Dictionary` value, a structured field byte
+/// sequence holding a SHA-256 hash.
+fn parse_hash(value: &HeaderValue) -> Option<Hash> {
+    use base64::Engine as _;
+
+    let encoded = value
+        .to_str()
+        .ok()?
+        .trim()
+        .strip_prefix(':')?
+        .strip_suffix(':')?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()?;
+    decoded.try_into().ok()
+}
+
+/// Returns the preferred dictionary encoding via the `Accept-Encoding` header.
+fn preferred_encoding(headers: &HeaderMap) -> Option<ContentCoding> {
+    headers
+        .typed_get::<AcceptEncoding>()?
+        .sorted_encodings()
+        .find(|coding| AVAILABLE_ENCODINGS.contains(coding))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write as _;
+
+    bytes.iter().fold(String::new(), |mut s, byte| {
+        let _ = write!(s, "{byte:02x}");
+        s
+    })
+}
+
+/// Opens the precomputed sidecar of a file for the given encoding and
+/// dictionary, if present and up to date.
+fn precomputed(file_path: &Path, coding: ContentCoding, hash: &Hash) -> Option<(File, Metadata)> {
+    let name = file_path.file_name()?.to_str()?;
+    let sidecar = file_path.with_file_name(format!("{name}.{}.{}", hex(hash), coding.as_str()));
+    let mut file = File::open(&sidecar).ok()?;
+    let meta = file.metadata().ok()?;
+    let is_fresh = fs::metadata(file_path)
+        .and_then(|source| source.modified())
+        .is_ok_and(|modified| meta.modified().is_ok_and(|m| m >= modified));
+    if !meta.is_file() || !is_fresh {
+        return None;
+    }
+
+    // The sidecar must be a complete encoded body
+    let mut head = [0; DCZ_MAGIC.len() + 32];
+    let head = &mut head[..header_len(coding)];
+    file.read_exact(head).ok()?;
+    if head != &encoded_header(coding, hash)[..] {
+        tracing::warn!(
+            "ignoring precomputed sidecar `{}` lacking its {} header",
+            sidecar.display(),
+            coding.as_str()
+        );
+        return None;
+    }
+    file.seek(SeekFrom::Start(0)).ok()?;
+    Some((file, meta))
+}
+
+fn header_len(coding: ContentCoding) -> usize {
+    match coding {
+        ContentCoding::DCB => DCB_MAGIC.len() + 32,
+        _ => DCZ_MAGIC.len() + 32,
+    }
+}
+
+/// Builds the header of an encoded body: the magic number of the encoding
+/// followed by the hash of the dictionary.
+fn encoded_header(coding: ContentCoding, hash: &Hash) -> Vec<u8> {
+    let magic: &[u8] = match coding {
+        ContentCoding::DCB => &DCB_MAGIC,
+        _ => &DCZ_MAGIC,
+    };
+    [magic, hash].concat()
+}
+
+/// Compresses a response body with a dictionary, off the async runtime.
+fn compress(
+    resp: Response<Body>,
+    coding: ContentCoding,
+    level: async_compression::Level,
+    dictionary: PathBuf,
+    hash: Hash,
+) -> Response<Body> {
+    let (mut head, body) = resp.into_parts();
+    head.headers.remove(CONTENT_LENGTH);
+    let level = match level {
+        async_compression::Level::Fastest => 1,
+        async_compression::Level::Best => match coding {
+            ContentCoding::DCB => 11,
+            _ => 19,
+        },
+        async_compression::Level::Precise(level) => level,
+        _ => 4,
+    };
+
+    let body = crate::body::stream(futures_util::stream::once(async move {
+        let data = body.collect().await?.to_bytes();
+        tokio::task::spawn_blocking(move || {
+            let dictionary = fs::read(&dictionary)?;
+            let mut out = encoded_header(coding, &hash);
+            encode(coding, level, &dictionary, &data, &mut out)?;
+            Ok(bytes::Bytes::from(out))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }));
+    Response::from_parts(head, body)
+}
+
+/// Encodes `data` using `dictionary` as a raw prefix dictionary.
+fn encode(
+    coding: ContentCoding,
+    level: i32,
+    dictionary: &[u8],
+    data: &[u8],
+    out: &mut Vec<u8>,
+) -> io::Result<()> {
+    match coding {
+        #[cfg(any(feature = "compression", feature = "compression-brotli"))]
+        ContentCoding::DCB => {
+            use brotli::enc::{BrotliEncoderParams, StandardAlloc};
+            use brotli::interface::{PredictionModeContextMap, StaticCommand};
+            use brotli::{InputPair, InputReferenceMut, IoReaderWrapper, IoWriterWrapper};
+
+            let params = BrotliEncoderParams {
+                quality: level.clamp(0, 11),
+                lgwin: 24,
+                size_hint: data.len(),
+                ..Default::default()
+            };
+            brotli::BrotliCompressCustomIoCustomDict(
+                &mut IoReaderWrapper(&mut &data[..]),
+                &mut IoWriterWrapper(out),
+                &mut [0; 4096],
+                &mut [0; 4096],
+                &params,
+                StandardAlloc::default(),
+                &mut |_: &mut PredictionModeContextMap<InputReferenceMut<'_>>,
+                      _: &mut [StaticCommand],
+                      _: InputPair<'_>,
+                      _: &mut StandardAlloc| (),
+                dictionary,
+                io::Error::from(io::ErrorKind::UnexpectedEof),
+            )?;
+            Ok(())
+        }
+        #[cfg(any(feature = "compression", feature = "compression-zstd"))]
+        ContentCoding::DCZ => {
+            use zstd::zstd_safe::CParameter;
+
+            // Decoders accept windows up to 8 MiB or 1.25 times the
+            // dictionary size, whichever is larger.
+            let max_window = (8 << 20).max(dictionary.len() + dictionary.len() / 4);
+            let window_log = (usize::BITS - 1 - max_window.leading_zeros()).min(27);
+            let mut compressor = zstd::bulk::Compressor::with_dictionary(level, dictionary)?;
+            compressor.set_parameter(CParameter::WindowLog(window_log))?;
+            out.extend_from_slice(&compressor.compress(data)?);
+            Ok(())
+        }
+        _ => Err(io::Error::other("unsupported dictionary encoding")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn use_as_dictionary_values_are_structured_fields() {
+        assert_eq!(sf_string("/app.*.js"), r#""/app.*.js""#);
+        assert_eq!(sf_string(r#"a"b\c"#), r#""a\"b\\c""#);
+    }
+
+    #[test]
+    fn available_dictionary_holds_a_sha256_hash() {
+        let hash = parse_hash(&HeaderValue::from_static(
+            ":pZGm1Av0IEBKARczz7exkNYsZb8LzaMrV7J32a2fFG4=:",
+        ))
+        .unwrap();
+        assert_eq!(
+            hex(&hash),
+            "a591a6d40bf420404a011733cfb7b190d62c65bf0bcda32b57b277d9ad9f146e"
+        );
+        assert!(parse_hash(&HeaderValue::from_static("pZGm1Av0IEBKARczz7exkNY=")).is_none());
+        assert!(parse_hash(&HeaderValue::from_static(":aGVsbG8=:")).is_none());
+    }
+
+    #[cfg(any(feature = "compression", feature = "compression-brotli"))]
+    #[test]
+    fn dcb_streams_decode_with_their_dictionary() {
+        let dictionary = b"function hello() { return 'hello world'; }\n".repeat(20);
+        let data = [&dictionary[..], b"function bye() { return 'bye'; }\n"].concat();
+        let mut out = Vec::new();
+        encode(ContentCoding::DCB, 5, &dictionary, &data, &mut out).unwrap();
+        assert!(out.len() < 64, "the dictionary should be referenced");
+
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new_with_custom_dict(&out[..], 4096, dictionary.into())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[cfg(any(feature = "compression", feature = "compression-zstd"))]
+    #[test]
+    fn dcz_streams_decode_with_their_dictionary() {
+        let dictionary = b"function hello() { return 'hello world'; }\n".repeat(20);
+        let data = [&dictionary[..], b"function bye() { return 'bye'; }\n"].concat();
+        let mut out = Vec::new();
+        encode(ContentCoding::DCZ, 3, &dictionary, &data, &mut out).unwrap();
+        assert!(out.len() < 64, "the dictionary should be referenced");
+
+        let decoded = zstd::bulk::Decompressor::with_dictionary(&dictionary)
+            .unwrap()
+            .decompress(&out, data.len())
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+}
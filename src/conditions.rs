@@ -0,0 +1,335 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Request conditions of redirects and rewrites.
+//!
+//! Rules may require request headers, query parameters, cookies or the
+//! host to be present (`has`) or absent (`missing`), optionally with a
+//! value matching a regex:
+//!
+//! ```toml
+//! [[advanced.redirects]]
+//! source = "/"
+//! destination = "/$1/"
+//! kind = 302
+//! has = [{ type = "header", key = "accept-language", value = "(de|fr).*" }]
+//! missing = [{ type = "cookie", key = "lang" }]
+//! ```
+//!
+//! Values captured by the `has` conditions extend the `$N` placeholders of
+//! the destination, following the ones of the source pattern: each
+//! condition adds the capture groups of its `value` regex, or the whole
+//! value when it has none.
+//!
+//! Responses to requests matching the source of rules checking request
+//! headers or cookies list them in the `Vary` header, whether the
+//! conditions were met or not, so caches keep one response per condition
+//! outcome.
+
+use hyper::header::{COOKIE, HOST, HeaderName};
+use hyper::{Request, Response};
+use regex_lite::Regex;
+
+use crate::exts::http::append_vary_header;
+use crate::settings::file::{RouteCondition, RouteConditionKind};
+use crate::{Context, Result};
+
+/// The compiled `has` and `missing` conditions of a rule.
+#[derive(Clone, Default)]
+pub struct Conditions {
+    has: Vec<Condition>,
+    missing: Vec<Condition>,
+    /// Request headers the conditions depend on.
+    vary: Vec<HeaderName>,
+}
+
+#[derive(Clone)]
+struct Condition {
+    kind: RouteConditionKind,
+    key: String,
+    /// Anchored value pattern.
+    value: Option<Regex>,
+}
+
+impl Conditions {
+    /// Compiles the `has` and `missing` conditions of a rule.
+    pub fn new(has: Option<&[RouteCondition]>, missing: Option<&[RouteCondition]>) -> Result<Self> {
+        let compile = |conditions: Option<&[RouteCondition]>| {
+            conditions
+                .unwrap_or_default()
+                .iter()
+                .map(Condition::new)
+                .collect::<Result<Vec<_>>>()
+        };
+        let has = compile(has)?;
+        let missing = compile(missing)?;
+        let mut vary = Vec::new();
+        for cond in has.iter().chain(&missing) {
+            let name = match cond.kind {
+                RouteConditionKind::Header => HeaderName::from_bytes(cond.key.as_bytes())?,
+                RouteConditionKind::Cookie => COOKIE,
+                RouteConditionKind::Query | RouteConditionKind::Host => continue,
+            };
+            if !vary.contains(&name) {
+                vary.push(name);
+            }
+        }
+        Ok(Self { has, missing, vary })
+    }
+
+    /// Whether the rule has no conditions.
+    pub fn is_empty(&self) -> bool {
+        self.has.is_empty() && self.missing.is_empty()
+    }
+
+    /// Number of placeholders the `has` conditions capture.
+    pub fn captures_len(&self) -> usize {
+        self.has.iter().map(Condition::captures_len).sum()
+    }
+
+    /// Returns the values captured by the `has` conditions if the request
+    /// meets all the conditions.
+    pub(crate) fn captures<T>(&self, req: &Request<T>) -> Option<Vec<String>> {
+        if self.missing.iter().any(|cond| cond.captures(req).is_some()) {
+            return None;
+        }
+        let mut captures = Vec::new();
+        for cond in &self.has {
+            captures.extend(cond.captures(req)?);
+        }
+        Some(captures)
+    }
+
+    /// Records the request headers the conditions depend on, to be listed
+    /// in the `Vary` header of the response by [`post_process`].
+    pub(crate) fn record_vary<T>(&self, req: &mut Request<T>) {
+        if self.vary.is_empty() {
+            return;
+        }
+        let vary = req
+            .extensions_mut()
+            .get_or_insert_default::<ConditionsVary>();
+        for name in &self.vary {
+            if !vary.0.contains(name) {
+                vary.0.push(name.clone());
+            }
+        }
+    }
+}
+
+/// Request headers checked by the conditions of the rules whose source
+/// matched the request, stored in the request extensions.
+#[derive(Clone, Default)]
+struct ConditionsVary(Vec<HeaderName>);
+
+/// Appends the request headers recorded by [`Conditions::record_vary`] to
+/// the `Vary` header of the response.
+pub(crate) fn post_process<T, B>(req: &Request<T>, mut resp: Response<B>) -> Result<Response<B>> {
+    if let Some(vary) = req.extensions().get::<ConditionsVary>() {
+        for name in &vary.0 {
+            append_vary_header(&mut resp, name);
+        }
+    }
+    Ok(resp)
+}
+
+impl Condition {
+    fn new(cond: &RouteCondition) -> Result<Self> {
+        let key = match (cond.kind, &cond.key) {
+            (RouteConditionKind::Host, _) => {
+                if cond.value.is_none() {
+                    bail!("a `host` condition requires a value");
+                }
+                String::new()
+            }
+            (RouteConditionKind::Header, Some(key)) => HeaderName::from_bytes(key.as_bytes())
+                .with_context(|| format!("invalid header name in condition: {key}"))?
+                .as_str()
+                .to_owned(),
+            (_, Some(key)) => key.to_owned(),
+            (kind, None) => bail!("a `{kind:?}` condition requires a key"),
+        };
+        let value = match &cond.value {
+            Some(value) => Some(
+                Regex::new(&["^(?:", value, ")$"].concat())
+                    .with_context(|| format!("can not compile condition value regex: {value}"))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            kind: cond.kind,
+            key,
+            value,
+        })
+    }
+
+    fn captures_len(&self) -> usize {
+        match &self.value {
+            Some(value) => value.captures_len() - 1,
+            None => 1,
+        }
+    }
+
+    /// Returns the captured values of the first request value meeting the
+    /// condition.
+    fn captures<T>(&self, req: &Request<T>) -> Option<Vec<String>> {
+        self.values(req)
+            .into_iter()
+            .find_map(|value| match &self.value {
+                None => Some(vec![value]),
+                Some(regex) => regex.captures(&value).map(|caps| {
+                    caps.iter()
+                        .skip(1)
+                        .map(|cap| cap.map_or("", |cap| cap.as_str()).to_owned())
+                        .collect()
+                }),
+            })
+    }
+
+    /// Values of the request part the condition checks.
+    fn values<T>(&self, req: &Request<T>) -> Vec<String> {
+        let headers = req.headers();
+        match self.kind {
+            RouteConditionKind::Header => headers
+                .get_all(self.key.as_str())
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .map(str::to_owned)
+                .collect(),
+            RouteConditionKind::Query => {
+                form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                    .filter(|(name, _)| *name == self.key)
+                    .map(|(_, value)| value.into_owned())
+                    .collect()
+            }
+            RouteConditionKind::Cookie => headers
+                .get_all(COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(';'))
+                .filter_map(|pair| pair.trim().split_once('='))
+                .filter(|(name, _)| *name == self.key)
+                .map(|(_, value)| value.trim_matches('"').to_owned())
+                .collect(),
+            RouteConditionKind::Host => {
+                let host = match req.uri().host() {
+                    Some(host) => host,
+                    None => headers
+                        .get(HOST)
+                        .and_then(|value| value.to_str().ok())
+                        .map(|host| {
+                            host.rsplit_once(':')
+                                .filter(|(_, port)| port.parse::<u16>().is_ok())
+                                .map_or(host, |(host, _)| host)
+                        })
+                        .unwrap_or_default(),
+                };
+                vec![host.to_owned()]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cond(kind: RouteConditionKind, key: Option<&str>, value: Option<&str>) -> RouteCondition {
+        RouteCondition {
+            kind,
+            key: key.map(str::to_owned),
+            value: value.map(str::to_owned),
+        }
+    }
+
+    fn request(uri: &str, headers: &[(&str, &str)]) -> Request<()> {
+        let mut builder = Request::builder().uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn conditions_capture_values() {
+        let has = [
+            cond(
+                RouteConditionKind::Header,
+                Some("Accept-Language"),
+                Some("(de|fr).*"),
+            ),
+            cond(RouteConditionKind::Query, Some("v"), None),
+        ];
+        let missing = [cond(RouteConditionKind::Cookie, Some("lang"), None)];
+        let conditions = Conditions::new(Some(&has), Some(&missing)).unwrap();
+        assert_eq!(conditions.captures_len(), 2);
+
+        let req = request("/?v=2+1", &[("accept-language", "de-DE,en;q=0.5")]);
+        assert_eq!(
+            conditions.captures(&req).unwrap(),
+            vec!["de".to_owned(), "2 1".to_owned()]
+        );
+
+        let req = request("/?v=2", &[("accept-language", "en")]);
+        assert!(conditions.captures(&req).is_none());
+
+        let req = request("/", &[("accept-language", "fr")]);
+        assert!(conditions.captures(&req).is_none());
+
+        let req = request(
+            "/?v=2",
+            &[("accept-language", "fr"), ("cookie", "a=1; lang=en")],
+        );
+        assert!(conditions.captures(&req).is_none());
+    }
+
+    #[test]
+    fn header_and_cookie_conditions_vary_responses() {
+        let has = [
+            cond(RouteConditionKind::Header, Some("Accept-Language"), None),
+            cond(RouteConditionKind::Cookie, Some("theme"), None),
+            cond(RouteConditionKind::Query, Some("v"), None),
+        ];
+        let missing = [cond(RouteConditionKind::Cookie, Some("lang"), None)];
+        let conditions = Conditions::new(Some(&has), Some(&missing)).unwrap();
+        let mut req = request("/", &[]);
+        conditions.record_vary(&mut req);
+        conditions.record_vary(&mut req);
+        let resp = post_process(&req, Response::new(())).unwrap();
+        assert_eq!(resp.headers()["vary"], "accept-language, cookie");
+
+        let mut req = request("/", &[]);
+        Conditions::default().record_vary(&mut req);
+        let resp = post_process(&req, Response::new(())).unwrap();
+        assert!(resp.headers().get("vary").is_none());
+    }
+
+    #[test]
+    fn host_conditions_ignore_the_port() {
+        let has = [cond(
+            RouteConditionKind::Host,
+            None,
+            Some(r"(\w+)\.example\.com"),
+        )];
+        let conditions = Conditions::new(Some(&has), None).unwrap();
+        let req = request("/", &[("host", "docs.example.com:8080")]);
+        assert_eq!(conditions.captures(&req).unwrap(), vec!["docs".to_owned()]);
+        let req = request("/", &[("host", "example.com")]);
+        assert!(conditions.captures(&req).is_none());
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        let invalid = [
+            cond(RouteConditionKind::Host, None, None),
+            cond(RouteConditionKind::Cookie, None, Some("x")),
+            cond(RouteConditionKind::Header, Some("bad header"), None),
+            cond(RouteConditionKind::Query, Some("v"), Some("(")),
+        ];
+        for cond in invalid {
+            assert!(Conditions::new(Some(&[cond]), None).is_err());
+        }
+    }
+}
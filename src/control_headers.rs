@@ -6,13 +6,20 @@
 //! It provides an arbitrary `Cache-Control` headers functionality
 //! for incoming requests based on a set of file types.
 //!
+//! Rules configured via `[[advanced.cache-control]]` take precedence, the
+//! first matching rule sets the `Cache-Control` (and optional `Expires`)
+//! header. The built-in file type table stays the fallback.
+//!
 
+use headers::{Expires, HeaderMapExt};
 use hyper::{
     Request, Response,
     header::{CACHE_CONTROL, HeaderValue},
 };
+use std::time::SystemTime;
 
 use crate::body::Body;
+use crate::settings::CacheControl;
 use crate::{Error, handler::RequestHandlerOpts};
 
 // Pre-computed static Cache-Control header values
@@ -39,12 +46,63 @@ pub(crate) fn post_process<T>(
     req: &Request<T>,
     mut resp: Response<Body>,
 ) -> Result<Response<Body>, Error> {
-    if opts.cache_control_headers {
-        append_headers(req.uri().path(), &mut resp);
+    let uri_path = req.uri().path();
+    let rule = opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.cache_control.as_deref())
+        .and_then(|rules| rules.iter().find(|rule| is_match(rule, uri_path)));
+
+    if let Some(rule) = rule {
+        resp.headers_mut().insert(CACHE_CONTROL, rule.value.clone());
+        if let Some(expires) = rule.expires {
+            resp.headers_mut()
+                .typed_insert(Expires::from(SystemTime::now() + expires));
+        }
+    } else if opts.cache_control_headers {
+        append_headers(uri_path, &mut resp);
     }
     Ok(resp)
 }
 
+/// Checks whether a request path matches all the criteria of a rule.
+fn is_match(rule: &CacheControl, uri_path: &str) -> bool {
+    rule.source
+        .as_ref()
+        .is_none_or(|source| source.is_match(uri_path))
+        && rule
+            .source_regex
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(uri_path))
+        && (!rule.fingerprinted || is_fingerprinted(uri_path))
+}
+
+/// Checks whether the file name of a request path carries a content hash,
+/// e.g. `app.3f9a1c.js`, `main-BcD3f9_a.js` or `chunk.0a1b2c3d4e5f.css`.
+///
+/// A hash is a `.` or `-` separated segment of the file stem made of at
+/// least 6 hex digits, or of 8 base64url characters, containing both
+/// letters and digits.
+fn is_fingerprinted(uri_path: &str) -> bool {
+    let name = uri_path.rsplit('/').next().unwrap_or_default();
+    let Some((stem, _ext)) = name.rsplit_once('.') else {
+        return false;
+    };
+    stem.split(['.', '-']).skip(1).any(|segment| {
+        let has_digit = segment.bytes().any(|b| b.is_ascii_digit());
+        let has_alpha = segment.bytes().any(|b| b.is_ascii_alphabetic());
+        let is_hex = segment.len() >= 6
+            && segment
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        let is_base64url = segment.len() == 8
+            && segment
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_');
+        has_digit && (is_hex || (is_base64url && has_alpha))
+    })
+}
+
 /// It appends a `Cache-Control` header to a response if that one is part of a set of file types.
 pub fn append_headers(uri: &str, resp: &mut Response<Body>) {
     let header_value = get_cache_control_header(uri);
@@ -102,6 +160,62 @@ mod tests {
     use super::*;
     use hyper::{Response, StatusCode};
 
+    fn rule(
+        source: Option<&str>,
+        source_regex: Option<&str>,
+        fingerprinted: bool,
+        value: &'static str,
+    ) -> CacheControl {
+        CacheControl {
+            source: source.map(|glob| globset::Glob::new(glob).unwrap().compile_matcher()),
+            source_regex: source_regex.map(|regex| regex_lite::Regex::new(regex).unwrap()),
+            fingerprinted,
+            value: HeaderValue::from_static(value),
+            expires: None,
+        }
+    }
+
+    #[test]
+    fn rules_match_all_given_criteria() {
+        let glob = rule(Some("/api/**"), None, false, "no-store");
+        assert!(is_match(&glob, "/api/v1/users.json"));
+        assert!(!is_match(&glob, "/assets/app.js"));
+
+        let regex = rule(None, Some(r"^/docs/.+\.html$"), false, "no-cache");
+        assert!(is_match(&regex, "/docs/intro.html"));
+        assert!(!is_match(&regex, "/docs/intro.pdf"));
+
+        let hashed_js = rule(Some("**/*.js"), None, true, "max-age=31536000, immutable");
+        assert!(is_match(&hashed_js, "/assets/app.3f9a1c.js"));
+        assert!(!is_match(&hashed_js, "/assets/app.js"));
+        assert!(!is_match(&hashed_js, "/assets/app.3f9a1c.css"));
+    }
+
+    #[test]
+    fn fingerprinted_file_names() {
+        for path in [
+            "/app.3f9a1c.js",
+            "/assets/main-BcD3f9_a.js",
+            "/chunk.0a1b2c3d4e5f.css",
+            "/vendor.min.5e4d3c2b1a.js",
+            "/logo.a1b2c3d4.svg",
+        ] {
+            assert!(is_fingerprinted(path), "{path}");
+        }
+        for path in [
+            "/app.js",
+            "/jquery-3.7.1.min.js",
+            "/polyfills.es2015.js",
+            "/facade.decade.js",
+            "/index.html",
+            "/3f9a1c.js",
+            "/assets.3f9a1c/app.js",
+            "/README",
+        ] {
+            assert!(!is_fingerprinted(path), "{path}");
+        }
+    }
+
     #[test]
     fn test_arrays_are_sorted() {
         assert!(
@@ -28,6 +28,15 @@ pub(crate) fn post_process<T>(
             opts.redirect_trailing_slash,
         )
     }
+    if let Some(headers) = crate::site_files::headers(opts) {
+        append_headers(
+            req.uri().path(),
+            Some(&headers),
+            &mut resp,
+            file_path,
+            opts.redirect_trailing_slash,
+        )
+    }
     Ok(resp)
 }
 
@@ -0,0 +1,391 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Integrity digests of static files.
+//!
+//! When enabled, requests carrying `Want-Repr-Digest` get a `Repr-Digest`
+//! header (RFC 9530) holding the digest of the selected representation,
+//! i.e. the whole file or its precompressed variant, including for range
+//! requests. `Want-Content-Digest` gets a `Content-Digest` header on full
+//! (200) responses. The `sha-256` and `sha-512` algorithms are supported:
+//!
+//! ```text
+//! Want-Repr-Digest: sha-256=1, sha-512=3
+//! Repr-Digest: sha-512=:<base64>:
+//! ```
+//!
+//! Digests are computed once per file revision and kept in the shared
+//! content hash index, or along with the entry and its encoded variants
+//! for in-memory cache hits. Files and large in-memory representations
+//! are hashed on the blocking thread pool. Responses compressed on the fly
+//! carry no digests since their content is only known once streamed.
+//!
+//! The module also builds Subresource Integrity manifests for the `sri`
+//! subcommand.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+#[cfg(feature = "mem-cache")]
+use bytes::Bytes;
+use hyper::header::{CONTENT_ENCODING, HeaderMap, HeaderName, HeaderValue};
+use hyper::{Response, StatusCode};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "mem-cache")]
+use std::sync::{Arc, OnceLock};
+
+use crate::body::Body;
+use crate::fs::hashes::{self, HashKind};
+use crate::handler::RequestHandlerOpts;
+use crate::{Context, Error, Result};
+
+/// `Repr-Digest` header name.
+pub(crate) static REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+/// `Content-Digest` header name.
+pub(crate) static CONTENT_DIGEST: HeaderName = HeaderName::from_static("content-digest");
+static WANT_REPR_DIGEST: HeaderName = HeaderName::from_static("want-repr-digest");
+static WANT_CONTENT_DIGEST: HeaderName = HeaderName::from_static("want-content-digest");
+
+/// Digest algorithms supported by the `Repr-Digest` and `Content-Digest` fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha-256",
+            Self::Sha512 => "sha-512",
+        }
+    }
+
+    fn kind(self) -> HashKind {
+        match self {
+            Self::Sha256 => HashKind::Sha256,
+            Self::Sha512 => HashKind::Sha512,
+        }
+    }
+}
+
+/// Representation served by a response, attached as a response extension
+/// when it is not read from the requested file.
+#[derive(Debug, Clone)]
+pub(crate) enum Representation {
+    /// A precompressed variant of the file.
+    File(PathBuf),
+    /// Bytes held in memory, along with their digests once computed.
+    #[cfg(feature = "mem-cache")]
+    Bytes(Bytes, Arc<Digests>),
+}
+
+/// Lazily computed digests of an in-memory representation.
+#[cfg(feature = "mem-cache")]
+#[derive(Debug, Default)]
+pub(crate) struct Digests {
+    sha256: OnceLock<Arc<[u8]>>,
+    sha512: OnceLock<Arc<[u8]>>,
+}
+
+/// Size up to which in-memory representations are hashed on the async
+/// worker, larger ones are hashed on the blocking thread pool.
+#[cfg(feature = "mem-cache")]
+const MAX_INLINE_HASH_LEN: usize = 64 * 1024;
+
+#[cfg(feature = "mem-cache")]
+impl Digests {
+    async fn get(&self, algorithm: Algorithm, data: &Bytes) -> Result<Arc<[u8]>> {
+        let slot = match algorithm {
+            Algorithm::Sha256 => &self.sha256,
+            Algorithm::Sha512 => &self.sha512,
+        };
+        if let Some(hash) = slot.get() {
+            return Ok(hash.clone());
+        }
+        let kind = algorithm.kind();
+        let hash = if data.len() <= MAX_INLINE_HASH_LEN {
+            hashes::hash_slice(kind, data)
+        } else {
+            let data = data.clone();
+            tokio::task::spawn_blocking(move || hashes::hash_slice(kind, &data)).await?
+        };
+        Ok(slot.get_or_init(|| hash).clone())
+    }
+}
+
+/// Attaches the served representation to a response if the client asks for
+/// one of its digests.
+pub(crate) fn attach(
+    resp: &mut Response<Body>,
+    headers: &HeaderMap,
+    representation: impl FnOnce() -> Representation,
+) {
+    if headers.contains_key(&WANT_REPR_DIGEST) || headers.contains_key(&WANT_CONTENT_DIGEST) {
+        resp.extensions_mut().insert(representation());
+    }
+}
+
+/// Initializes the integrity digest headers.
+pub(crate) fn init(enabled: bool, handler_opts: &mut RequestHandlerOpts) {
+    handler_opts.repr_digest = enabled;
+    tracing::info!(enabled, "repr-digest headers");
+}
+
+/// Appends the digest headers requested by the client if possible.
+pub(crate) async fn post_process(
+    opts: &RequestHandlerOpts,
+    headers: &HeaderMap,
+    mut resp: Response<Body>,
+    file_path: Option<&PathBuf>,
+) -> Result<Response<Body>, Error> {
+    if !opts.repr_digest {
+        return Ok(resp);
+    }
+    let is_full = match resp.status() {
+        StatusCode::OK => true,
+        StatusCode::PARTIAL_CONTENT => false,
+        _ => return Ok(resp),
+    };
+
+    let want_repr = headers.get(&WANT_REPR_DIGEST).and_then(preferred);
+    let want_content = headers
+        .get(&WANT_CONTENT_DIGEST)
+        .filter(|_| is_full)
+        .and_then(preferred);
+    if want_repr.is_none() && want_content.is_none() {
+        return Ok(resp);
+    }
+    let Some(representation) = representation(&resp, file_path) else {
+        return Ok(resp);
+    };
+
+    for (name, algorithm) in [(&REPR_DIGEST, want_repr), (&CONTENT_DIGEST, want_content)] {
+        let Some(algorithm) = algorithm else { continue };
+        let value = match &representation {
+            Representation::File(path) => digest_header(path, algorithm).await.inspect_err(|err| {
+                tracing::warn!(
+                    "unable to compute the digest of `{}`: {err:#}",
+                    path.display()
+                )
+            }),
+            #[cfg(feature = "mem-cache")]
+            Representation::Bytes(data, digests) => digests
+                .get(algorithm, data)
+                .await
+                .and_then(|hash| field_value(algorithm, &hash)),
+        };
+        if let Ok(value) = value {
+            resp.headers_mut().insert(name.clone(), value);
+        }
+    }
+    Ok(resp)
+}
+
+/// Selects the supported algorithm with the highest preference of a
+/// `Want-*-Digest` field. Ties favor `sha-512`, a zero preference means
+/// the algorithm is not acceptable.
+fn preferred(value: &HeaderValue) -> Option<Algorithm> {
+    value
+        .to_str()
+        .ok()?
+        .split(',')
+        .filter_map(|member| {
+            let (key, weight) = member.split_once('=').unwrap_or((member, "1"));
+            let algorithm = match key.trim() {
+                "sha-256" => Algorithm::Sha256,
+                "sha-512" => Algorithm::Sha512,
+                _ => return None,
+            };
+            let weight = weight.trim().parse::<u8>().ok().filter(|&w| w > 0)?;
+            Some((weight, algorithm == Algorithm::Sha512, algorithm))
+        })
+        .max_by_key(|&(weight, is_sha512, _)| (weight, is_sha512))
+        .map(|(_, _, algorithm)| algorithm)
+}
+
+/// Representation served by the response: the one it carries, or else the
+/// requested file unless the response is encoded by other means (e.g. on
+/// the fly).
+fn representation(resp: &Response<Body>, file_path: Option<&PathBuf>) -> Option<Representation> {
+    if let Some(representation) = resp.extensions().get::<Representation>() {
+        return Some(representation.clone());
+    }
+    if resp.headers().contains_key(CONTENT_ENCODING) {
+        return None;
+    }
+    file_path.map(|path| Representation::File(path.to_owned()))
+}
+
+/// Builds the digest field value of a file, e.g. `sha-256=:<base64>:`,
+/// hashing it off the async runtime.
+async fn digest_header(path: &Path, algorithm: Algorithm) -> Result<HeaderValue> {
+    let (path, kind) = (path.to_owned(), algorithm.kind());
+    let hash = tokio::task::spawn_blocking(move || hash_file(&path, kind)).await??;
+    field_value(algorithm, &hash)
+}
+
+fn field_value(algorithm: Algorithm, hash: &[u8]) -> Result<HeaderValue> {
+    let value = [algorithm.name(), "=:", &STANDARD.encode(hash), ":"].concat();
+    Ok(HeaderValue::from_str(&value)?)
+}
+
+fn hash_file(path: &Path, kind: HashKind) -> Result<std::sync::Arc<[u8]>> {
+    let mut file = File::open(path)?;
+    let meta = file.metadata()?;
+    if !meta.is_file() {
+        bail!("not a regular file");
+    }
+    Ok(hashes::hash_file(kind, &mut file, path, &meta)?)
+}
+
+/// Builds a Subresource Integrity manifest of the files of a directory,
+/// mapping each request path to its `sha384-<base64>` integrity value.
+///
+/// Symbolic links are not followed, hidden files are skipped unless
+/// `include_hidden` is set.
+pub fn sri_manifest(root: &Path, include_hidden: bool) -> Result<BTreeMap<String, String>> {
+    let mut manifest = BTreeMap::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir)
+            .with_context(|| format!("unable to read directory `{}`", dir.display()))?;
+        for entry in entries.flatten() {
+            let is_hidden = entry
+                .file_name()
+                .to_str()
+                .is_none_or(|n| n.starts_with('.'));
+            if is_hidden && !include_hidden {
+                continue;
+            }
+            let path = entry.path();
+            let Ok(meta) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            if meta.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if !meta.is_file() {
+                continue;
+            }
+            let Some(uri_path) = uri_path_of(&path, root) else {
+                tracing::warn!("skipping non UTF-8 path `{}`", path.display());
+                continue;
+            };
+            let hash = hash_file(&path, HashKind::Sha384)
+                .with_context(|| format!("unable to hash file `{}`", path.display()))?;
+            manifest.insert(uri_path, ["sha384-", &STANDARD.encode(hash)].concat());
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Maps a file path to its request path relative to `root`.
+pub(crate) fn uri_path_of(path: &Path, root: &Path) -> Option<String> {
+    let mut uri_path = String::new();
+    for component in path.strip_prefix(root).ok()?.components() {
+        uri_path.push('/');
+        uri_path.push_str(component.as_os_str().to_str()?);
+    }
+    Some(uri_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferred_algorithm_follows_weights() {
+        let pick = |value: &'static str| preferred(&HeaderValue::from_static(value));
+        assert_eq!(pick("sha-256=1, sha-512=3"), Some(Algorithm::Sha512));
+        assert_eq!(pick("sha-256=5, sha-512=3"), Some(Algorithm::Sha256));
+        assert_eq!(pick("sha-256=2, sha-512=2"), Some(Algorithm::Sha512));
+        assert_eq!(pick("sha-512=0, sha-256=1"), Some(Algorithm::Sha256));
+        assert_eq!(pick("sha-256"), Some(Algorithm::Sha256));
+        assert_eq!(pick("md5=10, sha=3"), None);
+        assert_eq!(pick("sha-512=0"), None);
+    }
+
+    #[tokio::test]
+    async fn digest_header_has_structured_field_syntax() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hello.txt");
+        fs::write(&path, "hello world").unwrap();
+        assert_eq!(
+            digest_header(&path, Algorithm::Sha256).await.unwrap(),
+            "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:"
+        );
+    }
+
+    #[test]
+    fn encoded_responses_need_their_representation() {
+        let path = PathBuf::from("/srv/public/app.js");
+        let mut resp = Response::new(crate::body::empty());
+        assert!(matches!(
+            representation(&resp, Some(&path)),
+            Some(Representation::File(file)) if file == path
+        ));
+
+        // Compressed on the fly, e.g. `deflate`
+        resp.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("deflate"));
+        assert!(representation(&resp, Some(&path)).is_none());
+
+        let precompressed = PathBuf::from("/srv/public/app.js.br");
+        resp.extensions_mut()
+            .insert(Representation::File(precompressed.clone()));
+        assert!(matches!(
+            representation(&resp, Some(&path)),
+            Some(Representation::File(file)) if file == precompressed
+        ));
+    }
+
+    #[cfg(feature = "mem-cache")]
+    #[tokio::test]
+    async fn in_memory_digests_are_computed_once() {
+        let digests = Digests::default();
+        let data = Bytes::from_static(b"hello world");
+        let hash = digests.get(Algorithm::Sha256, &data).await.unwrap();
+        assert_eq!(
+            field_value(Algorithm::Sha256, &hash).unwrap(),
+            "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:"
+        );
+        let other = Bytes::from_static(b"other bytes");
+        assert!(Arc::ptr_eq(
+            &hash,
+            &digests.get(Algorithm::Sha256, &other).await.unwrap()
+        ));
+        let hash = digests.get(Algorithm::Sha512, &data).await.unwrap();
+        assert_eq!(hash.len(), 64);
+
+        // Large representations are hashed on the blocking thread pool
+        let data = Bytes::from(vec![b'a'; MAX_INLINE_HASH_LEN + 1]);
+        let hash = Digests::default()
+            .get(Algorithm::Sha256, &data)
+            .await
+            .unwrap();
+        assert_eq!(hash, hashes::hash_slice(HashKind::Sha256, &data));
+    }
+
+    #[test]
+    fn sri_manifest_lists_files_by_request_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("js")).unwrap();
+        fs::write(dir.path().join("js/app.js"), "alert(1)").unwrap();
+        fs::write(dir.path().join(".hidden"), "secret").unwrap();
+
+        let manifest = sri_manifest(dir.path(), false).unwrap();
+        assert_eq!(manifest.len(), 1);
+        let integrity = &manifest["/js/app.js"];
+        assert!(integrity.starts_with("sha384-"), "{integrity}");
+        assert_eq!(integrity.len(), "sha384-".len() + 64);
+
+        assert_eq!(sri_manifest(dir.path(), true).unwrap().len(), 2);
+    }
+}
@@ -42,6 +42,7 @@ pub fn auto_index(opts: DirListOpts<'_>) -> Result<Response<Body>, StatusCode> {
                 accept: opts.accept,
                 include_hidden: opts.include_hidden,
                 follow_symlinks: opts.follow_symlinks,
+                hide_site_files: opts.site_files && parent == opts.root_path,
                 #[cfg(feature = "directory-listing-download")]
                 download: opts.dir_listing_download,
             };
@@ -18,6 +18,7 @@ use crate::directory_listing::autoindex::{html_auto_index, json_auto_index};
 use crate::directory_listing::file::{FileEntry, FileType};
 use crate::exts::headers::Accept;
 use crate::exts::http::append_vary_accept;
+use crate::site_files::is_site_file_name;
 use crate::{Context, Result};
 
 #[cfg(feature = "directory-listing-download")]
@@ -71,6 +72,8 @@ pub struct DirListOpts<'a> {
     pub include_hidden: bool,
     /// Prevent following symlinks for files and directories.
     pub follow_symlinks: bool,
+    /// Hide the `_redirects` and `_headers` site files of the root directory.
+    pub site_files: bool,
 }
 
 /// Defines read directory entries.
@@ -85,6 +88,7 @@ pub(crate) struct DirEntryOpts<'a> {
     pub(crate) accept: Option<&'a Accept>,
     pub(crate) include_hidden: bool,
     pub(crate) follow_symlinks: bool,
+    pub(crate) hide_site_files: bool,
     #[cfg(feature = "directory-listing-download")]
     pub(crate) download: &'a [DirDownloadFmt],
 }
@@ -122,6 +126,10 @@ pub(crate) fn read_dir_entries(mut opt: DirEntryOpts<'_>) -> Result<Response<Bod
             continue;
         }
 
+        if opt.hide_site_files && is_site_file_name(&name) {
+            continue;
+        }
+
         let (r#type, size) = if meta.is_dir() {
             dirs_count += 1;
             (FileType::Directory, None)
@@ -25,6 +25,7 @@ use crate::Result;
 use crate::body::Body;
 use crate::exts::http::MethodExt;
 use crate::handler::RequestHandlerOpts;
+use crate::site_files::is_site_file_name;
 
 /// query parameter key to download directory as tar.gz
 pub const DOWNLOAD_PARAM_KEY: &str = "download";
@@ -51,6 +52,8 @@ pub struct DirDownloadOpts<'a> {
     pub follow_symlinks: bool,
     /// Ignore hidden files (dotfiles).
     pub include_hidden: bool,
+    /// Leave out the `_redirects` and `_headers` site files of the directory.
+    pub site_files: bool,
 }
 
 /// Initializes directory listing download
@@ -103,6 +106,7 @@ async fn archive(
     cb: ChannelBuffer,
     follow_symlinks: bool,
     ignore_hidden: bool,
+    ignore_site_files: bool,
 ) -> Result {
     let gz = GzipEncoder::with_quality(cb, async_compression::Level::Default);
     let mut a = Builder::new(gz.compat_write());
@@ -127,6 +131,9 @@ async fn archive(
                 if ignore_hidden && name.as_encoded_bytes().first().is_some_and(|c| *c == b'.') {
                     continue;
                 }
+                if ignore_site_files && src == src_path && is_site_file_name(&name) {
+                    continue;
+                }
 
                 let file_type = entry.file_type().await?;
                 stack.push((entry.path(), file_type.is_dir(), file_type.is_symlink()));
@@ -202,6 +209,7 @@ where
         ChannelBuffer { writer: write_half },
         opts.follow_symlinks,
         !opts.include_hidden,
+        opts.site_files,
     ));
     *resp.body_mut() = body;
 
@@ -3,11 +3,12 @@
 // See https://static-web-server.net/ for more information
 // Copyright (C) 2019-present Jose Quintana <joseluisq.net>
 
-//! Weak `ETag` support for static files.
+//! `ETag` support for static files.
 //!
-//! The validator is derived from the file metadata only, `mtime` (in
-//! nanoseconds since the UNIX epoch) and `len` (in bytes), and is always
-//! emitted with the weak prefix (`W/`) using the nginx-style format:
+//! By default the validator is derived from the file metadata only,
+//! `mtime` (in nanoseconds since the UNIX epoch) and `len` (in bytes), and
+//! is always emitted with the weak prefix (`W/`) using the nginx-style
+//! format:
 //!
 //! ```text
 //! W/"<mtime_hex>-<len_hex>"
@@ -19,36 +20,171 @@
 //! [`HeaderValue`] is built once at insertion time and refcount-cloned
 //! thereafter).
 //!
+//! ## Strong validators
+//!
+//! With `--etag-strong` the validator is a strong tag holding the first
+//! 128 bits of the BLAKE3 hash of the file content:
+//!
+//! ```text
+//! "<blake3_hex>"
+//! ```
+//!
+//! Hashes are kept in a process-wide index keyed by the file identity
+//! (device and inode on Unix), modification time and size, so each file
+//! is read for hashing at most once until it changes. Precompressed
+//! variants (`.br` / `.gz` / `.zst`) are distinct files and thus get their
+//! own tags. Representations compressed on the fly carry the coding as a
+//! suffix (`"<blake3_hex>-br"`) and revalidate against it.
+//!
 //! ## Semantics
 //!
 //! * Weak comparison is correct for content negotiation (precompressed
 //!   `.br` / `.gz` / `.zst` variants are semantically equivalent to the
 //!   original). `Vary: Accept-Encoding` is emitted by the compression
-//!   pipeline so intermediary caches key variants separately.
+//!   pipeline so intermediary caches key variants separately. Encoded
+//!   variants served from the in-memory cache are the exception: they
+//!   carry the coding as a suffix (`W/"<mtime_hex>-<len_hex>-br"`).
 //! * `If-None-Match` short-circuits to `304 Not Modified` when the client
 //!   already holds a current representation.
 //! * `If-Match` returns `412 Precondition Failed` when the client's
 //!   expected validator does not match, weak validators never satisfy a
 //!   strong `If-Match` per RFC 7232 §2.3.2, so any non-`*` `If-Match`
-//!   against an SWS-issued ETag fails (the intended outcome).
+//!   against a weak SWS-issued ETag fails (the intended outcome). Strong
+//!   validators satisfy it when the content is unchanged.
 //! * `If-Range` falls back to a full 200 response when the validator does
-//!   not strongly match (again, weak validators never strongly match).
+//!   not strongly match (again, weak validators never strongly match), so
+//!   resumable downloads require strong validators.
 
-use std::fs::Metadata;
+use std::fs::{File, Metadata};
+use std::io;
+use std::path::Path;
 use std::time::UNIX_EPOCH;
 
 use headers::HeaderValue;
 
+use crate::fs::hashes::{self, HashKind};
 use crate::handler::RequestHandlerOpts;
 
 /// Maximum width of the generated header value:
 /// `W/"` (3) + 32 hex digits (mtime) + `-` (1) + 16 hex digits (len) + `"` (1).
 const ETAG_MAX_LEN: usize = 3 + 32 + 1 + 16 + 1;
 
+/// Number of hex digits of the BLAKE3 hash kept in strong validators.
+const STRONG_HEX_LEN: usize = 32;
+
 /// Initialises the ETag feature on the given handler options.
-pub(crate) fn init(enabled: bool, handler_opts: &mut RequestHandlerOpts) {
+pub(crate) fn init(enabled: bool, strong: bool, handler_opts: &mut RequestHandlerOpts) {
     handler_opts.etag = enabled;
-    tracing::info!(enabled, "etag headers");
+    handler_opts.etag_strong = enabled && strong;
+    if strong && !enabled {
+        tracing::warn!("strong etag headers require etag headers to be enabled, ignoring");
+    }
+    tracing::info!(enabled, strong = handler_opts.etag_strong, "etag headers");
+}
+
+/// Builds the `ETag` value of an opened file, a strong content hash when
+/// `strong` is set or a weak one derived from `meta` otherwise.
+///
+/// Files missing from the content hash index are hashed on the blocking
+/// thread pool. The file is handed back along with the tag, positioned at
+/// an unspecified offset. `path` and `meta` must belong to it.
+pub(crate) async fn build_for_file(
+    strong: bool,
+    mut file: File,
+    path: &Path,
+    meta: &Metadata,
+) -> io::Result<(File, Option<(headers::ETag, HeaderValue)>)> {
+    if !strong {
+        return Ok((file, build_from_meta(meta)));
+    }
+    if let Some(hash) = hashes::indexed(HashKind::Blake3, path, meta) {
+        return Ok((file, Some(typed(build_strong(&hash)))));
+    }
+
+    let (path, meta) = (path.to_owned(), meta.clone());
+    tokio::task::spawn_blocking(move || {
+        let etag = build_strong_for_file(&mut file, &path, &meta);
+        (file, etag)
+    })
+    .await
+    .map_err(io::Error::other)
+}
+
+/// Hashes an opened file for its strong `ETag`, falling back to a weak one
+/// on failure.
+fn build_strong_for_file(
+    file: &mut File,
+    path: &Path,
+    meta: &Metadata,
+) -> Option<(headers::ETag, HeaderValue)> {
+    match hashes::hash_file(HashKind::Blake3, file, path, meta) {
+        Ok(hash) => Some(typed(build_strong(&hash))),
+        Err(err) => {
+            tracing::warn!(
+                "unable to hash file `{}` for a strong etag, using a weak one: {err}",
+                path.display()
+            );
+            build_from_meta(meta)
+        }
+    }
+}
+
+/// Builds a strong `ETag` value for the already read `data` of the file at
+/// `path`, recording it in the content hash index.
+#[cfg(feature = "mem-cache")]
+#[must_use]
+pub(crate) fn build_strong_from_bytes(path: &Path, meta: &Metadata, data: &[u8]) -> HeaderValue {
+    build_strong(&hashes::hash_bytes(HashKind::Blake3, path, meta, data))
+}
+
+/// Derives the `ETag` of an encoded representation by appending the coding
+/// to the opaque tag, e.g. `W/"1b21dd2-2000"` becomes `W/"1b21dd2-2000-br"`.
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+))]
+pub(crate) fn encoded(etag: &HeaderValue, coding: &str) -> Option<HeaderValue> {
+    let tag = etag.to_str().ok()?.strip_suffix('"')?;
+    HeaderValue::from_str(&[tag, "-", coding, "\""].concat()).ok()
+}
+
+/// Whether the `ETag` value is a strong validator.
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+))]
+pub(crate) fn is_strong(etag: &HeaderValue) -> bool {
+    !etag.as_bytes().starts_with(b"W/")
+}
+
+/// Formats a strong `ETag` value from a content hash.
+fn build_strong(hash: &[u8]) -> HeaderValue {
+    use std::fmt::Write as _;
+
+    let mut s = String::with_capacity(STRONG_HEX_LEN + 2);
+    s.push('"');
+    for byte in &hash[..STRONG_HEX_LEN / 2] {
+        // Infallible: writing to a `String` cannot fail.
+        let _ = write!(s, "{byte:02x}");
+    }
+    s.push('"');
+    HeaderValue::from_str(&s).expect("etag value is valid header bytes")
+}
+
+/// Pairs a header value with its typed `ETag`.
+fn typed(value: HeaderValue) -> (headers::ETag, HeaderValue) {
+    let etag = value
+        .to_str()
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .expect("etag value conforms to RFC 7232");
+    (etag, value)
 }
 
 /// Builds a weak `ETag` value from file metadata.
@@ -158,6 +294,60 @@ mod tests {
         assert_eq!(tag, reparsed);
     }
 
+    #[cfg(feature = "compression")]
+    #[test]
+    fn encoded_etag_appends_the_coding() {
+        let etag = HeaderValue::from_static("W/\"1b21dd2-2000\"");
+        assert_eq!(encoded(&etag, "br").unwrap(), "W/\"1b21dd2-2000-br\"");
+        assert_eq!(encoded(&etag, "zstd").unwrap(), "W/\"1b21dd2-2000-zstd\"");
+        assert!(!is_strong(&etag));
+
+        let etag = HeaderValue::from_static("\"af1349b9f5f9a1a6a0404dea36dcc949\"");
+        assert!(is_strong(&etag));
+        assert_eq!(
+            encoded(&etag, "gzip").unwrap(),
+            "\"af1349b9f5f9a1a6a0404dea36dcc949-gzip\""
+        );
+    }
+
+    #[tokio::test]
+    async fn strong_etag_hashes_the_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hello.txt");
+        fs::write(&path, b"hello world").unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        let file = fs::File::open(&path).unwrap();
+
+        let (file, etag) = build_for_file(true, file, &path, &meta).await.unwrap();
+        let (tag, hv) = etag.unwrap();
+        // First 128 bits of `BLAKE3("hello world")`
+        assert_eq!(hv, "\"d74981efa70a0c880b8d8c1985d075db\"");
+        assert_eq!(tag, hv.to_str().unwrap().parse::<headers::ETag>().unwrap());
+
+        // Indexed from now on
+        let (file, etag) = build_for_file(true, file, &path, &meta).await.unwrap();
+        assert_eq!(etag.unwrap().1, hv);
+
+        let (_, etag) = build_for_file(false, file, &path, &meta).await.unwrap();
+        assert!(etag.unwrap().1.to_str().unwrap().starts_with("W/\""));
+    }
+
+    #[tokio::test]
+    async fn strong_etag_depends_on_content_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let etag_of = async |name: &str, content: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            let meta = fs::metadata(&path).unwrap();
+            let file = fs::File::open(&path).unwrap();
+            let (_, etag) = build_for_file(true, file, &path, &meta).await.unwrap();
+            etag.unwrap().1
+        };
+        let first = etag_of("index.html", b"<h1>one</h1>").await;
+        assert_eq!(first, etag_of("copy.html", b"<h1>one</h1>").await);
+        assert_ne!(first, etag_of("index.html", b"<h1>two!</h1>").await);
+    }
+
     #[test]
     fn build_from_meta_returns_none_for_unix_epoch_mtime() {
         // Cannot easily set mtime to UNIX_EPOCH cross-platform without
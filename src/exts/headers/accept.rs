@@ -58,6 +58,14 @@ impl Accept {
             .any(|value| value.eq_ignore_ascii_case(media_type))
     }
 
+    /// Check if a specific media type is listed with a non-zero quality.
+    /// Wildcard media ranges are not taken into account.
+    pub(crate) fn accepts_listed(&self, media_type: &str) -> bool {
+        self.0
+            .iter_accepted()
+            .any(|value| value.eq_ignore_ascii_case(media_type))
+    }
+
     /// Returns true if text/markdown is explicitly accepted
     pub(crate) fn accepts_markdown(&self) -> bool {
         self.accepts("text/markdown")
@@ -114,6 +122,15 @@ mod tests {
         assert!(!accept.accepts_markdown());
     }
 
+    #[test]
+    fn accepts_listed_skips_zero_qualities_and_wildcards() {
+        let val = HeaderValue::from_static("image/avif;q=0, image/webp, image/*");
+        let accept = Accept(val.into());
+        assert!(!accept.accepts_listed("image/avif"));
+        assert!(accept.accepts_listed("image/webp"));
+        assert!(!accept.accepts_listed("image/png"));
+    }
+
     #[test]
     fn preferred_media_type_json_first() {
         let val = HeaderValue::from_static("application/json, text/html");
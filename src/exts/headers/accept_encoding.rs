@@ -52,6 +52,20 @@ impl AcceptEncoding {
     pub(crate) fn sorted_encodings(&self) -> impl Iterator<Item = ContentCoding> + '_ {
         self.0.iter().map(ContentCoding::from)
     }
+
+    /// Returns a quality sorted iterator of the `ContentCoding` with ties
+    /// broken by the given priority, the higher the earlier.
+    pub(crate) fn sorted_encodings_by<F>(
+        &self,
+        priority: F,
+    ) -> impl Iterator<Item = ContentCoding> + use<'_, F>
+    where
+        F: Fn(ContentCoding) -> u8,
+    {
+        self.0
+            .iter_by(move |data| priority(ContentCoding::from(data)))
+            .map(ContentCoding::from)
+    }
 }
 
 #[cfg(test)]
@@ -70,4 +84,21 @@ mod tests {
         assert_eq!(encodings.next(), Some(ContentCoding::ZSTD));
         assert_eq!(encodings.next(), None);
     }
+
+    #[test]
+    fn sorted_by_server_priority() {
+        let val = HeaderValue::from_static("gzip, br, zstd;q=0.5");
+        let accept_enc = AcceptEncoding(val.into());
+
+        let priority = |coding| match coding {
+            ContentCoding::GZIP => 2,
+            ContentCoding::BROTLI => 1,
+            _ => 3,
+        };
+        let mut encodings = accept_enc.sorted_encodings_by(priority);
+        assert_eq!(encodings.next(), Some(ContentCoding::GZIP));
+        assert_eq!(encodings.next(), Some(ContentCoding::BROTLI));
+        assert_eq!(encodings.next(), Some(ContentCoding::ZSTD));
+        assert_eq!(encodings.next(), None);
+    }
 }
@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+use headers::{Error, Header};
+use hyper::header::{ACCEPT_LANGUAGE, HeaderName, HeaderValue};
+
+use super::QualityValue;
+
+/// `Accept-Language` header, defined in
+/// [RFC7231](https://tools.ietf.org/html/rfc7231#section-5.3.5)
+///
+/// The `Accept-Language` header field can be used by user agents to
+/// indicate the set of natural languages that are preferred in the
+/// response.
+///
+/// # ABNF
+///
+/// ```text
+/// Accept-Language = 1#( language-range [ weight ] )
+/// language-range  = <language-range, see [RFC4647], Section 2.1>
+/// ```
+///
+/// # Example Values
+///
+/// * `da, en-gb;q=0.8, en;q=0.7`
+/// * `en-us;q=1.0, en;q=0.5, fr`
+///
+#[derive(Clone, Debug)]
+pub(crate) struct AcceptLanguage(QualityValue);
+
+impl Header for AcceptLanguage {
+    fn name() -> &'static HeaderName {
+        &ACCEPT_LANGUAGE
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        QualityValue::try_from_values(values).map(Self)
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, values: &mut E) {
+        values.extend(std::iter::once((&self.0).into()))
+    }
+}
+
+impl AcceptLanguage {
+    /// Returns a quality sorted iterator of the accepted language ranges,
+    /// leaving out the ones with a zero quality.
+    pub(crate) fn sorted_languages(&self) -> impl Iterator<Item = &str> + '_ {
+        self.0.iter_accepted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_languages() {
+        let val = HeaderValue::from_static("en;q=0.7, da, en-GB;q=0.8, *;q=0.1");
+        let accept_lang = AcceptLanguage(val.into());
+
+        let mut languages = accept_lang.sorted_languages();
+        assert_eq!(languages.next(), Some("da"));
+        assert_eq!(languages.next(), Some("en-GB"));
+        assert_eq!(languages.next(), Some("en"));
+        assert_eq!(languages.next(), Some("*"));
+        assert_eq!(languages.next(), None);
+    }
+}
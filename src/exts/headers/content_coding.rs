@@ -98,6 +98,8 @@ impl ContentCoding {
 define_content_coding! {
     BROTLI; "br",
     COMPRESS; "compress",
+    DCB; "dcb",
+    DCZ; "dcz",
     DEFLATE; "deflate",
     GZIP; "gzip",
     IDENTITY; "identity",
@@ -124,6 +126,8 @@ mod tests {
         assert_eq!(ContentCoding::from("br"), ContentCoding::BROTLI);
         assert_eq!(ContentCoding::from("GZIP"), ContentCoding::GZIP);
         assert_eq!(ContentCoding::from("zstd"), ContentCoding::ZSTD);
+        assert_eq!(ContentCoding::from("dcb"), ContentCoding::DCB);
+        assert_eq!(ContentCoding::from("dcz"), ContentCoding::DCZ);
         assert_eq!(ContentCoding::from("blah blah"), ContentCoding::IDENTITY);
     }
 
@@ -60,6 +60,15 @@ impl<'a> TryFrom<&'a str> for QualityMeta<'a> {
 
 impl QualityValue {
     pub(crate) fn iter(&self) -> impl Iterator<Item = &str> + use<'_> {
+        self.iter_by(|data| ContentCoding::from(data).priority())
+    }
+
+    /// Same as [`QualityValue::iter`] with ties broken by the given priority,
+    /// the higher the earlier.
+    pub(crate) fn iter_by<F>(&self, priority: F) -> impl Iterator<Item = &str> + use<'_, F>
+    where
+        F: Fn(&str) -> u8,
+    {
         let mut items: Vec<_> = self
             .value
             .to_str()
@@ -71,9 +80,7 @@ impl QualityValue {
         items.sort_unstable_by(|a, b| {
             let quality_cmp = b.quality.cmp(&a.quality);
             if quality_cmp == Ordering::Equal {
-                let priority_a = ContentCoding::from(a.data).priority();
-                let priority_b = ContentCoding::from(b.data).priority();
-                priority_b.cmp(&priority_a)
+                priority(b.data).cmp(&priority(a.data))
             } else {
                 quality_cmp
             }
@@ -81,6 +88,22 @@ impl QualityValue {
         items.into_iter().map(|pair| pair.data)
     }
 
+    /// Returns the items with a non-zero quality, sorted by quality. Items
+    /// of the same quality keep their listed order.
+    pub(crate) fn iter_accepted(&self) -> impl Iterator<Item = &str> + use<'_> {
+        let mut items: Vec<_> = self
+            .value
+            .to_str()
+            .ok()
+            .into_iter()
+            .flat_map(|value_str| value_str.split(','))
+            .filter_map(|v| QualityMeta::try_from(v).ok())
+            .filter(|item| item.quality > 0 && !item.data.is_empty())
+            .collect();
+        items.sort_by_key(|item| std::cmp::Reverse(item.quality));
+        items.into_iter().map(|pair| pair.data)
+    }
+
     pub(crate) fn try_from_values<'i, I>(values: &mut I) -> Result<Self, Error>
     where
         I: Iterator<Item = &'i HeaderValue>,
@@ -272,6 +295,18 @@ mod tests {
         assert_eq!(values.next(), None);
     }
 
+    #[test]
+    fn accepted_keep_listed_order_and_skip_zero_qualities() {
+        let val = HeaderValue::from_static("fr;q=0.5, de, en-US;q=0, en, ;q=0.9");
+        let qual = QualityValue::from(val);
+
+        let mut values = qual.iter_accepted();
+        assert_eq!(values.next(), Some("de"));
+        assert_eq!(values.next(), Some("en"));
+        assert_eq!(values.next(), Some("fr"));
+        assert_eq!(values.next(), None);
+    }
+
     // Property-based regression tests for the RFC 7231 q-value parser.
     //
     // Inputs to `QualityValue::iter` come straight from a client
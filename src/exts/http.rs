@@ -5,7 +5,10 @@
 
 //! HTTP-related extension traits.
 
-use hyper::{Method, Response, header::HeaderValue};
+use hyper::{
+    Method, Response,
+    header::{HeaderName, HeaderValue},
+};
 
 /// A fixed list of HTTP methods supported by SWS.
 pub const HTTP_SUPPORTED_METHODS: &[Method; 3] = &[Method::OPTIONS, Method::HEAD, Method::GET];
@@ -65,12 +68,44 @@ pub(crate) fn append_vary_accept_encoding<B>(resp: &mut Response<B>) {
 
 /// Append `accept` to the response's `Vary` header, creating it if absent.
 /// Skips the update if `accept` is already listed.
-#[cfg(feature = "directory-listing")]
 pub(crate) fn append_vary_accept<B>(resp: &mut Response<B>) {
     static VARY_ACCEPT: HeaderValue = HeaderValue::from_static("accept");
     append_vary(resp, "accept", &VARY_ACCEPT);
 }
 
+/// Append `accept-language` to the response's `Vary` header, creating it if absent.
+/// Skips the update if `accept-language` is already listed.
+pub(crate) fn append_vary_accept_language<B>(resp: &mut Response<B>) {
+    static VARY_ACCEPT_LANGUAGE: HeaderValue = HeaderValue::from_static("accept-language");
+    append_vary(resp, "accept-language", &VARY_ACCEPT_LANGUAGE);
+}
+
+/// Append `cookie` to the response's `Vary` header, creating it if absent.
+/// Skips the update if `cookie` is already listed.
+pub(crate) fn append_vary_cookie<B>(resp: &mut Response<B>) {
+    static VARY_COOKIE: HeaderValue = HeaderValue::from_static("cookie");
+    append_vary(resp, "cookie", &VARY_COOKIE);
+}
+
+/// Append `available-dictionary` to the response's `Vary` header, creating it if absent.
+/// Skips the update if `available-dictionary` is already listed.
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-brotli",
+    feature = "compression-zstd"
+))]
+pub(crate) fn append_vary_available_dictionary<B>(resp: &mut Response<B>) {
+    static VARY_AVAILABLE_DICTIONARY: HeaderValue =
+        HeaderValue::from_static("available-dictionary");
+    append_vary(resp, "available-dictionary", &VARY_AVAILABLE_DICTIONARY);
+}
+
+/// Append a header name to the response's `Vary` header, creating it if absent.
+/// Skips the update if the name is already listed.
+pub(crate) fn append_vary_header<B>(resp: &mut Response<B>, name: &HeaderName) {
+    append_vary(resp, name.as_str(), &HeaderValue::from(name.clone()));
+}
+
 /// Append a value to the response's `Vary` header, creating it if absent.
 /// Skips the update if the value is already listed.
 fn append_vary<B>(resp: &mut Response<B>, value: &str, static_value: &HeaderValue) {
@@ -81,7 +116,10 @@ fn append_vary<B>(resp: &mut Response<B>, value: &str, static_value: &HeaderValu
         }
         Some(existing) => {
             let s = existing.to_str().unwrap_or_default();
-            if s.contains(value) {
+            // Compare whole names so `accept` is not taken for `accept-encoding`
+            if s.split(',')
+                .any(|name| name.trim().eq_ignore_ascii_case(value))
+            {
                 return;
             }
             // Append to existing value
@@ -102,7 +140,7 @@ fn append_vary<B>(resp: &mut Response<B>, value: &str, static_value: &HeaderValu
 mod tests {
     use hyper::{Method, Response, StatusCode};
 
-    use super::{MethodExt, append_vary_accept_encoding};
+    use super::{MethodExt, append_vary_accept, append_vary_accept_encoding};
 
     #[test]
     fn method_get_is_allowed() {
@@ -163,6 +201,17 @@ mod tests {
         assert_eq!(vary.to_str().unwrap(), "accept-encoding");
     }
 
+    #[test]
+    fn vary_names_are_compared_whole() {
+        let mut resp = Response::new(crate::body::empty());
+        resp.headers_mut()
+            .insert(hyper::header::VARY, "Accept-Encoding".parse().unwrap());
+        append_vary_accept(&mut resp);
+        append_vary_accept_encoding(&mut resp);
+        let vary = resp.headers().get(hyper::header::VARY).unwrap();
+        assert_eq!(vary.to_str().unwrap(), "Accept-Encoding, accept");
+    }
+
     #[test]
     fn vary_appended_to_existing_value() {
         let mut resp = Response::new(crate::body::empty());
@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Index of file content hashes.
+//!
+//! Hashes are keyed by the file identity (device and inode on Unix),
+//! modification time and size, so each revision of a file is read for
+//! hashing at most once per hash kind. Strong `ETag`s and representation
+//! digests share the index.
+
+use sha2::Digest as _;
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::SystemTime;
+
+/// Maximum number of hashes kept in the index. It is cleared when full,
+/// which only costs re-hashing the files requested afterwards.
+const INDEX_CAPACITY: usize = 65_536;
+
+type Index = HashMap<(FileKey, HashKind), Arc<[u8]>>;
+
+/// Content hashes of the files served so far.
+static INDEX: LazyLock<Mutex<Index>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The supported hash functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum HashKind {
+    Blake3,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashKind {
+    /// Hashes everything `reader` yields.
+    fn hash(self, reader: &mut impl Read) -> io::Result<Arc<[u8]>> {
+        fn digest<W: Write>(mut hasher: W, reader: &mut impl Read) -> io::Result<W> {
+            io::copy(reader, &mut hasher)?;
+            Ok(hasher)
+        }
+        Ok(match self {
+            Self::Blake3 => Arc::from(
+                digest(blake3::Hasher::new(), reader)?
+                    .finalize()
+                    .as_bytes()
+                    .as_slice(),
+            ),
+            Self::Sha256 => Arc::from(digest(sha2::Sha256::new(), reader)?.finalize().as_slice()),
+            Self::Sha384 => Arc::from(digest(sha2::Sha384::new(), reader)?.finalize().as_slice()),
+            Self::Sha512 => Arc::from(digest(sha2::Sha512::new(), reader)?.finalize().as_slice()),
+        })
+    }
+}
+
+/// Identifies a file revision: the same key implies the same content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct FileKey {
+    #[cfg(unix)]
+    dev: u64,
+    #[cfg(unix)]
+    ino: u64,
+    /// Files have no portable identity beyond their path elsewhere.
+    #[cfg(not(unix))]
+    path: std::path::PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileKey {
+    #[cfg(unix)]
+    fn new(_path: &Path, meta: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self {
+            dev: meta.dev(),
+            ino: meta.ino(),
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn new(path: &Path, meta: &Metadata) -> Self {
+        Self {
+            path: path.to_owned(),
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        }
+    }
+}
+
+/// Returns the indexed hash of a file revision, if any.
+pub(crate) fn indexed(kind: HashKind, path: &Path, meta: &Metadata) -> Option<Arc<[u8]>> {
+    lookup(&FileKey::new(path, meta), kind)
+}
+
+/// Returns the hash of an opened file, reading it from its start unless
+/// the index already holds it.
+///
+/// `path` and `meta` must belong to `file`, which is left positioned at an
+/// unspecified offset.
+pub(crate) fn hash_file(
+    kind: HashKind,
+    file: &mut File,
+    path: &Path,
+    meta: &Metadata,
+) -> io::Result<Arc<[u8]>> {
+    let key = FileKey::new(path, meta);
+    if let Some(hash) = lookup(&key, kind) {
+        return Ok(hash);
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+    let hash = kind.hash(file)?;
+    // Skip indexing when the file changed while it was hashed: its content
+    // may not match the metadata anymore.
+    let unchanged = file
+        .metadata()
+        .is_ok_and(|now| now.len() == meta.len() && now.modified().ok() == key.modified);
+    if unchanged {
+        remember(key, kind, hash.clone());
+    }
+    Ok(hash)
+}
+
+/// Returns the hash of the already read `data` of the file at `path`.
+#[cfg(feature = "mem-cache")]
+pub(crate) fn hash_bytes(kind: HashKind, path: &Path, meta: &Metadata, data: &[u8]) -> Arc<[u8]> {
+    let key = FileKey::new(path, meta);
+    if let Some(hash) = lookup(&key, kind) {
+        return hash;
+    }
+    let hash = hash_slice(kind, data);
+    if data.len() as u64 == meta.len() {
+        remember(key, kind, hash.clone());
+    }
+    hash
+}
+
+/// Returns the hash of `data`, which is not indexed.
+#[cfg(feature = "mem-cache")]
+pub(crate) fn hash_slice(kind: HashKind, data: &[u8]) -> Arc<[u8]> {
+    kind.hash(&mut &data[..])
+        .expect("reading from a slice can not fail")
+}
+
+fn lookup(key: &FileKey, kind: HashKind) -> Option<Arc<[u8]>> {
+    INDEX
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(&(key.clone(), kind))
+        .cloned()
+}
+
+fn remember(key: FileKey, kind: HashKind, hash: Arc<[u8]>) {
+    let mut index = INDEX.lock().unwrap_or_else(|err| err.into_inner());
+    let key = (key, kind);
+    if index.len() >= INDEX_CAPACITY && !index.contains_key(&key) {
+        index.clear();
+    }
+    index.insert(key, hash);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn hashes_are_indexed_by_file_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        fs::write(&path, b"<h1>one</h1>").unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let first = hash_file(HashKind::Sha256, &mut file, &path, &meta).unwrap();
+        assert_eq!(
+            lookup(&FileKey::new(&path, &meta), HashKind::Sha256).unwrap(),
+            first
+        );
+        assert!(lookup(&FileKey::new(&path, &meta), HashKind::Sha512).is_none());
+
+        // New revision: new key and new hash
+        fs::write(&path, b"<h1>two!</h1>").unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let second = hash_file(HashKind::Sha256, &mut file, &path, &meta).unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn hash_kinds_have_their_output_length() {
+        for (kind, len) in [
+            (HashKind::Blake3, 32),
+            (HashKind::Sha256, 32),
+            (HashKind::Sha384, 48),
+            (HashKind::Sha512, 64),
+        ] {
+            assert_eq!(kind.hash(&mut &b"abc"[..]).unwrap().len(), len);
+        }
+    }
+}
@@ -9,6 +9,7 @@
 use http::StatusCode;
 use std::fs::{File, Metadata};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use crate::Result;
 use crate::exts::headers::ContentCoding;
@@ -34,6 +35,15 @@ pub(crate) struct FileMetadata<'a> {
     pub file: Option<File>,
 }
 
+/// Modification time and size of a file, `None` when it is missing.
+pub(crate) type FileStamp = Option<(Option<SystemTime>, u64)>;
+
+/// Returns the stamp telling apart the revisions of a file.
+pub(crate) fn file_stamp(path: &Path) -> FileStamp {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok(), meta.len()))
+}
+
 /// Try to find the file system metadata for the given file path or return a `Not Found` error.
 pub(crate) fn try_metadata(file_path: &Path) -> Result<(Metadata, bool), StatusCode> {
     match std::fs::metadata(file_path) {
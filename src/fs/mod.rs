@@ -6,6 +6,7 @@
 //! A module that provides several facilities for working with files.
 //!
 
+pub(crate) mod hashes;
 pub(crate) mod meta;
 pub(crate) mod path;
 pub(crate) mod stream;
@@ -8,10 +8,13 @@
 
 use bytes::{Bytes, BytesMut};
 use futures_util::Stream;
-use std::fs::Metadata;
-use std::io::{self, Read};
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::vec;
+
+use crate::response::range::Segment;
 
 #[cfg(unix)]
 const DEFAULT_READ_BUF_SIZE: usize = 4_096;
@@ -53,6 +56,62 @@ impl<T: Read + Unpin> Stream for FileStream<T> {
     }
 }
 
+/// A stream of a `multipart/byteranges` body reading the ranges of a file.
+#[derive(Debug)]
+pub(crate) struct MultipartFileStream {
+    file: File,
+    segments: vec::IntoIter<Segment>,
+    /// Bytes left to read from the current range.
+    remaining: u64,
+    buf_size: usize,
+    buf: BytesMut,
+}
+
+impl MultipartFileStream {
+    pub(crate) fn new(file: File, buf_size: usize, segments: Vec<Segment>) -> Self {
+        let buf_size = buf_size.max(1);
+        Self {
+            file,
+            segments: segments.into_iter(),
+            remaining: 0,
+            buf_size,
+            buf: BytesMut::with_capacity(buf_size),
+        }
+    }
+}
+
+impl Stream for MultipartFileStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = Pin::into_inner(self);
+        while this.remaining == 0 {
+            match this.segments.next() {
+                None => return Poll::Ready(None),
+                Some(Segment::Bytes(bytes)) => return Poll::Ready(Some(Ok(bytes))),
+                Some(Segment::Range(start, end)) => {
+                    if let Err(err) = this.file.seek(SeekFrom::Start(start)) {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                    this.remaining = end - start;
+                }
+            }
+        }
+
+        let len = this.remaining.min(this.buf_size as u64) as usize;
+        this.buf.resize(len, 0);
+        match this.file.read(&mut this.buf[..]) {
+            // The file was truncated while streaming
+            Ok(0) => Poll::Ready(Some(Err(io::ErrorKind::UnexpectedEof.into()))),
+            Ok(n) => {
+                this.remaining -= n as u64;
+                Poll::Ready(Some(Ok(this.buf.split_to(n).freeze())))
+            }
+            Err(err) => Poll::Ready(Some(Err(err))),
+        }
+    }
+}
+
 pub(crate) fn optimal_buf_size(metadata: &Metadata) -> usize {
     let block_size = get_block_size(metadata);
     // If file length is smaller than block size,
@@ -37,13 +37,15 @@ use crate::fallback_page;
 use crate::metrics;
 
 #[cfg(feature = "mem-cache")]
-use crate::mem_cache::cache::MemCacheOpts;
+use crate::mem_cache::{self, cache::MemCacheOpts};
 
 use crate::{
-    Error, Result, control_headers, cors, custom_headers, error_page,
+    Error, Result, conditions, control_headers, cors, custom_headers, digest, error_page,
     exts::http::MethodExt,
-    health, log_addr, maintenance_mode, redirects, rewrites, security_headers,
+    health, image_negotiation, language_negotiation, log_addr, maintenance_mode, proxy,
+    redirect_maps, redirects, rewrites, security_headers,
     settings::Advanced,
+    site_files,
     static_files::{self, HandleOpts},
     text_charset, virtual_hosts,
 };
@@ -55,6 +57,7 @@ use crate::directory_listing::DirListFmt;
 use crate::directory_listing::download::DirDownloadFmt;
 
 /// It defines options for a request handler.
+#[derive(Clone)]
 pub struct RequestHandlerOpts {
     // General options
     /// Root directory of static files.
@@ -73,8 +76,36 @@ pub struct RequestHandlerOpts {
     ))]
     /// Compression level.
     pub compression_level: crate::settings::CompressionLevel,
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    /// On-disk cache of the dynamic compression output.
+    pub compression_cache: Option<Arc<crate::compression_cache::CompressionCache>>,
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    /// Dynamic compression policy. The built-in one applies when `None`.
+    pub compression_policy: Option<Arc<crate::compression::CompressionPolicy>>,
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-brotli",
+        feature = "compression-zstd"
+    ))]
+    /// Compression dictionaries.
+    pub compression_dictionaries:
+        Option<Arc<crate::compression_dictionary::CompressionDictionaries>>,
     /// Compression static feature.
     pub compression_static: bool,
+    /// AVIF and WebP image variants feature.
+    pub image_negotiation: bool,
     /// Directory listing feature.
     #[cfg(feature = "directory-listing")]
     #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
@@ -99,6 +130,20 @@ pub struct RequestHandlerOpts {
     pub cache_control_headers: bool,
     /// Weak ETag header feature.
     pub etag: bool,
+    /// Strong content-hash ETag header feature.
+    pub etag_strong: bool,
+    /// Repr-Digest and Content-Digest headers feature.
+    pub repr_digest: bool,
+    /// Netlify-style `_redirects` and `_headers` files of each site.
+    pub site_files: Option<Arc<crate::site_files::SiteFiles>>,
+    /// Redirect maps of exact request paths.
+    pub redirect_maps: Option<Arc<crate::redirect_maps::RedirectMaps>>,
+    /// Reverse proxy rules forwarding requests to upstream servers.
+    pub proxy: Option<Arc<crate::proxy::Proxies>>,
+    /// Language negotiation of localized files.
+    pub language_negotiation: Option<Arc<crate::language_negotiation::LanguageNegotiation>>,
+    /// Directories served under URL path prefixes.
+    pub mounts: Option<Arc<crate::mounts::Mounts>>,
     /// Page for 404 errors.
     pub page404: PathBuf,
     /// Page for 50x errors.
@@ -123,6 +168,8 @@ pub struct RequestHandlerOpts {
     pub trusted_proxies: Vec<IpAddr>,
     /// Redirect trailing slash feature.
     pub redirect_trailing_slash: bool,
+    /// Clean URLs feature.
+    pub clean_urls: bool,
     /// Ignore hidden files feature.
     pub include_hidden: bool,
     /// Prevent following symlinks for files and directories.
@@ -147,6 +194,8 @@ pub struct RequestHandlerOpts {
 
     /// Advanced options from the config file.
     pub advanced_opts: Option<Advanced>,
+    /// Virtual hosts along with their own options.
+    pub virtual_hosts: Option<Arc<virtual_hosts::VirtualHostsOpts>>,
 }
 
 impl Default for RequestHandlerOpts {
@@ -155,6 +204,7 @@ impl Default for RequestHandlerOpts {
             root_dir: PathBuf::from("./public"),
             compression: true,
             compression_static: false,
+            image_negotiation: false,
             #[cfg(any(
                 feature = "compression",
                 feature = "compression-gzip",
@@ -163,6 +213,28 @@ impl Default for RequestHandlerOpts {
                 feature = "compression-deflate"
             ))]
             compression_level: crate::settings::CompressionLevel::Default,
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-gzip",
+                feature = "compression-brotli",
+                feature = "compression-zstd",
+                feature = "compression-deflate"
+            ))]
+            compression_cache: None,
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-gzip",
+                feature = "compression-brotli",
+                feature = "compression-zstd",
+                feature = "compression-deflate"
+            ))]
+            compression_policy: None,
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-brotli",
+                feature = "compression-zstd"
+            ))]
+            compression_dictionaries: None,
             #[cfg(feature = "directory-listing")]
             dir_listing: false,
             #[cfg(feature = "directory-listing")]
@@ -177,6 +249,13 @@ impl Default for RequestHandlerOpts {
             security_headers: false,
             cache_control_headers: true,
             etag: true,
+            etag_strong: false,
+            repr_digest: false,
+            site_files: None,
+            redirect_maps: None,
+            proxy: None,
+            language_negotiation: None,
+            mounts: None,
             page404: PathBuf::from("./404.html"),
             page50x: PathBuf::from("./50x.html"),
             #[cfg(feature = "fallback-page")]
@@ -189,6 +268,7 @@ impl Default for RequestHandlerOpts {
             log_forwarded_for: false,
             trusted_proxies: Vec::new(),
             redirect_trailing_slash: true,
+            clean_urls: false,
             include_hidden: true,
             follow_symlinks: true,
             use_relative_root: false,
@@ -201,6 +281,7 @@ impl Default for RequestHandlerOpts {
             maintenance_mode_status: StatusCode::SERVICE_UNAVAILABLE,
             maintenance_mode_file: PathBuf::new(),
             advanced_opts: None,
+            virtual_hosts: None,
         }
     }
 }
@@ -219,29 +300,39 @@ impl RequestHandler {
         remote_addr: Option<SocketAddr>,
     ) -> impl Future<Output = Result<Response<Body>, Error>> + Send + 'a
     where
-        B: Send + 'a,
+        B: proxy::RequestBody + Send + 'a,
     {
-        let mut base_path = &self.opts.root_dir;
-        #[cfg(feature = "directory-listing")]
-        let dir_listing = self.opts.dir_listing;
-        #[cfg(feature = "directory-listing")]
-        let dir_listing_order = self.opts.dir_listing_order;
-        #[cfg(feature = "directory-listing")]
-        let dir_listing_format = &self.opts.dir_listing_format;
-        #[cfg(feature = "directory-listing-download")]
-        let dir_listing_download = &self.opts.dir_listing_download;
-        let redirect_trailing_slash = self.opts.redirect_trailing_slash;
-        let compression_static = self.opts.compression_static;
-        let etag = self.opts.etag;
-        let include_hidden = self.opts.include_hidden;
-        let follow_symlinks = self.opts.follow_symlinks;
-        let index_files: Vec<&str> = self.opts.index_files.iter().map(|s| s.as_str()).collect();
-        #[cfg(feature = "mem-cache")]
-        let memory_cache = self.opts.memory_cache.as_ref();
+        // Options of the virtual host matching the request if any
+        let vhost = virtual_hosts::select(&self.opts, req);
 
         log_addr::pre_process(&self.opts, req, remote_addr);
 
         async move {
+            let (opts, unknown_host) = match &vhost {
+                Ok(opts) => (opts.as_ref(), None),
+                Err(status) => (self.opts.as_ref(), Some(*status)),
+            };
+            let base_path = &opts.root_dir;
+            #[cfg(feature = "directory-listing")]
+            let dir_listing = opts.dir_listing;
+            #[cfg(feature = "directory-listing")]
+            let dir_listing_order = opts.dir_listing_order;
+            #[cfg(feature = "directory-listing")]
+            let dir_listing_format = &opts.dir_listing_format;
+            #[cfg(feature = "directory-listing-download")]
+            let dir_listing_download = &opts.dir_listing_download;
+            let redirect_trailing_slash = opts.redirect_trailing_slash;
+            let clean_urls = opts.clean_urls;
+            let compression_static = opts.compression_static;
+            let image_negotiation = opts.image_negotiation;
+            let etag = opts.etag;
+            let etag_strong = opts.etag_strong;
+            let include_hidden = opts.include_hidden;
+            let follow_symlinks = opts.follow_symlinks;
+            let index_files: Vec<&str> = opts.index_files.iter().map(|s| s.as_str()).collect();
+            #[cfg(feature = "mem-cache")]
+            let memory_cache = opts.memory_cache.as_ref();
+
             #[cfg(feature = "metrics")]
             let req_start = std::time::Instant::now();
             #[cfg(feature = "metrics")]
@@ -253,78 +344,121 @@ impl RequestHandler {
             }
 
             let result: Result<Response<Body>, Error> = async {
+                // In-memory cache purge endpoint (POST only)
+                #[cfg(feature = "mem-cache")]
+                if let Some(result) = mem_cache::purge::pre_process(&self.opts, req) {
+                    return result;
+                }
+
                 // Reject if the HTTP request method is not allowed
-                if !req.method().is_allowed() {
+                if !req.method().is_allowed() && !proxy::is_proxied(opts, req) {
                     return error_page::error_response(
                         req.uri(),
                         req.method(),
                         &StatusCode::METHOD_NOT_ALLOWED,
-                        &self.opts.page404,
-                        &self.opts.page50x,
+                        &opts.page404,
+                        &opts.page50x,
                     );
                 }
 
                 // Health endpoint check
-                if let Some(result) = health::pre_process(&self.opts, req) {
+                if let Some(result) = health::pre_process(opts, req) {
                     return result;
                 }
 
+                // Unknown virtual host
+                if let Some(status) = unknown_host {
+                    return error_page::error_response(
+                        req.uri(),
+                        req.method(),
+                        &status,
+                        &self.opts.page404,
+                        &self.opts.page50x,
+                    );
+                }
+
                 // CORS
-                if let Some(result) = cors::pre_process(&self.opts, req) {
+                if let Some(result) = cors::pre_process(opts, req) {
                     return result;
                 }
 
                 // `Basic` HTTP Authorization Schema
                 #[cfg(feature = "basic-auth")]
-                if let Some(response) = basic_auth::pre_process(&self.opts, req) {
+                if let Some(response) = basic_auth::pre_process(opts, req) {
                     return response;
                 }
 
                 // Metrics endpoint check
                 #[cfg(feature = "metrics")]
-                if let Some(result) = metrics::pre_process(&self.opts, req) {
+                if let Some(result) = metrics::pre_process(opts, req) {
                     return result;
                 }
 
                 // Maintenance Mode
-                if let Some(response) = maintenance_mode::pre_process(&self.opts, req) {
+                if let Some(response) = maintenance_mode::pre_process(opts, req) {
                     return response;
                 }
 
-                // Redirects
-                if let Some(result) = redirects::pre_process(&self.opts, req) {
+                // Redirect maps
+                if let Some(result) = redirect_maps::pre_process(opts, req) {
                     return result;
                 }
 
+                // Redirects
+                if let Some(result) = redirects::pre_process(opts, req) {
+                    return conditions::post_process(req, result?);
+                }
+
                 // Rewrites
-                if let Some(result) = rewrites::pre_process(&self.opts, req) {
-                    return result;
+                let request_uri = req.uri().clone();
+                if let Some(result) = rewrites::pre_process(opts, req) {
+                    return conditions::post_process(req, result?);
                 }
 
-                // Advanced options
-                if let Some(advanced) = &self.opts.advanced_opts {
-                    // If the "Host" header matches any virtual_host, change the root directory
-                    if let Some(root) =
-                        virtual_hosts::get_real_root(req, advanced.virtual_hosts.as_deref())
-                    {
-                        base_path = root;
-                    }
+                // Netlify-style `_redirects` file
+                if let Some(result) = site_files::pre_process(opts, req) {
+                    return conditions::post_process(req, result?);
+                }
+
+                // Reverse proxy, only appending the CORS, security, custom and `Vary` headers
+                if let Some(result) = proxy::pre_process(opts, req, remote_addr).await {
+                    let resp = cors::post_process(opts, req, result?)?;
+                    let resp = conditions::post_process(req, resp)?;
+                    let resp = security_headers::post_process(opts, req, resp)?;
+                    return custom_headers::post_process(opts, req, resp, None);
                 }
 
                 let index_files = index_files.as_ref();
+                let rewritten = req.uri().path() != request_uri.path();
 
                 // Check for markdown content negotiation (only if enabled)
-                let uri_path_md = if self.opts.accept_markdown {
+                let uri_path_md = if opts.accept_markdown {
                     crate::markdown::pre_process(
                         req,
                         base_path,
                         req.uri().path(),
-                        self.opts.include_hidden,
+                        opts.include_hidden,
+                    )
+                } else {
+                    None
+                };
+                // Check for a localized file variant
+                let negotiated = if uri_path_md.is_none() {
+                    language_negotiation::pre_process(
+                        opts,
+                        req,
+                        base_path,
+                        req.uri().path(),
+                        include_hidden,
                     )
                 } else {
                     None
                 };
-                let uri_path = uri_path_md.as_deref().unwrap_or(req.uri().path());
+                let lang_variant = negotiated.as_ref().and_then(|n| n.variant.as_ref());
+                let uri_path = uri_path_md
+                    .as_deref()
+                    .or(lang_variant.map(|variant| variant.uri_path.as_str()))
+                    .unwrap_or(req.uri().path());
 
                 // Static files
                 let (resp, file_path) = match static_files::handle(&HandleOpts {
@@ -344,8 +478,14 @@ impl RequestHandler {
                     #[cfg(feature = "directory-listing-download")]
                     dir_listing_download,
                     redirect_trailing_slash,
+                    // Neither the variant file name nor a rewritten path is the canonical one
+                    clean_urls: clean_urls && lang_variant.is_none() && !rewritten,
                     compression_static,
+                    image_negotiation,
+                    mounts: opts.mounts.as_deref(),
+                    site_files: opts.site_files.is_some(),
                     etag,
+                    etag_strong,
                     include_hidden,
                     index_files,
                     follow_symlinks,
@@ -355,7 +495,7 @@ impl RequestHandler {
                     Ok(result) => (result.resp, Some(result.file_path)),
                     Err(status) => {
                         #[cfg(feature = "fallback-page")]
-                        let will_serve_fallback = fallback_page::can_serve(&self.opts, req, status);
+                        let will_serve_fallback = fallback_page::can_serve(opts, req, status);
                         #[cfg(not(feature = "fallback-page"))]
                         let will_serve_fallback = false;
 
@@ -364,16 +504,16 @@ impl RequestHandler {
                                 req.uri(),
                                 req.method(),
                                 &status,
-                                &self.opts.page404,
-                                &self.opts.page50x,
+                                &opts.page404,
+                                &opts.page50x,
                             )?
                         } else {
                             error_page::error_response(
                                 req.uri(),
                                 req.method(),
                                 &status,
-                                &self.opts.page404,
-                                &self.opts.page50x,
+                                &opts.page404,
+                                &opts.page50x,
                             )?
                         };
 
@@ -383,19 +523,45 @@ impl RequestHandler {
 
                 // Check for a fallback response
                 #[cfg(feature = "fallback-page")]
-                let resp = fallback_page::post_process(&self.opts, req, resp)?;
+                let resp = fallback_page::post_process(opts, req, resp)?;
 
                 // Append CORS headers if they are present
-                let resp = cors::post_process(&self.opts, req, resp)?;
+                let resp = cors::post_process(opts, req, resp)?;
+
+                // Vary on the request headers checked by redirect and rewrite conditions
+                let resp = conditions::post_process(req, resp)?;
 
                 // Set Content-Type for markdown files
-                let resp = crate::markdown::post_process(uri_path_md.is_some(), &self.opts, resp)?;
+                let resp = crate::markdown::post_process(uri_path_md.is_some(), opts, resp)?;
+
+                // Set Content-Language for localized files
+                let resp = language_negotiation::post_process(negotiated.as_ref(), opts, resp)?;
 
                 // Declare a default charset for `text/*` responses
-                let resp = text_charset::post_process(&self.opts, resp)?;
+                let resp = text_charset::post_process(opts, resp)?;
 
                 // Add a `Vary` header if static compression is used
-                let resp = compression_static::post_process(&self.opts, req, resp)?;
+                let resp = compression_static::post_process(opts, req, resp)?;
+
+                // Add a `Vary` header if image variants are negotiated
+                let resp = image_negotiation::post_process(opts, resp, file_path.as_ref())?;
+
+                // Append integrity digests of the served representation
+                let resp =
+                    digest::post_process(opts, req.headers(), resp, file_path.as_ref()).await?;
+
+                // Compression using a dictionary available to the client
+                #[cfg(any(
+                    feature = "compression",
+                    feature = "compression-brotli",
+                    feature = "compression-zstd"
+                ))]
+                let resp = crate::compression_dictionary::post_process(
+                    opts,
+                    req,
+                    resp,
+                    file_path.as_ref(),
+                )?;
 
                 // Auto compression based on the `Accept-Encoding` header
                 #[cfg(any(
@@ -405,16 +571,24 @@ impl RequestHandler {
                     feature = "compression-zstd",
                     feature = "compression-deflate"
                 ))]
-                let resp = compression::post_process(&self.opts, req, resp)?;
+                let resp = compression::post_process(
+                    opts,
+                    req.method(),
+                    req.uri(),
+                    req.headers(),
+                    resp,
+                    file_path.as_ref(),
+                )
+                .await?;
 
                 // Append `Cache-Control` headers for web assets
-                let resp = control_headers::post_process(&self.opts, req, resp)?;
+                let resp = control_headers::post_process(opts, req, resp)?;
 
                 // Append security headers
-                let resp = security_headers::post_process(&self.opts, req, resp)?;
+                let resp = security_headers::post_process(opts, req, resp)?;
 
                 // Add/update custom headers
-                let resp = custom_headers::post_process(&self.opts, req, resp, file_path.as_ref())?;
+                let resp = custom_headers::post_process(opts, req, resp, file_path.as_ref())?;
 
                 Ok(resp)
             }
@@ -7,7 +7,7 @@
 //!
 
 use headers::{ContentType, HeaderMapExt};
-use hyper::{Method, Request, Response};
+use hyper::{Method, Request, Response, StatusCode};
 
 use crate::body::Body;
 use crate::{Error, handler::RequestHandlerOpts};
@@ -31,13 +31,20 @@ pub fn pre_process<T>(
         return None;
     }
 
+    let (status, text) = if is_warming_up(opts) {
+        (StatusCode::SERVICE_UNAVAILABLE, "WARMING UP")
+    } else {
+        (StatusCode::OK, "OK")
+    };
+
     let body = match *req.method() {
         Method::HEAD => crate::body::empty(),
-        Method::GET => crate::body::full("OK"),
+        Method::GET => crate::body::full(text),
         _ => return None,
     };
 
     let mut resp = Response::new(body);
+    *resp.status_mut() = status;
     // SECURITY: The body is a literal `OK` ASCII string, so advertise it
     // as `text/plain` instead of `text/html`. This eliminates any chance
     // of a downstream proxy / reverse-CDN rendering this endpoint as
@@ -47,6 +54,22 @@ pub fn pre_process<T>(
     Some(Ok(resp))
 }
 
+/// Whether the server is not ready yet because the in-memory cache is
+/// still being preloaded and readiness was configured to wait for it.
+fn is_warming_up(opts: &RequestHandlerOpts) -> bool {
+    #[cfg(feature = "mem-cache")]
+    if opts
+        .memory_cache
+        .as_ref()
+        .is_some_and(|mem_opts| mem_opts.preload_wait)
+    {
+        return crate::mem_cache::preload::is_running();
+    }
+
+    let _ = opts;
+    false
+}
+
 pub(crate) fn is_health_endpoint<T>(req: &Request<T>) -> bool {
     req.uri().path() == "/health"
 }
@@ -108,6 +131,25 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "mem-cache")]
+    #[test]
+    fn test_ready_when_not_waiting_for_preload() {
+        let mut mem_opts = crate::mem_cache::cache::MemCacheOpts::new(1024);
+        mem_opts.preload_wait = true;
+        let resp = pre_process(
+            &RequestHandlerOpts {
+                health: true,
+                memory_cache: Some(mem_opts),
+                ..Default::default()
+            },
+            &make_request("GET", "/health"),
+        )
+        .unwrap()
+        .unwrap();
+        // No preload was started in this process.
+        assert_eq!(resp.status(), hyper::StatusCode::OK);
+    }
+
     #[test]
     fn test_correct_request() {
         assert!(
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Image negotiation module to serve AVIF and WebP image variants directly from the file system.
+//!
+//! A `hero.jpg` request is answered with the smallest of `hero.jpg`,
+//! `hero.jpg.avif` and `hero.jpg.webp` whose media type is listed in the
+//! request `Accept` header. Wildcard ranges like `image/*` do not count
+//! since browsers send them regardless of their AVIF or WebP support.
+//!
+
+use headers::{HeaderMap, HeaderMapExt, HeaderValue};
+use hyper::Response;
+use std::ffi::OsStr;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+use crate::body::Body;
+use crate::exts::headers::Accept;
+use crate::exts::http::append_vary_accept;
+use crate::fs::meta::try_metadata;
+use crate::handler::RequestHandlerOpts;
+
+/// Extensions of the images whose variants are looked up.
+const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "gif"];
+
+/// Variant extensions along with their media type.
+const VARIANTS: [(&str, &str); 2] = [("avif", "image/avif"), ("webp", "image/webp")];
+
+/// It defines the image variant metadata of a particular file path.
+pub struct ImageFileVariant {
+    /// Current file path.
+    pub file_path: PathBuf,
+    /// The metadata of the current file.
+    pub metadata: Metadata,
+}
+
+/// Initializes image negotiation.
+pub fn init(enabled: bool, handler_opts: &mut RequestHandlerOpts) {
+    handler_opts.image_negotiation = enabled;
+    tracing::info!(enabled, "image negotiation");
+}
+
+/// Post-processing to add Vary header if necessary.
+pub(crate) fn post_process(
+    opts: &RequestHandlerOpts,
+    mut resp: Response<Body>,
+    file_path: Option<&PathBuf>,
+) -> Result<Response<Body>, Error> {
+    if !opts.image_negotiation {
+        return Ok(resp);
+    }
+
+    // The representation of an image or of its variants varies by `Accept`
+    if file_path.is_some_and(|path| is_image(path) || is_variant(path)) {
+        append_vary_accept(&mut resp);
+    }
+
+    Ok(resp)
+}
+
+/// Checks whether the file path is an image having possible variants.
+pub(crate) fn is_image(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|image_ext| ext.eq_ignore_ascii_case(image_ext))
+        })
+}
+
+/// Checks whether the file path is an image variant, e.g. `hero.jpg.avif`.
+fn is_variant(file_path: &Path) -> bool {
+    let ext = file_path.extension().and_then(OsStr::to_str);
+    VARIANTS
+        .iter()
+        .any(|(variant_ext, _)| ext == Some(variant_ext))
+        && is_image(&file_path.with_extension(""))
+}
+
+/// Search for the smallest accepted image variant of the given file path.
+/// Returns `None` when the original file is the smallest one.
+pub fn image_variant(
+    file_path: &Path,
+    metadata: &Metadata,
+    headers: &HeaderMap<HeaderValue>,
+) -> Option<ImageFileVariant> {
+    if !is_image(file_path) {
+        return None;
+    }
+    let accept = headers.typed_get::<Accept>()?;
+    let file_name = file_path.file_name().and_then(OsStr::to_str)?;
+
+    let mut smallest: Option<ImageFileVariant> = None;
+    for (ext, media_type) in VARIANTS {
+        if !accept.accepts_listed(media_type) {
+            continue;
+        }
+
+        let file_path = file_path.with_file_name([file_name, ".", ext].concat());
+        tracing::trace!(
+            "trying to get the image file variant metadata for {}",
+            file_path.display()
+        );
+        let metadata = match try_metadata(&file_path) {
+            Ok((metadata, false)) => metadata,
+            Ok((_, true)) => {
+                tracing::trace!("image file variant found but it's a directory, skipping");
+                continue;
+            }
+            Err(e) => {
+                tracing::trace!("image file variant error: {:?}", e);
+                continue;
+            }
+        };
+
+        let len = smallest.as_ref().map_or(u64::MAX, |v| v.metadata.len());
+        if metadata.len() < len {
+            smallest = Some(ImageFileVariant {
+                file_path,
+                metadata,
+            });
+        }
+    }
+
+    smallest.filter(|variant| variant.metadata.len() < metadata.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn accept(value: &'static str) -> HeaderMap<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        headers.insert(hyper::header::ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn picks_smallest_accepted_variant() {
+        let tmp = tempfile::tempdir().unwrap();
+        let image = tmp.path().join("hero.jpg");
+        fs::write(&image, [0; 100]).unwrap();
+        fs::write(tmp.path().join("hero.jpg.avif"), [0; 40]).unwrap();
+        fs::write(tmp.path().join("hero.jpg.webp"), [0; 60]).unwrap();
+        let metadata = fs::metadata(&image).unwrap();
+
+        let variant = |value| image_variant(&image, &metadata, &accept(value)).map(|v| v.file_path);
+        assert_eq!(
+            variant("image/avif,image/webp,*/*"),
+            Some(tmp.path().join("hero.jpg.avif"))
+        );
+        assert_eq!(
+            variant("image/webp,image/*"),
+            Some(tmp.path().join("hero.jpg.webp"))
+        );
+        assert_eq!(variant("image/avif;q=0,image/*"), None);
+        assert_eq!(variant("*/*"), None);
+    }
+
+    #[test]
+    fn keeps_smaller_original() {
+        let tmp = tempfile::tempdir().unwrap();
+        let image = tmp.path().join("icon.PNG");
+        fs::write(&image, [0; 10]).unwrap();
+        fs::write(tmp.path().join("icon.PNG.webp"), [0; 20]).unwrap();
+        let metadata = fs::metadata(&image).unwrap();
+
+        assert!(image_variant(&image, &metadata, &accept("image/webp")).is_none());
+        assert!(is_variant(Path::new("icon.PNG.webp")));
+        assert!(!is_variant(Path::new("icon.webp")));
+    }
+}
@@ -0,0 +1,367 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Language negotiation module.
+//!
+//! This module serves localized variants of HTML files named after their
+//! language tag, e.g. `page.en.html` and `page.de.html` for `/page` or
+//! `index.en.html` for `/docs/`. The variant is picked from an optional
+//! query parameter or cookie override, then from the `Accept-Language`
+//! header q-values and finally from the configured default language.
+//!
+//! ```toml
+//! [advanced.language-negotiation]
+//! default-language = "en"
+//! cookie = "lang"
+//! query-param = "lang"
+//! ```
+//!
+//! Language tags are matched in lowercase against the file names, so a
+//! `de-CH` preference is served by `page.de-ch.html` or else `page.de.html`.
+//!
+//! Responses to the HTML pages subject to negotiation vary on the
+//! `Accept-Language` header (and the cookie if configured), whether a
+//! variant was found or not.
+
+use headers::HeaderMapExt;
+use hyper::header::{CONTENT_LANGUAGE, COOKIE, HeaderValue};
+use hyper::{Request, Response, StatusCode};
+use percent_encoding::utf8_percent_encode;
+use std::path::Path;
+
+use crate::body::Body;
+use crate::exts::headers::AcceptLanguage;
+use crate::exts::http::{append_vary_accept_language, append_vary_cookie};
+use crate::fs::meta::try_metadata;
+use crate::fs::path::{PathExt, sanitize_path};
+use crate::handler::RequestHandlerOpts;
+use crate::markdown::URI_PATH_ESCAPE;
+use crate::{Error, Result, bail};
+
+/// Maximum number of `Accept-Language` ranges looked up per request.
+const MAX_LANGUAGE_RANGES: usize = 8;
+
+/// Maximum length of a language tag.
+const MAX_LANGUAGE_TAG_LEN: usize = 35;
+
+/// The language negotiation settings.
+pub struct LanguageNegotiation {
+    default_language: Option<String>,
+    cookie: Option<String>,
+    query_param: Option<String>,
+}
+
+/// A localized file variant chosen for a request.
+#[derive(Debug, PartialEq)]
+pub(crate) struct LanguageVariant {
+    /// The URI path of the variant file.
+    pub uri_path: String,
+    /// The language tag of the variant.
+    pub language: String,
+}
+
+/// Outcome of the language negotiation of an HTML page request.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Negotiated {
+    /// The localized file variant found, if any.
+    pub variant: Option<LanguageVariant>,
+}
+
+/// Initializes the language negotiation.
+pub fn init(handler_opts: &mut RequestHandlerOpts) -> Result {
+    let Some(config) = handler_opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.language_negotiation.as_ref())
+    else {
+        tracing::info!(enabled = false, "language negotiation");
+        return Ok(());
+    };
+
+    let default_language = match config.default_language.as_deref() {
+        Some(tag) => match language_tag(tag) {
+            Some(tag) => Some(tag),
+            None => bail!("language negotiation default language `{tag}` is not a valid tag"),
+        },
+        None => None,
+    };
+    let non_empty = |name: &Option<String>| name.as_ref().filter(|name| !name.is_empty()).cloned();
+
+    tracing::info!(
+        "language negotiation: enabled=true, default_language={:?}, cookie={:?}, query_param={:?}",
+        default_language,
+        config.cookie,
+        config.query_param
+    );
+    handler_opts.language_negotiation = Some(std::sync::Arc::new(LanguageNegotiation {
+        default_language,
+        cookie: non_empty(&config.cookie),
+        query_param: non_empty(&config.query_param),
+    }));
+
+    Ok(())
+}
+
+/// Pre-process a request to check if a localized variant URI should be used.
+/// Returns `None` when the request is not subject to negotiation.
+///
+/// Like the markdown negotiation, the URI path is sanitized before any
+/// filesystem access and the returned URI is checked again by the
+/// static-files handler.
+pub(crate) fn pre_process<T>(
+    opts: &RequestHandlerOpts,
+    req: &Request<T>,
+    base_path: &Path,
+    uri_path: &str,
+    include_hidden: bool,
+) -> Option<Negotiated> {
+    let negotiation = opts.language_negotiation.as_deref()?;
+
+    // SECURITY: percent-decode and strip traversal components before
+    // touching the filesystem.
+    let file_path = sanitize_path(base_path, uri_path).ok()?;
+    let relative = file_path.strip_prefix(base_path).ok()?;
+    if !include_hidden && relative.is_hidden() {
+        tracing::debug!("language: skipping hidden path {:?}", relative);
+        return None;
+    }
+
+    let (dir, stem, is_page) = if uri_path.ends_with('/') {
+        (file_path.as_path(), "index", true)
+    } else {
+        let name = file_path.file_name()?.to_str()?;
+        let stem = name.strip_suffix(".html");
+        (
+            file_path.parent()?,
+            stem.unwrap_or(name),
+            stem.is_some() || !name.contains('.'),
+        )
+    };
+
+    let overrides = [
+        negotiation
+            .query_param
+            .as_deref()
+            .and_then(|name| query_value(req, name)),
+        negotiation
+            .cookie
+            .as_deref()
+            .and_then(|name| cookie_value(req, name)),
+    ];
+    let accepted = req.headers().typed_get::<AcceptLanguage>();
+    let ranges = accepted
+        .iter()
+        .flat_map(|accepted| accepted.sorted_languages())
+        .take(MAX_LANGUAGE_RANGES)
+        .filter_map(language_tag)
+        .flat_map(|tag| fallbacks(&tag));
+    let candidates = overrides
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| language_tag(&tag))
+        .chain(ranges)
+        .chain(negotiation.default_language.clone());
+
+    for language in candidates {
+        let variant_path = dir.join([stem, ".", &language, ".html"].concat());
+        if !is_file(&variant_path) {
+            continue;
+        }
+        tracing::debug!("language: found variant {:?}", variant_path);
+
+        // Convert the variant path back into a URI path, escaping `%` so
+        // the static-files handler's re-decode resolves to the same file.
+        let relative = variant_path.strip_prefix(base_path).ok()?.to_str()?;
+        let relative = relative.replace(std::path::MAIN_SEPARATOR, "/");
+        return Some(Negotiated {
+            variant: Some(LanguageVariant {
+                uri_path: format!("/{}", utf8_percent_encode(&relative, URI_PATH_ESCAPE)),
+                language,
+            }),
+        });
+    }
+
+    // Other languages may still have a variant of the page
+    is_page.then_some(Negotiated { variant: None })
+}
+
+/// Post-process the response to declare the language of a localized variant
+/// and the request headers the negotiation depends on.
+pub(crate) fn post_process(
+    negotiated: Option<&Negotiated>,
+    opts: &RequestHandlerOpts,
+    mut resp: Response<Body>,
+) -> Result<Response<Body>, Error> {
+    let (Some(negotiated), Some(negotiation)) = (negotiated, opts.language_negotiation.as_deref())
+    else {
+        return Ok(resp);
+    };
+    if !(resp.status().is_success() || resp.status() == StatusCode::NOT_MODIFIED) {
+        return Ok(resp);
+    }
+
+    if let Some(variant) = &negotiated.variant
+        && let Ok(language) = HeaderValue::from_str(&variant.language)
+    {
+        resp.headers_mut().insert(CONTENT_LANGUAGE, language);
+    }
+    append_vary_accept_language(&mut resp);
+    if negotiation.cookie.is_some() {
+        append_vary_cookie(&mut resp);
+    }
+
+    Ok(resp)
+}
+
+/// Returns the lowercase language tag, or `None` if not a valid one.
+/// The `*` range is not a tag and left to the default language.
+fn language_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    let valid = !tag.is_empty()
+        && tag.len() <= MAX_LANGUAGE_TAG_LEN
+        && !tag.starts_with('-')
+        && !tag.ends_with('-')
+        && !tag.contains("--")
+        && tag.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-');
+    valid.then(|| tag.to_ascii_lowercase())
+}
+
+/// Returns a language tag followed by its shorter prefixes, e.g. `de-ch`
+/// then `de` as described by the RFC 4647 lookup scheme.
+fn fallbacks(tag: &str) -> impl Iterator<Item = String> + use<> {
+    let mut prefixes = vec![tag.to_owned()];
+    let mut tag = tag;
+    while let Some((prefix, _)) = tag.rsplit_once('-') {
+        prefixes.push(prefix.to_owned());
+        tag = prefix;
+    }
+    prefixes.into_iter()
+}
+
+fn query_value<T>(req: &Request<T>, name: &str) -> Option<String> {
+    form_urlencoded::parse(req.uri().query()?.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn cookie_value<T>(req: &Request<T>, name: &str) -> Option<String> {
+    req.headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.trim_matches('"').to_owned())
+}
+
+fn is_file(path: &Path) -> bool {
+    matches!(try_metadata(path), Ok((_, false)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Arc;
+
+    fn opts(default_language: Option<&str>) -> RequestHandlerOpts {
+        RequestHandlerOpts {
+            language_negotiation: Some(Arc::new(LanguageNegotiation {
+                default_language: default_language.map(str::to_owned),
+                cookie: Some("lang".to_owned()),
+                query_param: Some("hl".to_owned()),
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn negotiate(
+        opts: &RequestHandlerOpts,
+        base: &Path,
+        uri: &str,
+        headers: &[(&str, &str)],
+    ) -> Option<LanguageVariant> {
+        let mut req = Request::builder().uri(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req.body(()).unwrap();
+        pre_process(opts, &req, base, req.uri().path(), false).and_then(|n| n.variant)
+    }
+
+    fn variant(uri_path: &str, language: &str) -> Option<LanguageVariant> {
+        Some(LanguageVariant {
+            uri_path: uri_path.to_owned(),
+            language: language.to_owned(),
+        })
+    }
+
+    #[test]
+    fn language_tags() {
+        assert_eq!(language_tag(" de-CH "), Some("de-ch".to_owned()));
+        assert_eq!(language_tag("*"), None);
+        assert_eq!(language_tag("../en"), None);
+        assert_eq!(language_tag("en-"), None);
+        assert_eq!(language_tag(""), None);
+        assert_eq!(
+            fallbacks("zh-hant-tw").collect::<Vec<_>>(),
+            ["zh-hant-tw", "zh-hant", "zh"]
+        );
+    }
+
+    #[test]
+    fn negotiates_variants() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path();
+        fs::write(base.join("page.en.html"), "en").unwrap();
+        fs::write(base.join("page.de.html"), "de").unwrap();
+        fs::create_dir(base.join("docs")).unwrap();
+        fs::write(base.join("docs/index.fr.html"), "fr").unwrap();
+        let opts = opts(Some("en"));
+
+        let accept = |value| [("accept-language", value)];
+        assert_eq!(
+            negotiate(&opts, base, "/page", &accept("fr, de-CH;q=0.9, en;q=0.8")),
+            variant("/page.de.html", "de")
+        );
+        assert_eq!(
+            negotiate(&opts, base, "/page.html", &accept("de;q=0, *")),
+            variant("/page.en.html", "en")
+        );
+        assert_eq!(
+            negotiate(&opts, base, "/docs/", &accept("fr")),
+            variant("/docs/index.fr.html", "fr")
+        );
+        assert_eq!(negotiate(&opts, base, "/docs/", &accept("de")), None);
+        assert_eq!(negotiate(&opts, base, "/docs", &accept("fr")), None);
+        assert_eq!(negotiate(&opts, base, "/missing", &accept("de")), None);
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path();
+        fs::write(base.join("page.en.html"), "en").unwrap();
+        fs::write(base.join("page.de.html"), "de").unwrap();
+        fs::write(base.join("page.fr.html"), "fr").unwrap();
+        let opts = opts(None);
+
+        let headers = [("accept-language", "fr"), ("cookie", "a=1; lang=de")];
+        assert_eq!(
+            negotiate(&opts, base, "/page", &headers),
+            variant("/page.de.html", "de")
+        );
+        assert_eq!(
+            negotiate(&opts, base, "/page?hl=EN", &headers),
+            variant("/page.en.html", "en")
+        );
+        // Invalid or unavailable overrides are ignored
+        assert_eq!(
+            negotiate(&opts, base, "/page?hl=../x", &headers[..1]),
+            variant("/page.fr.html", "fr")
+        );
+    }
+}
@@ -136,11 +136,45 @@ pub mod body;
     )))
 )]
 pub mod compression;
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    )))
+)]
+pub mod compression_cache;
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-brotli",
+    feature = "compression-zstd"
+))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(
+        feature = "compression",
+        feature = "compression-brotli",
+        feature = "compression-zstd"
+    )))
+)]
+pub mod compression_dictionary;
 pub mod compression_static;
 pub(crate) mod conditional_headers;
+pub mod conditions;
 pub mod control_headers;
 pub mod cors;
 pub mod custom_headers;
+pub mod digest;
 #[cfg(feature = "directory-listing")]
 #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
 pub mod directory_listing;
@@ -156,6 +190,8 @@ pub(crate) mod health;
 #[cfg(feature = "tls")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
 pub mod https_redirect;
+pub mod image_negotiation;
+pub mod language_negotiation;
 pub(crate) mod log_addr;
 pub mod maintenance_mode;
 pub(crate) mod markdown;
@@ -164,6 +200,27 @@ pub(crate) mod markdown;
 pub mod mem_cache;
 #[cfg(feature = "metrics")]
 pub(crate) mod metrics;
+pub mod mounts;
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    )))
+)]
+pub mod precompress;
+pub mod proxy;
+pub mod redirect_maps;
 pub mod redirects;
 pub(crate) mod response;
 pub mod rewrites;
@@ -174,12 +231,13 @@ pub mod settings;
 #[cfg(any(unix, windows))]
 #[cfg_attr(docsrs, doc(cfg(any(unix, windows))))]
 pub mod signals;
+pub mod site_files;
 pub mod static_files;
 pub(crate) mod text_charset;
 #[cfg(feature = "tls")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
 pub mod tls;
-pub(crate) mod virtual_hosts;
+pub mod virtual_hosts;
 #[cfg(windows)]
 #[cfg_attr(docsrs, doc(cfg(windows)))]
 pub mod winservice;
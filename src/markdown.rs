@@ -24,7 +24,7 @@ use crate::{
 /// The returned URI is percent-decoded again by `sanitize_path` in the
 /// static-files handler, so `%` must be escaped to keep the encode/decode
 /// round-trip lossless for file names that contain it.
-const URI_PATH_ESCAPE: &AsciiSet = &CONTROLS.add(b'%');
+pub(crate) const URI_PATH_ESCAPE: &AsciiSet = &CONTROLS.add(b'%');
 
 /// Pre-process a request to check if a markdown variant URI should be used.
 ///
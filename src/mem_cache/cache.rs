@@ -12,19 +12,43 @@
 
 use bytes::Bytes;
 use compact_str::CompactString;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use headers::{AcceptRanges, ContentLength, ContentRange, HeaderMap, HeaderMapExt, LastModified};
 use hyper::header::{CONTENT_TYPE, ETAG, HeaderName, HeaderValue};
-use hyper::{Response, StatusCode};
-use mini_moka::sync::Cache;
-use std::path::Path;
+use hyper::{Method, Response, StatusCode};
+#[cfg(feature = "metrics")]
+use moka::notification::RemovalCause;
+use moka::sync::Cache;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
-use crate::Result;
 use crate::body::{self, Body};
 use crate::conditional_headers::{ConditionalBody, ConditionalHeaders, Validators};
+use crate::digest::{self, Digests, Representation};
 use crate::handler::RequestHandlerOpts;
-use crate::response::range::{BadRangeError, bytes_range};
+use crate::mem_cache::preload::{self, Preload};
+use crate::mem_cache::watcher;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::response::range::{BadRangeError, MAX_RANGES, Multipart, Segment, bytes_ranges};
+use crate::settings::Advanced;
+use crate::settings::file::MemoryCacheInvalidation;
+use crate::{Context, Result};
+
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+))]
+use crate::{
+    compression,
+    exts::headers::ContentCoding,
+    exts::http::{MethodExt, append_vary_accept_encoding},
+    mem_cache::variants::{EncodedVariant, PendingVariant, Variants},
+};
 
 /// Global cache that stores all files in memory.
 /// It provides expiration policies like Time to live (TTL) and Time to idle (TTI) support.
@@ -36,9 +60,78 @@ pub(crate) static X_CACHE: HeaderName = HeaderName::from_static("x-cache");
 pub(crate) static X_CACHE_HIT: HeaderValue = HeaderValue::from_static("HIT");
 
 /// It defines the in-memory files cache options.
+#[derive(Clone)]
 pub struct MemCacheOpts {
     /// The maximum size per file in bytes.
     pub max_file_size: u64,
+    /// Whether to compare the file metadata against the cached entry on every hit.
+    pub revalidate: bool,
+    /// Whether to serve (and lazily fill) the content-encoded variants of
+    /// the cached entries. It mirrors the dynamic compression setting.
+    pub compression: bool,
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    /// Dynamic compression policy deciding the served encoded variants.
+    /// It mirrors the dynamic compression policy setting.
+    pub compression_policy: Option<Arc<compression::CompressionPolicy>>,
+    /// Request paths eligible for caching. All paths when `None`.
+    pub include: Option<GlobSet>,
+    /// Request paths never cached. Takes precedence over `include`.
+    pub exclude: Option<GlobSet>,
+    /// Whether the health endpoint waits for the startup preload to finish.
+    pub preload_wait: bool,
+    /// Bearer token guarding the purge endpoint. Disabled when `None`.
+    pub purge_token: Option<String>,
+    /// Root directories whose files may be cached.
+    pub(crate) roots: Arc<[CacheRoot]>,
+}
+
+/// A root directory whose files may be cached, along with the request path
+/// prefix and the policies its files are served with.
+#[derive(Debug, Clone)]
+pub(crate) struct CacheRoot {
+    /// Root directory.
+    pub(crate) dir: PathBuf,
+    /// Request path prefix of the files below the root, e.g. a mount prefix.
+    pub(crate) uri_prefix: String,
+    /// Whether each subdirectory is the root of the site of a hostname.
+    pub(crate) hosts: bool,
+    /// Whether hidden files (dotfiles) are served.
+    pub(crate) include_hidden: bool,
+    /// Whether symbolic links are followed.
+    pub(crate) follow_symlinks: bool,
+}
+
+impl CacheRoot {
+    /// Creates a root served with the policies of the given options.
+    pub(crate) fn new(dir: PathBuf, uri_prefix: &str, opts: &RequestHandlerOpts) -> Self {
+        Self {
+            dir,
+            uri_prefix: uri_prefix.to_owned(),
+            hosts: false,
+            include_hidden: opts.include_hidden,
+            follow_symlinks: opts.follow_symlinks,
+        }
+    }
+
+    /// Maps a cache key (a file path) back to its request path.
+    pub(crate) fn uri_path_of(&self, file_path: &Path) -> Option<String> {
+        let mut components = file_path.strip_prefix(&self.dir).ok()?.components();
+        if self.hosts {
+            components.next()?;
+        }
+        let mut uri_path = self.uri_prefix.clone();
+        for component in components {
+            uri_path.push('/');
+            uri_path.push_str(component.as_os_str().to_str()?);
+        }
+        Some(uri_path)
+    }
 }
 
 /// Default capacity (number of entries).
@@ -58,6 +151,9 @@ const MAX_TTL: u64 = 86_400;
 const MAX_TTI: u64 = 3_600;
 /// Maximum allowed file size in KiB (32 MiB = 32768 KiB).
 const MAX_FILE_SIZE: u64 = 32_768;
+/// Maximum allowed file size in KiB when the store is bounded by bytes
+/// (1 GiB = 1048576 KiB). The byte budget itself also caps the file size.
+const MAX_WEIGHTED_FILE_SIZE: u64 = 1_048_576;
 
 impl MemCacheOpts {
     /// Creates a new instance of `MemCacheOpts`.
@@ -66,7 +162,59 @@ impl MemCacheOpts {
     pub fn new(max_file_size: u64) -> Self {
         Self {
             max_file_size: max_file_size * 1024,
+            revalidate: false,
+            compression: false,
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-gzip",
+                feature = "compression-brotli",
+                feature = "compression-zstd",
+                feature = "compression-deflate"
+            ))]
+            compression_policy: None,
+            include: None,
+            exclude: None,
+            preload_wait: false,
+            purge_token: None,
+            roots: Arc::new([]),
+        }
+    }
+
+    /// Whether the response for the given request path may be cached.
+    #[inline]
+    pub fn is_cacheable(&self, uri_path: &str) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|set| set.is_match(uri_path))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|set| set.is_match(uri_path))
+    }
+
+    /// Creates the variant slots of a new entry served at `uri_path`, none
+    /// when compression is disabled.
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    pub(crate) fn variants(
+        &self,
+        uri_path: &str,
+        content_type: &HeaderValue,
+        len: usize,
+    ) -> Variants {
+        if !self.compression {
+            return Variants::default();
         }
+        let policy = self
+            .compression_policy
+            .as_deref()
+            .unwrap_or(&compression::DEFAULT_POLICY);
+        Variants::new(policy, uri_path, content_type, len)
     }
 }
 
@@ -82,32 +230,102 @@ pub fn init(handler_opts: &mut RequestHandlerOpts) -> Result {
         let capacity = opts.capacity.unwrap_or(DEFAULT_CAPACITY).min(MAX_CAPACITY);
         let ttl = opts.ttl.unwrap_or(DEFAULT_TTL).min(MAX_TTL);
         let tti = opts.tti.unwrap_or(DEFAULT_TTI).min(MAX_TTI);
+        // A byte budget makes memory use predictable regardless of the file
+        // sizes, so larger files are allowed up to the budget itself.
+        let max_size = opts.max_size.map(|mib| mib.saturating_mul(1024 * 1024));
+        let max_file_size_limit = match max_size {
+            Some(bytes) => (bytes / 1024).min(MAX_WEIGHTED_FILE_SIZE),
+            None => MAX_FILE_SIZE,
+        };
         let max_file_size = opts
             .max_file_size
             .unwrap_or(DEFAULT_MAX_FILE_SIZE)
-            .min(MAX_FILE_SIZE);
+            .min(max_file_size_limit);
+        let invalidation = opts.invalidation.unwrap_or_default();
 
         tracing::info!(
             enabled = true,
             capacity,
+            max_size_mib = ?opts.max_size,
             ttl_seconds = ttl,
             tti_seconds = tti,
             max_file_size_kib = max_file_size,
+            invalidation = ?invalidation,
             "in-memory cache"
         );
+        if max_size.is_some() && opts.capacity.is_some() {
+            tracing::warn!(
+                "in-memory cache `max-size` is set, so the `capacity` entries limit is ignored"
+            );
+        }
 
-        let mem_opts = MemCacheOpts::new(max_file_size);
-
-        let cache = Cache::builder()
-            .max_capacity(capacity)
-            .time_to_live(Duration::from_secs(ttl))
-            .time_to_idle(Duration::from_secs(tti))
-            .build();
+        let mut mem_opts = MemCacheOpts::new(max_file_size);
+        mem_opts.compression = handler_opts.compression;
+        #[cfg(any(
+            feature = "compression",
+            feature = "compression-gzip",
+            feature = "compression-brotli",
+            feature = "compression-zstd",
+            feature = "compression-deflate"
+        ))]
+        {
+            mem_opts.compression_policy = handler_opts.compression_policy.clone();
+        }
+        mem_opts.include = build_globset(opts.include.as_deref())
+            .with_context(|| "invalid in-memory cache `include` pattern")?;
+        mem_opts.exclude = build_globset(opts.exclude.as_deref())
+            .with_context(|| "invalid in-memory cache `exclude` pattern")?;
 
+        let cache = build_store(ttl, tti, capacity, max_size);
         if CACHE_STORE.set(cache).is_err() {
             tracing::debug!("in-memory cache store already initialized; reusing existing store");
         }
 
+        mem_opts.roots = cache_roots(handler_opts, advanced_opts).into();
+
+        mem_opts.revalidate = match invalidation {
+            MemoryCacheInvalidation::None => false,
+            MemoryCacheInvalidation::Revalidate => true,
+            MemoryCacheInvalidation::Watch => match watcher::spawn(&mem_opts.roots) {
+                Ok(()) => false,
+                Err(err) => {
+                    tracing::warn!(
+                        "in-memory cache file watcher unavailable, revalidating entries on every hit instead: {err:#}"
+                    );
+                    true
+                }
+            },
+        };
+
+        if let Some(patterns) = build_globset(opts.preload.as_deref())
+            .with_context(|| "invalid in-memory cache `preload` pattern")?
+        {
+            mem_opts.preload_wait = opts.preload_wait.unwrap_or(false);
+            tracing::info!(
+                patterns = ?opts.preload,
+                wait = mem_opts.preload_wait,
+                "in-memory cache preload"
+            );
+            let preload = Preload::new(
+                patterns,
+                &mem_opts,
+                handler_opts.etag,
+                handler_opts.etag_strong,
+            );
+            preload::spawn(mem_opts.roots.clone(), preload)?;
+        }
+
+        mem_opts.purge_token = opts.purge_token.clone().filter(|token| {
+            if token.is_empty() {
+                tracing::warn!("in-memory cache `purge-token` is empty, purge endpoint disabled");
+            }
+            !token.is_empty()
+        });
+        tracing::info!(
+            enabled = mem_opts.purge_token.is_some(),
+            "in-memory cache purge endpoint"
+        );
+
         handler_opts.memory_cache = Some(mem_opts);
 
         return Ok(());
@@ -118,34 +336,248 @@ pub fn init(handler_opts: &mut RequestHandlerOpts) -> Result {
     Ok(())
 }
 
+/// Root directories whose files may be cached, with the policies of the
+/// general root, the virtual hosts and the mounts.
+fn cache_roots(handler_opts: &RequestHandlerOpts, advanced_opts: &Advanced) -> Vec<CacheRoot> {
+    let mut roots = vec![CacheRoot::new(
+        handler_opts.root_dir.clone(),
+        "",
+        handler_opts,
+    )];
+    if let Some(vhosts) = advanced_opts.virtual_hosts.as_ref() {
+        roots.extend(vhosts.iter().map(|vhost| {
+            CacheRoot {
+                dir: vhost.root.clone(),
+                uri_prefix: String::new(),
+                hosts: false,
+                include_hidden: vhost.include_hidden.unwrap_or(handler_opts.include_hidden),
+                follow_symlinks: vhost
+                    .follow_symlinks
+                    .unwrap_or(handler_opts.follow_symlinks),
+            }
+        }));
+    }
+    if let Some(mass) = advanced_opts.mass_virtual_hosts.as_ref() {
+        // Cached file paths are below the canonical host directories
+        let canonical = |dir: &PathBuf| dir.canonicalize().unwrap_or_else(|_| dir.clone());
+        roots.push(CacheRoot {
+            hosts: true,
+            ..CacheRoot::new(canonical(&mass.root), "", handler_opts)
+        });
+        roots.extend(
+            mass.fallback
+                .as_ref()
+                .map(|fallback| CacheRoot::new(canonical(fallback), "", handler_opts)),
+        );
+    }
+    if let Some(mounts) = handler_opts.mounts.as_deref() {
+        roots.extend(mounts.iter().map(|mount| CacheRoot {
+            dir: mount.root.clone(),
+            uri_prefix: mount.prefix.clone(),
+            hosts: false,
+            include_hidden: mount.include_hidden,
+            follow_symlinks: mount.follow_symlinks,
+        }));
+    }
+    roots
+}
+
+/// Builds the cache store, bounded by a byte budget if `max_size` is set
+/// or by a number of entries otherwise.
+fn build_store(
+    ttl: u64,
+    tti: u64,
+    capacity: u64,
+    max_size: Option<u64>,
+) -> Cache<CompactString, Arc<MemFile>> {
+    let builder = Cache::builder()
+        .time_to_live(Duration::from_secs(ttl))
+        .time_to_idle(Duration::from_secs(tti));
+    #[cfg(feature = "metrics")]
+    let builder = builder.eviction_listener(|_, _, cause| {
+        // Entries replaced by a newer revision of their file are not evicted
+        let cause = match cause {
+            RemovalCause::Size => "size",
+            RemovalCause::Expired => "expired",
+            RemovalCause::Explicit => "explicit",
+            RemovalCause::Replaced => return,
+        };
+        metrics::inc_memory_cache_evictions(cause);
+    });
+    match max_size {
+        Some(bytes) => builder
+            .weigher(|key: &CompactString, file: &Arc<MemFile>| file.weight(key))
+            .max_capacity(bytes)
+            .build(),
+        None => builder.max_capacity(capacity).build(),
+    }
+}
+
+/// Compiles a list of glob patterns into a single matcher.
+/// Returns `None` when the list is absent or empty.
+fn build_globset(patterns: Option<&[String]>) -> Result<Option<GlobSet>> {
+    let Some(patterns) = patterns.filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).with_context(|| format!("can not compile glob `{pattern}`"))?;
+        builder.add(glob);
+    }
+    Ok(Some(builder.build()?))
+}
+
 /// Try to get a cached response for the given file path.
 ///
 /// Returns `Some(result)` on a cache hit (the result itself may be an error
 /// status, e.g. for a malformed `Range` header) or `None` when the cache is
 /// disabled, the path is non-UTF-8, or there is no entry yet (cache miss).
 ///
+/// Request paths not cacheable under the `include` and `exclude` rules are
+/// never served from the cache, even if the same file was stored (or
+/// preloaded) for another request path.
+///
+/// When `opts.revalidate` is set, the file metadata is compared against the
+/// cached entry first and a stale entry is evicted and reported as a miss.
+///
+/// The `admit` check receives whether the entry was stored while following
+/// symbolic links and reports an entry not served under the request policies
+/// as a miss, so that the regular pipeline handles it.
+///
+/// When `opts.compression` is set, `GET` requests for which the compression
+/// policy selects `br`, `zstd` or `gzip` are served from the matching encoded
+/// variant of the entry.
+/// If that variant is not cached yet, the identity response is tagged so
+/// that the compression stage fills it (see [`variants::tee`]).
+///
 /// The caller is responsible for reading the file from disk on a miss and
 /// inserting it into the cache via the streaming pipeline. There is no
-/// single-flight serialization: mini-moka's `Cache` is concurrency-safe and
+/// single-flight serialization: moka's `Cache` is concurrency-safe and
 /// duplicate inserts under contention are benign and rare in practice.
 pub(crate) fn lookup(
     file_path: &Path,
+    uri_path: &str,
+    method: &Method,
     headers_opt: &HeaderMap,
+    opts: &MemCacheOpts,
+    admit: impl FnOnce(bool) -> bool,
 ) -> Option<Result<Response<Body>, StatusCode>> {
+    if !opts.is_cacheable(uri_path) {
+        return None;
+    }
     let file_path_str = file_path.to_str()?;
     let store = CACHE_STORE.get()?;
     let key = CompactString::from(file_path_str);
-    let mem_file = store.get(&key)?;
+    let Some(mem_file) = store
+        .get(&key)
+        .filter(|mem_file| admit(mem_file.followed_symlinks))
+    else {
+        #[cfg(feature = "metrics")]
+        metrics::inc_memory_cache_misses();
+        return None;
+    };
+    if opts.revalidate && !mem_file.is_fresh(file_path) {
+        tracing::debug!("file `{file_path_str}` changed on disk, evicting in-memory cache entry");
+        store.invalidate(&key);
+        #[cfg(feature = "metrics")]
+        {
+            metrics::inc_memory_cache_purges("changed", 1);
+            metrics::inc_memory_cache_misses();
+        }
+        return None;
+    }
+    #[cfg(feature = "metrics")]
+    metrics::inc_memory_cache_hits();
     tracing::debug!("file `{file_path_str}` served from the in-memory cache store");
+
+    let resp = encoded_response(&key, &mem_file, uri_path, method, headers_opt, opts)
+        .unwrap_or_else(|| mem_file.response_body(headers_opt));
+
     // Tag the response with `X-Cache: HIT` so clients and tooling can
     // identify that it was served from the in-memory cache.
-    Some(mem_file.response_body(headers_opt).map(|mut resp| {
+    Some(resp.map(|mut resp| {
         resp.headers_mut()
             .insert(X_CACHE.clone(), X_CACHE_HIT.clone());
         resp
     }))
 }
 
+/// Builds the response for a cache hit negotiating a cached encoding.
+///
+/// Returns `None` when the identity body must be served untouched, i.e.
+/// compression is disabled, the request is not a `GET`, the client accepts
+/// no cached encoding or the entry is not compressible.
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+))]
+fn encoded_response(
+    key: &CompactString,
+    mem_file: &Arc<MemFile>,
+    uri_path: &str,
+    method: &Method,
+    headers: &HeaderMap,
+    opts: &MemCacheOpts,
+) -> Option<Result<Response<Body>, StatusCode>> {
+    if !opts.compression || !method.is_get() {
+        return None;
+    }
+    let policy = opts
+        .compression_policy
+        .as_deref()
+        .unwrap_or(&compression::DEFAULT_POLICY);
+    let mime = mem_file.content_type.to_str().ok()?.parse().ok();
+    let len = Some(mem_file.data.len() as u64);
+    let coding = policy.select(uri_path, headers, mime.as_ref(), len)?;
+    let slot = mem_file.variants.slot(coding)?;
+
+    if let Some(variant) = slot.get() {
+        tracing::trace!("serving cached `{}` variant", coding.as_str());
+        return Some(mem_file.encoded_response_body(coding, variant, headers));
+    }
+
+    // Only full responses can fill the variant slot.
+    let etag = mem_file
+        .etag
+        .as_ref()
+        .and_then(|etag| crate::etag::encoded(etag, coding.as_str()));
+    Some(mem_file.response_body(headers).map(|mut resp| {
+        if resp.status() == StatusCode::OK {
+            resp.extensions_mut().insert(PendingVariant::new(
+                key.clone(),
+                mem_file.clone(),
+                coding,
+                etag,
+            ));
+        }
+        resp
+    }))
+}
+
+/// Without any compression feature the identity body is always served.
+#[cfg(not(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+)))]
+#[inline(always)]
+fn encoded_response(
+    _key: &CompactString,
+    _mem_file: &Arc<MemFile>,
+    _uri_path: &str,
+    _method: &Method,
+    _headers: &HeaderMap,
+    _opts: &MemCacheOpts,
+) -> Option<Result<Response<Body>, StatusCode>> {
+    None
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct MemFileTempOpts {
     pub(crate) file_path: String,
@@ -157,6 +589,19 @@ pub(crate) struct MemFileTempOpts {
     /// Pre-built weak `ETag` value. Built once on the disk path and
     /// reused on every cache hit (refcount-clone only).
     pub(crate) etag: Option<HeaderValue>,
+    /// Exact file modification time used to revalidate the entry.
+    pub(crate) modified: Option<SystemTime>,
+    /// Whether the file was served while following symbolic links.
+    pub(crate) followed_symlinks: bool,
+    /// Content-encoded variant slots of the entry.
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    pub(crate) variants: Variants,
 }
 
 impl MemFileTempOpts {
@@ -165,14 +610,39 @@ impl MemFileTempOpts {
         content_type: HeaderValue,
         last_modified: Option<LastModified>,
         etag: Option<HeaderValue>,
+        modified: Option<SystemTime>,
+        followed_symlinks: bool,
     ) -> Self {
         Self {
             file_path,
             content_type,
             last_modified,
             etag,
+            modified,
+            followed_symlinks,
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-gzip",
+                feature = "compression-brotli",
+                feature = "compression-zstd",
+                feature = "compression-deflate"
+            ))]
+            variants: Variants::default(),
         }
     }
+
+    /// Sets the content-encoded variant slots of the entry.
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    pub(crate) fn with_variants(mut self, variants: Variants) -> Self {
+        self.variants = variants;
+        self
+    }
 }
 
 /// In-memory file representation to be stored in the cache.
@@ -194,6 +664,22 @@ pub(crate) struct MemFile {
     /// present it is both emitted on the response and used for
     /// `If-None-Match` / `If-Match` / `If-Range` evaluation.
     etag: Option<HeaderValue>,
+    /// Exact modification time of the file when it was cached.
+    modified: Option<SystemTime>,
+    /// Whether the file was cached while following symbolic links, so its
+    /// path may go through them.
+    followed_symlinks: bool,
+    /// Integrity digests of the cached bytes, computed on demand.
+    digests: Arc<Digests>,
+    /// Lazily filled content-encoded variants of the file.
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    variants: Variants,
 }
 
 impl MemFile {
@@ -203,12 +689,89 @@ impl MemFile {
         content_type: HeaderValue,
         last_modified: Option<LastModified>,
         etag: Option<HeaderValue>,
+        modified: Option<SystemTime>,
+        followed_symlinks: bool,
     ) -> Self {
         Self {
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-gzip",
+                feature = "compression-brotli",
+                feature = "compression-zstd",
+                feature = "compression-deflate"
+            ))]
+            variants: Variants::default(),
             data,
             content_type,
             last_modified,
             etag,
+            modified,
+            followed_symlinks,
+            digests: Arc::default(),
+        }
+    }
+
+    /// Sets the content-encoded variant slots of the file, see
+    /// [`MemCacheOpts::variants`].
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    pub(crate) fn with_variants(mut self, variants: Variants) -> Self {
+        self.variants = variants;
+        self
+    }
+
+    /// Content-encoded variants of the file.
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    #[inline]
+    pub(crate) fn variants(&self) -> &Variants {
+        &self.variants
+    }
+
+    /// Weight of the entry in bytes for a byte-bounded store: its key, its
+    /// body and the encoded variants filled so far.
+    pub(crate) fn weight(&self, key: &CompactString) -> u32 {
+        #[cfg(any(
+            feature = "compression",
+            feature = "compression-gzip",
+            feature = "compression-brotli",
+            feature = "compression-zstd",
+            feature = "compression-deflate"
+        ))]
+        let variants_len = self.variants.len();
+        #[cfg(not(any(
+            feature = "compression",
+            feature = "compression-gzip",
+            feature = "compression-brotli",
+            feature = "compression-zstd",
+            feature = "compression-deflate"
+        )))]
+        let variants_len = 0;
+
+        let bytes = key.len() + self.data.len() + variants_len;
+        u32::try_from(bytes).unwrap_or(u32::MAX)
+    }
+
+    /// Whether the file on disk still matches the cached entry.
+    ///
+    /// Costs a single `stat(2)` syscall. A file that can not be read
+    /// anymore (e.g. removed) is considered stale.
+    pub(crate) fn is_fresh(&self, file_path: &Path) -> bool {
+        match std::fs::metadata(file_path) {
+            Ok(meta) => {
+                meta.len() == self.data.len() as u64 && meta.modified().ok() == self.modified
+            }
+            Err(_) => false,
         }
     }
 
@@ -220,6 +783,53 @@ impl MemFile {
     /// on the hot path; the response body is a single data frame, not a
     /// chunked stream.
     pub(crate) fn response_body(&self, headers: &HeaderMap) -> Result<Response<Body>, StatusCode> {
+        let mut resp = self.build_response(&self.data, self.etag.as_ref(), headers, MAX_RANGES)?;
+        digest::attach(&mut resp, headers, || {
+            Representation::Bytes(self.data.clone(), self.digests.clone())
+        });
+        Ok(resp)
+    }
+
+    /// Build a response for a cache hit served from an encoded variant.
+    ///
+    /// Ranges apply to the encoded bytes, and `Content-Encoding` is set so
+    /// that the compression stage leaves the response untouched. Requests
+    /// for several ranges get the full content since `Content-Encoding`
+    /// would otherwise apply to the `multipart/byteranges` body.
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    fn encoded_response_body(
+        &self,
+        coding: ContentCoding,
+        variant: &EncodedVariant,
+        headers: &HeaderMap,
+    ) -> Result<Response<Body>, StatusCode> {
+        let mut resp = self.build_response(&variant.data, variant.etag.as_ref(), headers, 1)?;
+        if matches!(resp.status(), StatusCode::OK | StatusCode::PARTIAL_CONTENT) {
+            resp.headers_mut()
+                .insert(hyper::header::CONTENT_ENCODING, coding.into());
+        }
+        digest::attach(&mut resp, headers, || {
+            Representation::Bytes(variant.data.clone(), variant.digests.clone())
+        });
+        append_vary_accept_encoding(&mut resp);
+        Ok(resp)
+    }
+
+    /// Build a response for the given representation of the file, serving
+    /// up to `max_ranges` ranges.
+    fn build_response(
+        &self,
+        data: &Bytes,
+        etag: Option<&HeaderValue>,
+        headers: &HeaderMap,
+        max_ranges: usize,
+    ) -> Result<Response<Body>, StatusCode> {
         let conditionals = ConditionalHeaders::new(headers);
         let modified = self.last_modified;
 
@@ -230,9 +840,7 @@ impl MemFile {
             || conditionals.if_match.is_some()
             || conditionals.if_range.is_some()
         {
-            self.etag
-                .as_ref()
-                .and_then(|hv| hv.to_str().ok().and_then(|s| s.parse().ok()))
+            etag.and_then(|hv| hv.to_str().ok().and_then(|s| s.parse().ok()))
         } else {
             None
         };
@@ -240,16 +848,45 @@ impl MemFile {
         let validators = Validators {
             last_modified: modified,
             etag: etag_typed.as_ref(),
-            etag_value: self.etag.as_ref(),
+            etag_value: etag,
         };
 
         match conditionals.check(validators) {
             ConditionalBody::NoBody(resp) => Ok(resp),
             ConditionalBody::WithBody(range) => {
-                let total_len = self.data.len() as u64;
+                let total_len = data.len() as u64;
 
-                bytes_range(range, total_len)
-                    .map(|(start, end)| {
+                bytes_ranges(range, total_len, max_ranges)
+                    .map(|ranges| {
+                        let (start, end) = match ranges[..] {
+                            [range] => range,
+                            _ => {
+                                let multipart =
+                                    Multipart::new(&ranges, total_len, &self.content_type);
+                                let mut resp = multipart.response(|segments| {
+                                    // Zero-copy parts sliced from the cached bytes
+                                    let chunks: Vec<std::io::Result<Bytes>> = segments
+                                        .into_iter()
+                                        .map(|segment| match segment {
+                                            Segment::Bytes(bytes) => Ok(bytes),
+                                            Segment::Range(start, end) => {
+                                                Ok(data.slice(start as usize..end as usize))
+                                            }
+                                        })
+                                        .collect();
+                                    body::stream(futures_util::stream::iter(chunks))
+                                });
+                                let h = resp.headers_mut();
+                                h.typed_insert(AcceptRanges::bytes());
+                                if let Some(last_modified) = modified {
+                                    h.typed_insert(last_modified);
+                                }
+                                if let Some(etag) = etag {
+                                    h.insert(ETAG, etag.clone());
+                                }
+                                return Ok(resp);
+                            }
+                        };
                         let sub_len = end - start;
                         let is_partial = sub_len != total_len;
 
@@ -257,9 +894,9 @@ impl MemFile {
                         // `Bytes` (refcount bump); for a range we use
                         // `Bytes::slice` (O(1), shared buffer).
                         let body_bytes = if is_partial {
-                            self.data.slice(start as usize..end as usize)
+                            data.slice(start as usize..end as usize)
                         } else {
-                            self.data.clone()
+                            data.clone()
                         };
                         let mut resp = Response::new(body::full(body_bytes));
 
@@ -290,7 +927,7 @@ impl MemFile {
                         if let Some(last_modified) = modified {
                             h.typed_insert(last_modified);
                         }
-                        if let Some(etag) = self.etag.as_ref() {
+                        if let Some(etag) = etag {
                             h.insert(ETAG, etag.clone());
                         }
 
@@ -369,7 +1006,17 @@ mod tests {
                 rewrites: None,
                 redirects: None,
                 virtual_hosts: None,
+                unknown_host_status: None,
+                mass_virtual_hosts: None,
+                mounts: None,
                 memory_cache: None,
+                compression_cache: None,
+                cache_control: None,
+                compression_dictionaries: None,
+                compression: None,
+                redirect_maps: None,
+                proxy: None,
+                language_negotiation: None,
             }),
             ..Default::default()
         };
@@ -386,12 +1033,29 @@ mod tests {
                 rewrites: None,
                 redirects: None,
                 virtual_hosts: None,
+                unknown_host_status: None,
+                mass_virtual_hosts: None,
+                mounts: None,
                 memory_cache: Some(crate::settings::file::MemoryCache {
                     capacity: None,
+                    max_size: None,
                     ttl: None,
                     tti: None,
                     max_file_size: None,
+                    invalidation: None,
+                    include: None,
+                    exclude: None,
+                    preload: None,
+                    preload_wait: None,
+                    purge_token: None,
                 }),
+                compression_cache: None,
+                cache_control: None,
+                compression_dictionaries: None,
+                compression: None,
+                redirect_maps: None,
+                proxy: None,
+                language_negotiation: None,
             }),
             ..Default::default()
         };
@@ -402,6 +1066,43 @@ mod tests {
         assert_eq!(opts.max_file_size, DEFAULT_MAX_FILE_SIZE * 1024);
     }
 
+    #[test]
+    fn weighted_file_size_limit_fits_the_weigher() {
+        const {
+            assert!(MAX_WEIGHTED_FILE_SIZE >= MAX_FILE_SIZE);
+            assert!(MAX_WEIGHTED_FILE_SIZE * 1024 < u32::MAX as u64);
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn store_evictions_are_counted_by_cause() {
+        let entry = || {
+            Arc::new(MemFile::new(
+                Bytes::from_static(b"hello"),
+                HeaderValue::from_static("text/plain"),
+                None,
+                None,
+                None,
+                false,
+            ))
+        };
+        let size = metrics::memory_cache_evictions("size");
+        let explicit = metrics::memory_cache_evictions("explicit");
+
+        let store = build_store(60, 60, 1, None);
+        store.insert("/a".into(), entry());
+        store.insert("/a".into(), entry());
+        store.run_pending_tasks();
+        store.insert("/b".into(), entry());
+        store.run_pending_tasks();
+        assert!(metrics::memory_cache_evictions("size") > size);
+
+        store.invalidate_all();
+        store.run_pending_tasks();
+        assert!(metrics::memory_cache_evictions("explicit") > explicit);
+    }
+
     #[test]
     fn init_clamps_values_to_max() {
         // We can't call init() twice due to OnceLock, so test the clamping
@@ -432,10 +1133,69 @@ mod tests {
         // tests context. If another test in this module ever initializes the
         // global store, this assertion becomes a hit/miss check instead.
         if CACHE_STORE.get().is_none() {
-            assert!(lookup(path, &headers).is_none());
+            let opts = MemCacheOpts::new(DEFAULT_MAX_FILE_SIZE);
+            assert!(lookup(path, "/path.txt", &Method::GET, &headers, &opts, |_| true).is_none());
         }
     }
 
+    #[test]
+    fn mem_file_is_fresh_until_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.txt");
+        std::fs::write(&path, b"abc").unwrap();
+        let meta = std::fs::metadata(&path).unwrap();
+        let mem_file = MemFile::new(
+            Bytes::from_static(b"abc"),
+            HeaderValue::from_static("text/plain"),
+            None,
+            None,
+            meta.modified().ok(),
+            false,
+        );
+        assert!(mem_file.is_fresh(&path));
+
+        std::fs::write(&path, b"abcd").unwrap();
+        assert!(!mem_file.is_fresh(&path));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!mem_file.is_fresh(&path));
+    }
+
+    #[test]
+    fn is_cacheable_applies_include_and_exclude_rules() {
+        let mut opts = MemCacheOpts::new(DEFAULT_MAX_FILE_SIZE);
+        assert!(opts.is_cacheable("/anything.bin"));
+
+        let include = vec!["/assets/**".to_owned(), "*.html".to_owned()];
+        let exclude = vec!["/assets/video/**".to_owned()];
+        opts.include = build_globset(Some(&include)).unwrap();
+        opts.exclude = build_globset(Some(&exclude)).unwrap();
+        assert!(opts.is_cacheable("/assets/app.js"));
+        assert!(opts.is_cacheable("/docs/index.html"));
+        assert!(!opts.is_cacheable("/assets/video/intro.mp4"));
+        assert!(!opts.is_cacheable("/downloads/archive.zip"));
+    }
+
+    #[test]
+    fn build_globset_rejects_invalid_patterns() {
+        assert!(build_globset(None).unwrap().is_none());
+        assert!(build_globset(Some(&[])).unwrap().is_none());
+        assert!(build_globset(Some(&["/assets/[".to_owned()])).is_err());
+    }
+
+    #[test]
+    fn mem_file_weight_counts_key_and_body() {
+        let mem_file = MemFile::new(
+            Bytes::from_static(b"0123456789"),
+            HeaderValue::from_static("application/octet-stream"),
+            None,
+            None,
+            None,
+            false,
+        );
+        assert_eq!(mem_file.weight(&CompactString::from("/srv/a.bin")), 20);
+    }
+
     #[test]
     fn x_cache_header_constants_are_valid() {
         assert_eq!(X_CACHE.as_str(), "x-cache");
@@ -6,8 +6,25 @@
 //! In-memory file cache with LFU admission and LRU eviction.
 //!
 //! Provides file-level caching with configurable capacity, TTL, and TTI.
+//! Compressible entries also keep their `br`, `zstd` and `gzip` encodings,
+//! filled lazily as clients negotiate them.
+//! Entries can be evicted when their files change on disk, either through
+//! a file system watcher or by revalidating the file metadata on each hit,
+//! when the `invalidation` setting opts in.
+//! An optional token-protected endpoint purges entries on demand.
 //! Enabled via the `mem-cache` Cargo feature and configured through the
 //! `[advanced.memory-cache]` TOML section.
 
 pub mod cache;
+pub(crate) mod preload;
+pub(crate) mod purge;
 pub(crate) mod stream;
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-gzip",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+    feature = "compression-deflate"
+))]
+pub(crate) mod variants;
+pub(crate) mod watcher;
@@ -0,0 +1,416 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Warm-up of the in-memory cache store at startup.
+//!
+//! The root directories are walked once on a background thread and every
+//! file whose request path matches one of the `preload` globs is read into
+//! the store, so the first visitors after a restart do not pay the
+//! cold-cache latency. The walk of each root applies the hidden-file,
+//! symlink and containment policies its files are served with.
+
+use bytes::Bytes;
+use globset::GlobSet;
+use headers::LastModified;
+use std::collections::HashSet;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, UNIX_EPOCH};
+
+use crate::mem_cache::cache::{CACHE_STORE, CacheRoot, MemCacheOpts, MemFile};
+use crate::response::content_type_for;
+use crate::{Context, Result};
+
+/// Whether a warm-up is still in progress.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the startup warm-up is still in progress.
+#[inline]
+pub(crate) fn is_running() -> bool {
+    RUNNING.load(Ordering::Acquire)
+}
+
+/// Options of a warm-up run.
+#[derive(Clone)]
+pub(crate) struct Preload {
+    /// Request paths to preload.
+    pub(crate) patterns: GlobSet,
+    /// Options of the in-memory cache the files are loaded into.
+    pub(crate) mem_opts: MemCacheOpts,
+    /// Whether to store an `ETag` along with each entry.
+    pub(crate) etag: bool,
+    /// Whether the stored `ETag` is a strong content hash.
+    pub(crate) etag_strong: bool,
+}
+
+/// Totals of a warm-up run.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Totals {
+    files: u64,
+    bytes: u64,
+}
+
+/// Starts warming up the cache store from the given root directories on a
+/// background thread.
+pub(crate) fn spawn(roots: Arc<[CacheRoot]>, preload: Preload) -> Result {
+    RUNNING.store(true, Ordering::Release);
+    std::thread::Builder::new()
+        .name("sws-cache-preload".into())
+        .spawn(move || {
+            let started = Instant::now();
+            let mut totals = Totals::default();
+            for root in roots.iter() {
+                if root.hosts {
+                    for site in host_roots(root) {
+                        preload.walk(&site, &mut totals);
+                    }
+                } else {
+                    preload.walk(root, &mut totals);
+                }
+            }
+            RUNNING.store(false, Ordering::Release);
+            tracing::info!(
+                files = totals.files,
+                bytes = totals.bytes,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "in-memory cache preloaded"
+            );
+        })
+        .with_context(|| "unable to spawn the in-memory cache preload thread")
+        .inspect_err(|_| RUNNING.store(false, Ordering::Release))?;
+    Ok(())
+}
+
+impl Preload {
+    /// Builds the warm-up options from the in-memory cache options.
+    pub(crate) fn new(
+        patterns: GlobSet,
+        mem_opts: &MemCacheOpts,
+        etag: bool,
+        etag_strong: bool,
+    ) -> Self {
+        Self {
+            patterns,
+            mem_opts: mem_opts.clone(),
+            etag,
+            etag_strong,
+        }
+    }
+
+    /// Walks a root directory and loads the matching files into the store.
+    fn walk(&self, root: &CacheRoot, totals: &mut Totals) {
+        let Some(store) = CACHE_STORE.get() else {
+            return;
+        };
+        let canonical_root = match root.dir.canonicalize() {
+            Ok(path) => path,
+            Err(err) => {
+                tracing::warn!(
+                    "unable to preload directory `{}` into the in-memory cache: {err}",
+                    root.dir.display()
+                );
+                return;
+            }
+        };
+
+        let mut visited = HashSet::from([canonical_root.clone()]);
+        let mut pending = vec![(root.dir.clone(), root.uri_prefix.clone())];
+
+        while let Some((dir, uri_dir)) = pending.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    tracing::debug!("unable to read directory `{}`: {err}", dir.display());
+                    continue;
+                }
+            };
+
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let Some(name) = name.to_str() else {
+                    continue;
+                };
+                if !root.include_hidden && name.starts_with('.') {
+                    continue;
+                }
+                let path = entry.path();
+                let Some(meta) =
+                    entry_metadata(&path, &canonical_root, root.follow_symlinks, &mut visited)
+                else {
+                    continue;
+                };
+                let uri_path = [uri_dir.as_str(), "/", name].concat();
+
+                if meta.is_dir() {
+                    pending.push((path, uri_path));
+                } else if meta.is_file() && self.is_eligible(&uri_path, &meta) {
+                    let Some(key) = path.to_str() else {
+                        continue;
+                    };
+                    match self.load(&path, &uri_path, &meta, root.follow_symlinks) {
+                        Ok(mem_file) => {
+                            totals.files += 1;
+                            totals.bytes += meta.len();
+                            store.insert(key.into(), Arc::new(mem_file));
+                        }
+                        Err(err) => {
+                            tracing::debug!("unable to preload file `{key}`: {err}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a file must be preloaded.
+    fn is_eligible(&self, uri_path: &str, meta: &Metadata) -> bool {
+        meta.len() <= self.mem_opts.max_file_size
+            && self.patterns.is_match(uri_path)
+            && self.mem_opts.is_cacheable(uri_path)
+    }
+
+    /// Reads a file into a new cache entry.
+    #[cfg_attr(
+        not(any(
+            feature = "compression",
+            feature = "compression-gzip",
+            feature = "compression-brotli",
+            feature = "compression-zstd",
+            feature = "compression-deflate"
+        )),
+        allow(unused_variables)
+    )]
+    fn load(
+        &self,
+        path: &Path,
+        uri_path: &str,
+        meta: &Metadata,
+        followed_symlinks: bool,
+    ) -> std::io::Result<MemFile> {
+        let data = Bytes::from(fs::read(path)?);
+        let last_modified = meta
+            .modified()
+            .ok()
+            .filter(|&t| t != UNIX_EPOCH)
+            .map(LastModified::from);
+        let etag = match (self.etag, self.etag_strong) {
+            (true, true) => Some(crate::etag::build_strong_from_bytes(path, meta, &data)),
+            (true, false) => crate::etag::build_from_meta(meta).map(|(_, value)| value),
+            (false, _) => None,
+        };
+        let content_type = content_type_for(path);
+        #[cfg(any(
+            feature = "compression",
+            feature = "compression-gzip",
+            feature = "compression-brotli",
+            feature = "compression-zstd",
+            feature = "compression-deflate"
+        ))]
+        let variants = self.mem_opts.variants(uri_path, &content_type, data.len());
+        let mem_file = MemFile::new(
+            data,
+            content_type,
+            last_modified,
+            etag,
+            meta.modified().ok(),
+            followed_symlinks,
+        );
+        #[cfg(any(
+            feature = "compression",
+            feature = "compression-gzip",
+            feature = "compression-brotli",
+            feature = "compression-zstd",
+            feature = "compression-deflate"
+        ))]
+        let mem_file = mem_file.with_variants(variants);
+        Ok(mem_file)
+    }
+}
+
+/// Returns the root directories of the hosts of a mass virtual hosting root,
+/// skipping the symlinked ones unless symbolic links are followed.
+fn host_roots(root: &CacheRoot) -> Vec<CacheRoot> {
+    let entries = match fs::read_dir(&root.dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            tracing::warn!(
+                "unable to preload directory `{}` into the in-memory cache: {err}",
+                root.dir.display()
+            );
+            return Vec::new();
+        }
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let is_symlink = entry.file_type().is_ok_and(|kind| kind.is_symlink());
+            !entry.file_name().to_string_lossy().starts_with('.')
+                && (root.follow_symlinks || !is_symlink)
+                && entry.path().is_dir()
+        })
+        .map(|entry| {
+            // Cached file paths are below the canonical host directories
+            let dir = entry.path();
+            CacheRoot {
+                dir: dir.canonicalize().unwrap_or(dir),
+                uri_prefix: String::new(),
+                hosts: false,
+                ..root.clone()
+            }
+        })
+        .collect()
+}
+
+/// Returns the metadata of a directory entry, following symbolic links
+/// only when allowed and only when they resolve inside the root.
+///
+/// Directories reached through symbolic links are visited once, which
+/// guards against link cycles.
+fn entry_metadata(
+    path: &Path,
+    canonical_root: &Path,
+    follow_symlinks: bool,
+    visited: &mut HashSet<PathBuf>,
+) -> Option<Metadata> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    if !meta.file_type().is_symlink() {
+        return Some(meta);
+    }
+    if !follow_symlinks {
+        return None;
+    }
+
+    let target = path.canonicalize().ok()?;
+    if !target.starts_with(canonical_root) {
+        tracing::debug!(
+            "skipping symbolic link `{}` pointing outside of the root",
+            path.display()
+        );
+        return None;
+    }
+    let meta = fs::metadata(&target).ok()?;
+    if meta.is_dir() && !visited.insert(target) {
+        return None;
+    }
+    Some(meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globset::{Glob, GlobSetBuilder};
+
+    fn globset(patterns: &[&str]) -> GlobSet {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern).unwrap());
+        }
+        builder.build().unwrap()
+    }
+
+    fn preload(patterns: &[&str]) -> Preload {
+        Preload {
+            patterns: globset(patterns),
+            mem_opts: MemCacheOpts::new(1),
+            etag: true,
+            etag_strong: false,
+        }
+    }
+
+    fn meta_of_len(dir: &Path, len: usize) -> Metadata {
+        let path = dir.join(format!("file-{len}"));
+        fs::write(&path, vec![b'x'; len]).unwrap();
+        fs::metadata(path).unwrap()
+    }
+
+    #[test]
+    fn eligibility_applies_patterns_rules_and_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = meta_of_len(dir.path(), 10);
+        let large = meta_of_len(dir.path(), 2048);
+
+        let mut opts = preload(&["/*.html", "/assets/**"]);
+        assert!(opts.is_eligible("/index.html", &small));
+        assert!(opts.is_eligible("/assets/css/main.css", &small));
+        assert!(!opts.is_eligible("/index.html", &large));
+        assert!(!opts.is_eligible("/docs/page.txt", &small));
+
+        opts.mem_opts.exclude = Some(globset(&["/assets/css/**"]));
+        assert!(!opts.is_eligible("/assets/css/main.css", &small));
+        assert!(opts.is_eligible("/assets/app.js", &small));
+    }
+
+    #[test]
+    fn load_builds_entry_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        fs::write(&path, "<h1>hi</h1>").unwrap();
+        let meta = fs::metadata(&path).unwrap();
+
+        let mem_file = preload(&["/**"])
+            .load(&path, "/index.html", &meta, false)
+            .unwrap();
+        assert!(mem_file.is_fresh(&path));
+    }
+
+    #[test]
+    fn host_roots_are_subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("example.com")).unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(root.join("README"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("example.com"), root.join("www.example.com")).unwrap();
+
+        let mut mass = CacheRoot {
+            dir: root.clone(),
+            uri_prefix: String::new(),
+            hosts: true,
+            include_hidden: false,
+            follow_symlinks: false,
+        };
+        let dirs = |mass: &CacheRoot| {
+            let mut dirs: Vec<_> = host_roots(mass).into_iter().map(|site| site.dir).collect();
+            dirs.sort();
+            dirs
+        };
+        assert_eq!(dirs(&mass), [root.join("example.com")]);
+
+        #[cfg(unix)]
+        {
+            mass.follow_symlinks = true;
+            assert_eq!(
+                dirs(&mass),
+                [root.join("example.com"), root.join("example.com")]
+            );
+        }
+    }
+
+    #[test]
+    fn symlinks_are_followed_only_when_allowed_and_contained() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(root.join("index.html"), "ok").unwrap();
+
+        let mut visited = HashSet::new();
+        assert!(entry_metadata(&root.join("index.html"), &root, false, &mut visited).is_some());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("index.html"), root.join("link.html")).unwrap();
+            assert!(entry_metadata(&root.join("link.html"), &root, false, &mut visited).is_none());
+            assert!(entry_metadata(&root.join("link.html"), &root, true, &mut visited).is_some());
+
+            let outside = tempfile::tempdir().unwrap();
+            fs::write(outside.path().join("secret.txt"), "no").unwrap();
+            std::os::unix::fs::symlink(outside.path().join("secret.txt"), root.join("out.txt"))
+                .unwrap();
+            assert!(entry_metadata(&root.join("out.txt"), &root, true, &mut visited).is_none());
+        }
+    }
+}
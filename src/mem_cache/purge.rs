@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Authenticated endpoint to purge in-memory cache entries.
+//!
+//! Enabled by setting `purge-token` under `[advanced.memory-cache]`.
+//! Deploy scripts can then send:
+//!
+//! ```text
+//! POST /_sws/cache/purge                      # every entry
+//! POST /_sws/cache/purge?prefix=/assets/      # request paths starting with a prefix
+//! POST /_sws/cache/purge?glob=/docs/**/*.html # request paths matching a glob
+//! Authorization: Bearer <purge-token>
+//! ```
+//!
+//! Entries are matched by their request path relative to the root directory
+//! (or virtual host root) they were served from, prefixed by the URL path
+//! prefix of a mount. The response reports the number of purged entries as
+//! JSON, e.g. `{"purged":12}`.
+
+use globset::{Glob, GlobMatcher};
+use headers::{ContentType, HeaderMapExt};
+use hyper::header::{AUTHORIZATION, HeaderValue, WWW_AUTHENTICATE};
+use hyper::{Method, Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::body::Body;
+use crate::error_page;
+use crate::handler::RequestHandlerOpts;
+use crate::mem_cache::cache::{CACHE_STORE, CacheRoot};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::{Error, Result};
+
+/// Request path of the purge endpoint.
+pub(crate) const PURGE_ENDPOINT: &str = "/_sws/cache/purge";
+
+/// Which entries a purge request targets.
+#[derive(Debug, Default)]
+struct Filter {
+    prefix: Option<String>,
+    glob: Option<GlobMatcher>,
+}
+
+impl Filter {
+    /// Parses the `prefix` and `glob` query parameters.
+    fn from_query(query: Option<&str>) -> Result<Self, StatusCode> {
+        let mut filter = Filter::default();
+        for (name, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match name.as_ref() {
+                "prefix" => filter.prefix = Some(value.into_owned()),
+                "glob" => {
+                    let glob = Glob::new(&value).map_err(|err| {
+                        tracing::debug!("invalid in-memory cache purge glob `{value}`: {err}");
+                        StatusCode::BAD_REQUEST
+                    })?;
+                    filter.glob = Some(glob.compile_matcher());
+                }
+                _ => {}
+            }
+        }
+        Ok(filter)
+    }
+
+    fn is_all(&self) -> bool {
+        self.prefix.is_none() && self.glob.is_none()
+    }
+
+    fn matches(&self, uri_path: &str) -> bool {
+        self.prefix
+            .as_deref()
+            .is_none_or(|prefix| uri_path.starts_with(prefix))
+            && self
+                .glob
+                .as_ref()
+                .is_none_or(|glob| glob.is_match(uri_path))
+    }
+}
+
+/// Handles purge requests.
+pub(crate) fn pre_process<T>(
+    opts: &RequestHandlerOpts,
+    req: &Request<T>,
+) -> Option<Result<Response<Body>, Error>> {
+    let token = opts.memory_cache.as_ref()?.purge_token.as_deref()?;
+    if req.uri().path() != PURGE_ENDPOINT || req.method() != Method::POST {
+        return None;
+    }
+
+    let status = if !is_authorized(req, token) {
+        tracing::warn!("in-memory cache purge request rejected: invalid or missing token");
+        StatusCode::UNAUTHORIZED
+    } else {
+        match Filter::from_query(req.uri().query()) {
+            Ok(filter) => return Some(Ok(purged_response(purge(opts, &filter)))),
+            Err(status) => status,
+        }
+    };
+
+    let mut result = error_page::error_response(
+        req.uri(),
+        req.method(),
+        &status,
+        &opts.page404,
+        &opts.page50x,
+    );
+    if status == StatusCode::UNAUTHORIZED
+        && let Ok(ref mut resp) = result
+    {
+        resp.headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    Some(result)
+}
+
+/// Whether the request carries the configured bearer token.
+fn is_authorized<T>(req: &Request<T>, token: &str) -> bool {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes()))
+}
+
+/// Compares two byte strings without short-circuiting on the first
+/// difference, so the comparison time does not leak the token contents.
+/// Both are hashed first so that it does not leak the token length either.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+    a.iter().zip(&b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Evicts the matching entries and returns how many were purged.
+fn purge(opts: &RequestHandlerOpts, filter: &Filter) -> u64 {
+    let Some(store) = CACHE_STORE.get() else {
+        return 0;
+    };
+
+    // Options built by hand lack the roots, so only the general one is known
+    let general;
+    let roots = match opts.memory_cache.as_ref() {
+        Some(mem_opts) if !mem_opts.roots.is_empty() => &mem_opts.roots[..],
+        _ => {
+            general = [CacheRoot::new(opts.root_dir.clone(), "", opts)];
+            &general[..]
+        }
+    };
+    let keys = store
+        .iter()
+        .filter(|(key, _)| filter.is_all() || matches(filter, Path::new(key.as_str()), roots))
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+
+    let purged = keys.len() as u64;
+    for key in keys {
+        store.invalidate(key.as_ref());
+    }
+
+    tracing::info!(
+        purged,
+        prefix = filter.prefix.as_deref(),
+        glob = filter.glob.as_ref().map(|glob| glob.glob().glob()),
+        "in-memory cache purged"
+    );
+    #[cfg(feature = "metrics")]
+    metrics::inc_memory_cache_purges("purged", purged);
+
+    purged
+}
+
+/// Whether a cache key (a file path) is served under a request path matched
+/// by the filter, from any of the roots containing it.
+fn matches(filter: &Filter, file_path: &Path, roots: &[CacheRoot]) -> bool {
+    roots
+        .iter()
+        .filter_map(|root| root.uri_path_of(file_path))
+        .any(|uri_path| filter.matches(&uri_path))
+}
+
+fn purged_response(purged: u64) -> Response<Body> {
+    let mut resp = Response::new(crate::body::full(format!("{{\"purged\":{purged}}}")));
+    resp.headers_mut().typed_insert(ContentType::json());
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem_cache::cache::MemCacheOpts;
+    use std::path::PathBuf;
+
+    fn handler_opts(token: Option<&str>) -> RequestHandlerOpts {
+        let mut mem_opts = MemCacheOpts::new(1024);
+        mem_opts.purge_token = token.map(str::to_owned);
+        RequestHandlerOpts {
+            root_dir: PathBuf::from("/srv/public"),
+            memory_cache: Some(mem_opts),
+            ..Default::default()
+        }
+    }
+
+    fn purge_request(uri: &str, auth: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().method("POST").uri(uri);
+        if let Some(auth) = auth {
+            builder = builder.header(AUTHORIZATION, auth);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn endpoint_is_disabled_without_token() {
+        let req = purge_request(PURGE_ENDPOINT, Some("Bearer secret"));
+        assert!(pre_process(&handler_opts(None), &req).is_none());
+    }
+
+    #[test]
+    fn other_paths_and_methods_are_ignored() {
+        let opts = handler_opts(Some("secret"));
+        let req = purge_request("/index.html", Some("Bearer secret"));
+        assert!(pre_process(&opts, &req).is_none());
+
+        let req = Request::builder().uri(PURGE_ENDPOINT).body(()).unwrap();
+        assert!(pre_process(&opts, &req).is_none());
+    }
+
+    #[test]
+    fn requests_without_a_valid_token_are_rejected() {
+        let opts = handler_opts(Some("secret"));
+        for auth in [None, Some("Bearer nope"), Some("Basic c2VjcmV0")] {
+            let resp = pre_process(&opts, &purge_request(PURGE_ENDPOINT, auth))
+                .unwrap()
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(resp.headers()[WWW_AUTHENTICATE], "Bearer");
+        }
+    }
+
+    #[test]
+    fn invalid_glob_is_a_bad_request() {
+        let opts = handler_opts(Some("secret"));
+        let req = purge_request(
+            &format!("{PURGE_ENDPOINT}?glob=/assets/%5B"),
+            Some("Bearer secret"),
+        );
+        let resp = pre_process(&opts, &req).unwrap().unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn filter_matches_prefix_and_glob() {
+        let filter = Filter::from_query(Some("prefix=/assets/&glob=**/*.css")).unwrap();
+        assert!(filter.matches("/assets/css/main.css"));
+        assert!(!filter.matches("/assets/app.js"));
+        assert!(!filter.matches("/theme/main.css"));
+        assert!(Filter::from_query(None).unwrap().is_all());
+    }
+
+    fn root(dir: &str, uri_prefix: &str) -> CacheRoot {
+        CacheRoot {
+            dir: PathBuf::from(dir),
+            uri_prefix: uri_prefix.to_owned(),
+            hosts: false,
+            include_hidden: false,
+            follow_symlinks: false,
+        }
+    }
+
+    #[test]
+    fn cache_keys_map_back_to_request_paths() {
+        let root = root("/srv/public", "");
+        let vhost = self::root("/srv/blog", "");
+        let mount = self::root("/srv/public/manual", "/docs");
+        let uri_path_of = |path| {
+            [&root, &vhost, &mount]
+                .iter()
+                .find_map(|root| root.uri_path_of(Path::new(path)))
+        };
+        assert_eq!(
+            uri_path_of("/srv/public/assets/app.js").unwrap(),
+            "/assets/app.js"
+        );
+        assert_eq!(uri_path_of("/srv/blog/index.html").unwrap(), "/index.html");
+        assert!(uri_path_of("/etc/passwd").is_none());
+        assert_eq!(
+            mount
+                .uri_path_of(Path::new("/srv/public/manual/intro.html"))
+                .unwrap(),
+            "/docs/intro.html"
+        );
+
+        let mut mass = self::root("/srv/sites", "");
+        mass.hosts = true;
+        assert_eq!(
+            mass.uri_path_of(Path::new("/srv/sites/example.com/assets/app.js"))
+                .unwrap(),
+            "/assets/app.js"
+        );
+
+        let roots = [root, mount];
+        let filter = Filter::from_query(Some("prefix=/docs/")).unwrap();
+        assert!(matches(
+            &filter,
+            Path::new("/srv/public/manual/intro.html"),
+            &roots
+        ));
+        assert!(!matches(
+            &filter,
+            Path::new("/srv/public/index.html"),
+            &roots
+        ));
+    }
+
+    #[test]
+    fn tokens_are_compared_exactly() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
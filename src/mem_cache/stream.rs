@@ -108,12 +108,23 @@ fn try_insert(
     };
     let file_path = opts.file_path.as_str();
     tracing::debug!("file `{file_path}` inserted into in-memory cache store");
-    let mem_file = Arc::new(MemFile::new(
+    let mem_file = MemFile::new(
         buf.freeze(),
         opts.content_type,
         opts.last_modified,
         opts.etag,
-    ));
+        opts.modified,
+        opts.followed_symlinks,
+    );
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    let mem_file = mem_file.with_variants(opts.variants);
+    let mem_file = Arc::new(mem_file);
     if let Some(store) = CACHE_STORE.get() {
         store.insert(file_path.into(), mem_file);
     }
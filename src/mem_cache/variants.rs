@@ -0,0 +1,354 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Content-encoded variants (`br`, `zstd` and `gzip`) of in-memory cache entries.
+//!
+//! Variants are filled lazily. The first cache hit negotiating a given
+//! encoding is served from the identity body and compressed on the fly by
+//! the regular compression stage, which tees the encoded output into the
+//! entry (see [`tee`]). Subsequent hits for that encoding are then served
+//! straight from memory with `Content-Encoding` already set, so the
+//! compression stage skips them.
+//!
+//! Each variant carries its own `ETag`, derived from the identity one by
+//! appending the coding (e.g. `W/"1b21dd2-2000-br"`), so that validators
+//! never match across encodings.
+
+use bytes::{Bytes, BytesMut};
+use compact_str::CompactString;
+use futures_util::Stream;
+use headers::HeaderValue;
+use http_body_util::BodyExt as _;
+use hyper::Response;
+use hyper::header::{CONTENT_ENCODING, ETAG};
+use mime_guess::Mime;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+
+use crate::body::Body;
+use crate::compression::CompressionPolicy;
+use crate::digest::Digests;
+use crate::exts::headers::ContentCoding;
+use crate::mem_cache::cache::{CACHE_STORE, MemFile};
+
+/// An encoded representation of a cached file.
+#[derive(Debug, Clone)]
+pub(crate) struct EncodedVariant {
+    /// Encoded bytes of the file.
+    pub(crate) data: Bytes,
+    /// `ETag` of the encoded representation, if the entry has one.
+    pub(crate) etag: Option<HeaderValue>,
+    /// Integrity digests of the encoded bytes, computed on demand.
+    pub(crate) digests: Arc<Digests>,
+}
+
+/// Encodings of the variant slots, in that order.
+///
+/// `deflate` is rarely negotiated by clients and keeps being compressed on
+/// the fly.
+const CODINGS: [ContentCoding; 3] = [
+    ContentCoding::BROTLI,
+    ContentCoding::ZSTD,
+    ContentCoding::GZIP,
+];
+
+/// Per-encoding variant slots of a cache entry.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Variants {
+    /// Whether the compression policy allows each encoding of [`CODINGS`].
+    allowed: [bool; 3],
+    /// Slots for each encoding of [`CODINGS`].
+    slots: [OnceLock<EncodedVariant>; 3],
+}
+
+impl Variants {
+    /// Creates the (empty) variant slots for an entry served at `uri_path`
+    /// with the given content type and length.
+    ///
+    /// Only the encodings the compression policy allows for the entry get
+    /// a slot, so the ones the compression stage skips are never cached.
+    pub(crate) fn new(
+        policy: &CompressionPolicy,
+        uri_path: &str,
+        content_type: &HeaderValue,
+        len: usize,
+    ) -> Self {
+        let mime = content_type
+            .to_str()
+            .ok()
+            .and_then(|s| s.parse::<Mime>().ok());
+        let len = Some(len as u64);
+        Self {
+            allowed: CODINGS.map(|coding| policy.allows(uri_path, mime.as_ref(), len, coding)),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the slot for the given encoding, or `None` when the policy
+    /// does not allow it for the entry or the encoding is not cached.
+    pub(crate) fn slot(&self, coding: ContentCoding) -> Option<&OnceLock<EncodedVariant>> {
+        let index = CODINGS.iter().position(|&c| c == coding)?;
+        self.allowed[index].then(|| &self.slots[index])
+    }
+
+    /// Total length in bytes of the variants filled so far.
+    pub(crate) fn len(&self) -> usize {
+        self.slots
+            .iter()
+            .filter_map(OnceLock::get)
+            .map(|variant| variant.data.len())
+            .sum()
+    }
+}
+
+/// Marker attached as a response extension to cache hits served from the
+/// identity body whose encoded variant is not cached yet.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingVariant {
+    key: CompactString,
+    mem_file: Arc<MemFile>,
+    coding: ContentCoding,
+    etag: Option<HeaderValue>,
+}
+
+impl PendingVariant {
+    pub(crate) fn new(
+        key: CompactString,
+        mem_file: Arc<MemFile>,
+        coding: ContentCoding,
+        etag: Option<HeaderValue>,
+    ) -> Self {
+        Self {
+            key,
+            mem_file,
+            coding,
+            etag,
+        }
+    }
+}
+
+/// Tees the body of a response compressed on the fly into the pending
+/// variant slot of its cache entry.
+///
+/// The response is returned untouched when it was not compressed with the
+/// pending encoding (e.g. a size or MIME check skipped compression).
+pub(crate) fn tee(resp: Response<Body>, pending: PendingVariant) -> Response<Body> {
+    let is_encoded = resp
+        .headers()
+        .get(CONTENT_ENCODING)
+        .is_some_and(|v| v.as_bytes() == pending.coding.as_str().as_bytes());
+    if !is_encoded {
+        return resp;
+    }
+
+    let (mut head, body) = resp.into_parts();
+    if let Some(etag) = pending.etag.as_ref() {
+        head.headers.insert(ETAG, etag.clone());
+    }
+    let body = crate::body::stream(VariantFillStream {
+        inner: body.into_data_stream(),
+        buf: Some(BytesMut::new()),
+        pending: Some(pending),
+    });
+    Response::from_parts(head, body)
+}
+
+/// A stream that forwards an encoded body and, once it has been fully
+/// produced, stores it as a variant of the cache entry.
+struct VariantFillStream<S> {
+    inner: S,
+    /// Accumulator for the encoded body. Dropped on error.
+    buf: Option<BytesMut>,
+    /// Target slot. Taken on completion.
+    pending: Option<PendingVariant>,
+}
+
+impl<S> Stream for VariantFillStream<S>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin,
+{
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = Pin::into_inner(self);
+        let item = match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(item) => item,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        match &item {
+            Some(Ok(chunk)) => {
+                if let Some(buf) = this.buf.as_mut() {
+                    buf.extend_from_slice(chunk);
+                }
+            }
+            Some(Err(_)) => this.buf = None,
+            None => {
+                if let (Some(buf), Some(pending)) = (this.buf.take(), this.pending.take()) {
+                    store(pending, buf.freeze());
+                }
+            }
+        }
+
+        Poll::Ready(item)
+    }
+}
+
+/// Stores an encoded body into its variant slot. A concurrent fill of the
+/// same slot wins and this one is discarded.
+///
+/// The entry is then re-inserted (if still current) so that a byte-bounded
+/// store accounts for the variant.
+fn store(pending: PendingVariant, data: Bytes) {
+    let Some(slot) = pending.mem_file.variants().slot(pending.coding) else {
+        return;
+    };
+    let len = data.len();
+    let variant = EncodedVariant {
+        data,
+        etag: pending.etag,
+        digests: Arc::default(),
+    };
+    if slot.set(variant).is_err() {
+        return;
+    }
+    tracing::debug!(
+        "{} variant ({len} bytes) inserted into in-memory cache entry",
+        pending.coding.as_str(),
+    );
+
+    if let Some(store) = CACHE_STORE.get()
+        && store
+            .get(&pending.key)
+            .is_some_and(|current| Arc::ptr_eq(&current, &pending.mem_file))
+    {
+        store.insert(pending.key, pending.mem_file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{self, DEFAULT_POLICY, MIN_COMPRESS_SIZE};
+    use crate::handler::RequestHandlerOpts;
+    use crate::settings::Advanced;
+    use crate::settings::file::{self, CompressionRule};
+
+    fn mem_file(content_type: &'static str, len: usize) -> Arc<MemFile> {
+        let content_type = HeaderValue::from_static(content_type);
+        let variants = Variants::new(&DEFAULT_POLICY, "/style.css", &content_type, len);
+        Arc::new(
+            MemFile::new(
+                Bytes::from(vec![b'x'; len]),
+                content_type,
+                None,
+                Some(HeaderValue::from_static("W/\"1b21dd2-2000\"")),
+                None,
+                false,
+            )
+            .with_variants(variants),
+        )
+    }
+
+    fn variants(policy: &CompressionPolicy, uri_path: &str, mime: &'static str) -> Variants {
+        Variants::new(
+            policy,
+            uri_path,
+            &HeaderValue::from_static(mime),
+            MIN_COMPRESS_SIZE,
+        )
+    }
+
+    #[test]
+    fn only_compressible_entries_get_slots() {
+        let html = variants(&DEFAULT_POLICY, "/index.html", "text/html");
+        assert!(html.slot(ContentCoding::BROTLI).is_some());
+        assert!(html.slot(ContentCoding::GZIP).is_some());
+        assert!(html.slot(ContentCoding::DEFLATE).is_none());
+
+        let small = Variants::new(
+            &DEFAULT_POLICY,
+            "/index.html",
+            &HeaderValue::from_static("text/html"),
+            10,
+        );
+        assert!(small.slot(ContentCoding::BROTLI).is_none());
+
+        let png = variants(&DEFAULT_POLICY, "/logo.png", "image/png");
+        assert!(png.slot(ContentCoding::BROTLI).is_none());
+    }
+
+    #[test]
+    fn slots_follow_the_compression_policy() {
+        let rule = |source: Option<&str>, mime: Option<&str>, enabled, encodings: Option<&str>| {
+            CompressionRule {
+                source: source.map(str::to_owned),
+                mime: mime.map(str::to_owned),
+                enabled: Some(enabled),
+                encodings: encodings.map(|coding| vec![coding.to_owned()]),
+            }
+        };
+        let mut opts = RequestHandlerOpts {
+            advanced_opts: Some(Advanced {
+                compression: Some(file::CompressionPolicy {
+                    min_size: None,
+                    encodings: None,
+                    levels: None,
+                    rules: Some(vec![
+                        rule(Some("/api/**"), None, false, None),
+                        rule(None, Some("application/wasm"), true, Some("gzip")),
+                    ]),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        compression::init_policy(&mut opts).unwrap();
+        let policy = opts.compression_policy.unwrap();
+
+        let wasm = variants(&policy, "/app.wasm", "application/wasm");
+        assert!(wasm.slot(ContentCoding::GZIP).is_some());
+        assert!(wasm.slot(ContentCoding::BROTLI).is_none());
+
+        let api = variants(&policy, "/api/users", "application/json");
+        assert!(api.slot(ContentCoding::GZIP).is_none());
+    }
+
+    #[tokio::test]
+    async fn tee_fills_the_slot_once_the_body_is_drained() {
+        let file = mem_file("text/css", 512);
+        let etag = crate::etag::encoded(
+            &HeaderValue::from_static("W/\"1b21dd2-2000\""),
+            ContentCoding::GZIP.as_str(),
+        );
+        let pending =
+            PendingVariant::new("style.css".into(), file.clone(), ContentCoding::GZIP, etag);
+
+        let mut resp = Response::new(crate::body::full("encoded"));
+        resp.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        let resp = tee(resp, pending);
+        assert_eq!(resp.headers()[ETAG], "W/\"1b21dd2-2000-gzip\"");
+
+        let slot = file.variants().slot(ContentCoding::GZIP).unwrap();
+        assert!(slot.get().is_none());
+
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let variant = slot.get().unwrap();
+        assert_eq!(variant.data, body);
+        assert_eq!(variant.etag.as_ref().unwrap(), "W/\"1b21dd2-2000-gzip\"");
+    }
+
+    #[test]
+    fn tee_ignores_responses_with_another_encoding() {
+        let file = mem_file("text/css", 512);
+        let pending = PendingVariant::new("style.css".into(), file, ContentCoding::BROTLI, None);
+
+        let resp = tee(Response::new(crate::body::full("plain")), pending);
+        assert!(resp.headers().get(ETAG).is_none());
+    }
+}
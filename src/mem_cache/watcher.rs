@@ -0,0 +1,226 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! File system watcher that evicts in-memory cache entries whose files
+//! change on disk.
+//!
+//! The platform watcher (`inotify` on Linux, `FSEvents` on macOS, `kqueue`
+//! on BSDs and `ReadDirectoryChangesW` on Windows) is owned by a dedicated
+//! background thread for the lifetime of the process. Events are collected
+//! in short batches so that a deploy touching thousands of files results in
+//! a single pass over the cache store instead of one pass per event.
+
+use notify::event::EventKind;
+use notify::{Event, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use compact_str::CompactString;
+
+use crate::mem_cache::cache::{CACHE_STORE, CacheRoot};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::{Context, Result};
+
+/// Time window used to coalesce bursts of file system events.
+const BATCH_WINDOW: Duration = Duration::from_millis(50);
+
+/// Maximum number of changed paths collected in a single batch.
+const BATCH_MAX_PATHS: usize = 4096;
+
+/// A batch of changes to apply to the cache store.
+#[derive(Debug, Default)]
+struct Changes {
+    /// Paths reported as created, modified, renamed or removed.
+    paths: HashSet<PathBuf>,
+    /// Whether the watcher lost events and the whole store must be purged.
+    rescan: bool,
+}
+
+/// Starts watching the given root directories recursively.
+///
+/// Returns an error when the platform watcher can not be created or when
+/// any of the roots can not be watched (e.g. inotify watch limit reached or
+/// an unsupported network file system). The caller is expected to fall back
+/// to per-hit revalidation in that case.
+pub(crate) fn spawn(roots: &[CacheRoot]) -> Result {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .with_context(|| "unable to create the file system watcher")?;
+
+    for root in roots {
+        let root = &root.dir;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("unable to watch directory `{}`", root.display()))?;
+        tracing::debug!("in-memory cache watching directory `{}`", root.display());
+    }
+
+    std::thread::Builder::new()
+        .name("sws-cache-watcher".into())
+        .spawn(move || {
+            // The watcher must outlive the event loop below; dropping it
+            // would stop event delivery and close the channel.
+            let _watcher = watcher;
+            while let Some(changes) = next_batch(&rx) {
+                apply(changes);
+            }
+        })
+        .with_context(|| "unable to spawn the file system watcher thread")?;
+
+    Ok(())
+}
+
+/// Blocks until at least one relevant event is received and then collects
+/// the events arriving within [`BATCH_WINDOW`].
+///
+/// Returns `None` when the channel is closed.
+fn next_batch(rx: &Receiver<notify::Result<Event>>) -> Option<Changes> {
+    let mut changes = Changes::default();
+    collect(&mut changes, rx.recv().ok()?);
+
+    while changes.paths.len() < BATCH_MAX_PATHS {
+        match rx.recv_timeout(BATCH_WINDOW) {
+            Ok(res) => collect(&mut changes, res),
+            Err(RecvTimeoutError::Timeout) => break,
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Some(changes)
+}
+
+/// Adds the paths of a single watcher event to the current batch.
+fn collect(changes: &mut Changes, res: notify::Result<Event>) {
+    let event = match res {
+        Ok(event) => event,
+        Err(err) => {
+            tracing::warn!("in-memory cache watcher error: {err}");
+            return;
+        }
+    };
+
+    if event.need_rescan() {
+        changes.rescan = true;
+        return;
+    }
+
+    if is_relevant(&event.kind) {
+        changes.paths.extend(event.paths);
+    }
+}
+
+/// Whether an event kind may affect the content served for a path.
+fn is_relevant(kind: &EventKind) -> bool {
+    !matches!(kind, EventKind::Access(_))
+}
+
+/// Evicts the cache entries affected by a batch of changes.
+fn apply(changes: Changes) {
+    let Some(store) = CACHE_STORE.get() else {
+        return;
+    };
+
+    if changes.rescan {
+        tracing::debug!("in-memory cache watcher lost events, purging the whole store");
+        #[cfg(feature = "metrics")]
+        metrics::inc_memory_cache_purges("changed", store.entry_count());
+        store.invalidate_all();
+        return;
+    }
+
+    // Exact entries are evicted directly. Paths that are no longer regular
+    // files may have been directories (removed or renamed), so every entry
+    // below them must be evicted as well.
+    let mut evicted = 0u64;
+    let mut dirs: HashSet<&Path> = HashSet::new();
+    for path in &changes.paths {
+        if let Some(key) = path.to_str() {
+            let key = CompactString::from(key);
+            if store.contains_key(&key) {
+                store.invalidate(&key);
+                evicted += 1;
+            }
+        }
+        if !path.is_file() {
+            dirs.insert(path.as_path());
+        }
+    }
+
+    if !dirs.is_empty() {
+        let stale = store
+            .iter()
+            .filter(|(key, _)| is_below_any(Path::new(key.as_str()), &dirs))
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        evicted += stale.len() as u64;
+        for key in stale {
+            store.invalidate(key.as_ref());
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    metrics::inc_memory_cache_purges("changed", evicted);
+
+    tracing::debug!(
+        "in-memory cache evicted {evicted} entries for {} changed path(s)",
+        changes.paths.len()
+    );
+}
+
+/// Whether any ancestor of `path` is contained in `dirs`.
+fn is_below_any(path: &Path, dirs: &HashSet<&Path>) -> bool {
+    path.ancestors().skip(1).any(|dir| dirs.contains(dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, ModifyKind, RemoveKind};
+
+    #[test]
+    fn access_events_are_ignored() {
+        assert!(!is_relevant(&EventKind::Access(AccessKind::Any)));
+        assert!(is_relevant(&EventKind::Create(CreateKind::File)));
+        assert!(is_relevant(&EventKind::Modify(ModifyKind::Any)));
+        assert!(is_relevant(&EventKind::Remove(RemoveKind::Folder)));
+    }
+
+    #[test]
+    fn collect_flags_rescan_events() {
+        let mut changes = Changes::default();
+        let event = Event::new(EventKind::Other).set_flag(notify::event::Flag::Rescan);
+        collect(&mut changes, Ok(event));
+        assert!(changes.rescan);
+        assert!(changes.paths.is_empty());
+    }
+
+    #[test]
+    fn collect_keeps_paths_of_relevant_events() {
+        let mut changes = Changes::default();
+        let event = Event::new(EventKind::Modify(ModifyKind::Any))
+            .add_path(PathBuf::from("/srv/public/index.html"));
+        collect(&mut changes, Ok(event));
+        let event = Event::new(EventKind::Access(AccessKind::Any))
+            .add_path(PathBuf::from("/srv/public/main.css"));
+        collect(&mut changes, Ok(event));
+        assert_eq!(changes.paths.len(), 1);
+        assert!(changes.paths.contains(Path::new("/srv/public/index.html")));
+    }
+
+    #[test]
+    fn paths_below_removed_directories_are_matched() {
+        let dirs = HashSet::from([Path::new("/srv/public/assets")]);
+        assert!(is_below_any(Path::new("/srv/public/assets/app.js"), &dirs));
+        assert!(is_below_any(
+            Path::new("/srv/public/assets/img/logo.png"),
+            &dirs
+        ));
+        assert!(!is_below_any(Path::new("/srv/public/assets"), &dirs));
+        assert!(!is_below_any(Path::new("/srv/public/assets2/a.js"), &dirs));
+    }
+}
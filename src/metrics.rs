@@ -10,6 +10,8 @@ use std::sync::LazyLock;
 
 use headers::{ContentType, HeaderMapExt};
 use hyper::{Request, Response, StatusCode};
+#[cfg(feature = "mem-cache")]
+use prometheus::IntCounter;
 use prometheus::{
     Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, TextEncoder,
     default_registry,
@@ -75,6 +77,68 @@ static HTTP_CONNECTIONS_ACTIVE: LazyLock<IntGauge> = LazyLock::new(|| {
     .unwrap()
 });
 
+#[cfg(feature = "mem-cache")]
+static MEMORY_CACHE_HITS_TOTAL: LazyLock<IntCounter> = LazyLock::new(|| {
+    IntCounter::new(
+        "sws_memory_cache_hits_total",
+        "Total requests served from the in-memory cache.",
+    )
+    .unwrap()
+});
+
+#[cfg(feature = "mem-cache")]
+static MEMORY_CACHE_MISSES_TOTAL: LazyLock<IntCounter> = LazyLock::new(|| {
+    IntCounter::new(
+        "sws_memory_cache_misses_total",
+        "Total in-memory cache lookups without a current entry.",
+    )
+    .unwrap()
+});
+
+#[cfg(feature = "mem-cache")]
+static MEMORY_CACHE_PURGES_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    IntCounterVec::new(
+        Opts::new(
+            "sws_memory_cache_purges_total",
+            "Total in-memory cache entries purged by SWS, by reason (`changed` when their \
+             file changed on disk or `purged` through the purge endpoint).",
+        ),
+        &["reason"],
+    )
+    .unwrap()
+});
+
+#[cfg(feature = "mem-cache")]
+static MEMORY_CACHE_EVICTIONS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    IntCounterVec::new(
+        Opts::new(
+            "sws_memory_cache_evictions_total",
+            "Total entries removed from the in-memory cache, by cause (`size` when over \
+             capacity, `expired` after their time to live or idle, or `explicit` when purged).",
+        ),
+        &["cause"],
+    )
+    .unwrap()
+});
+
+#[cfg(feature = "mem-cache")]
+static MEMORY_CACHE_ENTRIES: LazyLock<IntGauge> = LazyLock::new(|| {
+    IntGauge::new(
+        "sws_memory_cache_entries",
+        "Approximate number of entries in the in-memory cache.",
+    )
+    .unwrap()
+});
+
+#[cfg(feature = "mem-cache")]
+static MEMORY_CACHE_WEIGHTED_SIZE: LazyLock<IntGauge> = LazyLock::new(|| {
+    IntGauge::new(
+        "sws_memory_cache_weighted_size",
+        "Approximate weighted size of the in-memory cache (bytes when `max-size` is set, entries otherwise).",
+    )
+    .unwrap()
+});
+
 /// Initializes the metrics endpoint and registers HTTP-level collectors.
 /// Tokio runtime metrics are additionally registered when the `experimental`
 /// feature is enabled and built with `RUSTFLAGS="--cfg tokio_unstable"`.
@@ -127,6 +191,24 @@ pub fn init(enabled: bool, handler_opts: &mut RequestHandlerOpts) {
         if let Err(err) = registry.register(Box::new(HTTP_CONNECTIONS_ACTIVE.clone())) {
             tracing::debug!("metrics collector registration skipped: {err:?}");
         }
+
+        // In-memory cache metrics
+        #[cfg(feature = "mem-cache")]
+        {
+            let collectors: [Box<dyn prometheus::core::Collector>; 6] = [
+                Box::new(MEMORY_CACHE_HITS_TOTAL.clone()),
+                Box::new(MEMORY_CACHE_MISSES_TOTAL.clone()),
+                Box::new(MEMORY_CACHE_PURGES_TOTAL.clone()),
+                Box::new(MEMORY_CACHE_EVICTIONS_TOTAL.clone()),
+                Box::new(MEMORY_CACHE_ENTRIES.clone()),
+                Box::new(MEMORY_CACHE_WEIGHTED_SIZE.clone()),
+            ];
+            for collector in collectors {
+                if let Err(err) = registry.register(collector) {
+                    tracing::debug!("metrics collector registration skipped: {err:?}");
+                }
+            }
+        }
     }
 }
 
@@ -150,6 +232,9 @@ pub fn pre_process<T>(
     }
 
     let body = if method.is_get() {
+        #[cfg(feature = "mem-cache")]
+        update_memory_cache_gauges();
+
         let encoder = TextEncoder::new();
         let mut buffer = Vec::new();
         if let Err(err) = encoder.encode(&default_registry().gather(), &mut buffer) {
@@ -198,6 +283,51 @@ pub fn record_request<T>(req: &Request<T>, status: StatusCode, bytes: u64, elaps
     }
 }
 
+/// Records a request served from the in-memory cache.
+#[cfg(feature = "mem-cache")]
+pub(crate) fn inc_memory_cache_hits() {
+    MEMORY_CACHE_HITS_TOTAL.inc();
+}
+
+/// Records an in-memory cache lookup without a current entry.
+#[cfg(feature = "mem-cache")]
+pub(crate) fn inc_memory_cache_misses() {
+    MEMORY_CACHE_MISSES_TOTAL.inc();
+}
+
+/// Records in-memory cache entries purged for the given reason.
+#[cfg(feature = "mem-cache")]
+pub(crate) fn inc_memory_cache_purges(reason: &str, count: u64) {
+    MEMORY_CACHE_PURGES_TOTAL
+        .with_label_values(&[reason])
+        .inc_by(count);
+}
+
+/// Records an entry removed from the in-memory cache for the given cause.
+#[cfg(feature = "mem-cache")]
+pub(crate) fn inc_memory_cache_evictions(cause: &str) {
+    MEMORY_CACHE_EVICTIONS_TOTAL
+        .with_label_values(&[cause])
+        .inc();
+}
+
+/// Returns the in-memory cache entries removed for the given cause.
+#[cfg(all(test, feature = "mem-cache"))]
+pub(crate) fn memory_cache_evictions(cause: &str) -> u64 {
+    MEMORY_CACHE_EVICTIONS_TOTAL
+        .with_label_values(&[cause])
+        .get()
+}
+
+/// Refreshes the in-memory cache size gauges from the store.
+#[cfg(feature = "mem-cache")]
+fn update_memory_cache_gauges() {
+    if let Some(store) = crate::mem_cache::cache::CACHE_STORE.get() {
+        MEMORY_CACHE_ENTRIES.set(i64::try_from(store.entry_count()).unwrap_or(i64::MAX));
+        MEMORY_CACHE_WEIGHTED_SIZE.set(i64::try_from(store.weighted_size()).unwrap_or(i64::MAX));
+    }
+}
+
 /// Increments the inflight requests gauge.
 pub fn inc_requests_inflight() {
     HTTP_REQUESTS_INFLIGHT.inc();
@@ -365,6 +495,39 @@ mod tests {
         assert_eq!(HTTP_CONNECTIONS_ACTIVE.get(), before);
     }
 
+    #[cfg(feature = "mem-cache")]
+    #[test]
+    fn test_memory_cache_counters() {
+        let hits = MEMORY_CACHE_HITS_TOTAL.get();
+        let misses = MEMORY_CACHE_MISSES_TOTAL.get();
+        let purged = MEMORY_CACHE_PURGES_TOTAL
+            .with_label_values(&["purged"])
+            .get();
+        let evicted = MEMORY_CACHE_EVICTIONS_TOTAL
+            .with_label_values(&["expired"])
+            .get();
+
+        inc_memory_cache_hits();
+        inc_memory_cache_misses();
+        inc_memory_cache_purges("purged", 3);
+        inc_memory_cache_evictions("expired");
+
+        assert!(MEMORY_CACHE_HITS_TOTAL.get() > hits);
+        assert!(MEMORY_CACHE_MISSES_TOTAL.get() > misses);
+        assert!(
+            MEMORY_CACHE_PURGES_TOTAL
+                .with_label_values(&["purged"])
+                .get()
+                >= purged + 3
+        );
+        assert!(
+            MEMORY_CACHE_EVICTIONS_TOTAL
+                .with_label_values(&["expired"])
+                .get()
+                > evicted
+        );
+    }
+
     #[test]
     fn test_inflight_gauge() {
         let before = HTTP_REQUESTS_INFLIGHT.get();
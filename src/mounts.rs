@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Module that serves several root directories under URL path prefixes.
+//!
+//! Each `[[advanced.mounts]]` entry maps a URL path prefix to a directory
+//! with its own directory listing, index files, hidden files and symlinks
+//! policy, all defaulting to the general settings.
+//!
+//! ```toml
+//! [[advanced.mounts]]
+//! prefix = "/docs"
+//! root = "/srv/docs-build"
+//!
+//! [[advanced.mounts]]
+//! prefix = "/downloads"
+//! root = "/mnt/nfs/downloads"
+//! directory-listing = true
+//! ```
+//!
+//! A request is served from the mount of the longest prefix matching whole
+//! path segments, so `/docs/guide` is served by `/docs` but `/docsearch`
+//! is not. Requests matching no mount are served from the general root.
+
+use std::path::PathBuf;
+
+use crate::handler::RequestHandlerOpts;
+use crate::{Context, Result, bail, helpers};
+
+#[cfg(feature = "directory-listing")]
+use crate::directory_listing::DirListFmt;
+
+/// The mounts sorted by descending prefix length.
+pub struct Mounts {
+    mounts: Vec<Mount>,
+}
+
+/// A root directory served under a URL path prefix.
+pub(crate) struct Mount {
+    /// URL path prefix without trailing slash, empty for `/`.
+    pub prefix: String,
+    /// Root directory of the mount.
+    pub root: PathBuf,
+    /// Index files of the mount.
+    pub index_files: Vec<String>,
+    /// Directory listing of the mount.
+    #[cfg(feature = "directory-listing")]
+    pub dir_listing: bool,
+    /// Directory listing order of the mount.
+    #[cfg(feature = "directory-listing")]
+    pub dir_listing_order: u8,
+    /// Directory listing format of the mount.
+    #[cfg(feature = "directory-listing")]
+    pub dir_listing_format: DirListFmt,
+    /// Hidden files policy of the mount.
+    pub include_hidden: bool,
+    /// Symlinks policy of the mount.
+    pub follow_symlinks: bool,
+}
+
+/// Initializes the mounts.
+pub fn init(handler_opts: &mut RequestHandlerOpts) -> Result {
+    let Some(entries) = handler_opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.mounts.as_deref())
+        .filter(|entries| !entries.is_empty())
+    else {
+        tracing::info!(enabled = false, "mounts");
+        return Ok(());
+    };
+
+    let mut mounts: Vec<Mount> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let prefix = match entry.prefix.trim() {
+            "" => bail!("mount prefix can not be empty"),
+            "/" => "",
+            prefix => prefix,
+        };
+        let valid = prefix.is_empty()
+            || (prefix.starts_with('/')
+                && !prefix.ends_with('/')
+                && prefix[1..]
+                    .split('/')
+                    .all(|segment| !matches!(segment, "" | "." | "..")));
+        if !valid || prefix.contains(['*', '?', '#', '%', '\\']) {
+            bail!("invalid mount prefix `{}`", entry.prefix);
+        }
+        if mounts.iter().any(|mount| mount.prefix == prefix) {
+            bail!("duplicate mount prefix `{}`", entry.prefix);
+        }
+
+        // Make sure path is valid and canonical like the general root
+        let root = helpers::get_valid_dirpath(&entry.root).with_context(|| {
+            format!(
+                "root directory of mount `{}` was not found or inaccessible",
+                entry.prefix
+            )
+        })?;
+        let root = if handler_opts.use_relative_root {
+            root
+        } else {
+            root.canonicalize().unwrap_or(root)
+        };
+
+        let index_files = match &entry.index_files {
+            Some(index_files) => index_files
+                .split(',')
+                .map(|s| s.trim().to_owned())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            None => handler_opts.index_files.clone(),
+        };
+        if index_files.is_empty() {
+            bail!("index files list of mount `{}` is empty", entry.prefix);
+        }
+
+        tracing::info!("mount: prefix={} root={}", entry.prefix, root.display());
+        mounts.push(Mount {
+            prefix: prefix.to_owned(),
+            root,
+            index_files,
+            #[cfg(feature = "directory-listing")]
+            dir_listing: entry.directory_listing.unwrap_or(handler_opts.dir_listing),
+            #[cfg(feature = "directory-listing")]
+            dir_listing_order: entry
+                .directory_listing_order
+                .unwrap_or(handler_opts.dir_listing_order),
+            #[cfg(feature = "directory-listing")]
+            dir_listing_format: entry
+                .directory_listing_format
+                .clone()
+                .unwrap_or_else(|| handler_opts.dir_listing_format.clone()),
+            include_hidden: entry.include_hidden.unwrap_or(handler_opts.include_hidden),
+            follow_symlinks: entry
+                .follow_symlinks
+                .unwrap_or(handler_opts.follow_symlinks),
+        });
+    }
+    mounts.sort_by_key(|mount| std::cmp::Reverse(mount.prefix.len()));
+
+    handler_opts.mounts = Some(std::sync::Arc::new(Mounts { mounts }));
+    Ok(())
+}
+
+impl Mounts {
+    /// Returns the mount of the longest prefix matching the request path
+    /// along with the rest of the path.
+    pub(crate) fn find<'a>(&self, uri_path: &'a str) -> Option<(&Mount, &'a str)> {
+        self.mounts.iter().find_map(|mount| {
+            let tail = uri_path.strip_prefix(mount.prefix.as_str())?;
+            (tail.is_empty() || tail.starts_with('/')).then_some((mount, tail))
+        })
+    }
+
+    /// The mounts sorted by descending prefix length.
+    #[cfg(feature = "mem-cache")]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Mount> {
+        self.mounts.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{Advanced, file::Mount as MountEntry};
+
+    fn entry(prefix: &str, root: &std::path::Path) -> MountEntry {
+        MountEntry {
+            prefix: prefix.to_owned(),
+            root: root.to_path_buf(),
+            index_files: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing_order: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing_format: None,
+            include_hidden: None,
+            follow_symlinks: None,
+        }
+    }
+
+    fn init_mounts(entries: Vec<MountEntry>) -> Result<RequestHandlerOpts> {
+        let mut opts = RequestHandlerOpts {
+            advanced_opts: Some(Advanced {
+                mounts: Some(entries),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        init(&mut opts)?;
+        Ok(opts)
+    }
+
+    #[test]
+    fn finds_longest_prefix() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut docs = entry("/docs", tmp.path());
+        docs.index_files = Some("readme.html".to_owned());
+        let opts = init_mounts(vec![
+            entry("/", tmp.path()),
+            docs,
+            entry("/docs/api", tmp.path()),
+        ])
+        .unwrap();
+        let mounts = opts.mounts.as_deref().unwrap();
+
+        let find = |path| {
+            mounts
+                .find(path)
+                .map(|(mount, tail)| (mount.prefix.as_str(), tail))
+        };
+        assert_eq!(find("/docs"), Some(("/docs", "")));
+        assert_eq!(find("/docs/guide/"), Some(("/docs", "/guide/")));
+        assert_eq!(find("/docs/api/v1"), Some(("/docs/api", "/v1")));
+        assert_eq!(find("/docsearch"), Some(("", "/docsearch")));
+        assert_eq!(mounts.find("/docs").unwrap().0.index_files, ["readme.html"]);
+        assert_eq!(mounts.find("/").unwrap().0.index_files, ["index.html"]);
+    }
+
+    #[test]
+    fn invalid_mounts() {
+        let tmp = tempfile::tempdir().unwrap();
+        for prefix in [
+            "",
+            "docs",
+            "/docs/",
+            "/docs//api",
+            "/docs/../api",
+            "/docs/*",
+        ] {
+            assert!(
+                init_mounts(vec![entry(prefix, tmp.path())]).is_err(),
+                "{prefix}"
+            );
+        }
+        assert!(init_mounts(vec![entry("/docs", &tmp.path().join("missing"))]).is_err());
+        assert!(init_mounts(vec![entry("/docs", tmp.path()), entry("/docs", tmp.path())]).is_err());
+    }
+}
@@ -0,0 +1,504 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Generation of the pre-compressed file variants (`.br`, `.gz` and `.zst`)
+//! served by the compression static feature.
+//!
+//! Powers the `compress` subcommand. Every compressible file of a root
+//! directory gets one sidecar per format, using the same MIME type rules
+//! and compression levels as the on-the-fly compression. Runs are
+//! incremental: a sidecar is only regenerated when the modification time
+//! of its source file changed, and sidecars not smaller than their source
+//! are not kept.
+
+#[cfg(any(feature = "compression", feature = "compression-brotli"))]
+use async_compression::tokio::bufread::BrotliEncoder;
+#[cfg(any(feature = "compression", feature = "compression-gzip"))]
+use async_compression::tokio::bufread::GzipEncoder;
+#[cfg(any(feature = "compression", feature = "compression-zstd"))]
+use async_compression::tokio::bufread::ZstdEncoder;
+
+use mime_guess::Mime;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Instant, SystemTime};
+
+use crate::compression::{MIN_COMPRESS_SIZE, algorithm_level};
+use crate::exts::headers::ContentCoding;
+use crate::exts::mime::MimeExt;
+use crate::response::content_type_for;
+use crate::settings::CompressionLevel;
+use crate::{Context, Result, bail};
+
+/// Format of a pre-compressed file variant.
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SidecarFormat {
+    /// Brotli (`.br`).
+    Br,
+    /// Gzip (`.gz`).
+    Gzip,
+    /// Zstandard (`.zst`).
+    Zstd,
+}
+
+impl SidecarFormat {
+    /// Formats available in the current build.
+    pub const AVAILABLE: &[SidecarFormat] = &[
+        #[cfg(any(feature = "compression", feature = "compression-brotli"))]
+        SidecarFormat::Br,
+        #[cfg(any(feature = "compression", feature = "compression-gzip"))]
+        SidecarFormat::Gzip,
+        #[cfg(any(feature = "compression", feature = "compression-zstd"))]
+        SidecarFormat::Zstd,
+    ];
+
+    /// File extension looked up by
+    /// [`precompressed_variant`](crate::compression_static::precompressed_variant).
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Br => "br",
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+
+    fn coding(self) -> ContentCoding {
+        match self {
+            Self::Br => ContentCoding::BROTLI,
+            Self::Gzip => ContentCoding::GZIP,
+            Self::Zstd => ContentCoding::ZSTD,
+        }
+    }
+}
+
+/// Options of a pre-compression run.
+#[derive(Debug, Clone)]
+pub struct PrecompressOpts {
+    /// Root directory to walk.
+    pub root: PathBuf,
+    /// Formats to generate. All the available ones when empty.
+    pub formats: Vec<SidecarFormat>,
+    /// Compression level.
+    pub level: CompressionLevel,
+    /// Number of files compressed in parallel.
+    pub jobs: usize,
+    /// Regenerate the sidecars even when they are up to date.
+    pub force: bool,
+    /// Whether to compress hidden files (dotfiles).
+    pub include_hidden: bool,
+}
+
+/// Totals of a pre-compression run, counted per sidecar.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// Sidecars written.
+    pub written: u64,
+    /// Sidecars already up to date.
+    pub up_to_date: u64,
+    /// Sidecars skipped (and stale ones removed) because compression did not
+    /// reduce the file size.
+    pub not_smaller: u64,
+    /// Sidecars that could not be generated.
+    pub failed: u64,
+    /// Total size in bytes of the source files of the written sidecars.
+    pub bytes_in: u64,
+    /// Total size in bytes of the written sidecars.
+    pub bytes_out: u64,
+}
+
+/// What happened to a sidecar.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Written { bytes_in: u64, bytes_out: u64 },
+    UpToDate,
+    NotSmaller,
+}
+
+impl Summary {
+    fn add(&mut self, outcome: &io::Result<Outcome>) {
+        match outcome {
+            Ok(Outcome::Written {
+                bytes_in,
+                bytes_out,
+            }) => {
+                self.written += 1;
+                self.bytes_in += bytes_in;
+                self.bytes_out += bytes_out;
+            }
+            Ok(Outcome::UpToDate) => self.up_to_date += 1,
+            Ok(Outcome::NotSmaller) => self.not_smaller += 1,
+            Err(_) => self.failed += 1,
+        }
+    }
+}
+
+/// Generates the pre-compressed variants of the compressible files found
+/// below the root directory.
+pub fn run(opts: &PrecompressOpts) -> Result<Summary> {
+    let formats = if opts.formats.is_empty() {
+        SidecarFormat::AVAILABLE.to_vec()
+    } else {
+        opts.formats.clone()
+    };
+    if let Some(format) = formats
+        .iter()
+        .find(|format| !SidecarFormat::AVAILABLE.contains(format))
+    {
+        bail!("the `{format:?}` format is not available in this build");
+    }
+
+    let root = opts.root.canonicalize().with_context(|| {
+        format!(
+            "unable to resolve the root directory `{}`",
+            opts.root.display()
+        )
+    })?;
+
+    let started = Instant::now();
+    let files = collect(&root, opts.include_hidden);
+    let jobs = opts.jobs.clamp(1, files.len().max(1));
+    tracing::info!(
+        files = files.len(),
+        jobs,
+        formats = ?formats,
+        level = ?opts.level,
+        "pre-compressing files of `{}`",
+        root.display()
+    );
+
+    let next = AtomicUsize::new(0);
+    let summary = Mutex::new(Summary::default());
+    std::thread::scope(|scope| -> Result {
+        for _ in 0..jobs {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .with_context(|| "unable to create the compression runtime")?;
+            let (next, summary, files, formats) = (&next, &summary, &files, &formats);
+            scope.spawn(move || {
+                while let Some((path, meta)) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    for &format in formats {
+                        let outcome = precompress(&runtime, path, meta, format, opts);
+                        match &outcome {
+                            Ok(outcome) => {
+                                tracing::debug!(
+                                    "{}.{}: {outcome:?}",
+                                    path.display(),
+                                    format.extension()
+                                )
+                            }
+                            Err(err) => tracing::error!(
+                                "unable to pre-compress `{}` ({format:?}): {err}",
+                                path.display()
+                            ),
+                        }
+                        summary
+                            .lock()
+                            .unwrap_or_else(|err| err.into_inner())
+                            .add(&outcome);
+                    }
+                }
+            });
+        }
+        Ok(())
+    })?;
+
+    let summary = summary.into_inner().unwrap_or_else(|err| err.into_inner());
+    tracing::info!(
+        written = summary.written,
+        up_to_date = summary.up_to_date,
+        not_smaller = summary.not_smaller,
+        failed = summary.failed,
+        bytes_in = summary.bytes_in,
+        bytes_out = summary.bytes_out,
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "pre-compression finished"
+    );
+    if summary.failed > 0 {
+        bail!("{} file variant(s) could not be generated", summary.failed);
+    }
+
+    Ok(summary)
+}
+
+/// Collects the files eligible for compression below a root directory.
+///
+/// Symbolic links are not followed, so that files outside of the root never
+/// get sidecars written next to them.
+fn collect(root: &Path, include_hidden: bool) -> Vec<(PathBuf, Metadata)> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!("unable to read directory `{}`: {err}", dir.display());
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let is_hidden = entry
+                .file_name()
+                .to_str()
+                .is_none_or(|n| n.starts_with('.'));
+            if is_hidden && !include_hidden {
+                continue;
+            }
+            let Ok(meta) = fs::symlink_metadata(entry.path()) else {
+                continue;
+            };
+            let path = entry.path();
+            if meta.is_dir() {
+                pending.push(path);
+            } else if meta.is_file() && is_compressible(&path, &meta) {
+                files.push((path, meta));
+            }
+        }
+    }
+
+    files
+}
+
+/// Whether a file would be compressed on the fly. Existing sidecars are
+/// never compressed again as their MIME types are not compressible.
+fn is_compressible(path: &Path, meta: &Metadata) -> bool {
+    meta.len() >= MIN_COMPRESS_SIZE as u64
+        && content_type_for(path)
+            .to_str()
+            .ok()
+            .and_then(|s| s.parse::<Mime>().ok())
+            .is_some_and(|mime| mime.is_compressible())
+}
+
+/// Generates a single sidecar.
+///
+/// The sidecar is written to a temporary file renamed into place and gets
+/// the modification time of its source, which is what makes later runs
+/// incremental. Any other modification time means the source changed, even
+/// an older one as restored by `rsync -a` or `tar`.
+fn precompress(
+    runtime: &tokio::runtime::Runtime,
+    path: &Path,
+    meta: &Metadata,
+    format: SidecarFormat,
+    opts: &PrecompressOpts,
+) -> io::Result<Outcome> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::other("invalid file name"))?;
+    let sidecar = path.with_file_name([file_name, ".", format.extension()].concat());
+    let modified = meta.modified()?;
+
+    if !opts.force
+        && let Ok(sidecar_meta) = fs::metadata(&sidecar)
+        && sidecar_meta.modified()? == modified
+    {
+        return Ok(Outcome::UpToDate);
+    }
+
+    let data = fs::read(path)?;
+    let compressed = runtime.block_on(encode(&data, format, opts.level))?;
+
+    if compressed.len() >= data.len() {
+        // A stale sidecar would be served in place of the changed file
+        match fs::remove_file(&sidecar) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => return Ok(Outcome::NotSmaller),
+        }
+    }
+
+    let tmp = path.with_file_name(format!(
+        ".{file_name}.{}.{}.tmp",
+        format.extension(),
+        std::process::id()
+    ));
+    let written =
+        write_sidecar(&tmp, &compressed, modified).and_then(|_| fs::rename(&tmp, &sidecar));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written?;
+
+    Ok(Outcome::Written {
+        bytes_in: data.len() as u64,
+        bytes_out: compressed.len() as u64,
+    })
+}
+
+fn write_sidecar(path: &Path, data: &[u8], modified: SystemTime) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(data)?;
+    file.set_modified(modified)?;
+    file.sync_data()
+}
+
+/// Compresses a buffer with the same encoders and levels as the
+/// on-the-fly compression.
+async fn encode(
+    data: &[u8],
+    format: SidecarFormat,
+    level: CompressionLevel,
+) -> io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt as _;
+
+    let level = algorithm_level(format.coding(), level);
+    let mut out = Vec::with_capacity(data.len() / 2);
+    match format {
+        #[cfg(any(feature = "compression", feature = "compression-brotli"))]
+        SidecarFormat::Br => {
+            BrotliEncoder::with_quality(data, level)
+                .read_to_end(&mut out)
+                .await?
+        }
+        #[cfg(any(feature = "compression", feature = "compression-gzip"))]
+        SidecarFormat::Gzip => {
+            GzipEncoder::with_quality(data, level)
+                .read_to_end(&mut out)
+                .await?
+        }
+        #[cfg(any(feature = "compression", feature = "compression-zstd"))]
+        SidecarFormat::Zstd => {
+            ZstdEncoder::with_quality(data, level)
+                .read_to_end(&mut out)
+                .await?
+        }
+        #[allow(unreachable_patterns)]
+        _ => return Err(io::Error::other("format not available in this build")),
+    };
+    Ok(out)
+}
+
+#[cfg(test)]
+#[cfg(any(feature = "compression", feature = "compression-gzip"))]
+mod tests {
+    use super::*;
+
+    fn opts(root: &Path) -> PrecompressOpts {
+        PrecompressOpts {
+            root: root.to_owned(),
+            formats: vec![SidecarFormat::Gzip],
+            level: CompressionLevel::Default,
+            jobs: 2,
+            force: false,
+            include_hidden: false,
+        }
+    }
+
+    fn write(path: &Path, contents: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn sidecars_are_generated_for_compressible_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let css = "body { color: red; }\n".repeat(50);
+        write(&dir.path().join("assets/main.css"), css.as_bytes());
+        write(&dir.path().join("index.html"), css.as_bytes());
+        write(&dir.path().join("logo.png"), &[7; 1024]);
+        write(&dir.path().join("tiny.txt"), b"tiny");
+        write(&dir.path().join(".hidden/secret.txt"), css.as_bytes());
+
+        let summary = run(&opts(dir.path())).unwrap();
+        assert_eq!(summary.written, 2);
+        assert!(summary.bytes_out < summary.bytes_in);
+        assert!(dir.path().join("assets/main.css.gz").is_file());
+        assert!(dir.path().join("index.html.gz").is_file());
+        assert!(!dir.path().join("logo.png.gz").exists());
+        assert!(!dir.path().join("tiny.txt.gz").exists());
+        assert!(!dir.path().join(".hidden/secret.txt.gz").exists());
+
+        let gz = fs::metadata(dir.path().join("index.html.gz")).unwrap();
+        let html = fs::metadata(dir.path().join("index.html")).unwrap();
+        assert_eq!(gz.modified().unwrap(), html.modified().unwrap());
+    }
+
+    #[test]
+    fn runs_are_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.js");
+        write(&path, "console.log(1);\n".repeat(40).as_bytes());
+
+        assert_eq!(run(&opts(dir.path())).unwrap().written, 1);
+        let summary = run(&opts(dir.path())).unwrap();
+        assert_eq!((summary.written, summary.up_to_date), (0, 1));
+
+        let forced = PrecompressOpts {
+            force: true,
+            ..opts(dir.path())
+        };
+        assert_eq!(run(&forced).unwrap().written, 1);
+
+        write(&path, "console.log(2);\n".repeat(40).as_bytes());
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(run(&opts(dir.path())).unwrap().written, 1);
+    }
+
+    #[test]
+    fn sources_replaced_by_older_files_are_compressed_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.js");
+        write(&path, "console.log(1);\n".repeat(40).as_bytes());
+        assert_eq!(run(&opts(dir.path())).unwrap().written, 1);
+
+        // A deploy preserving the modification times of an older build
+        write(&path, "console.log(2);\n".repeat(40).as_bytes());
+        let earlier = SystemTime::now() - std::time::Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+        assert_eq!(run(&opts(dir.path())).unwrap().written, 1);
+
+        let gz = fs::metadata(dir.path().join("app.js.gz")).unwrap();
+        assert_eq!(gz.modified().unwrap(), earlier);
+    }
+
+    #[test]
+    fn stale_sidecars_are_removed_when_compression_does_not_help() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("noise.txt");
+        // Pseudo-random bytes do not compress
+        let mut seed = 0x2545f491u32;
+        let noise = (0..4096)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect::<Vec<_>>();
+        write(&path, &noise);
+        write(&dir.path().join("noise.txt.gz"), b"outdated");
+        File::options()
+            .write(true)
+            .open(dir.path().join("noise.txt.gz"))
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let summary = run(&opts(dir.path())).unwrap();
+        assert_eq!(summary.not_smaller, 1);
+        assert!(!dir.path().join("noise.txt.gz").exists());
+    }
+
+    #[test]
+    fn missing_root_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(run(&opts(&dir.path().join("missing"))).is_err());
+    }
+}
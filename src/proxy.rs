@@ -0,0 +1,615 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Reverse proxy forwarding selected request paths to upstream servers.
+//!
+//! Each `[[advanced.proxy]]` rule forwards the requests whose path matches
+//! its glob `source` to an HTTP/1 upstream reached over TCP or a Unix
+//! domain socket, before any static file is looked up:
+//!
+//! ```toml
+//! [[advanced.proxy]]
+//! source = "/api/**"
+//! upstream = "http://127.0.0.1:3000"
+//!
+//! [[advanced.proxy]]
+//! source = "/app/**"
+//! upstream = "unix:/run/app.sock"
+//! strip-prefix = "/app"
+//! ```
+//!
+//! Sources are matched against the percent-decoded request path once its
+//! dot-segments are resolved, and only the resolved path is forwarded. Paths
+//! with encoded separators or invalid UTF-8 answer with `400 Bad Request`.
+//!
+//! Request and response bodies are streamed through, hop-by-hop headers are
+//! dropped and the `X-Forwarded-For`, `X-Forwarded-Proto` and
+//! `X-Forwarded-Host` headers are set. An `X-Forwarded-For` chain sent by the
+//! client is only extended when the peer is one of the `trusted-proxies`,
+//! otherwise it is replaced by the peer address. Upstream connections are kept
+//! alive and reused. Upstreams which can not be reached answer with
+//! `502 Bad Gateway` and those exceeding the connect or response headers
+//! timeouts with `504 Gateway Timeout`, while a response body stalling for
+//! longer than the timeout is aborted.
+//!
+//! Proxied responses get the CORS, security and custom headers but no other
+//! post-processing such as compression or `Cache-Control` headers.
+
+use bytes::Bytes;
+use globset::{GlobBuilder, GlobMatcher};
+use http_body::{Frame, SizeHint};
+use http_body_util::BodyExt;
+use hyper::body::Incoming;
+use hyper::client::conn::http1::SendRequest;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::{Request, Response, StatusCode, Uri, Version};
+use hyper_util::rt::TokioIo;
+use percent_encoding::percent_decode_str;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{Instant, Sleep};
+
+use crate::body::{self, Body};
+use crate::handler::RequestHandlerOpts;
+use crate::{Context, Error, Result, error_page, redirects};
+
+/// Default seconds to wait for a connection to the upstream.
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+
+/// Default seconds to wait for the upstream response headers.
+const DEFAULT_TIMEOUT: u64 = 60;
+
+/// Maximum number of idle connections kept per upstream.
+const MAX_IDLE_CONNECTIONS: usize = 32;
+
+/// Headers only meaningful for a single connection, never forwarded.
+const HOP_BY_HOP_HEADERS: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+static X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+static X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// A request body which can be forwarded to an upstream.
+pub trait RequestBody {
+    /// Takes the body out of the request, leaving an empty one behind.
+    fn take(&mut self) -> Body;
+}
+
+impl RequestBody for () {
+    fn take(&mut self) -> Body {
+        body::empty()
+    }
+}
+
+impl RequestBody for Body {
+    fn take(&mut self) -> Body {
+        std::mem::take(self)
+    }
+}
+
+/// The configured reverse proxy rules.
+pub struct Proxies {
+    rules: Vec<ProxyRule>,
+    https: bool,
+}
+
+struct ProxyRule {
+    source: GlobMatcher,
+    host: Option<String>,
+    upstream: Upstream,
+    strip_prefix: Option<String>,
+    preserve_host: bool,
+    connect_timeout: Duration,
+    timeout: Duration,
+    /// Upstream connections kept alive for reuse.
+    idle: Mutex<Vec<SendRequest<Body>>>,
+}
+
+/// An upstream server address.
+struct Upstream {
+    endpoint: Endpoint,
+    /// `Host` header sent to the upstream.
+    host: HeaderValue,
+    /// Path prepended to the forwarded request paths, without a trailing slash.
+    base_path: String,
+}
+
+enum Endpoint {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+/// Initializes the reverse proxy rules.
+pub fn init(https: bool, handler_opts: &mut RequestHandlerOpts) -> Result {
+    let Some(entries) = handler_opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.proxy.as_ref())
+        .filter(|entries| !entries.is_empty())
+    else {
+        tracing::info!(enabled = false, "reverse proxy");
+        return Ok(());
+    };
+
+    let mut rules = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let source = GlobBuilder::new(&entry.source)
+            .literal_separator(true)
+            .build()
+            .with_context(|| {
+                format!(
+                    "can not compile glob pattern for proxy source: {}",
+                    entry.source
+                )
+            })?
+            .compile_matcher();
+        let upstream = Upstream::parse(&entry.upstream)
+            .with_context(|| format!("invalid upstream for proxy source: {}", entry.source))?;
+        tracing::info!(
+            "reverse proxy `{}` -> `{}`: host={:?}",
+            entry.source,
+            entry.upstream,
+            entry.host
+        );
+        rules.push(ProxyRule {
+            source,
+            host: entry.host.to_owned(),
+            upstream,
+            strip_prefix: entry
+                .strip_prefix
+                .as_deref()
+                .map(|prefix| prefix.trim_end_matches('/').to_owned()),
+            preserve_host: entry.preserve_host.unwrap_or(false),
+            connect_timeout: Duration::from_secs(
+                entry.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+            ),
+            timeout: Duration::from_secs(entry.timeout.unwrap_or(DEFAULT_TIMEOUT)),
+            idle: Mutex::new(Vec::new()),
+        });
+    }
+    handler_opts.proxy = Some(Arc::new(Proxies { rules, https }));
+    Ok(())
+}
+
+/// Checks whether a request is forwarded to an upstream.
+pub(crate) fn is_proxied<T>(opts: &RequestHandlerOpts, req: &Request<T>) -> bool {
+    opts.proxy
+        .as_ref()
+        .is_some_and(|proxies| proxies.find(req).is_some())
+}
+
+/// Forwards a request to its upstream if any rule matches it.
+pub(crate) async fn pre_process<T: RequestBody>(
+    opts: &RequestHandlerOpts,
+    req: &mut Request<T>,
+    remote_addr: Option<SocketAddr>,
+) -> Option<Result<Response<Body>, Error>> {
+    let proxies = opts.proxy.as_ref()?;
+    let (rule, path) = proxies.find(req)?;
+    tracing::trace!("uri matches proxy source `{}`", rule.source.glob());
+
+    let result = match path {
+        Some(path) => match rule
+            .forward(opts, proxies.https, req, &path, remote_addr)
+            .await
+        {
+            Ok(resp) => return Some(Ok(resp)),
+            Err(status) => status,
+        },
+        None => StatusCode::BAD_REQUEST,
+    };
+    Some(error_page::error_response(
+        req.uri(),
+        req.method(),
+        &result,
+        &opts.page404,
+        &opts.page50x,
+    ))
+}
+
+impl Proxies {
+    /// Returns the first rule matching the request along with the path to
+    /// forward, if it could be normalized.
+    fn find<T>(&self, req: &Request<T>) -> Option<(&ProxyRule, Option<String>)> {
+        let (path, decoded_path) = match normalize_path(req.uri().path()) {
+            Some((path, decoded_path)) => (Some(path), decoded_path),
+            None => (None, req.uri().path().to_owned()),
+        };
+        let mut uri_host = None;
+        let rule = self.rules.iter().find(|rule| {
+            if let Some(host) = &rule.host {
+                let uri_host = uri_host.get_or_insert_with(|| redirects::request_host(req));
+                if host != uri_host {
+                    return false;
+                }
+            }
+            rule.source.is_match(&decoded_path)
+        })?;
+        Some((rule, path))
+    }
+}
+
+/// Resolves the dot-segments of a request path, percent-encoded ones too.
+///
+/// Returns the resolved path, with its segments encoded as received, and its
+/// decoded form, or `None` if a segment is not valid UTF-8 or contains an
+/// encoded path separator.
+fn normalize_path(path: &str) -> Option<(String, String)> {
+    let mut segments = Vec::new();
+    let mut trailing_dot = false;
+    for segment in path.split('/').skip(1) {
+        let decoded = percent_decode_str(segment).decode_utf8().ok()?;
+        if decoded.contains(['/', '\\']) {
+            return None;
+        }
+        trailing_dot = matches!(decoded.as_ref(), "." | "..");
+        match decoded.as_ref() {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push((segment, decoded)),
+        }
+    }
+    if trailing_dot {
+        segments.push(("", "".into()));
+    }
+    if segments.is_empty() {
+        return Some(("/".to_owned(), "/".to_owned()));
+    }
+
+    let mut normalized = String::with_capacity(path.len());
+    let mut decoded_path = String::with_capacity(path.len());
+    for (segment, decoded) in segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+        decoded_path.push('/');
+        decoded_path.push_str(&decoded);
+    }
+    Some((normalized, decoded_path))
+}
+
+impl ProxyRule {
+    /// Sends the request to the upstream and returns its response.
+    async fn forward<T: RequestBody>(
+        &self,
+        opts: &RequestHandlerOpts,
+        https: bool,
+        req: &mut Request<T>,
+        path: &str,
+        remote_addr: Option<SocketAddr>,
+    ) -> Result<Response<Body>, StatusCode> {
+        let uri = self.upstream_uri(path, req.uri().query())?;
+        let mut headers = req.headers().clone();
+        remove_hop_by_hop_headers(&mut headers);
+        let host = req.headers().get(header::HOST).cloned();
+        if !self.preserve_host || host.is_none() {
+            headers.insert(header::HOST, self.upstream.host.clone());
+        }
+        if let Some(host) = host {
+            headers.insert(X_FORWARDED_HOST.clone(), host);
+        }
+        headers.insert(
+            X_FORWARDED_PROTO.clone(),
+            HeaderValue::from_static(if https { "https" } else { "http" }),
+        );
+        if let Some(addr) = remote_addr {
+            // Only extend the chain sent by a proxy explicitly trusted
+            let trusted = opts.trusted_proxies.contains(&addr.ip());
+            let forwarded_for = match headers.get(&X_FORWARDED_FOR) {
+                Some(chain) if trusted => {
+                    format!("{}, {}", chain.to_str().unwrap_or_default(), addr.ip())
+                }
+                _ => addr.ip().to_string(),
+            };
+            if let Ok(value) = HeaderValue::from_str(&forwarded_for) {
+                headers.insert(X_FORWARDED_FOR.clone(), value);
+            }
+        }
+
+        let mut upstream_req = Request::new(req.body_mut().take());
+        *upstream_req.method_mut() = req.method().clone();
+        *upstream_req.uri_mut() = uri;
+        *upstream_req.version_mut() = Version::HTTP_11;
+        *upstream_req.headers_mut() = headers;
+
+        let mut sender = match self.idle_connection() {
+            Some(sender) => sender,
+            None => self.open().await?,
+        };
+        let resp = match tokio::time::timeout(self.timeout, sender.send_request(upstream_req)).await
+        {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(err)) => {
+                tracing::error!("proxy upstream request failed: {err}");
+                return Err(StatusCode::BAD_GATEWAY);
+            }
+            Err(_) => {
+                tracing::error!("timed out waiting for the proxy upstream response");
+                return Err(StatusCode::GATEWAY_TIMEOUT);
+            }
+        };
+        // The connection is ready again once the response body is read
+        self.release(sender);
+
+        let (mut parts, incoming) = resp.into_parts();
+        remove_hop_by_hop_headers(&mut parts.headers);
+        let body = IdleTimeoutBody {
+            inner: incoming,
+            timeout: self.timeout,
+            sleep: Box::pin(tokio::time::sleep(self.timeout)),
+        };
+        Ok(Response::from_parts(parts, body.boxed()))
+    }
+
+    /// Takes an idle connection to the upstream if any, dropping closed ones.
+    fn idle_connection(&self) -> Option<SendRequest<Body>> {
+        let mut idle = self.idle.lock().unwrap_or_else(|err| err.into_inner());
+        idle.retain(|sender| !sender.is_closed());
+        let ready = idle.iter().position(|sender| sender.is_ready())?;
+        Some(idle.swap_remove(ready))
+    }
+
+    /// Keeps a connection to the upstream for later requests.
+    fn release(&self, sender: SendRequest<Body>) {
+        let mut idle = self.idle.lock().unwrap_or_else(|err| err.into_inner());
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(sender);
+        }
+    }
+
+    /// Opens a new connection to the upstream.
+    async fn open(&self) -> Result<SendRequest<Body>, StatusCode> {
+        match &self.upstream.endpoint {
+            Endpoint::Tcp(addr) => self.handshake(tokio::net::TcpStream::connect(addr)).await,
+            #[cfg(unix)]
+            Endpoint::Unix(path) => self.handshake(tokio::net::UnixStream::connect(path)).await,
+        }
+    }
+
+    /// Waits for a connection to the upstream and performs the HTTP/1 handshake.
+    async fn handshake<S>(
+        &self,
+        connect: impl Future<Output = io::Result<S>>,
+    ) -> Result<SendRequest<Body>, StatusCode>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let connecting = async {
+            let stream = connect.await?;
+            hyper::client::conn::http1::handshake(TokioIo::new(stream))
+                .await
+                .map_err(io::Error::other)
+        };
+        let (sender, conn) = match tokio::time::timeout(self.connect_timeout, connecting).await {
+            Ok(Ok(handshake)) => handshake,
+            Ok(Err(err)) => {
+                tracing::error!("unable to connect to the proxy upstream: {err}");
+                return Err(StatusCode::BAD_GATEWAY);
+            }
+            Err(_) => {
+                tracing::error!("timed out connecting to the proxy upstream");
+                return Err(StatusCode::GATEWAY_TIMEOUT);
+            }
+        };
+        tokio::spawn(async move {
+            if let Err(err) = conn.await {
+                tracing::error!("proxy upstream connection error: {err}");
+            }
+        });
+        Ok(sender)
+    }
+
+    /// Builds the origin-form URI of the request sent to the upstream.
+    fn upstream_uri(&self, mut path: &str, query: Option<&str>) -> Result<Uri, StatusCode> {
+        if let Some(prefix) = &self.strip_prefix {
+            path = match path.strip_prefix(prefix.as_str()) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+                _ => path,
+            };
+        }
+        let mut path_and_query = format!("{}{}", self.upstream.base_path, path);
+        if !path_and_query.starts_with('/') {
+            path_and_query.insert(0, '/');
+        }
+        if let Some(query) = query {
+            path_and_query.push('?');
+            path_and_query.push_str(query);
+        }
+        path_and_query.parse().map_err(|err| {
+            tracing::error!("invalid proxy upstream uri `{path_and_query}`: {err}");
+            StatusCode::BAD_GATEWAY
+        })
+    }
+}
+
+impl Upstream {
+    /// Parses an `http://host[:port][/path]` or `unix:/path/to/socket` URL.
+    fn parse(upstream: &str) -> Result<Self> {
+        if let Some(path) = upstream.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(Self {
+                endpoint: Endpoint::Unix(path.into()),
+                host: HeaderValue::from_static("localhost"),
+                base_path: String::new(),
+            });
+            #[cfg(not(unix))]
+            bail!("unix socket upstreams are not supported on this platform: {path}");
+        }
+
+        let uri: Uri = upstream.parse()?;
+        if uri.scheme_str() != Some("http") {
+            bail!("only `http` and `unix` upstreams are supported: {upstream}");
+        }
+        let Some(authority) = uri.authority() else {
+            bail!("missing upstream host: {upstream}");
+        };
+        let addr = format!(
+            "{}:{}",
+            authority.host(),
+            authority.port_u16().unwrap_or(80)
+        );
+        Ok(Self {
+            endpoint: Endpoint::Tcp(addr),
+            host: HeaderValue::from_str(authority.as_str())?,
+            base_path: uri.path().trim_end_matches('/').to_owned(),
+        })
+    }
+}
+
+/// An upstream response body aborted when no data is received in time.
+struct IdleTimeoutBody {
+    inner: Incoming,
+    timeout: Duration,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl http_body::Body for IdleTimeoutBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_frame(cx) {
+            Poll::Ready(frame) => {
+                this.sleep.as_mut().reset(Instant::now() + this.timeout);
+                Poll::Ready(frame.map(|frame| frame.map_err(io::Error::other)))
+            }
+            Poll::Pending => match this.sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "timed out reading the proxy upstream response body",
+                )))),
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Removes the hop-by-hop headers, including those listed by `Connection`.
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed.iter().chain(HOP_BY_HOP_HEADERS.iter()) {
+        headers.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(upstream: &str, strip_prefix: Option<&str>) -> ProxyRule {
+        ProxyRule {
+            source: globset::Glob::new("/**").unwrap().compile_matcher(),
+            host: None,
+            upstream: Upstream::parse(upstream).unwrap(),
+            strip_prefix: strip_prefix.map(str::to_owned),
+            preserve_host: false,
+            connect_timeout: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn upstream_uri(rule: &ProxyRule, uri: &str) -> String {
+        let uri: Uri = uri.parse().unwrap();
+        rule.upstream_uri(uri.path(), uri.query())
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn upstream_parse() {
+        let upstream = Upstream::parse("http://127.0.0.1:3000/v1/").unwrap();
+        assert!(matches!(&upstream.endpoint, Endpoint::Tcp(addr) if addr == "127.0.0.1:3000"));
+        assert_eq!(upstream.host, "127.0.0.1:3000");
+        assert_eq!(upstream.base_path, "/v1");
+
+        let upstream = Upstream::parse("http://backend").unwrap();
+        assert!(matches!(&upstream.endpoint, Endpoint::Tcp(addr) if addr == "backend:80"));
+        assert_eq!(upstream.base_path, "");
+
+        assert!(Upstream::parse("https://backend").is_err());
+        assert!(Upstream::parse("backend:3000").is_err());
+    }
+
+    #[test]
+    fn upstream_uri_paths() {
+        let api = rule("http://127.0.0.1:3000", None);
+        assert_eq!(upstream_uri(&api, "/api/users?page=2"), "/api/users?page=2");
+
+        let stripped = rule("http://127.0.0.1:3000/v1", Some("/api"));
+        assert_eq!(upstream_uri(&stripped, "/api/users"), "/v1/users");
+        assert_eq!(upstream_uri(&stripped, "/api"), "/v1");
+        assert_eq!(upstream_uri(&stripped, "/apis"), "/v1/apis");
+
+        let root = rule("http://127.0.0.1:3000", Some("/api"));
+        assert_eq!(upstream_uri(&root, "/api"), "/");
+    }
+
+    #[test]
+    fn normalized_paths() {
+        let normalize = normalize_path;
+        let same = |path: &str| Some((path.to_owned(), path.to_owned()));
+        assert_eq!(normalize("/"), same("/"));
+        assert_eq!(normalize("/api/users/"), same("/api/users/"));
+        assert_eq!(normalize("/api/../admin"), same("/admin"));
+        assert_eq!(normalize("/api/%2e%2E/admin"), same("/admin"));
+        assert_eq!(normalize("/api/./users/.%2e"), same("/api/"));
+        assert_eq!(normalize("/../../admin"), same("/admin"));
+        assert_eq!(
+            normalize("/api/a%20b"),
+            Some(("/api/a%20b".to_owned(), "/api/a b".to_owned()))
+        );
+        assert_eq!(normalize("/api/..%2fadmin"), None);
+        assert_eq!(normalize("/api/..%5cadmin"), None);
+        assert_eq!(normalize("/api/%ff"), None);
+    }
+
+    #[test]
+    fn hop_by_hop_headers_removed() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, "keep-alive, x-secret".parse().unwrap());
+        headers.insert("keep-alive", "timeout=5".parse().unwrap());
+        headers.insert("x-secret", "1".parse().unwrap());
+        headers.insert(header::ACCEPT, "*/*".parse().unwrap());
+        remove_hop_by_hop_headers(&mut headers);
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key(header::ACCEPT));
+    }
+}
@@ -0,0 +1,377 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Redirect maps of exact request paths loaded from CSV or JSON files.
+//!
+//! Each `[[advanced.redirect-maps]]` file maps request paths to their
+//! destination and optional status, and is looked up in constant time
+//! before the `[[advanced.redirects]]` rules run:
+//!
+//! ```text
+//! # redirects.csv: source,destination[,status]
+//! /old-page,/new-page
+//! /legacy/about.php,https://example.com/about,302
+//! ```
+//!
+//! ```json
+//! [{ "source": "/old-page", "destination": "/new-page", "status": 308 }]
+//! ```
+//!
+//! A JSON object of source to destination strings is accepted too. The
+//! `410` and `451` statuses answer with an error page and need no
+//! destination. Paths
+//! match percent-decoded and regardless of a trailing slash, the request
+//! query string is appended to the destination. A background thread checks
+//! the files for changes once per second, a file failing to reload keeps
+//! its previous entries.
+
+use hyper::header::HeaderValue;
+use hyper::{Request, Response, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+use crate::body::Body;
+use crate::fs::meta::{FileStamp, file_stamp};
+use crate::handler::RequestHandlerOpts;
+use crate::settings::file::RedirectsKind;
+use crate::{Context, Error, Result, redirects};
+
+/// Time between two checks for changes of the map files.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The configured redirect maps.
+pub struct RedirectMaps {
+    maps: Vec<RedirectMap>,
+}
+
+struct RedirectMap {
+    path: PathBuf,
+    host: Option<String>,
+    default_kind: StatusCode,
+    /// Current entries, replaced as a whole when the file is reloaded.
+    entries: RwLock<Arc<Entries>>,
+}
+
+type Entries = HashMap<String, Target>;
+
+#[derive(Debug, PartialEq)]
+struct Target {
+    destination: String,
+    kind: StatusCode,
+}
+
+/// An entry of a JSON map file.
+#[derive(Deserialize)]
+struct JsonEntry {
+    source: String,
+    destination: String,
+    status: Option<u16>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonMap {
+    List(Vec<JsonEntry>),
+    Object(HashMap<String, String>),
+}
+
+/// Initializes the redirect maps, loading their files.
+pub fn init(handler_opts: &mut RequestHandlerOpts) -> Result {
+    let Some(entries) = handler_opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.redirect_maps.as_ref())
+        .filter(|entries| !entries.is_empty())
+    else {
+        tracing::info!(enabled = false, "redirect maps");
+        return Ok(());
+    };
+
+    let mut maps = Vec::with_capacity(entries.len());
+    let mut stamps = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let default_kind = entry
+            .kind
+            .map_or(StatusCode::MOVED_PERMANENTLY, RedirectsKind::status_code);
+        stamps.push(file_stamp(&entry.path));
+        let entries = load(&entry.path, default_kind)?;
+        tracing::info!(
+            "redirect map `{}`: host={:?} entries={}",
+            entry.path.display(),
+            entry.host,
+            entries.len()
+        );
+        maps.push(RedirectMap {
+            path: entry.path.to_owned(),
+            host: entry.host.to_owned(),
+            default_kind,
+            entries: RwLock::new(Arc::new(entries)),
+        });
+    }
+    let redirect_maps = Arc::new(RedirectMaps { maps });
+    spawn_reloader(Arc::downgrade(&redirect_maps), stamps)?;
+    handler_opts.redirect_maps = Some(redirect_maps);
+    Ok(())
+}
+
+/// Starts the thread reloading the map files when they change, it exits
+/// once the maps are dropped.
+fn spawn_reloader(redirect_maps: Weak<RedirectMaps>, mut stamps: Vec<FileStamp>) -> Result {
+    std::thread::Builder::new()
+        .name("sws-redirect-maps".into())
+        .spawn(move || {
+            loop {
+                std::thread::sleep(RELOAD_INTERVAL);
+                let Some(redirect_maps) = redirect_maps.upgrade() else {
+                    break;
+                };
+                for (map, stamp) in redirect_maps.maps.iter().zip(&mut stamps) {
+                    map.reload(stamp);
+                }
+            }
+        })
+        .with_context(|| "unable to spawn the redirect maps reload thread")?;
+    Ok(())
+}
+
+/// Applies the redirect maps to a request if necessary.
+pub(crate) fn pre_process<T>(
+    opts: &RequestHandlerOpts,
+    req: &Request<T>,
+) -> Option<Result<Response<Body>, Error>> {
+    let redirect_maps = opts.redirect_maps.as_ref()?;
+    let uri_path = percent_decode_str(req.uri().path()).decode_utf8_lossy();
+    let uri_path = normalize(&uri_path);
+    let uri_host = redirects::request_host(req);
+    for map in &redirect_maps.maps {
+        if map.host.as_ref().is_some_and(|host| *host != uri_host) {
+            continue;
+        }
+        if let Some(target) = map.entries().get(uri_path) {
+            tracing::trace!("uri matches redirect map `{}`", map.path.display());
+            return redirects::location_response(
+                opts,
+                req,
+                target.destination.to_owned(),
+                target.kind,
+                true,
+            );
+        }
+    }
+    None
+}
+
+impl RedirectMap {
+    /// Returns the current entries of the map.
+    fn entries(&self) -> Arc<Entries> {
+        self.entries
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    /// Reloads the entries if the file changed since its last known stamp.
+    fn reload(&self, stamp: &mut FileStamp) {
+        let current = file_stamp(&self.path);
+        if current == *stamp {
+            return;
+        }
+        *stamp = current;
+        match load(&self.path, self.default_kind) {
+            Ok(entries) => {
+                tracing::info!(
+                    "reloaded redirect map `{}`: entries={}",
+                    self.path.display(),
+                    entries.len()
+                );
+                *self.entries.write().unwrap_or_else(|err| err.into_inner()) = Arc::new(entries);
+            }
+            Err(err) => tracing::error!("{err:?}"),
+        }
+    }
+}
+
+/// Strips the trailing slash of a path, except for the root one.
+fn normalize(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// Loads a map file, JSON when its extension is `.json` and CSV otherwise.
+fn load(path: &Path, default_kind: StatusCode) -> Result<Entries> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read redirect map `{}`", path.display()))?;
+    let is_json = path.extension().is_some_and(|ext| ext == "json");
+    let entries = match is_json {
+        true => parse_json(&content, default_kind),
+        false => parse_csv(&content, default_kind),
+    };
+    entries.with_context(|| format!("invalid redirect map `{}`", path.display()))
+}
+
+fn parse_csv(content: &str, default_kind: StatusCode) -> Result<Entries> {
+    let mut entries = Entries::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line
+            .split(',')
+            .map(|f| f.trim().trim_matches('"'))
+            .collect();
+        // Skip an optional header row
+        if i == 0 && fields[0].eq_ignore_ascii_case("source") {
+            continue;
+        }
+        // Destinations may contain commas, the status is the trailing number if any
+        let (destination, status) = match fields.as_slice() {
+            [_] => (String::new(), None),
+            [_, rest @ .., status] if !rest.is_empty() && status.parse::<u16>().is_ok() => {
+                (rest.join(","), status.parse().ok())
+            }
+            [_, rest @ ..] => (rest.join(","), None),
+            [] => unreachable!("split always yields a field"),
+        };
+        insert(&mut entries, fields[0], destination, status, default_kind)
+            .with_context(|| format!("line {}", i + 1))?;
+    }
+    Ok(entries)
+}
+
+fn parse_json(content: &str, default_kind: StatusCode) -> Result<Entries> {
+    let mut entries = Entries::new();
+    match serde_json::from_str(content)? {
+        JsonMap::List(list) => {
+            for entry in list {
+                insert(
+                    &mut entries,
+                    &entry.source,
+                    entry.destination,
+                    entry.status,
+                    default_kind,
+                )
+                .with_context(|| format!("entry `{}`", entry.source))?;
+            }
+        }
+        JsonMap::Object(object) => {
+            for (source, destination) in object {
+                insert(&mut entries, &source, destination, None, default_kind)
+                    .with_context(|| format!("entry `{source}`"))?;
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Validates and inserts an entry, the first one of a source wins.
+fn insert(
+    entries: &mut Entries,
+    source: &str,
+    destination: String,
+    status: Option<u16>,
+    default_kind: StatusCode,
+) -> Result {
+    if !source.starts_with('/') {
+        bail!("source `{source}` is not an absolute path");
+    }
+    let kind = match status {
+        Some(code @ (301 | 302 | 303 | 307 | 308 | 410 | 451)) => StatusCode::from_u16(code)?,
+        Some(code) => bail!("unsupported redirect status `{code}`"),
+        None => default_kind,
+    };
+    if destination.is_empty() && kind.is_redirection() {
+        bail!("missing destination");
+    }
+    HeaderValue::from_str(&destination)
+        .with_context(|| format!("invalid destination `{destination}`"))?;
+    let source = percent_decode_str(source).decode_utf8_lossy();
+    entries
+        .entry(normalize(&source).to_owned())
+        .or_insert(Target { destination, kind });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(destination: &str, kind: StatusCode) -> Target {
+        Target {
+            destination: destination.to_owned(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn csv_maps_are_parsed() {
+        let entries = parse_csv(
+            "source,destination,status\n\
+             # comment\n\
+             /old-page/,/new-page\n\
+             \"/legacy/about.php\",https://example.com/?a=1,b=2,302\n\
+             /old-page,/ignored\n",
+            StatusCode::MOVED_PERMANENTLY,
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries["/old-page"],
+            target("/new-page", StatusCode::MOVED_PERMANENTLY)
+        );
+        assert_eq!(
+            entries["/legacy/about.php"],
+            target("https://example.com/?a=1,b=2", StatusCode::FOUND)
+        );
+    }
+
+    #[test]
+    fn json_maps_are_parsed() {
+        let entries = parse_json(
+            r#"[{"source": "/a", "destination": "/b", "status": 308}, {"source": "/c", "destination": "/d"}]"#,
+            StatusCode::FOUND,
+        )
+        .unwrap();
+        assert_eq!(entries["/a"], target("/b", StatusCode::PERMANENT_REDIRECT));
+        assert_eq!(entries["/c"], target("/d", StatusCode::FOUND));
+
+        let entries = parse_json(r#"{"/": "/home"}"#, StatusCode::FOUND).unwrap();
+        assert_eq!(entries["/"], target("/home", StatusCode::FOUND));
+    }
+
+    #[test]
+    fn sources_are_percent_decoded() {
+        let entries = parse_csv("/caf%C3%A9,/cafe\n/a%20b/,/ab\n", StatusCode::FOUND).unwrap();
+        assert_eq!(entries["/café"], target("/cafe", StatusCode::FOUND));
+        assert_eq!(entries["/a b"], target("/ab", StatusCode::FOUND));
+    }
+
+    #[test]
+    fn terminal_entries_need_no_destination() {
+        let entries = parse_csv("/removed\n/blocked,,451\n", StatusCode::GONE).unwrap();
+        assert_eq!(entries["/removed"], target("", StatusCode::GONE));
+        assert_eq!(
+            entries["/blocked"],
+            target("", StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS)
+        );
+    }
+
+    #[test]
+    fn invalid_maps_are_rejected() {
+        let kind = StatusCode::MOVED_PERMANENTLY;
+        assert!(parse_csv("/a\n", kind).is_err());
+        assert!(parse_csv("/a,,302\n", kind).is_err());
+        assert!(parse_csv("a,/b\n", kind).is_err());
+        assert!(parse_csv("/a,/b,200\n", kind).is_err());
+        assert!(parse_json(r#"[{"source": "/a"}]"#, kind).is_err());
+        assert!(parse_json(r#"{"/a": "/b\n"}"#, kind).is_err());
+    }
+}
@@ -23,7 +23,9 @@ use hyper::{Request, Response, StatusCode};
 use regex_lite::Regex;
 
 use crate::body::Body;
-use crate::{Error, error_page, handler::RequestHandlerOpts, settings::Redirects};
+use crate::{
+    Error, conditions::Conditions, error_page, handler::RequestHandlerOpts, settings::Redirects,
+};
 
 /// Maximum URI length (bytes) that will be fed to the redirect regex
 /// engine. Requests above this size skip redirect matching entirely.
@@ -36,7 +38,7 @@ pub(crate) const MAX_URI_LEN_FOR_REGEX: usize = 8 * 1024;
 /// Applies redirect rules to a request if necessary.
 pub(crate) fn pre_process<T>(
     opts: &RequestHandlerOpts,
-    req: &Request<T>,
+    req: &mut Request<T>,
 ) -> Option<Result<Response<Body>, Error>> {
     let redirects = opts.advanced_opts.as_ref()?.redirects.as_deref()?;
 
@@ -52,6 +54,25 @@ pub(crate) fn pre_process<T>(
         );
         return None;
     }
+    let uri_host = request_host(req);
+    let mut source_matched = Vec::new();
+    let found = find_redirection(
+        Some(req),
+        &uri_host,
+        uri_path,
+        Some(redirects),
+        |conditions| source_matched.push(conditions),
+    );
+    for conditions in source_matched {
+        conditions.record_vary(req);
+    }
+    let (matched, captures) = found?;
+    redirect(opts, req, matched, &captures)
+}
+
+/// Returns the request host, including the port if any.
+pub(crate) fn request_host<T>(req: &Request<T>) -> String {
+    let uri = req.uri();
     let host = req
         .headers()
         .get(http::header::HOST)
@@ -61,20 +82,58 @@ pub(crate) fn pre_process<T>(
     if let Some(uri_port) = uri.port_u16() {
         uri_host.push_str(&format!(":{uri_port}"));
     }
-    let matched = get_redirection(&uri_host, uri_path, Some(redirects))?;
-    let mut dest = match replace_placeholders(
-        uri_path,
+    uri_host
+}
+
+/// Produces the redirect response of a rule matching the request, given
+/// the values captured by its conditions.
+pub(crate) fn redirect<T>(
+    opts: &RequestHandlerOpts,
+    req: &Request<T>,
+    matched: &Redirects,
+    captures: &[String],
+) -> Option<Result<Response<Body>, Error>> {
+    let subject = match_subject(req, matched.match_query);
+    let dest = match replace_placeholders_with(
+        subject,
         &matched.source,
+        captures,
         &matched.destination,
         &matched.replacer,
     ) {
         Ok(dest) => dest,
         Err(err) => return handle_error(err, opts, req),
     };
+    location_response(opts, req, dest, matched.kind, !matched.match_query)
+}
+
+/// Returns the part of the request URI a rule source matches against,
+/// the path and query if `match_query` is set or the path otherwise.
+pub(crate) fn match_subject<T>(req: &Request<T>, match_query: bool) -> &str {
+    let uri = req.uri();
+    match uri.path_and_query() {
+        Some(path_and_query) if match_query => path_and_query.as_str(),
+        _ => uri.path(),
+    }
+}
+
+/// Produces a redirect response to `dest` with the given status, or the
+/// error page of the 410 and 451 statuses. The query string of the request
+/// is appended to `dest` if `append_query` is set.
+pub(crate) fn location_response<T>(
+    opts: &RequestHandlerOpts,
+    req: &Request<T>,
+    mut dest: String,
+    kind: StatusCode,
+    append_query: bool,
+) -> Option<Result<Response<Body>, Error>> {
+    if kind.is_client_error() {
+        return terminal_response(opts, req, &dest, kind);
+    }
 
     // Preserve the client's query string across the redirect
     // in an Apache's QSA rewrite option fashion.
-    if let Some(query) = uri.query() {
+    if let Some(query) = req.uri().query().filter(|_| append_query) {
         if !dest.ends_with('?') && !dest.ends_with('&') {
             dest.push(if dest.contains('?') { '&' } else { '?' });
         }
@@ -85,11 +144,8 @@ pub(crate) fn pre_process<T>(
         Ok(loc) => {
             let mut resp = Response::new(crate::body::empty());
             resp.headers_mut().insert(hyper::header::LOCATION, loc);
-            *resp.status_mut() = matched.kind;
-            tracing::trace!(
-                "uri matches redirects glob pattern, redirecting with status '{}'",
-                matched.kind
-            );
+            *resp.status_mut() = kind;
+            tracing::trace!("uri matches redirects rule, redirecting with status '{kind}'");
             Some(Ok(resp))
         }
         Err(err) => handle_error(
@@ -100,6 +156,43 @@ pub(crate) fn pre_process<T>(
     }
 }
 
+/// Produces the error page of a rule ending the request with a 410 or 451
+/// status. The destination of the latter, if any, is linked as the
+/// blocking authority (RFC 7725).
+pub(crate) fn terminal_response<T>(
+    opts: &RequestHandlerOpts,
+    req: &Request<T>,
+    dest: &str,
+    kind: StatusCode,
+) -> Option<Result<Response<Body>, Error>> {
+    tracing::trace!("uri matches a rule, responding with status '{kind}'");
+    let mut resp = match error_page::error_response(
+        req.uri(),
+        req.method(),
+        &kind,
+        &opts.page404,
+        &opts.page50x,
+    ) {
+        Ok(resp) => resp,
+        Err(err) => return Some(Err(err)),
+    };
+    if kind == StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS && !dest.is_empty() {
+        match HeaderValue::from_str(&format!("<{dest}>; rel=\"blocked-by\"")) {
+            Ok(link) => {
+                resp.headers_mut().insert(hyper::header::LINK, link);
+            }
+            Err(err) => {
+                return handle_error(
+                    Error::new(err).context("invalid blocking authority link"),
+                    opts,
+                    req,
+                );
+            }
+        }
+    }
+    Some(Ok(resp))
+}
+
 /// Replaces placeholders in the destination URI by matching capture groups from the original URI.
 #[doc(hidden)]
 pub fn replace_placeholders(
@@ -107,6 +200,18 @@ pub fn replace_placeholders(
     regex: &Regex,
     dest_uri: &str,
     ac: &aho_corasick::AhoCorasick,
+) -> Result<String, Error> {
+    replace_placeholders_with(orig_uri, regex, &[], dest_uri, ac)
+}
+
+/// Same as [`replace_placeholders`] with the `extra` values replacing the
+/// placeholders that follow the capture groups, before the `$name` ones.
+pub(crate) fn replace_placeholders_with(
+    orig_uri: &str,
+    regex: &Regex,
+    extra: &[String],
+    dest_uri: &str,
+    ac: &aho_corasick::AhoCorasick,
 ) -> Result<String, Error> {
     let regex_caps = if let Some(regex_caps) = regex.captures(orig_uri) {
         regex_caps
@@ -116,6 +221,13 @@ pub fn replace_placeholders(
 
     let caps: Vec<&str> = (0..regex_caps.len())
         .map(|i| regex_caps.get(i).map(|s| s.as_str()).unwrap_or(""))
+        .chain(extra.iter().map(String::as_str))
+        .chain(
+            regex
+                .capture_names()
+                .flatten()
+                .map(|name| regex_caps.name(name).map_or("", |s| s.as_str())),
+        )
         .collect();
 
     tracing::debug!("url redirects/rewrites regex equivalent: {regex}");
@@ -149,28 +261,65 @@ pub(crate) fn handle_error<T>(
 
 /// It returns a redirect's destination path and status code if the current request uri
 /// matches against the provided redirect's array.
+///
+/// Rules with `has` or `missing` conditions are skipped, see
+/// [`get_redirection_with_request`].
 pub fn get_redirection<'a>(
     uri_host: &'a str,
     uri_path: &'a str,
     redirects_opts: Option<&'a [Redirects]>,
 ) -> Option<&'a Redirects> {
-    if let Some(redirects_vec) = redirects_opts {
-        for redirect_entry in redirects_vec {
-            // Match `host` redirect against `uri_host` if specified
-            if let Some(host) = &redirect_entry.host {
-                tracing::debug!(
-                    "checking host '{host}' redirect entry against uri host '{uri_host}'"
-                );
-                if !host.eq(uri_host) {
-                    continue;
-                }
-            }
+    find_redirection::<()>(None, uri_host, uri_path, redirects_opts, |_| {})
+        .map(|(matched, _)| matched)
+}
+
+/// Same as [`get_redirection`] for the given request, whose query may be
+/// matched by the rule source and which must meet the rule conditions.
+/// The values captured by the `has` conditions are returned along with
+/// the rule.
+pub fn get_redirection_with_request<'a, T>(
+    req: &Request<T>,
+    uri_host: &str,
+    uri_path: &str,
+    redirects_opts: Option<&'a [Redirects]>,
+) -> Option<(&'a Redirects, Vec<String>)> {
+    find_redirection(Some(req), uri_host, uri_path, redirects_opts, |_| {})
+}
 
-            // Match source glob pattern against the request uri path
-            if redirect_entry.source.is_match(uri_path) {
-                return Some(redirect_entry);
+/// Returns the first rule matching the request, calling `source_matched`
+/// with the conditions of every rule whose source matched along the way.
+fn find_redirection<'a, T>(
+    req: Option<&Request<T>>,
+    uri_host: &str,
+    uri_path: &str,
+    redirects_opts: Option<&'a [Redirects]>,
+    mut source_matched: impl FnMut(&'a Conditions),
+) -> Option<(&'a Redirects, Vec<String>)> {
+    for redirect_entry in redirects_opts? {
+        // Match `host` redirect against `uri_host` if specified
+        if let Some(host) = &redirect_entry.host {
+            tracing::debug!("checking host '{host}' redirect entry against uri host '{uri_host}'");
+            if !host.eq(uri_host) {
+                continue;
             }
         }
+
+        // Match source pattern against the request uri path, or path and query
+        let subject = match req {
+            Some(req) if redirect_entry.match_query => match_subject(req, true),
+            _ => uri_path,
+        };
+        if subject.len() > MAX_URI_LEN_FOR_REGEX || !redirect_entry.source.is_match(subject) {
+            continue;
+        }
+        source_matched(&redirect_entry.conditions);
+        let captures = match req {
+            Some(req) => redirect_entry.conditions.captures(req),
+            None => redirect_entry.conditions.is_empty().then(Vec::new),
+        };
+        if let Some(captures) = captures {
+            return Some((redirect_entry, captures));
+        }
     }
 
     None
@@ -215,6 +364,8 @@ mod tests {
                 source: s1,
                 destination: "/destination1".into(),
                 kind: StatusCode::FOUND,
+                match_query: false,
+                conditions: Default::default(),
                 replacer: r1,
             },
             Redirects {
@@ -222,6 +373,8 @@ mod tests {
                 source: s2,
                 destination: "/destination2".into(),
                 kind: StatusCode::MOVED_PERMANENTLY,
+                match_query: false,
+                conditions: Default::default(),
                 replacer: r2,
             },
             Redirects {
@@ -229,6 +382,8 @@ mod tests {
                 source: s3,
                 destination: "/destination3/$2/$3".into(),
                 kind: StatusCode::MOVED_PERMANENTLY,
+                match_query: false,
+                conditions: Default::default(),
                 replacer: r3,
             },
             Redirects {
@@ -236,6 +391,8 @@ mod tests {
                 source: s4,
                 destination: "/destination4?p=$1".into(),
                 kind: StatusCode::FOUND,
+                match_query: false,
+                conditions: Default::default(),
                 replacer: r4,
             },
         ]
@@ -258,7 +415,7 @@ mod tests {
                     advanced_opts: None,
                     ..Default::default()
                 },
-                &make_request("", "/")
+                &mut make_request("", "/")
             )
             .is_none()
         );
@@ -272,7 +429,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("", "/")
+                &mut make_request("", "/")
             )
             .is_none()
         );
@@ -289,7 +446,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("example.com", "/source2/whatever")
+                &mut make_request("example.com", "/source2/whatever")
             )
             .is_none()
         );
@@ -303,7 +460,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("", "/source2")
+                &mut make_request("", "/source2")
             )
             .is_none()
         );
@@ -320,7 +477,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("", "/source1")
+                &mut make_request("", "/source1")
             )),
             Some((StatusCode::FOUND, "/destination1".into()))
         );
@@ -334,7 +491,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("example.com", "/source2")
+                &mut make_request("example.com", "/source2")
             )),
             Some((StatusCode::MOVED_PERMANENTLY, "/destination2".into()))
         );
@@ -348,7 +505,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("example.info", "/source3/whatever")
+                &mut make_request("example.info", "/source3/whatever")
             )),
             Some((
                 StatusCode::MOVED_PERMANENTLY,
@@ -365,7 +522,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("", "/source4/whatever")
+                &mut make_request("", "/source4/whatever")
             )),
             Some((StatusCode::FOUND, "/destination4?p=whatever".into()))
         );
@@ -382,7 +539,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("", "/source1?q=query-string")
+                &mut make_request("", "/source1?q=query-string")
             )),
             Some((StatusCode::FOUND, "/destination1?q=query-string".into()))
         );
@@ -396,7 +553,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("example.com", "/source2?q=query-string")
+                &mut make_request("example.com", "/source2?q=query-string")
             )),
             Some((
                 StatusCode::MOVED_PERMANENTLY,
@@ -413,7 +570,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("example.info", "/source3/whatever?q=query-string")
+                &mut make_request("example.info", "/source3/whatever?q=query-string")
             )),
             Some((
                 StatusCode::MOVED_PERMANENTLY,
@@ -430,7 +587,7 @@ mod tests {
                     }),
                     ..Default::default()
                 },
-                &make_request("", "/source4/whatever?q=query-string")
+                &mut make_request("", "/source4/whatever?q=query-string")
             )),
             Some((
                 StatusCode::FOUND,
@@ -505,6 +662,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_redirection_skips_conditional_rules() {
+        use crate::conditions::Conditions;
+        use crate::settings::file::{RouteCondition, RouteConditionKind};
+
+        let mut redirects = get_redirects();
+        assert!(super::get_redirection("", "/source1", Some(&redirects)).is_some());
+
+        let has = [RouteCondition {
+            kind: RouteConditionKind::Cookie,
+            key: Some("beta".into()),
+            value: None,
+        }];
+        redirects[0].conditions = Conditions::new(Some(&has), None).unwrap();
+        assert!(super::get_redirection("", "/source1", Some(&redirects)).is_none());
+
+        let req = make_request("", "/source1");
+        assert!(
+            super::get_redirection_with_request(&req, "", "/source1", Some(&redirects)).is_none()
+        );
+        let req = Request::builder()
+            .uri("/source1")
+            .header("cookie", "beta=1")
+            .body(())
+            .unwrap();
+        let (matched, captures) =
+            super::get_redirection_with_request(&req, "", "/source1", Some(&redirects)).unwrap();
+        assert_eq!(matched.destination, "/destination1");
+        assert_eq!(captures, ["1"]);
+    }
+
     /// `MAX_URI_LEN_FOR_REGEX` is a security/perf invariant; this test
     /// keeps it as a tripwire if anyone ever lowers it accidentally.
     #[test]
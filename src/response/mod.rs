@@ -17,9 +17,9 @@ use std::path::Path;
 
 use crate::body::Body;
 use crate::conditional_headers::{ConditionalBody, ConditionalHeaders, Validators};
-use crate::fs::stream::{FileStream, optimal_buf_size};
+use crate::fs::stream::{FileStream, MultipartFileStream, optimal_buf_size};
 
-pub(crate) use range::{BadRangeError, bytes_range};
+pub(crate) use range::{BadRangeError, MAX_RANGES, Multipart, bytes_ranges};
 pub(crate) mod range;
 
 /// Pre-computed `HeaderValue` for `Accept-Ranges: bytes`.
@@ -48,7 +48,7 @@ thread_local! {
 
 /// Returns the `Content-Type` `HeaderValue` for the given file path,
 /// using a per-thread cache keyed by the file extension.
-fn content_type_for(path: &Path) -> HeaderValue {
+pub(crate) fn content_type_for(path: &Path) -> HeaderValue {
     let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
     CONTENT_TYPE_CACHE.with(|cache| {
         if let Some(hv) = cache.borrow().get(ext) {
@@ -80,13 +80,20 @@ use crate::mem_cache::{
 
 /// It converts a file object into a corresponding HTTP response or
 /// returns an error holding an HTTP status code otherwise.
+///
+/// Requests for more than `max_ranges` ranges get the full content.
+///
+/// Full responses are stored in the in-memory cache of `memory_cache` if any
+/// and the request path is cacheable, along with whether the file was served
+/// while following symbolic links.
 pub(crate) fn response_body(
     mut file: File,
     path: &Path,
     meta: &Metadata,
     conditionals: ConditionalHeaders,
-    etag_enabled: bool,
-    #[cfg(feature = "mem-cache")] memory_cache: Option<&MemCacheOpts>,
+    etag: Option<(headers::ETag, HeaderValue)>,
+    max_ranges: usize,
+    #[cfg(feature = "mem-cache")] memory_cache: Option<(&MemCacheOpts, &str, bool)>,
 ) -> Result<Response<Body>, StatusCode> {
     let mut len = meta.len();
     // If the file's modified time is the UNIX epoch, then it's likely not valid and should
@@ -97,14 +104,9 @@ pub(crate) fn response_body(
         .filter(|&t| t != std::time::UNIX_EPOCH)
         .map(LastModified::from);
 
-    // Build a weak ETag from `(mtime, size)` once per response. The same
-    // header value is used for the body response, the cache entry (when
+    // The ETag is built once per response by the caller. The same header
+    // value is used for the body response, the cache entry (when
     // applicable) and the 304 / If-Range short-circuit paths.
-    let etag = if etag_enabled {
-        crate::etag::build_from_meta(meta)
-    } else {
-        None
-    };
     let (etag_typed, etag_value) = match etag.as_ref() {
         Some((t, v)) => (Some(t), Some(v)),
         None => (None, None),
@@ -121,8 +123,32 @@ pub(crate) fn response_body(
         ConditionalBody::WithBody(range) => {
             let buf_size = optimal_buf_size(meta);
 
-            bytes_range(range, len)
-                .map(|(start, end)| {
+            bytes_ranges(range, len, max_ranges)
+                .map(|ranges| {
+                    let (start, end) = match ranges[..] {
+                        [range] => range,
+                        _ => {
+                            let multipart = Multipart::new(&ranges, len, &content_type_for(path));
+                            let mut resp = multipart.response(|segments| {
+                                crate::body::stream(MultipartFileStream::new(
+                                    file, buf_size, segments,
+                                ))
+                            });
+                            insert_raw(
+                                resp.headers_mut(),
+                                ACCEPT_RANGES,
+                                ACCEPT_RANGES_BYTES.clone(),
+                            );
+                            if let Some(last_modified) = modified {
+                                resp.headers_mut().typed_insert(last_modified);
+                            }
+                            if let Some(hv) = etag_value {
+                                insert_raw(resp.headers_mut(), ETAG, hv.clone());
+                            }
+                            return Ok(resp);
+                        }
+                    };
+
                     match file.seek(SeekFrom::Start(start)) {
                         Ok(_) => (),
                         Err(err) => {
@@ -147,13 +173,30 @@ pub(crate) fn response_body(
                     let body = {
                         let is_full_response = sub_len == len;
                         let mem_opts = match (is_full_response, memory_cache, path.to_str()) {
-                            (true, Some(opts), Some(path_str)) if len <= opts.max_file_size => {
-                                Some(MemFileTempOpts::new(
+                            (true, Some((opts, uri_path, follow_symlinks)), Some(path_str))
+                                if len <= opts.max_file_size && opts.is_cacheable(uri_path) =>
+                            {
+                                let mem_opts = MemFileTempOpts::new(
                                     path_str.to_owned(),
                                     content_type.clone(),
                                     modified,
                                     etag_value.cloned(),
-                                ))
+                                    meta.modified().ok(),
+                                    follow_symlinks,
+                                );
+                                #[cfg(any(
+                                    feature = "compression",
+                                    feature = "compression-gzip",
+                                    feature = "compression-brotli",
+                                    feature = "compression-zstd",
+                                    feature = "compression-deflate"
+                                ))]
+                                let mem_opts = mem_opts.with_variants(opts.variants(
+                                    uri_path,
+                                    &content_type,
+                                    len as usize,
+                                ));
+                                Some(mem_opts)
                             }
                             _ => None,
                         };
@@ -5,51 +5,170 @@
 
 //! Module to handle HTTP `Range` headers and byte ranges.
 //!
+//! Requests for several ranges are answered with a `multipart/byteranges`
+//! body (RFC 9110 §14.6). Overlapping and adjacent ranges are coalesced and
+//! the number of parts is capped, so a `Range` header can not make a
+//! response noticeably larger than the whole file.
 
+use bytes::Bytes;
 use headers::{Header, Range};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderValue};
+use hyper::{Response, StatusCode};
+use std::hash::{BuildHasher, Hasher};
 use std::ops::Bound;
 
+use crate::body::Body;
+
+/// Maximum number of parts of a `multipart/byteranges` response.
+///
+/// Requests asking for more ranges, once coalesced, get the full content.
+pub(crate) const MAX_RANGES: usize = 16;
+
 #[derive(Debug)]
 pub(crate) struct BadRangeError;
 
-/// It handles the `Range` header returning the corresponding start/end-range bytes
-/// or returns an error for bad ranges otherwise.
-pub(crate) fn bytes_range(range: Option<Range>, max_len: u64) -> Result<(u64, u64), BadRangeError> {
+/// It handles the `Range` header returning the corresponding start/end-range
+/// bytes, sorted and coalesced, or returns an error for bad ranges otherwise.
+///
+/// The full content is returned when there is no `Range` header or when
+/// more than `max_ranges` ranges remain after coalescing.
+pub(crate) fn bytes_ranges(
+    range: Option<Range>,
+    max_len: u64,
+    max_ranges: usize,
+) -> Result<Vec<(u64, u64)>, BadRangeError> {
     let range = if let Some(range) = range {
         range
     } else {
-        return Ok((0, max_len));
+        return Ok(vec![(0, max_len)]);
     };
 
+    let mut ranges = Vec::with_capacity(1);
     for (start, end) in range.satisfiable_ranges(max_len) {
         tracing::trace!("range request received, {:?}-{:?}-{}", start, end, max_len);
         match normalize_byte_range(start, end, max_len)? {
-            Some((start, end)) => {
-                tracing::trace!("range request to return: {}-{}/{}", start, end, max_len);
-                return Ok((start, end));
-            }
+            Some(range) => ranges.push(range),
             None => tracing::trace!("unsatisfiable byte range for length {max_len}"),
         }
     }
 
     // NOTE: default to `BadRangeError` in case of wrong `Range` bytes format.
     // Special case: suffix ranges (bytes=-N) where N > file size are valid per
-    // RFC 9110 §14.1.2 and should return the entire file (200), but headers 0.4
-    // `satisfiable_ranges(len)` filters them out. Inspect the original header on
-    // this cold path so out-of-bounds first-byte ranges (e.g. bytes=5000-) remain
+    // RFC 9110 §14.1.2 and stand for the entire file, but headers 0.4
+    // `satisfiable_ranges(len)` filters them out. Inspect the original header
+    // so out-of-bounds first-byte ranges (e.g. bytes=5000-) remain
     // unsatisfiable instead of being mistaken for an oversized suffix range.
-    if has_oversized_suffix_range(&range, max_len) {
+    if ranges.first() != Some(&(0, max_len)) && has_oversized_suffix_range(&range, max_len) {
+        tracing::trace!("suffix range exceeds file size, returning full content");
+        return Ok(vec![(0, max_len)]);
+    }
+    if ranges.is_empty() {
+        return Err(BadRangeError);
+    }
+
+    let ranges = coalesce(ranges);
+    if ranges.len() > max_ranges {
         tracing::trace!(
-            "suffix range exceeds file size, returning full content: 0-{}/{}",
-            max_len,
-            max_len
+            "{} ranges requested while up to {} are served, returning full content",
+            ranges.len(),
+            max_ranges
         );
-        Ok((0, max_len))
-    } else {
-        Err(BadRangeError)
+        return Ok(vec![(0, max_len)]);
+    }
+    tracing::trace!("range request to return: {:?}/{}", ranges, max_len);
+    Ok(ranges)
+}
+
+/// Sorts the ranges and merges the overlapping or adjacent ones.
+fn coalesce(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    if ranges.len() < 2 {
+        return ranges;
+    }
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// A piece of a `multipart/byteranges` body.
+#[derive(Debug)]
+pub(crate) enum Segment {
+    /// Boundary delimiter and part headers.
+    Bytes(Bytes),
+    /// Byte range of the content, end excluded.
+    Range(u64, u64),
+}
+
+/// Layout of a `multipart/byteranges` body.
+#[derive(Debug)]
+pub(crate) struct Multipart {
+    boundary: String,
+    segments: Vec<Segment>,
+    len: u64,
+}
+
+impl Multipart {
+    /// Lays out a body made of the given ranges of a content of `total_len`
+    /// bytes and type `content_type`.
+    pub(crate) fn new(ranges: &[(u64, u64)], total_len: u64, content_type: &HeaderValue) -> Self {
+        let boundary = new_boundary();
+        let content_type = content_type.to_str().unwrap_or("application/octet-stream");
+        let mut segments = Vec::with_capacity(ranges.len() * 2 + 1);
+        let mut len = 0;
+        for (i, &(start, end)) in ranges.iter().enumerate() {
+            let head = format!(
+                "{}--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: bytes {start}-{}/{total_len}\r\n\r\n",
+                if i == 0 { "" } else { "\r\n" },
+                end - 1,
+            );
+            len += head.len() as u64 + (end - start);
+            segments.push(Segment::Bytes(head.into()));
+            segments.push(Segment::Range(start, end));
+        }
+        let tail = format!("\r\n--{boundary}--\r\n");
+        len += tail.len() as u64;
+        segments.push(Segment::Bytes(tail.into()));
+        Self {
+            boundary,
+            segments,
+            len,
+        }
+    }
+
+    /// Value of the `Content-Type` header of the response.
+    fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("multipart/byteranges; boundary={}", self.boundary))
+            .expect("boundary should be a valid header value")
+    }
+
+    /// Builds the `206 Partial Content` response whose body streams the
+    /// given pieces, in order.
+    pub(crate) fn response(self, body: impl FnOnce(Vec<Segment>) -> Body) -> Response<Body> {
+        let content_type = self.content_type();
+        let mut resp = Response::new(body(self.segments));
+        *resp.status_mut() = StatusCode::PARTIAL_CONTENT;
+        resp.headers_mut().insert(CONTENT_TYPE, content_type);
+        resp.headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(self.len));
+        resp
     }
 }
 
+/// Builds a boundary delimiter unlikely to be found in any content.
+fn new_boundary() -> String {
+    let random = || {
+        std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish()
+    };
+    format!("{:016x}{:016x}", random(), random())
+}
+
 fn normalize_byte_range(
     start: Bound<u64>,
     end: Bound<u64>,
@@ -73,7 +192,6 @@ fn normalize_byte_range(
     }
 }
 
-#[cold]
 fn has_oversized_suffix_range(range: &Range, max_len: u64) -> bool {
     if max_len == 0 {
         return false;
@@ -121,7 +239,7 @@ fn suffix_len_exceeds(spec: &str, max_len: u64) -> bool {
 mod tests {
     use headers::{HeaderMap, HeaderMapExt, Range};
 
-    use super::bytes_range;
+    use super::*;
 
     fn range(s: &str) -> Option<Range> {
         let mut map = HeaderMap::new();
@@ -131,158 +249,274 @@ mod tests {
 
     #[test]
     fn no_range_returns_full_file() {
-        assert_eq!(bytes_range(None, 1000).unwrap(), (0, 1000));
+        assert_eq!(bytes_ranges(None, 1000, MAX_RANGES).unwrap(), [(0, 1000)]);
     }
 
     #[test]
     fn inclusive_range_within_bounds() {
         // bytes=0-499 of 1000-byte file → (0, 500)
-        assert_eq!(bytes_range(range("0-499"), 1000).unwrap(), (0, 500));
+        assert_eq!(
+            bytes_ranges(range("0-499"), 1000, MAX_RANGES).unwrap(),
+            [(0, 500)]
+        );
     }
 
     #[test]
     fn inclusive_range_to_last_byte() {
         // bytes=500-999 of 1000-byte file → (500, 1000)
-        assert_eq!(bytes_range(range("500-999"), 1000).unwrap(), (500, 1000));
+        assert_eq!(
+            bytes_ranges(range("500-999"), 1000, MAX_RANGES).unwrap(),
+            [(500, 1000)]
+        );
     }
 
     #[test]
     fn suffix_range_within_file() {
         // bytes=-200 of 1000-byte file → last 200 bytes = (800, 1000)
-        assert_eq!(bytes_range(range("-200"), 1000).unwrap(), (800, 1000));
+        assert_eq!(
+            bytes_ranges(range("-200"), 1000, MAX_RANGES).unwrap(),
+            [(800, 1000)]
+        );
     }
 
     #[test]
     fn suffix_range_larger_than_file_returns_full() {
         // bytes=-2000 of 1000-byte file: suffix exceeds file size → return entire file
-        assert_eq!(bytes_range(range("-2000"), 1000).unwrap(), (0, 1000));
+        assert_eq!(
+            bytes_ranges(range("-2000"), 1000, MAX_RANGES).unwrap(),
+            [(0, 1000)]
+        );
     }
 
     #[test]
     fn enormous_suffix_range_returns_full() {
         assert_eq!(
-            bytes_range(range("-18446744073709551616"), 1000).unwrap(),
-            (0, 1000)
+            bytes_ranges(range("-18446744073709551616"), 1000, MAX_RANGES).unwrap(),
+            [(0, 1000)]
         );
     }
 
     #[test]
     fn open_ended_range_from_offset() {
         // bytes=100- of 1000-byte file → (100, 1000)
-        assert_eq!(bytes_range(range("100-"), 1000).unwrap(), (100, 1000));
+        assert_eq!(
+            bytes_ranges(range("100-"), 1000, MAX_RANGES).unwrap(),
+            [(100, 1000)]
+        );
     }
 
     #[test]
     fn range_start_equals_end_is_single_byte() {
         // bytes=5-5 of 1000-byte file → (5, 6)
-        assert_eq!(bytes_range(range("5-5"), 1000).unwrap(), (5, 6));
+        assert_eq!(
+            bytes_ranges(range("5-5"), 1000, MAX_RANGES).unwrap(),
+            [(5, 6)]
+        );
     }
 
     #[test]
     fn range_start_greater_than_end_is_error() {
         // bytes=100-50 → invalid
-        assert!(bytes_range(range("100-50"), 1000).is_err());
+        assert!(bytes_ranges(range("100-50"), 1000, MAX_RANGES).is_err());
     }
 
     #[test]
     fn range_start_beyond_file_size_is_error() {
         // bytes=2000-3000 of 1000-byte file → unsatisfiable
-        assert!(bytes_range(range("2000-3000"), 1000).is_err());
+        assert!(bytes_ranges(range("2000-3000"), 1000, MAX_RANGES).is_err());
     }
 
     #[test]
     fn open_ended_range_starting_at_file_size_is_error() {
-        assert!(bytes_range(range("1000-"), 1000).is_err());
+        assert!(bytes_ranges(range("1000-"), 1000, MAX_RANGES).is_err());
     }
 
     #[test]
     fn out_of_bounds_first_byte_ranges_are_errors() {
-        assert!(bytes_range(range("5000-"), 100).is_err());
-        assert!(bytes_range(range("5000-5999"), 100).is_err());
-        assert!(bytes_range(range("100-199"), 100).is_err());
+        assert!(bytes_ranges(range("5000-"), 100, MAX_RANGES).is_err());
+        assert!(bytes_ranges(range("5000-5999"), 100, MAX_RANGES).is_err());
+        assert!(bytes_ranges(range("100-199"), 100, MAX_RANGES).is_err());
     }
 
     #[test]
     fn range_end_beyond_file_size_is_clamped() {
-        assert_eq!(bytes_range(range("50-999"), 100).unwrap(), (50, 100));
+        assert_eq!(
+            bytes_ranges(range("50-999"), 100, MAX_RANGES).unwrap(),
+            [(50, 100)]
+        );
     }
 
     #[test]
     fn huge_range_end_does_not_overflow() {
         assert_eq!(
-            bytes_range(range("0-18446744073709551615"), 100).unwrap(),
-            (0, 100)
+            bytes_ranges(range("0-18446744073709551615"), 100, MAX_RANGES).unwrap(),
+            [(0, 100)]
         );
     }
 
     #[test]
     fn invalid_empty_range_is_error() {
-        assert!(bytes_range(range("-"), 100).is_err());
+        assert!(bytes_ranges(range("-"), 100, MAX_RANGES).is_err());
     }
 
     #[test]
     fn range_on_zero_byte_file_is_unsatisfiable() {
-        assert!(bytes_range(range("0-0"), 0).is_err());
-        assert!(bytes_range(range("-1"), 0).is_err());
-        assert!(bytes_range(range("0-"), 0).is_err());
+        assert!(bytes_ranges(range("0-0"), 0, MAX_RANGES).is_err());
+        assert!(bytes_ranges(range("-1"), 0, MAX_RANGES).is_err());
+        assert!(bytes_ranges(range("0-"), 0, MAX_RANGES).is_err());
     }
 
     #[test]
     fn later_satisfiable_range_is_used() {
-        assert_eq!(bytes_range(range("200-300,0-9"), 100).unwrap(), (0, 10));
+        assert_eq!(
+            bytes_ranges(range("200-300,0-9"), 100, MAX_RANGES).unwrap(),
+            [(0, 10)]
+        );
     }
 
     #[test]
     fn suffix_range_equal_to_file_size_returns_full() {
         // bytes=-1000 of 1000-byte file → entire file (last 1000 bytes == full)
-        assert_eq!(bytes_range(range("-1000"), 1000).unwrap(), (0, 1000));
+        assert_eq!(
+            bytes_ranges(range("-1000"), 1000, MAX_RANGES).unwrap(),
+            [(0, 1000)]
+        );
     }
 
     #[test]
     fn suffix_range_of_one_byte() {
         // bytes=-1 of 1000-byte file → final byte = (999, 1000)
-        assert_eq!(bytes_range(range("-1"), 1000).unwrap(), (999, 1000));
+        assert_eq!(
+            bytes_ranges(range("-1"), 1000, MAX_RANGES).unwrap(),
+            [(999, 1000)]
+        );
     }
 
     #[test]
     fn zero_length_suffix_is_unsatisfiable() {
         // bytes=-0 is explicitly forbidden by RFC 9110; we return 416.
-        assert!(bytes_range(range("-0"), 1000).is_err());
+        assert!(bytes_ranges(range("-0"), 1000, MAX_RANGES).is_err());
     }
 
     #[test]
     fn leading_zeros_in_suffix_are_parsed() {
         // bytes=-00200 == bytes=-200
-        assert_eq!(bytes_range(range("-00200"), 1000).unwrap(), (800, 1000));
+        assert_eq!(
+            bytes_ranges(range("-00200"), 1000, MAX_RANGES).unwrap(),
+            [(800, 1000)]
+        );
         // bytes=-02000 on 1000-byte file → oversized → full file.
-        assert_eq!(bytes_range(range("-02000"), 1000).unwrap(), (0, 1000));
+        assert_eq!(
+            bytes_ranges(range("-02000"), 1000, MAX_RANGES).unwrap(),
+            [(0, 1000)]
+        );
     }
 
     #[test]
     fn full_file_range_returns_full() {
         // bytes=0-999 on 1000-byte file == full file as partial content.
-        assert_eq!(bytes_range(range("0-999"), 1000).unwrap(), (0, 1000));
+        assert_eq!(
+            bytes_ranges(range("0-999"), 1000, MAX_RANGES).unwrap(),
+            [(0, 1000)]
+        );
     }
 
     #[test]
-    fn multi_range_first_satisfiable_wins() {
-        // Both within bounds — first one wins.
-        assert_eq!(bytes_range(range("0-9,50-59"), 100).unwrap(), (0, 10));
+    fn multi_range_returns_every_range() {
+        assert_eq!(
+            bytes_ranges(range("0-9,50-59"), 100, MAX_RANGES).unwrap(),
+            [(0, 10), (50, 60)]
+        );
     }
 
     #[test]
-    fn multi_range_with_oversized_suffix_picks_first_valid() {
-        // First range is valid; oversized suffix never reached.
-        assert_eq!(bytes_range(range("10-19,-99999"), 100).unwrap(), (10, 20));
+    fn multi_range_with_oversized_suffix_returns_full() {
+        // The oversized suffix stands for the whole file.
+        assert_eq!(
+            bytes_ranges(range("10-19,-99999"), 100, MAX_RANGES).unwrap(),
+            [(0, 100)]
+        );
+    }
+
+    #[test]
+    fn multi_range_is_sorted_and_coalesced() {
+        // Overlapping and adjacent ranges are merged.
+        assert_eq!(
+            bytes_ranges(range("50-59,0-9,5-14,15-19,-10"), 100, MAX_RANGES).unwrap(),
+            [(0, 20), (50, 60), (90, 100)]
+        );
+        // Repeated ranges do not amplify the response.
+        let spec = vec!["0-49"; 100].join(",");
+        assert_eq!(
+            bytes_ranges(range(&spec), 100, MAX_RANGES).unwrap(),
+            [(0, 50)]
+        );
+    }
+
+    #[test]
+    fn too_many_ranges_return_full() {
+        let spec = (0..=MAX_RANGES)
+            .map(|i| format!("{0}-{0}", i * 2))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_eq!(
+            bytes_ranges(range(&spec), 100, MAX_RANGES).unwrap(),
+            [(0, 100)]
+        );
+        assert_eq!(
+            bytes_ranges(range("0-9,50-59"), 100, 1).unwrap(),
+            [(0, 100)]
+        );
+    }
+
+    #[test]
+    fn multipart_layout_matches_its_length() {
+        let content_type = HeaderValue::from_static("text/plain");
+        let multipart = Multipart::new(&[(0, 10), (50, 60)], 100, &content_type);
+        let boundary = multipart.boundary.clone();
+        let data = [b'x'; 100];
+        let mut body = Vec::new();
+        let resp = multipart.response(|segments| {
+            for segment in segments {
+                match segment {
+                    Segment::Bytes(bytes) => body.extend_from_slice(&bytes),
+                    Segment::Range(start, end) => {
+                        body.extend_from_slice(&data[start as usize..end as usize])
+                    }
+                }
+            }
+            crate::body::empty()
+        });
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            resp.headers()[CONTENT_TYPE],
+            format!("multipart/byteranges; boundary={boundary}").as_str()
+        );
+        assert_eq!(
+            resp.headers()[CONTENT_LENGTH],
+            body.len().to_string().as_str()
+        );
+        let body = String::from_utf8(body).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-9/100\r\n\r\nxxxxxxxxxx\r\n\
+                 --{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 50-59/100\r\n\r\nxxxxxxxxxx\r\n\
+                 --{boundary}--\r\n"
+            )
+        );
     }
 
     #[test]
     fn range_at_u64_boundary_file_size() {
         // Open-ended range on a (notionally) u64::MAX-sized file shouldn't overflow.
-        assert_eq!(bytes_range(range("0-"), u64::MAX).unwrap(), (0, u64::MAX));
+        assert_eq!(
+            bytes_ranges(range("0-"), u64::MAX, MAX_RANGES).unwrap(),
+            [(0, u64::MAX)]
+        );
     }
 
-    // Property-based regression tests for `bytes_range`.
+    // Property-based regression tests for `bytes_ranges`.
     //
     // These properties encode invariants that should hold for any
     // satisfiable response: the returned slice must always be in-bounds
@@ -304,7 +538,9 @@ mod tests {
                 .prop_map(|(m, x, y)| (m, x.min(y), x.max(y))),
         ) {
             let req = format!("{a}-{b}");
-            let (start, end) = bytes_range(range(&req), max_len).unwrap();
+            let [(start, end)] = bytes_ranges(range(&req), max_len, MAX_RANGES).unwrap()[..] else {
+                panic!("a single range is expected");
+            };
             prop_assert_eq!((start, end), (a, b + 1));
         }
 
@@ -314,7 +550,9 @@ mod tests {
             (max_len, a) in (1u64..=10_000).prop_flat_map(|m| (Just(m), 0u64..m)),
         ) {
             let req = format!("{a}-");
-            let (start, end) = bytes_range(range(&req), max_len).unwrap();
+            let [(start, end)] = bytes_ranges(range(&req), max_len, MAX_RANGES).unwrap()[..] else {
+                panic!("a single range is expected");
+            };
             prop_assert_eq!((start, end), (a, max_len));
         }
 
@@ -325,7 +563,9 @@ mod tests {
             (max_len, n) in (1u64..=10_000).prop_flat_map(|m| (Just(m), 1u64..=m)),
         ) {
             let req = format!("-{n}");
-            let (start, end) = bytes_range(range(&req), max_len).unwrap();
+            let [(start, end)] = bytes_ranges(range(&req), max_len, MAX_RANGES).unwrap()[..] else {
+                panic!("a single range is expected");
+            };
             prop_assert_eq!((start, end), (max_len - n, max_len));
         }
 
@@ -337,12 +577,14 @@ mod tests {
                 .prop_flat_map(|m| (Just(m), (m + 1)..=u64::MAX)),
         ) {
             let req = format!("-{n}");
-            let (start, end) = bytes_range(range(&req), max_len).unwrap();
+            let [(start, end)] = bytes_ranges(range(&req), max_len, MAX_RANGES).unwrap()[..] else {
+                panic!("a single range is expected");
+            };
             prop_assert_eq!((start, end), (0, max_len));
         }
 
-        /// Every successful response MUST be a non-empty, in-bounds slice:
-        /// `start < end <= max_len`.
+        /// Every successful response MUST be made of non-empty, in-bounds,
+        /// sorted and disjoint slices: `start < end <= max_len`.
         #[test]
         fn prop_successful_slice_is_in_bounds(
             max_len in 1u64..=10_000,
@@ -354,9 +596,18 @@ mod tests {
             if let Ok(value) = format!("bytes={spec}").parse() {
                 map.insert(http::header::RANGE, value);
                 let parsed = map.typed_get::<Range>();
-                if let Ok((start, end)) = bytes_range(parsed, max_len) {
-                    prop_assert!(start < end, "empty slice: {start}-{end}");
-                    prop_assert!(end <= max_len, "end > max_len: {end} > {max_len}");
+                if let Ok(ranges) = bytes_ranges(parsed, max_len, MAX_RANGES) {
+                    prop_assert!(!ranges.is_empty() && ranges.len() <= MAX_RANGES);
+                    let mut prev_end = None;
+                    for (start, end) in ranges {
+                        prop_assert!(start < end, "empty slice: {start}-{end}");
+                        prop_assert!(end <= max_len, "end > max_len: {end} > {max_len}");
+                        prop_assert!(
+                            prev_end.is_none_or(|prev_end| prev_end < start),
+                            "overlapping or unsorted ranges"
+                        );
+                        prev_end = Some(end);
+                    }
                 }
             }
         }
@@ -369,7 +620,7 @@ mod tests {
         ) {
             let start = max_len.saturating_add(offset);
             let req = format!("{start}-");
-            prop_assert!(bytes_range(range(&req), max_len).is_err());
+            prop_assert!(bytes_ranges(range(&req), max_len, MAX_RANGES).is_err());
         }
     }
 }
@@ -11,14 +11,15 @@
 //! [`crate::redirects::MAX_URI_LEN_FOR_REGEX`] are skipped.
 
 use headers::HeaderValue;
-use hyper::{Request, Response, StatusCode, Uri, header::HOST};
+use hyper::{Request, Response, Uri, header::HOST};
 
 use crate::body::Body;
 use crate::{
     Error,
+    conditions::Conditions,
     handler::RequestHandlerOpts,
-    redirects::{handle_error, replace_placeholders},
-    settings::{Rewrites, file::RedirectsKind},
+    redirects::{handle_error, match_subject, replace_placeholders_with, terminal_response},
+    settings::Rewrites,
 };
 
 /// Applies rewrite rules to a request if necessary.
@@ -38,10 +39,31 @@ pub(crate) fn pre_process<T>(
         return None;
     }
 
-    let matched = rewrite_uri_path(uri_path, Some(rewrites))?;
-    let dest = match replace_placeholders(
-        uri_path,
+    let mut source_matched = Vec::new();
+    let found = find_rewrite(Some(req), uri_path, Some(rewrites), |conditions| {
+        source_matched.push(conditions)
+    });
+    for conditions in source_matched {
+        conditions.record_vary(req);
+    }
+    let (matched, captures) = found?;
+    rewrite(opts, req, matched, &captures)
+}
+
+/// Applies a rule matching the request given the values captured by its
+/// conditions, either redirecting the client or rewriting the request URI
+/// in place.
+pub(crate) fn rewrite<T>(
+    opts: &RequestHandlerOpts,
+    req: &mut Request<T>,
+    matched: &Rewrites,
+    captures: &[String],
+) -> Option<Result<Response<Body>, Error>> {
+    let subject = match_subject(req, matched.match_query);
+    let dest = match replace_placeholders_with(
+        subject,
         &matched.source,
+        captures,
         &matched.destination,
         &matched.replacer,
     ) {
@@ -49,7 +71,12 @@ pub(crate) fn pre_process<T>(
         Err(err) => return handle_error(err, opts, req),
     };
 
-    if let Some(redirect_type) = &matched.redirect {
+    if let Some(redirect_type) = matched.redirect {
+        let status = redirect_type.status_code();
+        if status.is_client_error() {
+            return terminal_response(opts, req, &dest, status);
+        }
+
         // Handle redirects
         let loc = match HeaderValue::from_str(&dest) {
             Ok(val) => val,
@@ -63,14 +90,12 @@ pub(crate) fn pre_process<T>(
         };
         let mut resp = Response::new(crate::body::empty());
         resp.headers_mut().insert(hyper::header::LOCATION, loc);
-        *resp.status_mut() = match redirect_type {
-            RedirectsKind::Permanent => StatusCode::MOVED_PERMANENTLY,
-            RedirectsKind::Temporary => StatusCode::FOUND,
-        };
+        *resp.status_mut() = status;
         Some(Ok(resp))
     } else {
         // Handle internal rewrites
-        *req.uri_mut() = match merge_uris(req.uri(), &dest) {
+        // Sources matching the query replace it with the one of the destination
+        *req.uri_mut() = match merge_uris(req.uri(), &dest, !matched.match_query) {
             Ok(uri) => uri,
             Err(err) => {
                 return handle_error(
@@ -96,7 +121,7 @@ pub(crate) fn pre_process<T>(
     }
 }
 
-fn merge_uris(orig_uri: &Uri, new_uri: &str) -> Result<Uri, Error> {
+fn merge_uris(orig_uri: &Uri, new_uri: &str, keep_query: bool) -> Result<Uri, Error> {
     let mut parts = new_uri.parse::<Uri>()?.into_parts();
     if parts.scheme.is_none() {
         parts.scheme = orig_uri.scheme().cloned();
@@ -108,7 +133,10 @@ fn merge_uris(orig_uri: &Uri, new_uri: &str) -> Result<Uri, Error> {
         parts.path_and_query = orig_uri.path_and_query().cloned();
     }
     if let Some(path_and_query) = &mut parts.path_and_query
-        && let (None, Some(query)) = (path_and_query.query(), orig_uri.query())
+        && let (None, Some(query)) = (
+            path_and_query.query(),
+            orig_uri.query().filter(|_| keep_query),
+        )
     {
         *path_and_query = [path_and_query.as_str(), "?", query]
             .into_iter()
@@ -120,16 +148,54 @@ fn merge_uris(orig_uri: &Uri, new_uri: &str) -> Result<Uri, Error> {
 
 /// It returns a rewrite's destination path if the current request uri
 /// matches against the provided rewrites array.
+///
+/// Rules with `has` or `missing` conditions are skipped, see
+/// [`rewrite_uri_path_with_request`].
 pub fn rewrite_uri_path<'a>(
-    uri_path: &'a str,
+    uri_path: &str,
     rewrites_opts: Option<&'a [Rewrites]>,
 ) -> Option<&'a Rewrites> {
-    if let Some(rewrites_vec) = rewrites_opts {
-        for rewrites_entry in rewrites_vec {
-            // Match source glob pattern against request uri path
-            if rewrites_entry.source.is_match(uri_path) {
-                return Some(rewrites_entry);
-            }
+    find_rewrite::<()>(None, uri_path, rewrites_opts, |_| {}).map(|(matched, _)| matched)
+}
+
+/// Same as [`rewrite_uri_path`] for the given request, whose query may be
+/// matched by the rule source and which must meet the rule conditions.
+/// The values captured by the `has` conditions are returned along with
+/// the rule.
+pub fn rewrite_uri_path_with_request<'a, T>(
+    req: &Request<T>,
+    uri_path: &str,
+    rewrites_opts: Option<&'a [Rewrites]>,
+) -> Option<(&'a Rewrites, Vec<String>)> {
+    find_rewrite(Some(req), uri_path, rewrites_opts, |_| {})
+}
+
+/// Returns the first rule matching the request, calling `source_matched`
+/// with the conditions of every rule whose source matched along the way.
+fn find_rewrite<'a, T>(
+    req: Option<&Request<T>>,
+    uri_path: &str,
+    rewrites_opts: Option<&'a [Rewrites]>,
+    mut source_matched: impl FnMut(&'a Conditions),
+) -> Option<(&'a Rewrites, Vec<String>)> {
+    for rewrites_entry in rewrites_opts? {
+        // Match source pattern against request uri path, or path and query
+        let subject = match req {
+            Some(req) if rewrites_entry.match_query => match_subject(req, true),
+            _ => uri_path,
+        };
+        if subject.len() > crate::redirects::MAX_URI_LEN_FOR_REGEX
+            || !rewrites_entry.source.is_match(subject)
+        {
+            continue;
+        }
+        source_matched(&rewrites_entry.conditions);
+        let captures = match req {
+            Some(req) => rewrites_entry.conditions.captures(req),
+            None => rewrites_entry.conditions.is_empty().then(Vec::new),
+        };
+        if let Some(captures) = captures {
+            return Some((rewrites_entry, captures));
         }
     }
 
@@ -174,24 +240,32 @@ mod tests {
                 source: s1,
                 destination: "/destination1".into(),
                 redirect: None,
+                match_query: false,
+                conditions: Default::default(),
                 replacer: r1,
             },
             Rewrites {
                 source: s2,
                 destination: "/destination2".into(),
                 redirect: Some(RedirectsKind::Temporary),
+                match_query: false,
+                conditions: Default::default(),
                 replacer: r2,
             },
             Rewrites {
                 source: s3,
                 destination: "/destination3/$2/$3".into(),
                 redirect: Some(RedirectsKind::Permanent),
+                match_query: false,
+                conditions: Default::default(),
                 replacer: r3,
             },
             Rewrites {
                 source: s4,
                 destination: "http://example.net:1234/destination4/$1?$2".into(),
                 redirect: None,
+                match_query: false,
+                conditions: Default::default(),
                 replacer: r4,
             },
         ]
@@ -9,8 +9,9 @@ use crate::handler::RequestHandlerOpts;
 use crate::settings::Advanced;
 use crate::settings::cli::General;
 use crate::{
-    Context, Result, compression_static, control_headers, cors, etag, health, helpers, log_addr,
-    maintenance_mode, security_headers,
+    Context, Result, compression_static, control_headers, cors, digest, etag, health, helpers,
+    image_negotiation, language_negotiation, log_addr, maintenance_mode, mounts, proxy,
+    redirect_maps, security_headers, site_files, virtual_hosts,
 };
 
 #[cfg(feature = "directory-listing")]
@@ -38,7 +39,14 @@ use crate::mem_cache;
     feature = "compression-brotli",
     feature = "compression-zstd",
 ))]
-use crate::compression;
+use crate::{compression, compression_cache};
+
+#[cfg(any(
+    feature = "compression",
+    feature = "compression-brotli",
+    feature = "compression-zstd",
+))]
+use crate::compression_dictionary;
 
 /// Output of the handler options initialization.
 pub(super) struct HandlerOptsResult {
@@ -101,6 +109,7 @@ pub(super) fn init(general: &General, advanced: Option<Advanced>) -> Result<Hand
         enabled = general.redirect_trailing_slash,
         "redirect trailing slash"
     );
+    tracing::info!(enabled = general.clean_urls, "clean urls");
     tracing::info!(enabled = general.include_hidden, "include hidden files");
     tracing::info!(enabled = general.follow_symlinks, "follow symlinks");
     tracing::info!(enabled = general.use_relative_root, "use relative root");
@@ -129,6 +138,7 @@ pub(super) fn init(general: &General, advanced: Option<Advanced>) -> Result<Hand
         log_forwarded_for: general.log_forwarded_for,
         trusted_proxies: general.trusted_proxies.clone(),
         redirect_trailing_slash: general.redirect_trailing_slash,
+        clean_urls: general.clean_urls,
         include_hidden: general.include_hidden,
         follow_symlinks: general.follow_symlinks,
         use_relative_root: general.use_relative_root,
@@ -194,6 +204,9 @@ pub(super) fn init(general: &General, advanced: Option<Advanced>) -> Result<Hand
     // Pre-compressed static files
     compression_static::init(general.compression_static, &mut handler_opts);
 
+    // AVIF and WebP image variants
+    image_negotiation::init(general.image_negotiation, &mut handler_opts);
+
     // Auto-compression based on Accept-Encoding header
     #[cfg(any(
         feature = "compression",
@@ -208,19 +221,72 @@ pub(super) fn init(general: &General, advanced: Option<Advanced>) -> Result<Hand
         &mut handler_opts,
     );
 
+    // Dynamic compression policy
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-deflate",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+    ))]
+    compression::init_policy(&mut handler_opts)?;
+
+    // On-disk cache of the auto-compression output
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-deflate",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+    ))]
+    compression_cache::init(&mut handler_opts)?;
+
+    // Compression dictionaries
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+    ))]
+    compression_dictionary::init(&mut handler_opts)?;
+
     // Cache-Control headers
     control_headers::init(general.cache_control_headers, &mut handler_opts);
 
-    // Weak ETag headers
-    etag::init(general.etag, &mut handler_opts);
+    // ETag headers
+    etag::init(general.etag, general.etag_strong, &mut handler_opts);
+
+    // Repr-Digest headers
+    digest::init(general.repr_digest, &mut handler_opts);
+
+    // Netlify-style `_redirects` and `_headers` files
+    site_files::init(general.site_files, &mut handler_opts);
+
+    // Redirect maps
+    redirect_maps::init(&mut handler_opts)?;
+
+    // Reverse proxy
+    #[cfg(feature = "tls")]
+    let https = general.tls;
+    #[cfg(not(feature = "tls"))]
+    let https = false;
+    proxy::init(https, &mut handler_opts)?;
+
+    // Language negotiation
+    language_negotiation::init(&mut handler_opts)?;
 
     // Security headers
     security_headers::init(general.security_headers, &mut handler_opts);
 
+    // Directories under URL path prefixes
+    mounts::init(&mut handler_opts)?;
+
     // In-memory cache
     #[cfg(feature = "mem-cache")]
     mem_cache::cache::init(&mut handler_opts)?;
 
+    // Virtual hosts inheriting the options above
+    virtual_hosts::init(&mut handler_opts)?;
+
     Ok(HandlerOptsResult {
         handler_opts,
         #[cfg(feature = "tls")]
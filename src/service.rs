@@ -6,6 +6,7 @@
 //! The module provides a custom [Hyper service](hyper::service::Service).
 //!
 
+use http_body_util::BodyExt;
 use hyper::{Request, Response, body::Incoming, service::Service};
 use std::future::Future;
 use std::net::SocketAddr;
@@ -67,8 +68,10 @@ impl Service<Request<Incoming>> for RequestService {
     type Future =
         Pin<Box<dyn Future<Output = Result<Response<body::Body>, Error>> + Send + 'static>>;
 
-    fn call(&self, mut req: Request<Incoming>) -> Self::Future {
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
         let handler = self.handler.clone();
+        // Boxed so the reverse proxy can take the request body out
+        let mut req = req.map(|body| body.map_err(std::io::Error::other).boxed());
         let remote_addr = self.remote_addr;
         Box::pin(async move { handler.handle(&mut req, remote_addr).await })
     }
@@ -405,6 +405,18 @@ pub struct General {
     /// The compression type is determined by the `Accept-Encoding` header.
     pub compression_static: bool,
 
+    #[arg(
+        long,
+        default_value = "false",
+        default_missing_value("true"),
+        num_args(0..=1),
+        require_equals(false),
+        action = clap::ArgAction::Set,
+        env = "SERVER_IMAGE_NEGOTIATION",
+    )]
+    /// Look up the AVIF and WebP variants (`.avif` or `.webp` suffixed) on disk of a requested JPEG, PNG or GIF image and serve the smallest one the `Accept` header lists.
+    pub image_negotiation: bool,
+
     #[cfg(feature = "directory-listing")]
     #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
     #[arg(
@@ -522,6 +534,42 @@ pub struct General {
     /// Enable weak `ETag` headers (`W/"<mtime>-<size>"`) and full conditional request handling (`If-None-Match`, `If-Match`, `If-Range`). Composes with `--cache-control-headers`; emits validators on every static-file response so clients can revalidate hot HTML even when long `max-age` is configured elsewhere.
     pub etag: bool,
 
+    #[arg(
+        long,
+        default_value = "false",
+        default_missing_value("true"),
+        num_args(0..=1),
+        require_equals(false),
+        action = clap::ArgAction::Set,
+        env = "SERVER_ETAG_STRONG",
+    )]
+    /// Emit strong `ETag` headers derived from a BLAKE3 hash of the file content instead of weak `(mtime, size)` validators. Each file is hashed at most once per inode, modification time and size; precompressed and on-the-fly compressed representations get distinct tags. Requires `--etag`.
+    pub etag_strong: bool,
+
+    #[arg(
+        long,
+        default_value = "false",
+        default_missing_value("true"),
+        num_args(0..=1),
+        require_equals(false),
+        action = clap::ArgAction::Set,
+        env = "SERVER_REPR_DIGEST",
+    )]
+    /// Answer `Want-Repr-Digest` and `Want-Content-Digest` request headers with `Repr-Digest` and `Content-Digest` (RFC 9530) headers using `sha-256` or `sha-512`. Digests are computed once per file revision.
+    pub repr_digest: bool,
+
+    #[arg(
+        long,
+        default_value = "false",
+        default_missing_value("true"),
+        num_args(0..=1),
+        require_equals(false),
+        action = clap::ArgAction::Set,
+        env = "SERVER_SITE_FILES",
+    )]
+    /// Load Netlify-style `_redirects` and `_headers` files from the root directory of the server and of each virtual host. Their rules apply after the ones of the config file and are reloaded when the files change.
+    pub site_files: bool,
+
     #[cfg(feature = "basic-auth")]
     /// It provides The "Basic" HTTP Authentication scheme using credentials as "user-id:password" pairs. Password must be encoded using the "BCrypt" password-hashing function.
     #[arg(long, default_value = "", env = "SERVER_BASIC_AUTH")]
@@ -599,6 +647,18 @@ pub struct General {
     /// Check for a trailing slash in the requested directory URI and redirect permanently (308) to the same path with a trailing slash suffix if it is missing.
     pub redirect_trailing_slash: bool,
 
+    #[arg(
+        long,
+        default_value = "false",
+        default_missing_value("true"),
+        num_args(0..=1),
+        require_equals(false),
+        action = clap::ArgAction::Set,
+        env = "SERVER_CLEAN_URLS",
+    )]
+    /// Redirect permanently (301) requests for `.html` files and `index.html` pages to their extensionless or directory path, keeping the query string, and announce that path with a `Link: rel=canonical` header.
+    pub clean_urls: bool,
+
     #[arg(
         long,
         default_value = "false",
@@ -763,6 +823,39 @@ pub enum Commands {
         /// Path to write generated artifacts to
         out_dir: PathBuf,
     },
+
+    /// Generate the pre-compressed variants (`.br`, `.gz` or `.zst`) of the compressible files of a directory
+    #[cfg(any(
+        feature = "compression",
+        feature = "compression-gzip",
+        feature = "compression-brotli",
+        feature = "compression-zstd",
+        feature = "compression-deflate"
+    ))]
+    #[command(name = "compress")]
+    Compress {
+        /// Formats to generate, comma-separated. Defaults to all the formats available
+        #[arg(long, value_delimiter = ',')]
+        formats: Vec<crate::precompress::SidecarFormat>,
+        /// Number of files compressed in parallel. Defaults to the number of CPUs
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+        /// Regenerate all the variants, including the up-to-date ones
+        #[arg(long)]
+        force: bool,
+        /// Directory to pre-compress. Defaults to the root directory (`--root`)
+        dir: Option<PathBuf>,
+    },
+
+    /// Generate a Subresource Integrity manifest (request path to `sha384-` integrity value) of the files of a directory as JSON
+    #[command(name = "sri")]
+    Sri {
+        /// File to write the manifest to. Defaults to the standard output
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+        /// Directory to hash. Defaults to the root directory (`--root`)
+        dir: Option<PathBuf>,
+    },
 }
 
 fn value_parser_pathbuf(s: &str) -> Result<PathBuf, String> {
@@ -6,6 +6,7 @@
 //! The server configuration file options (manifest)
 
 use headers::HeaderMap;
+use hyper::StatusCode;
 use serde::Deserialize;
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use std::net::IpAddr;
@@ -119,7 +120,7 @@ pub struct Headers {
     pub headers: HeaderMap,
 }
 
-#[derive(Debug, Serialize_repr, Deserialize_repr, Clone)]
+#[derive(Debug, Serialize_repr, Deserialize_repr, Copy, Clone, PartialEq, Eq)]
 #[repr(u16)]
 /// Represents redirects types.
 pub enum RedirectsKind {
@@ -127,6 +128,31 @@ pub enum RedirectsKind {
     Permanent = 301,
     /// Found
     Temporary = 302,
+    /// See Other
+    SeeOther = 303,
+    /// Temporary Redirect, preserving the request method
+    TemporaryRedirect = 307,
+    /// Permanent Redirect, preserving the request method
+    PermanentRedirect = 308,
+    /// Gone, answered with an error page instead of a redirect
+    Gone = 410,
+    /// Unavailable For Legal Reasons, answered with an error page instead of a redirect
+    UnavailableForLegalReasons = 451,
+}
+
+impl RedirectsKind {
+    /// Returns the response status code of the redirect type.
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            Self::Permanent => StatusCode::MOVED_PERMANENTLY,
+            Self::Temporary => StatusCode::FOUND,
+            Self::SeeOther => StatusCode::SEE_OTHER,
+            Self::TemporaryRedirect => StatusCode::TEMPORARY_REDIRECT,
+            Self::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+            Self::Gone => StatusCode::GONE,
+            Self::UnavailableForLegalReasons => StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -135,34 +161,183 @@ pub enum RedirectsKind {
 pub struct Redirects {
     /// Optional host to match against an incoming URI host if specified
     pub host: Option<String>,
-    /// Source of the redirect.
+    /// Glob source of the redirect.
+    #[serde(default)]
     pub source: String,
-    /// Redirect destination.
+    /// Regex source of the redirect, an alternative to `source` whose named
+    /// capture groups are referenced as `$name` in the destination.
+    pub source_regex: Option<String>,
+    /// Whether the source matches regardless of case.
+    pub case_insensitive: Option<bool>,
+    /// Whether the source matches the request path and query instead of the path only.
+    pub match_query: Option<bool>,
+    /// Redirect destination, optional for the 410 and 451 types.
+    #[serde(default)]
     pub destination: String,
-    /// Redirect type either 301 (Moved Permanently) or 302 (Found).
+    /// Redirect type, a 3xx redirection or a 410 or 451 error.
     pub kind: RedirectsKind,
+    /// Optional request conditions that must all match.
+    pub has: Option<Vec<RouteCondition>>,
+    /// Optional request conditions that must all not match.
+    pub missing: Option<Vec<RouteCondition>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 /// Represents rewrites types.
 pub struct Rewrites {
-    /// Source of the rewrite.
+    /// Glob source of the rewrite.
+    #[serde(default)]
     pub source: String,
-    /// Rewrite destination.
+    /// Regex source of the rewrite, an alternative to `source` whose named
+    /// capture groups are referenced as `$name` in the destination.
+    pub source_regex: Option<String>,
+    /// Whether the source matches regardless of case.
+    pub case_insensitive: Option<bool>,
+    /// Whether the source matches the request path and query instead of the path only.
+    pub match_query: Option<bool>,
+    /// Rewrite destination, optional for the 410 and 451 redirect types.
+    #[serde(default)]
     pub destination: String,
-    /// Optional redirect type either 301 (Moved Permanently) or 302 (Found).
+    /// Optional redirect type, a 3xx redirection or a 410 or 451 error.
     pub redirect: Option<RedirectsKind>,
+    /// Optional request conditions that must all match.
+    pub has: Option<Vec<RouteCondition>>,
+    /// Optional request conditions that must all not match.
+    pub missing: Option<Vec<RouteCondition>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Represents the request parts a redirect or rewrite condition checks.
+pub enum RouteConditionKind {
+    /// A request header, named by `key`.
+    Header,
+    /// A query string parameter, named by `key`.
+    Query,
+    /// A cookie, named by `key`.
+    Cookie,
+    /// The request host, without its port.
+    Host,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
-/// Represents virtual hosts with different root directories
+/// Represents a request condition of redirects and rewrites.
+pub struct RouteCondition {
+    /// The request part to check.
+    #[serde(rename = "type")]
+    pub kind: RouteConditionKind,
+    /// Name of the header, query parameter or cookie.
+    pub key: Option<String>,
+    /// Optional regex the whole value must match, any value matches if omitted.
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents virtual hosts with different root directories and settings
 pub struct VirtualHosts {
-    /// The value to check for in the "Host" header
+    /// The value to check for in the "Host" header, `*.example.com` matches any subdomain
     pub host: String,
-    /// The root directory for this virtual host
+    /// The root directory for this virtual host, the general one by default
     pub root: Option<PathBuf>,
+    /// Whether this virtual host serves requests not matching any other one
+    pub default: Option<bool>,
+    /// List of files to be used as an index for requests ending with the slash character
+    pub index_files: Option<String>,
+    /// HTML file path for 404 errors, relative to the virtual host root
+    pub page404: Option<PathBuf>,
+    /// HTML file path for 50x errors, relative to the virtual host root
+    pub page50x: Option<PathBuf>,
+    /// Page fallback feature.
+    #[cfg(feature = "fallback-page")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "fallback-page")))]
+    pub page_fallback: Option<PathBuf>,
+    /// Basic Authentication feature.
+    #[cfg(feature = "basic-auth")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "basic-auth")))]
+    pub basic_auth: Option<String>,
+    /// Directory listing feature.
+    #[cfg(feature = "directory-listing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
+    pub directory_listing: Option<bool>,
+    /// Directory listing order feature.
+    #[cfg(feature = "directory-listing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
+    pub directory_listing_order: Option<u8>,
+    /// Directory listing format feature.
+    #[cfg(feature = "directory-listing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
+    pub directory_listing_format: Option<DirListFmt>,
+    /// Redirect trailing slash feature.
+    pub redirect_trailing_slash: Option<bool>,
+    /// Clean URLs feature.
+    pub clean_urls: Option<bool>,
+    /// Ignore hidden files feature.
+    pub include_hidden: Option<bool>,
+    /// Follow symlinks feature.
+    pub follow_symlinks: Option<bool>,
+    /// Headers replacing the advanced ones for this virtual host
+    pub headers: Option<Vec<Headers>>,
+    /// Rewrites replacing the advanced ones for this virtual host
+    pub rewrites: Option<Vec<Rewrites>>,
+    /// Redirects replacing the advanced ones for this virtual host
+    pub redirects: Option<Vec<Redirects>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents mass virtual hosting from a `<root>/<host>/` directory layout
+pub struct MassVirtualHosts {
+    /// The directory containing a root directory per hostname
+    pub root: PathBuf,
+    /// Optional root directory of the site serving unknown hosts
+    pub fallback: Option<PathBuf>,
+    /// Seconds a host directory lookup is cached, 10 by default
+    pub cache_ttl: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents a directory served under a URL path prefix
+pub struct Mount {
+    /// The URL path prefix, e.g. `/docs`
+    pub prefix: String,
+    /// The root directory served under the prefix
+    pub root: PathBuf,
+    /// List of files to be used as an index for requests ending with the slash character
+    pub index_files: Option<String>,
+    /// Directory listing feature.
+    #[cfg(feature = "directory-listing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
+    pub directory_listing: Option<bool>,
+    /// Directory listing order feature.
+    #[cfg(feature = "directory-listing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
+    pub directory_listing_order: Option<u8>,
+    /// Directory listing format feature.
+    #[cfg(feature = "directory-listing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "directory-listing")))]
+    pub directory_listing_format: Option<DirListFmt>,
+    /// Ignore hidden files feature.
+    pub include_hidden: Option<bool>,
+    /// Follow symlinks feature.
+    pub follow_symlinks: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// Represents the in-memory file cache invalidation strategies.
+pub enum MemoryCacheInvalidation {
+    /// Entries expire only via their time to live or time to idle (default).
+    #[default]
+    None,
+    /// Watch the root directories and evict entries whose files change on disk.
+    /// Falls back to `revalidate` when the watcher can not be started.
+    Watch,
+    /// Compare the file modification time and size on every cache hit.
+    Revalidate,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -171,12 +346,160 @@ pub struct VirtualHosts {
 pub struct MemoryCache {
     /// Maximum capacity entries of the memory cache store.
     pub capacity: Option<u64>,
+    /// Maximum total size in MiB of the cached files.
+    /// When set, it bounds the store by bytes instead of `capacity` entries.
+    pub max_size: Option<u64>,
     /// Time to live in seconds of a cached file entry.
     pub ttl: Option<u64>,
     /// Time to idle in seconds of a cached file entry.
     pub tti: Option<u64>,
     /// Maximum file size in KiB for a file entry to be cached.
     pub max_file_size: Option<u64>,
+    /// Strategy used to evict entries whose files change on disk, `none` by default.
+    pub invalidation: Option<MemoryCacheInvalidation>,
+    /// Glob patterns of the request paths eligible for caching.
+    /// All paths are eligible when empty.
+    pub include: Option<Vec<String>>,
+    /// Glob patterns of the request paths never cached.
+    /// Takes precedence over `include`.
+    pub exclude: Option<Vec<String>>,
+    /// Glob patterns of the request paths loaded into the cache at startup.
+    pub preload: Option<Vec<String>>,
+    /// Whether the health endpoint reports the server as unavailable
+    /// until the startup preload finishes.
+    pub preload_wait: Option<bool>,
+    /// Bearer token required by the cache purge endpoint.
+    /// The endpoint is disabled when unset.
+    pub purge_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents the on-disk cache of the dynamic compression output.
+pub struct CompressionCache {
+    /// Directory where the compressed files are stored.
+    pub dir: PathBuf,
+    /// Maximum total size in MiB of the cached files.
+    pub max_size: Option<u64>,
+    /// Maximum size in KiB of a file for its compressed output to be cached.
+    pub max_file_size: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents the dynamic compression policy.
+pub struct CompressionPolicy {
+    /// Minimum response body size in bytes for it to be compressed.
+    pub min_size: Option<u64>,
+    /// Encodings used, in order of preference among the ones a client
+    /// accepts with the same quality, e.g. `["zstd", "br", "gzip"]`.
+    pub encodings: Option<Vec<String>>,
+    /// Compression levels per encoding.
+    pub levels: Option<CompressionLevels>,
+    /// Rules enabling or disabling compression per request path or MIME type.
+    pub rules: Option<Vec<CompressionRule>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents the compression levels per encoding.
+pub struct CompressionLevels {
+    /// `gzip` level.
+    pub gzip: Option<i32>,
+    /// `deflate` level.
+    pub deflate: Option<i32>,
+    /// `br` level.
+    #[serde(rename = "br")]
+    pub brotli: Option<i32>,
+    /// `zstd` level.
+    pub zstd: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents a dynamic compression rule.
+pub struct CompressionRule {
+    /// Glob pattern matched against the request path.
+    pub source: Option<String>,
+    /// MIME type matched against the response `Content-Type`, e.g. `application/wasm` or `image/*`.
+    pub mime: Option<String>,
+    /// Whether matching responses are compressed. Defaults to `true`.
+    pub enabled: Option<bool>,
+    /// Encodings allowed for matching responses.
+    pub encodings: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents a `Cache-Control` rule.
+pub struct CacheControl {
+    /// Glob pattern matched against the request path.
+    pub source: Option<String>,
+    /// Regular expression matched against the request path.
+    pub source_regex: Option<String>,
+    /// Whether to match only fingerprinted file names, e.g. `app.3f9a1c.js`.
+    pub fingerprinted: Option<bool>,
+    /// `Cache-Control` header value, e.g. `public, max-age=31536000, immutable`.
+    pub value: String,
+    /// Optional `Expires` header value in seconds relative to the response time.
+    pub expires: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents a compression dictionary served to clients.
+pub struct CompressionDictionary {
+    /// Glob pattern of the request paths of the resources used as dictionaries.
+    pub source: String,
+    /// URL pattern of the requests the dictionaries apply to, e.g. `/assets/app.*.js`.
+    #[serde(rename = "match")]
+    pub url_match: String,
+    /// Optional dictionary identifier echoed back by clients.
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents a CSV or JSON file of exact redirects.
+pub struct RedirectMap {
+    /// Path of the file, JSON when its extension is `.json` and CSV otherwise.
+    pub path: PathBuf,
+    /// Optional host to match against an incoming URI host if specified.
+    pub host: Option<String>,
+    /// Type of the entries without a status, 301 (Moved Permanently) by default.
+    pub kind: Option<RedirectsKind>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents a reverse proxy rule forwarding requests to an upstream server.
+pub struct Proxy {
+    /// Glob pattern matched against the request path, e.g. `/api/**`.
+    pub source: String,
+    /// Upstream URL, either `http://host[:port][/path]` or `unix:/path/to/socket`.
+    pub upstream: String,
+    /// Optional host to match against an incoming URI host if specified.
+    pub host: Option<String>,
+    /// Optional request path prefix removed before forwarding, e.g. `/api`.
+    pub strip_prefix: Option<String>,
+    /// Whether to forward the original `Host` header instead of the upstream one.
+    pub preserve_host: Option<bool>,
+    /// Seconds to wait for a connection to the upstream, 10 by default.
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for the upstream response headers or body data, 60 by default.
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+/// Represents the language negotiation of localized `<name>.<lang>.html` files.
+pub struct LanguageNegotiation {
+    /// Language served when none of the accepted ones has a variant, e.g. `en`.
+    pub default_language: Option<String>,
+    /// Optional cookie name whose value overrides the `Accept-Language` header.
+    pub cookie: Option<String>,
+    /// Optional query parameter name whose value overrides the cookie and the `Accept-Language` header.
+    pub query_param: Option<String>,
 }
 
 /// Advanced server options only available in configuration file mode.
@@ -191,8 +514,28 @@ pub struct Advanced {
     pub redirects: Option<Vec<Redirects>>,
     /// Name-based virtual hosting
     pub virtual_hosts: Option<Vec<VirtualHosts>>,
+    /// Status code for requests not matching any virtual host, either 404 or 421
+    pub unknown_host_status: Option<u16>,
+    /// Mass virtual hosting from a directory per hostname
+    pub mass_virtual_hosts: Option<MassVirtualHosts>,
+    /// Directories served under URL path prefixes
+    pub mounts: Option<Vec<Mount>>,
     /// In-memory cache feature.
     pub memory_cache: Option<MemoryCache>,
+    /// On-disk compression cache feature.
+    pub compression_cache: Option<CompressionCache>,
+    /// Cache-Control rules
+    pub cache_control: Option<Vec<CacheControl>>,
+    /// Compression dictionaries
+    pub compression_dictionaries: Option<Vec<CompressionDictionary>>,
+    /// Dynamic compression policy
+    pub compression: Option<CompressionPolicy>,
+    /// Redirect maps
+    pub redirect_maps: Option<Vec<RedirectMap>>,
+    /// Reverse proxy rules
+    pub proxy: Option<Vec<Proxy>>,
+    /// Language negotiation
+    pub language_negotiation: Option<LanguageNegotiation>,
 }
 
 /// General server options available in configuration file mode.
@@ -222,6 +565,15 @@ pub struct General {
     /// Weak ETag headers.
     pub etag: Option<bool>,
 
+    /// Strong content-hash ETag headers.
+    pub etag_strong: Option<bool>,
+
+    /// Repr-Digest and Content-Digest headers.
+    pub repr_digest: Option<bool>,
+
+    /// Netlify-style `_redirects` and `_headers` files.
+    pub site_files: Option<bool>,
+
     /// Compression.
     #[cfg(any(
         feature = "compression",
@@ -265,6 +617,9 @@ pub struct General {
     /// Check for a pre-compressed file on disk.
     pub compression_static: Option<bool>,
 
+    /// Check for AVIF and WebP image variants on disk.
+    pub image_negotiation: Option<bool>,
+
     /// Error 404 pages.
     pub page404: Option<PathBuf>,
     /// Error 50x pages.
@@ -385,6 +740,9 @@ pub struct General {
     /// Redirect trailing slash feature.
     pub redirect_trailing_slash: Option<bool>,
 
+    /// Clean URLs feature.
+    pub clean_urls: Option<bool>,
+
     /// Include hidden files (dotfiles) feature.
     pub include_hidden: Option<bool>,
 
@@ -6,14 +6,16 @@
 //! Module that provides all settings of SWS.
 //!
 
-use aho_corasick::AhoCorasick;
+use aho_corasick::{AhoCorasick, MatchKind};
 use clap::Parser;
 use globset::{Glob, GlobBuilder, GlobMatcher};
-use headers::HeaderMap;
+use headers::{HeaderMap, HeaderValue};
 use hyper::StatusCode;
-use regex_lite::Regex;
+use regex_lite::{Regex, RegexBuilder};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::conditions::Conditions;
 use crate::{Context, Result, helpers, logger};
 
 pub mod cli;
@@ -25,7 +27,10 @@ pub use cli::Commands;
 
 use cli::General;
 
-use self::file::MemoryCache;
+use self::file::{
+    CompressionCache, CompressionDictionary, CompressionPolicy, LanguageNegotiation,
+    MassVirtualHosts, MemoryCache, Mount, Proxy, RedirectMap,
+};
 
 use self::file::{RedirectsKind, Settings as FileSettings};
 
@@ -39,6 +44,7 @@ use self::file::{RedirectsKind, Settings as FileSettings};
 pub use file::CompressionLevel;
 
 /// The `headers` file options.
+#[derive(Clone)]
 pub struct Headers {
     /// Source pattern glob matcher
     pub source: GlobMatcher,
@@ -47,18 +53,24 @@ pub struct Headers {
 }
 
 /// The `Rewrites` file options.
+#[derive(Clone)]
 pub struct Rewrites {
     /// Source pattern Regex matcher
     pub source: Regex,
     /// A local file that must exist
     pub destination: String,
-    /// Optional redirect type either 301 (Moved Permanently) or 302 (Found).
+    /// Optional redirect type, a 3xx redirection or a 410 or 451 error.
     pub redirect: Option<RedirectsKind>,
+    /// Whether the source matches the request path and query.
+    pub match_query: bool,
+    /// Request conditions of the rule.
+    pub conditions: Conditions,
     /// Pre-compiled Aho-Corasick automaton for placeholder replacement.
     pub replacer: AhoCorasick,
 }
 
 /// The `Redirects` file options.
+#[derive(Clone)]
 pub struct Redirects {
     /// Optional host to match against an incoming URI host if specified
     pub host: Option<String>,
@@ -66,22 +78,79 @@ pub struct Redirects {
     pub source: Regex,
     /// A local file that must exist
     pub destination: String,
-    /// Redirection type either 301 (Moved Permanently) or 302 (Found)
+    /// Redirection type, a 3xx redirection or a 410 or 451 error
     pub kind: StatusCode,
+    /// Whether the source matches the request path and query.
+    pub match_query: bool,
+    /// Request conditions of the rule.
+    pub conditions: Conditions,
     /// Pre-compiled Aho-Corasick automaton for placeholder replacement.
     pub replacer: AhoCorasick,
 }
 
+/// The `CacheControl` file options.
+#[derive(Clone)]
+pub struct CacheControl {
+    /// Source pattern glob matcher
+    pub source: Option<GlobMatcher>,
+    /// Source pattern Regex matcher
+    pub source_regex: Option<Regex>,
+    /// Whether only fingerprinted file names match
+    pub fingerprinted: bool,
+    /// `Cache-Control` header value
+    pub value: HeaderValue,
+    /// Optional `Expires` header value relative to the response time
+    pub expires: Option<Duration>,
+}
+
 /// The `VirtualHosts` file options.
+#[derive(Clone)]
 pub struct VirtualHosts {
     /// The value to check for in the "Host" header
     pub host: String,
     /// The root directory for this virtual host
     pub root: PathBuf,
+    /// Whether it serves requests not matching any other virtual host
+    pub default: bool,
+    /// Optional index files list override
+    pub index_files: Option<String>,
+    /// Optional 404 error page override
+    pub page404: Option<PathBuf>,
+    /// Optional 50x error page override
+    pub page50x: Option<PathBuf>,
+    /// Optional fallback page override
+    #[cfg(feature = "fallback-page")]
+    pub page_fallback: Option<PathBuf>,
+    /// Optional basic authentication override
+    #[cfg(feature = "basic-auth")]
+    pub basic_auth: Option<String>,
+    /// Optional directory listing override
+    #[cfg(feature = "directory-listing")]
+    pub directory_listing: Option<bool>,
+    /// Optional directory listing order override
+    #[cfg(feature = "directory-listing")]
+    pub directory_listing_order: Option<u8>,
+    /// Optional directory listing format override
+    #[cfg(feature = "directory-listing")]
+    pub directory_listing_format: Option<crate::directory_listing::DirListFmt>,
+    /// Optional redirect trailing slash override
+    pub redirect_trailing_slash: Option<bool>,
+    /// Optional clean URLs override
+    pub clean_urls: Option<bool>,
+    /// Optional hidden files override
+    pub include_hidden: Option<bool>,
+    /// Optional follow symlinks override
+    pub follow_symlinks: Option<bool>,
+    /// Optional headers list replacing the advanced one
+    pub headers: Option<Vec<Headers>>,
+    /// Optional rewrites list replacing the advanced one
+    pub rewrites: Option<Vec<Rewrites>>,
+    /// Optional redirects list replacing the advanced one
+    pub redirects: Option<Vec<Redirects>>,
 }
 
 /// The `advanced` file options.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Advanced {
     /// Headers list.
     pub headers: Option<Vec<Headers>>,
@@ -91,15 +160,105 @@ pub struct Advanced {
     pub redirects: Option<Vec<Redirects>>,
     /// Name-based virtual hosting
     pub virtual_hosts: Option<Vec<VirtualHosts>>,
+    /// Status code for requests not matching any virtual host.
+    pub unknown_host_status: Option<StatusCode>,
+    /// Mass virtual hosting configuration.
+    pub mass_virtual_hosts: Option<MassVirtualHosts>,
+    /// Directories served under URL path prefixes.
+    pub mounts: Option<Vec<Mount>>,
     /// In-memory cache configuration.
     pub memory_cache: Option<MemoryCache>,
+    /// On-disk compression cache configuration.
+    pub compression_cache: Option<CompressionCache>,
+    /// Cache-Control rules list.
+    pub cache_control: Option<Vec<CacheControl>>,
+    /// Compression dictionaries list.
+    pub compression_dictionaries: Option<Vec<CompressionDictionary>>,
+    /// Dynamic compression policy.
+    pub compression: Option<CompressionPolicy>,
+    /// Redirect maps list.
+    pub redirect_maps: Option<Vec<RedirectMap>>,
+    /// Reverse proxy rules list.
+    pub proxy: Option<Vec<Proxy>>,
+    /// Language negotiation of localized files.
+    pub language_negotiation: Option<LanguageNegotiation>,
 }
 
 /// Build an `AhoCorasick` automaton for the placeholder patterns `$0`, `$1`, ..., `$N`
-/// based on the number of capture groups in the given regex.
+/// based on the number of capture groups in the given regex, followed by
+/// the `$name` patterns of its named groups.
 pub fn build_placeholder_replacer(regex: &Regex) -> AhoCorasick {
-    let patterns: Vec<String> = (0..regex.captures_len()).map(|i| format!("${i}")).collect();
-    AhoCorasick::new(&patterns).expect("failed to build Aho-Corasick automaton for placeholders")
+    build_placeholder_replacer_with(regex, 0)
+}
+
+/// Same as [`build_placeholder_replacer`] with `extra_len` more numbered
+/// placeholders following the ones of the capture groups.
+pub fn build_placeholder_replacer_with(regex: &Regex, extra_len: usize) -> AhoCorasick {
+    let patterns: Vec<String> = (0..regex.captures_len() + extra_len)
+        .map(|i| format!("${i}"))
+        .chain(
+            regex
+                .capture_names()
+                .flatten()
+                .map(|name| format!("${name}")),
+        )
+        .collect();
+    AhoCorasick::builder()
+        .match_kind(MatchKind::LeftmostLongest)
+        .build(&patterns)
+        .expect("failed to build Aho-Corasick automaton for placeholders")
+}
+
+/// Compiles the source of a redirect or rewrite rule, either a glob or a
+/// regex, into the regex matched against the requests.
+fn compile_route_source(
+    rule: &str,
+    source: &str,
+    source_regex: Option<&str>,
+    case_insensitive: bool,
+) -> Result<Regex> {
+    let pattern = match source_regex {
+        Some(_) if !source.is_empty() => {
+            bail!("{rule} source `{source}` can not be combined with a `source-regex`")
+        }
+        Some(pattern) => pattern.to_owned(),
+        None if source.is_empty() => bail!("missing source or source-regex for {rule}"),
+        None => {
+            let glob = GlobBuilder::new(source)
+                .literal_separator(true)
+                .build()
+                .with_context(|| {
+                    format!("can not compile glob pattern for {rule} source: {source}")
+                })?
+                .compile_matcher();
+            let pattern = glob
+                .glob()
+                .regex()
+                .trim_start_matches("(?-u)")
+                .replace("?:.*", ".*")
+                .replace("?:", "")
+                .replace(".*.*", ".*")
+                .replace("([^/]*[^/]*)", "(.*)")
+                .to_owned();
+            tracing::debug!("url {rule}s glob pattern: {source}");
+            tracing::debug!("url {rule}s regex equivalent: {pattern}");
+            pattern
+        }
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .with_context(|| format!("can not compile regex pattern for {rule} source: {pattern}"))
+}
+
+/// Build an `AhoCorasick` automaton for `len` placeholder patterns `$0`, `$1`, ...
+/// The longest placeholder wins so `$10` is not taken for `$1`.
+pub fn build_placeholder_replacer_len(len: usize) -> AhoCorasick {
+    let patterns: Vec<String> = (0..len).map(|i| format!("${i}")).collect();
+    AhoCorasick::builder()
+        .match_kind(MatchKind::LeftmostLongest)
+        .build(&patterns)
+        .expect("failed to build Aho-Corasick automaton for placeholders")
 }
 
 /// The full server CLI and File options.
@@ -142,6 +301,9 @@ impl Settings {
         let mut config_file = opts.config_file.clone();
         let mut cache_control_headers = opts.cache_control_headers;
         let mut etag = opts.etag;
+        let mut etag_strong = opts.etag_strong;
+        let mut repr_digest = opts.repr_digest;
+        let mut site_files = opts.site_files;
 
         #[cfg(any(
             feature = "compression",
@@ -161,6 +323,7 @@ impl Settings {
         let mut compression_level = opts.compression_level;
 
         let mut compression_static = opts.compression_static;
+        let mut image_negotiation = opts.image_negotiation;
 
         let mut page404 = opts.page404;
         let mut page50x = opts.page50x;
@@ -219,6 +382,7 @@ impl Settings {
         let mut log_forwarded_for = opts.log_forwarded_for;
         let mut trusted_proxies = opts.trusted_proxies;
         let mut redirect_trailing_slash = opts.redirect_trailing_slash;
+        let mut clean_urls = opts.clean_urls;
         let mut include_hidden = opts.include_hidden;
         let mut follow_symlinks = opts.follow_symlinks;
         let mut use_relative_root = opts.use_relative_root;
@@ -284,6 +448,15 @@ impl Settings {
                 if let Some(v) = general.etag {
                     etag = v
                 }
+                if let Some(v) = general.etag_strong {
+                    etag_strong = v
+                }
+                if let Some(v) = general.repr_digest {
+                    repr_digest = v
+                }
+                if let Some(v) = general.site_files {
+                    site_files = v
+                }
                 #[cfg(any(
                     feature = "compression",
                     feature = "compression-gzip",
@@ -307,6 +480,9 @@ impl Settings {
                 if let Some(v) = general.compression_static {
                     compression_static = v
                 }
+                if let Some(v) = general.image_negotiation {
+                    image_negotiation = v
+                }
                 if let Some(v) = general.page404 {
                     page404 = v
                 }
@@ -430,6 +606,9 @@ impl Settings {
                 if let Some(v) = general.redirect_trailing_slash {
                     redirect_trailing_slash = v
                 }
+                if let Some(v) = general.clean_urls {
+                    clean_urls = v
+                }
                 if let Some(v) = general.include_hidden {
                     include_hidden = v
                 }
@@ -496,172 +675,172 @@ impl Settings {
             // File-based "advanced" options
             if let Some(advanced) = settings.advanced {
                 // 1. Custom HTTP headers assignment
-                let headers_entries = match advanced.headers {
-                    Some(headers_entries) => {
-                        let mut headers_vec: Vec<Headers> = Vec::new();
-
-                        // Compile a glob pattern for each header sources entry
-                        for headers_entry in headers_entries.iter() {
-                            let source = Glob::new(&headers_entry.source)
-                                .with_context(|| {
-                                    format!(
-                                        "can not compile glob pattern for header source: {}",
-                                        headers_entry.source
-                                    )
-                                })?
-                                .compile_matcher();
-
-                            headers_vec.push(Headers {
-                                source,
-                                headers: headers_entry.headers.to_owned(),
-                            });
-                        }
-                        Some(headers_vec)
-                    }
-                    _ => None,
-                };
+                let headers_entries = advanced
+                    .headers
+                    .as_deref()
+                    .map(compile_headers)
+                    .transpose()?;
 
                 // 2. Rewrites assignment
-                let rewrites_entries = match advanced.rewrites {
-                    Some(rewrites_entries) => {
-                        let mut rewrites_vec: Vec<Rewrites> = Vec::new();
-
-                        // Compile a glob pattern for each rewrite sources entry
-                        for rewrites_entry in rewrites_entries.iter() {
-                            let source = GlobBuilder::new(&rewrites_entry.source)
-                                .literal_separator(true)
-                                .build()
-                                .with_context(|| {
-                                    format!(
-                                        "can not compile glob pattern for rewrite source: {}",
-                                        rewrites_entry.source
-                                    )
-                                })?
-                                .compile_matcher();
-
-                            let pattern = source
-                                .glob()
-                                .regex()
-                                .trim_start_matches("(?-u)")
-                                .replace("?:.*", ".*")
-                                .replace("?:", "")
-                                .replace(".*.*", ".*")
-                                .replace("([^/]*[^/]*)", "(.*)")
-                                .to_owned();
-                            tracing::debug!(
-                                "url rewrites glob pattern: {}",
-                                &rewrites_entry.source
-                            );
-                            tracing::debug!("url rewrites regex equivalent: {}", pattern);
+                let rewrites_entries = advanced
+                    .rewrites
+                    .as_deref()
+                    .map(compile_rewrites)
+                    .transpose()?;
 
-                            let source = Regex::new(&pattern).with_context(|| {
-                                    format!(
-                                        "can not compile regex pattern equivalent for rewrite source: {}",
-                                        pattern
-                                    )
-                                })?;
+                // 3. Redirects assignment
+                let redirects_entries = advanced
+                    .redirects
+                    .as_deref()
+                    .map(compile_redirects)
+                    .transpose()?;
 
-                            let replacer = build_placeholder_replacer(&source);
-                            rewrites_vec.push(Rewrites {
-                                source,
-                                destination: rewrites_entry.destination.to_owned(),
-                                redirect: rewrites_entry.redirect.to_owned(),
-                                replacer,
-                            });
-                        }
-                        Some(rewrites_vec)
-                    }
-                    _ => None,
-                };
+                // 3. Virtual hosts assignment
+                let vhosts_entries = match advanced.virtual_hosts {
+                    Some(vhosts_entries) => {
+                        let mut vhosts_vec: Vec<VirtualHosts> = Vec::new();
 
-                // 3. Redirects assignment
-                let redirects_entries = match advanced.redirects {
-                    Some(redirects_entries) => {
-                        let mut redirects_vec: Vec<Redirects> = Vec::new();
-
-                        // Compile a glob pattern for each redirect sources entry
-                        for redirects_entry in redirects_entries.iter() {
-                            let source = GlobBuilder::new(&redirects_entry.source)
-                                .literal_separator(true)
-                                .build()
-                                .with_context(|| {
-                                    format!(
-                                        "can not compile glob pattern for redirect source: {}",
-                                        redirects_entry.source
-                                    )
-                                })?
-                                .compile_matcher();
-
-                            let pattern = source
-                                .glob()
-                                .regex()
-                                .trim_start_matches("(?-u)")
-                                .replace("?:.*", ".*")
-                                .replace("?:", "")
-                                .replace(".*.*", ".*")
-                                .replace("([^/]*[^/]*)", "(.*)")
-                                .to_owned();
+                        for vhosts_entry in vhosts_entries {
+                            // Make sure path is valid, the general root is used by default
+                            let root_dir = vhosts_entry.root.as_ref().unwrap_or(&root);
+                            let root_dir = helpers::get_valid_dirpath(root_dir).with_context(
+                                || "root directory for virtual host was not found or inaccessible",
+                            )?;
+                            // Canonicalize once so the per-request
+                            // containment check can skip a `canonicalize`
+                            // syscall (see `static_files::security`), unless
+                            // `use_relative_root` is enabled.
+                            let root_dir = if use_relative_root {
+                                root_dir
+                            } else {
+                                root_dir.canonicalize().unwrap_or(root_dir)
+                            };
                             tracing::debug!(
-                                "url redirects glob pattern: {}",
-                                &redirects_entry.source
+                                "added virtual host: {} -> {}",
+                                vhosts_entry.host,
+                                root_dir.display()
                             );
-                            tracing::debug!("url redirects regex equivalent: {}", pattern);
-
-                            let source = Regex::new(&pattern).with_context(|| {
-                                    format!(
-                                        "can not compile regex pattern equivalent for redirect source: {}",
-                                        pattern
-                                    )
-                                })?;
-
-                            let status_code = redirects_entry.kind.to_owned() as u16;
-                            let replacer = build_placeholder_replacer(&source);
-                            redirects_vec.push(Redirects {
-                                host: redirects_entry.host.to_owned(),
-                                source,
-                                destination: redirects_entry.destination.to_owned(),
-                                kind: StatusCode::from_u16(status_code).with_context(|| {
-                                    format!("invalid redirect status code: {status_code}")
-                                })?,
-                                replacer,
+                            vhosts_vec.push(VirtualHosts {
+                                host: vhosts_entry.host.trim().to_ascii_lowercase(),
+                                root: root_dir,
+                                default: vhosts_entry.default.unwrap_or(false),
+                                index_files: vhosts_entry.index_files,
+                                page404: vhosts_entry.page404,
+                                page50x: vhosts_entry.page50x,
+                                #[cfg(feature = "fallback-page")]
+                                page_fallback: vhosts_entry.page_fallback,
+                                #[cfg(feature = "basic-auth")]
+                                basic_auth: vhosts_entry.basic_auth,
+                                #[cfg(feature = "directory-listing")]
+                                directory_listing: vhosts_entry.directory_listing,
+                                #[cfg(feature = "directory-listing")]
+                                directory_listing_order: vhosts_entry.directory_listing_order,
+                                #[cfg(feature = "directory-listing")]
+                                directory_listing_format: vhosts_entry.directory_listing_format,
+                                redirect_trailing_slash: vhosts_entry.redirect_trailing_slash,
+                                clean_urls: vhosts_entry.clean_urls,
+                                include_hidden: vhosts_entry.include_hidden,
+                                follow_symlinks: vhosts_entry.follow_symlinks,
+                                headers: vhosts_entry
+                                    .headers
+                                    .as_deref()
+                                    .map(compile_headers)
+                                    .transpose()?,
+                                rewrites: vhosts_entry
+                                    .rewrites
+                                    .as_deref()
+                                    .map(compile_rewrites)
+                                    .transpose()?,
+                                redirects: vhosts_entry
+                                    .redirects
+                                    .as_deref()
+                                    .map(compile_redirects)
+                                    .transpose()?,
                             });
                         }
-                        Some(redirects_vec)
+                        Some(vhosts_vec)
                     }
                     _ => None,
                 };
+                let unknown_host_status = match advanced.unknown_host_status {
+                    Some(code @ (404 | 421)) => StatusCode::from_u16(code).ok(),
+                    Some(code) => {
+                        bail!("unknown host status must be either 404 or 421, got {code}")
+                    }
+                    None => None,
+                };
+                let defaults = vhosts_entries
+                    .iter()
+                    .flatten()
+                    .filter(|vhost| vhost.default)
+                    .count();
+                if defaults > 1 {
+                    bail!("only one virtual host can be the default one");
+                }
+                if defaults > 0 && unknown_host_status.is_some() {
+                    bail!("unknown host status can not be used along with a default virtual host");
+                }
+                if advanced
+                    .mass_virtual_hosts
+                    .as_ref()
+                    .is_some_and(|mass| mass.fallback.is_some())
+                    && (defaults > 0 || unknown_host_status.is_some())
+                {
+                    bail!(
+                        "mass virtual hosts fallback can not be used along with a default virtual host or an unknown host status"
+                    );
+                }
 
-                // 3. Virtual hosts assignment
-                let vhosts_entries = match advanced.virtual_hosts {
-                    Some(vhosts_entries) => {
-                        let mut vhosts_vec: Vec<VirtualHosts> = Vec::new();
-
-                        for vhosts_entry in vhosts_entries.iter() {
-                            if let Some(root) = vhosts_entry.root.to_owned() {
-                                // Make sure path is valid
-                                let root_dir = helpers::get_valid_dirpath(&root)
-                                    .with_context(|| "root directory for virtual host was not found or inaccessible")?;
-                                // Canonicalize once so the per-request
-                                // containment check can skip a `canonicalize`
-                                // syscall (see `static_files::security`), unless
-                                // `use_relative_root` is enabled.
-                                let root_dir = if use_relative_root {
-                                    root_dir
-                                } else {
-                                    root_dir.canonicalize().unwrap_or(root_dir)
-                                };
-                                tracing::debug!(
-                                    "added virtual host: {} -> {}",
-                                    vhosts_entry.host,
-                                    root_dir.display()
+                // 4. Cache-Control rules assignment
+                let cache_control_entries = match advanced.cache_control {
+                    Some(cache_control_entries) => {
+                        let mut cache_control_vec: Vec<CacheControl> = Vec::new();
+
+                        for entry in cache_control_entries.iter() {
+                            let fingerprinted = entry.fingerprinted.unwrap_or(false);
+                            if entry.source.is_none()
+                                && entry.source_regex.is_none()
+                                && !fingerprinted
+                            {
+                                bail!(
+                                    "cache-control rule `{}` requires a `source`, `source-regex` or `fingerprinted` matcher",
+                                    entry.value
                                 );
-                                vhosts_vec.push(VirtualHosts {
-                                    host: vhosts_entry.host.to_owned(),
-                                    root: root_dir,
-                                });
                             }
+
+                            let source = match entry.source.as_deref() {
+                                Some(source) => Some(
+                                    Glob::new(source)
+                                        .with_context(|| {
+                                            format!(
+                                                "can not compile glob pattern for cache-control source: {source}"
+                                            )
+                                        })?
+                                        .compile_matcher(),
+                                ),
+                                None => None,
+                            };
+                            let source_regex = match entry.source_regex.as_deref() {
+                                Some(pattern) => Some(Regex::new(pattern).with_context(|| {
+                                    format!(
+                                        "can not compile regex pattern for cache-control source: {pattern}"
+                                    )
+                                })?),
+                                None => None,
+                            };
+                            let value = HeaderValue::from_str(&entry.value).with_context(|| {
+                                format!("invalid cache-control header value: {}", entry.value)
+                            })?;
+
+                            cache_control_vec.push(CacheControl {
+                                source,
+                                source_regex,
+                                fingerprinted,
+                                value,
+                                expires: entry.expires.map(Duration::from_secs),
+                            });
                         }
-                        Some(vhosts_vec)
+                        Some(cache_control_vec)
                     }
                     _ => None,
                 };
@@ -671,7 +850,17 @@ impl Settings {
                     rewrites: rewrites_entries,
                     redirects: redirects_entries,
                     virtual_hosts: vhosts_entries,
+                    unknown_host_status,
+                    mass_virtual_hosts: advanced.mass_virtual_hosts,
+                    mounts: advanced.mounts,
                     memory_cache: advanced.memory_cache,
+                    compression_cache: advanced.compression_cache,
+                    cache_control: cache_control_entries,
+                    compression_dictionaries: advanced.compression_dictionaries,
+                    compression: advanced.compression,
+                    redirect_maps: advanced.redirect_maps,
+                    proxy: advanced.proxy,
+                    language_negotiation: advanced.language_negotiation,
                 });
             }
         } else if log_init {
@@ -720,6 +909,9 @@ impl Settings {
                 config_file,
                 cache_control_headers,
                 etag,
+                etag_strong,
+                repr_digest,
+                site_files,
                 #[cfg(any(
                     feature = "compression",
                     feature = "compression-gzip",
@@ -737,6 +929,7 @@ impl Settings {
                 ))]
                 compression_level,
                 compression_static,
+                image_negotiation,
                 page404,
                 page50x,
                 #[cfg(feature = "http2")]
@@ -786,6 +979,7 @@ impl Settings {
                 log_forwarded_for,
                 trusted_proxies,
                 redirect_trailing_slash,
+                clean_urls,
                 include_hidden,
                 follow_symlinks,
                 use_relative_root,
@@ -809,6 +1003,107 @@ impl Settings {
     }
 }
 
+/// Compiles the glob pattern of each custom headers entry.
+fn compile_headers(headers_entries: &[file::Headers]) -> Result<Vec<Headers>> {
+    let mut headers_vec: Vec<Headers> = Vec::new();
+
+    for headers_entry in headers_entries {
+        let source = Glob::new(&headers_entry.source)
+            .with_context(|| {
+                format!(
+                    "can not compile glob pattern for header source: {}",
+                    headers_entry.source
+                )
+            })?
+            .compile_matcher();
+
+        headers_vec.push(Headers {
+            source,
+            headers: headers_entry.headers.to_owned(),
+        });
+    }
+    Ok(headers_vec)
+}
+
+/// Compiles the glob or regex pattern of each rewrites entry.
+fn compile_rewrites(rewrites_entries: &[file::Rewrites]) -> Result<Vec<Rewrites>> {
+    let mut rewrites_vec: Vec<Rewrites> = Vec::new();
+
+    for rewrites_entry in rewrites_entries {
+        let source = compile_route_source(
+            "rewrite",
+            &rewrites_entry.source,
+            rewrites_entry.source_regex.as_deref(),
+            rewrites_entry.case_insensitive.unwrap_or(false),
+        )?;
+        let rule_source = rewrites_entry
+            .source_regex
+            .as_deref()
+            .unwrap_or(&rewrites_entry.source);
+
+        let is_terminal = rewrites_entry
+            .redirect
+            .is_some_and(|kind| kind.status_code().is_client_error());
+        if rewrites_entry.destination.is_empty() && !is_terminal {
+            bail!("missing destination for rewrite source: {}", rule_source);
+        }
+        let conditions = Conditions::new(
+            rewrites_entry.has.as_deref(),
+            rewrites_entry.missing.as_deref(),
+        )
+        .with_context(|| format!("invalid conditions for rewrite source: {}", rule_source))?;
+        let replacer = build_placeholder_replacer_with(&source, conditions.captures_len());
+        rewrites_vec.push(Rewrites {
+            source,
+            destination: rewrites_entry.destination.to_owned(),
+            redirect: rewrites_entry.redirect.to_owned(),
+            match_query: rewrites_entry.match_query.unwrap_or(false),
+            conditions,
+            replacer,
+        });
+    }
+    Ok(rewrites_vec)
+}
+
+/// Compiles the glob or regex pattern of each redirects entry.
+fn compile_redirects(redirects_entries: &[file::Redirects]) -> Result<Vec<Redirects>> {
+    let mut redirects_vec: Vec<Redirects> = Vec::new();
+
+    for redirects_entry in redirects_entries {
+        let source = compile_route_source(
+            "redirect",
+            &redirects_entry.source,
+            redirects_entry.source_regex.as_deref(),
+            redirects_entry.case_insensitive.unwrap_or(false),
+        )?;
+        let rule_source = redirects_entry
+            .source_regex
+            .as_deref()
+            .unwrap_or(&redirects_entry.source);
+
+        let kind = redirects_entry.kind.status_code();
+        if redirects_entry.destination.is_empty() && kind.is_redirection() {
+            bail!("missing destination for redirect source: {}", rule_source);
+        }
+        let conditions = Conditions::new(
+            redirects_entry.has.as_deref(),
+            redirects_entry.missing.as_deref(),
+        )
+        .with_context(|| format!("invalid conditions for redirect source: {}", rule_source))?;
+        let replacer = build_placeholder_replacer_with(&source, conditions.captures_len());
+        redirects_vec.push(Redirects {
+            host: redirects_entry.host.to_owned(),
+            source,
+            destination: redirects_entry.destination.to_owned(),
+            kind,
+            match_query: redirects_entry.match_query.unwrap_or(false),
+            conditions,
+            replacer,
+        });
+    }
+    Ok(redirects_vec)
+}
+
 fn read_file_settings(config_file: &Path) -> Result<Option<(FileSettings, PathBuf)>> {
     if config_file.is_file() {
         let file_path_resolved = config_file
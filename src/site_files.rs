@@ -0,0 +1,674 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Netlify-style `_redirects` and `_headers` files.
+//!
+//! When enabled, the `_redirects` and `_headers` files found at the root
+//! directory of the server and of each virtual host are compiled into the
+//! same rules used by the `[[advanced.redirects]]`, `[[advanced.rewrites]]`
+//! and `[[advanced.headers]]` settings:
+//!
+//! ```text
+//! # _redirects
+//! /home              /                301
+//! /blog/:year/:slug  /posts/:slug     302
+//! /docs/*            /guide/:splat
+//! /app/*             /app/index.html  200
+//! /old/*             /new/:splat      308!
+//!
+//! # _headers
+//! /assets/*
+//!   Cache-Control: public, max-age=31536000, immutable
+//! ```
+//!
+//! Rules apply top-down and the first match wins. Statuses `301` (the
+//! default), `302`, `303`, `307` and `308` redirect while `200` rewrites
+//! the request internally. Rules are skipped when a file exists at the
+//! request path unless the status is forced with `!`. Query parameter
+//! matching, conditions and other statuses are not supported and the
+//! lines using them are ignored with a warning.
+//!
+//! The files are checked for changes at most once per second. They are
+//! never served themselves, whatever the case of the request path, nor
+//! listed in the root directory listing or archive download.
+
+use globset::{Glob, GlobMatcher};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::{Request, Response, StatusCode};
+use regex_lite::Regex;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::body::Body;
+use crate::fs::meta::{FileStamp, file_stamp};
+use crate::fs::path::sanitize_path;
+use crate::handler::RequestHandlerOpts;
+use crate::settings::{Headers, Redirects, Rewrites, build_placeholder_replacer};
+use crate::{Context, Error, error_page, redirects, rewrites};
+
+/// File holding the redirect and rewrite rules of a site.
+const REDIRECTS_FILE: &str = "_redirects";
+/// File holding the custom headers of a site.
+const HEADERS_FILE: &str = "_headers";
+/// Files larger than this are ignored.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Minimum time between two checks for changes of the files of a site.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The rules of the sites served so far, keyed by their root directory.
+pub struct SiteFiles {
+    sites: RwLock<HashMap<PathBuf, Site>>,
+    reload_interval: Duration,
+}
+
+struct Site {
+    checked: Instant,
+    stamps: [FileStamp; 2],
+    rules: Arc<Rules>,
+}
+
+/// The compiled `_redirects` and `_headers` files of a site.
+#[derive(Default)]
+struct Rules {
+    redirects: Vec<Rule>,
+    headers: Arc<[Headers]>,
+}
+
+/// A `_redirects` rule.
+struct Rule {
+    action: Action,
+    /// Whether the rule applies even if a file exists at the request path.
+    force: bool,
+}
+
+enum Action {
+    Redirect(Redirects),
+    Rewrite(Rewrites),
+}
+
+impl Rule {
+    fn source(&self) -> &Regex {
+        match &self.action {
+            Action::Redirect(redirect) => &redirect.source,
+            Action::Rewrite(rewrite) => &rewrite.source,
+        }
+    }
+}
+
+/// Initializes the `_redirects` and `_headers` files support.
+pub fn init(enabled: bool, handler_opts: &mut RequestHandlerOpts) {
+    tracing::info!(enabled, "site files");
+    if !enabled {
+        return;
+    }
+
+    let site_files = SiteFiles::new(RELOAD_INTERVAL);
+    let vhosts = handler_opts
+        .advanced_opts
+        .as_ref()
+        .and_then(|advanced| advanced.virtual_hosts.as_deref())
+        .unwrap_or_default();
+    for root in std::iter::once(&handler_opts.root_dir).chain(vhosts.iter().map(|v| &v.root)) {
+        let rules = site_files.rules(root);
+        tracing::info!(
+            "site files of `{}`: redirects={} headers={}",
+            root.display(),
+            rules.redirects.len(),
+            rules.headers.len()
+        );
+    }
+    handler_opts.site_files = Some(Arc::new(site_files));
+}
+
+impl SiteFiles {
+    fn new(reload_interval: Duration) -> Self {
+        Self {
+            sites: RwLock::new(HashMap::new()),
+            reload_interval,
+        }
+    }
+
+    /// Returns the rules of the site at `root`, reloading them if its
+    /// files changed.
+    ///
+    /// The files are read and parsed without holding the lock, which is
+    /// only taken for writing to store the outcome.
+    fn rules(&self, root: &Path) -> Arc<Rules> {
+        let known = {
+            let sites = self.sites.read().unwrap_or_else(|err| err.into_inner());
+            match sites.get(root) {
+                Some(site) if site.checked.elapsed() < self.reload_interval => {
+                    return site.rules.clone();
+                }
+                Some(site) => Some((site.stamps, site.rules.clone())),
+                None => None,
+            }
+        };
+
+        let stamps = stamps(root);
+        let rules = match known {
+            Some((known_stamps, rules)) if known_stamps == stamps => rules,
+            Some(_) => {
+                tracing::info!("reloading site files of `{}`", root.display());
+                Arc::new(Rules::load(root))
+            }
+            None => Arc::new(Rules::load(root)),
+        };
+        let site = Site {
+            checked: Instant::now(),
+            stamps,
+            rules: rules.clone(),
+        };
+        self.sites
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(root.to_owned(), site);
+        rules
+    }
+}
+
+fn stamps(root: &Path) -> [FileStamp; 2] {
+    [REDIRECTS_FILE, HEADERS_FILE].map(|name| file_stamp(&root.join(name)))
+}
+
+impl Rules {
+    fn load(root: &Path) -> Self {
+        Self {
+            redirects: read(root, REDIRECTS_FILE)
+                .map(|content| parse_redirects(&content))
+                .unwrap_or_default(),
+            headers: read(root, HEADERS_FILE)
+                .map(|content| parse_headers(&content).into())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Reads a site file, logging why it can not be used if so.
+fn read(root: &Path, name: &str) -> Option<String> {
+    let path = root.join(name);
+    let meta = fs::metadata(&path).ok()?;
+    if meta.len() > MAX_FILE_SIZE {
+        tracing::warn!(
+            "ignoring `{}`: larger than {MAX_FILE_SIZE} bytes",
+            path.display()
+        );
+        return None;
+    }
+    match fs::read_to_string(&path) {
+        Ok(content) => Some(content),
+        Err(err) => {
+            tracing::warn!("unable to read `{}`: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// Applies the `_redirects` rules of the requested site if necessary.
+pub(crate) fn pre_process<T>(
+    opts: &RequestHandlerOpts,
+    req: &mut Request<T>,
+) -> Option<Result<Response<Body>, Error>> {
+    let site_files = opts.site_files.as_ref()?;
+
+    let root = opts.root_dir.as_path();
+    let uri_path = req.uri().path();
+    if is_site_file(root, uri_path) {
+        return Some(error_page::error_response(
+            req.uri(),
+            req.method(),
+            &StatusCode::NOT_FOUND,
+            &opts.page404,
+            &opts.page50x,
+        ));
+    }
+    // SECURITY (ReDoS bound): mirror redirects' cap on regex input size.
+    if uri_path.len() > redirects::MAX_URI_LEN_FOR_REGEX {
+        return None;
+    }
+
+    let rules = site_files.rules(root);
+    let uri_host = redirects::request_host(req);
+    let mut shadowed = None;
+    for rule in &rules.redirects {
+        if let Action::Redirect(Redirects {
+            host: Some(host), ..
+        }) = &rule.action
+            && *host != uri_host
+        {
+            continue;
+        }
+        if !rule.source().is_match(uri_path) {
+            continue;
+        }
+        if !rule.force && *shadowed.get_or_insert_with(|| is_shadowed(opts, root, uri_path)) {
+            continue;
+        }
+        return match &rule.action {
+            Action::Redirect(redirect) => redirects::redirect(opts, req, redirect, &[]),
+            Action::Rewrite(rewrite) => rewrites::rewrite(opts, req, rewrite, &[]),
+        };
+    }
+    None
+}
+
+/// Returns the `_headers` rules of the requested site.
+pub(crate) fn headers(opts: &RequestHandlerOpts) -> Option<Arc<[Headers]>> {
+    let site_files = opts.site_files.as_ref()?;
+    Some(site_files.rules(&opts.root_dir).headers.clone())
+}
+
+/// Whether the request path resolves to one of the site files. Names are
+/// compared regardless of their case, as case-insensitive file systems
+/// would serve them.
+fn is_site_file(root: &Path, uri_path: &str) -> bool {
+    sanitize_path(root, uri_path).is_ok_and(|path| {
+        path.parent() == Some(root) && path.file_name().is_some_and(is_site_file_name)
+    })
+}
+
+/// Whether a file name is the one of a site file, regardless of its case.
+pub(crate) fn is_site_file_name(name: &OsStr) -> bool {
+    name.to_str().is_some_and(|name| {
+        name.eq_ignore_ascii_case(REDIRECTS_FILE) || name.eq_ignore_ascii_case(HEADERS_FILE)
+    })
+}
+
+/// Whether a file, or a directory with an index file, exists at the
+/// request path.
+fn is_shadowed(opts: &RequestHandlerOpts, root: &Path, uri_path: &str) -> bool {
+    let Ok(path) = sanitize_path(root, uri_path) else {
+        return false;
+    };
+    match fs::metadata(&path) {
+        Ok(meta) if meta.is_file() => true,
+        Ok(meta) if meta.is_dir() => opts
+            .index_files
+            .iter()
+            .any(|name| path.join(name).is_file()),
+        _ => false,
+    }
+}
+
+/// Parses the content of a `_redirects` file, skipping invalid lines.
+fn parse_redirects(content: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_redirect(line) {
+            Ok(rule) => rules.push(rule),
+            Err(err) => tracing::warn!("{REDIRECTS_FILE}:{}: skipping rule: {err}", i + 1),
+        }
+    }
+    rules
+}
+
+fn parse_redirect(line: &str) -> Result<Rule, Error> {
+    let mut tokens = line.split_whitespace();
+    let from = tokens.next().unwrap_or_default();
+    let Some(to) = tokens.next() else {
+        bail!("missing destination");
+    };
+    let is_path_or_url =
+        to.starts_with('/') || to.starts_with("http://") || to.starts_with("https://");
+    if !is_path_or_url && to.contains('=') {
+        bail!("query parameter matching is not supported");
+    }
+    let (status, force) = match tokens.next() {
+        None => (StatusCode::MOVED_PERMANENTLY, false),
+        Some(token) => {
+            let (code, force) = match token.strip_suffix('!') {
+                Some(code) => (code, true),
+                None => (token, false),
+            };
+            let status = code
+                .parse::<u16>()
+                .ok()
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .with_context(|| format!("invalid status `{token}`"))?;
+            (status, force)
+        }
+    };
+    if tokens.next().is_some() {
+        bail!("conditions are not supported");
+    }
+    if !is_path_or_url {
+        bail!("destination `{to}` is neither a path nor an URL");
+    }
+
+    let (host, path) = split_url(from)?;
+    let (source, names) = compile_source(path)?;
+    let destination = compile_destination(to, &names);
+    let replacer = build_placeholder_replacer(&source);
+    let action = match status.as_u16() {
+        200 => {
+            if host.is_some() {
+                bail!("rewrites can not match a host");
+            }
+            Action::Rewrite(Rewrites {
+                source,
+                destination,
+                redirect: None,
+                match_query: false,
+                conditions: Default::default(),
+                replacer,
+            })
+        }
+        301 | 302 | 303 | 307 | 308 => Action::Redirect(Redirects {
+            host: host.map(str::to_owned),
+            source,
+            destination,
+            kind: status,
+            match_query: false,
+            conditions: Default::default(),
+            replacer,
+        }),
+        _ => bail!("status `{status}` is not supported"),
+    };
+    Ok(Rule { action, force })
+}
+
+/// Splits a rule source into its host, if it is an absolute URL, and path.
+fn split_url(source: &str) -> Result<(Option<&str>, &str), Error> {
+    let Some(rest) = source
+        .strip_prefix("https://")
+        .or_else(|| source.strip_prefix("http://"))
+    else {
+        if !source.starts_with('/') {
+            bail!("source `{source}` is neither a path nor an URL");
+        }
+        return Ok((None, source));
+    };
+    Ok(match rest.find('/') {
+        Some(i) => (Some(&rest[..i]), &rest[i..]),
+        None => (Some(rest), "/"),
+    })
+}
+
+/// Compiles a source path into a regex along with the names of its
+/// capture groups. `:name` segments match a single segment and a trailing
+/// `*` the rest of the path, named `splat`. A trailing slash is optional.
+fn compile_source(path: &str) -> Result<(Regex, Vec<&str>), Error> {
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').skip(1).collect();
+    let mut pattern = String::from("^");
+    let mut names = Vec::new();
+    let mut has_splat = false;
+    for (i, segment) in segments.iter().enumerate() {
+        let is_last = i + 1 == segments.len();
+        if *segment == "*" && is_last {
+            pattern.push_str("(?:/(.*))?");
+            names.push("splat");
+            has_splat = true;
+        } else if let Some(name) = segment.strip_prefix(':').filter(|n| !n.is_empty()) {
+            pattern.push_str("/([^/]+)");
+            names.push(name);
+        } else if let Some(prefix) = segment.strip_suffix('*').filter(|_| is_last) {
+            pattern.push('/');
+            pattern.push_str(&regex_lite::escape(prefix));
+            pattern.push_str("(.*)");
+            names.push("splat");
+            has_splat = true;
+        } else {
+            pattern.push('/');
+            pattern.push_str(&regex_lite::escape(segment));
+        }
+    }
+    if !has_splat {
+        pattern.push_str("/?");
+    }
+    pattern.push('$');
+    let regex = Regex::new(&pattern)
+        .with_context(|| format!("can not compile regex pattern for source: {path}"))?;
+    Ok((regex, names))
+}
+
+/// Replaces the `:name` placeholders of a destination by the `$N`
+/// placeholders of the matching capture groups.
+fn compile_destination(destination: &str, names: &[&str]) -> String {
+    let mut compiled = String::with_capacity(destination.len());
+    let mut rest = destination;
+    while let Some(pos) = rest.find(':') {
+        compiled.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let len = after
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(after.len());
+        let name = &after[..len];
+        match names.iter().position(|n| *n == name) {
+            Some(i) if !name.is_empty() => compiled.push_str(&format!("${}", i + 1)),
+            _ => {
+                compiled.push(':');
+                compiled.push_str(name);
+            }
+        }
+        rest = &after[len..];
+    }
+    compiled.push_str(rest);
+    compiled
+}
+
+/// Parses the content of a `_headers` file, skipping invalid entries.
+fn parse_headers(content: &str) -> Vec<Headers> {
+    let mut entries = Vec::new();
+    let mut current: Option<(GlobMatcher, HeaderMap)> = None;
+    let mut skipping = false;
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if !line.starts_with(char::is_whitespace) {
+            entries.extend(
+                current
+                    .take()
+                    .map(|(source, headers)| Headers { source, headers }),
+            );
+            match compile_headers_source(trimmed) {
+                Ok(source) => {
+                    current = Some((source, HeaderMap::new()));
+                    skipping = false;
+                }
+                Err(err) => {
+                    tracing::warn!("{HEADERS_FILE}:{}: skipping path: {err}", i + 1);
+                    skipping = true;
+                }
+            }
+            continue;
+        }
+
+        let Some((_, headers)) = current.as_mut() else {
+            if !skipping {
+                tracing::warn!("{HEADERS_FILE}:{}: header without a path", i + 1);
+            }
+            continue;
+        };
+        if let Err(err) = append_header(headers, trimmed) {
+            tracing::warn!("{HEADERS_FILE}:{}: skipping header: {err}", i + 1);
+        }
+    }
+    entries.extend(current.map(|(source, headers)| Headers { source, headers }));
+    entries.retain(|entry| !entry.headers.is_empty());
+    entries
+}
+
+/// Compiles a `_headers` path into a glob, `:name` segments matching any
+/// segment.
+fn compile_headers_source(path: &str) -> Result<GlobMatcher, Error> {
+    if !path.starts_with('/') {
+        bail!("`{path}` is not an absolute path");
+    }
+    let pattern = path
+        .split('/')
+        .map(|segment| match segment.starts_with(':') {
+            true => "*",
+            false => segment,
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    Ok(Glob::new(&pattern)
+        .with_context(|| format!("can not compile glob pattern for header source: {path}"))?
+        .compile_matcher())
+}
+
+/// Appends a `Name: value` line to a header map, joining the values of
+/// repeated names.
+fn append_header(headers: &mut HeaderMap, line: &str) -> Result<(), Error> {
+    let Some((name, value)) = line.split_once(':') else {
+        bail!("missing `:` separator");
+    };
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .with_context(|| format!("invalid header name `{}`", name.trim()))?;
+    let value = value.trim();
+    let value = match headers.get(&name).and_then(|prev| prev.to_str().ok()) {
+        Some(prev) => HeaderValue::from_str(&[prev, ", ", value].concat()),
+        None => HeaderValue::from_str(value),
+    }
+    .with_context(|| format!("invalid value for header `{name}`"))?;
+    headers.insert(name, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redirects::replace_placeholders;
+
+    fn dest(rule: &Rule, uri_path: &str) -> (StatusCode, String) {
+        let (status, source, destination, replacer) = match &rule.action {
+            Action::Redirect(r) => (r.kind, &r.source, &r.destination, &r.replacer),
+            Action::Rewrite(r) => (StatusCode::OK, &r.source, &r.destination, &r.replacer),
+        };
+        let dest = replace_placeholders(uri_path, source, destination, replacer).unwrap();
+        (status, dest)
+    }
+
+    #[test]
+    fn redirects_are_parsed() {
+        let rules = parse_redirects(
+            "# comment\n\
+             /home  /  \n\
+             /blog/:year/:slug   /posts/:slug?y=:year   302\n\
+             /docs/*   /guide/:splat\n\
+             /app/*    /app/index.html  200!\n\
+             https://old.example.com/*  https://example.com/:splat  308\n",
+        );
+        assert_eq!(rules.len(), 5);
+
+        assert_eq!(
+            dest(&rules[0], "/home/"),
+            (StatusCode::MOVED_PERMANENTLY, "/".into())
+        );
+        assert!(!rules[0].force);
+        assert_eq!(
+            dest(&rules[1], "/blog/2024/hello"),
+            (StatusCode::FOUND, "/posts/hello?y=2024".into())
+        );
+        assert!(!rules[1].source().is_match("/blog/2024/hello/extra"));
+        assert_eq!(
+            dest(&rules[2], "/docs/a/b.html"),
+            (StatusCode::MOVED_PERMANENTLY, "/guide/a/b.html".into())
+        );
+        assert_eq!(
+            dest(&rules[2], "/docs"),
+            (StatusCode::MOVED_PERMANENTLY, "/guide/".into())
+        );
+        assert_eq!(
+            dest(&rules[3], "/app/x"),
+            (StatusCode::OK, "/app/index.html".into())
+        );
+        assert!(rules[3].force);
+        let Action::Redirect(redirect) = &rules[4].action else {
+            panic!("expected a redirect");
+        };
+        assert_eq!(redirect.host.as_deref(), Some("old.example.com"));
+        assert_eq!(
+            dest(&rules[4], "/a"),
+            (
+                StatusCode::PERMANENT_REDIRECT,
+                "https://example.com/a".into()
+            )
+        );
+    }
+
+    #[test]
+    fn unsupported_redirects_are_skipped() {
+        let rules = parse_redirects(
+            "/only-source\n\
+             /store id=:id /blog/:id 301\n\
+             /a /b 404\n\
+             /a /b 302 Country=us\n\
+             /a b\n\
+             /a /b abc\n\
+             https://example.com/a /b 200\n",
+        );
+        assert!(rules.is_empty());
+    }
+
+    #[test]
+    fn placeholders_are_mapped_to_capture_groups() {
+        let names = ["year", "splat"];
+        assert_eq!(
+            compile_destination("/:year/:splat/:other:8080", &names),
+            "/$1/$2/:other:8080"
+        );
+        assert_eq!(
+            compile_destination("https://example.com/:year", &names),
+            "https://example.com/$1"
+        );
+    }
+
+    #[test]
+    fn headers_are_parsed() {
+        let headers = parse_headers(
+            "# comment\n\
+             /assets/*\n  Cache-Control: public\n  Cache-Control: max-age=60\n\
+             /users/:id\n\tX-Robots-Tag: noindex\n\
+             /empty\n\
+             relative\n  X-Skipped: 1\n\
+             /invalid\n  Not a header\n  X-Ok: 1\n",
+        );
+        assert_eq!(headers.len(), 3);
+        assert!(headers[0].source.is_match("/assets/css/main.css"));
+        assert_eq!(headers[0].headers["cache-control"], "public, max-age=60");
+        assert!(headers[1].source.is_match("/users/42"));
+        assert_eq!(headers[1].headers["x-robots-tag"], "noindex");
+        assert_eq!(headers[2].headers.len(), 1);
+    }
+
+    #[test]
+    fn site_files_are_matched_regardless_of_case() {
+        let root = Path::new("/var/www");
+        for uri_path in ["/_redirects", "/_REDIRECTS", "/_Headers", "/%5Fheaders"] {
+            assert!(is_site_file(root, uri_path), "{uri_path}");
+        }
+        for uri_path in ["/docs/_redirects", "/_redirects.txt", "/redirects"] {
+            assert!(!is_site_file(root, uri_path), "{uri_path}");
+        }
+    }
+
+    #[test]
+    fn rules_are_reloaded_when_files_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let site_files = SiteFiles::new(Duration::ZERO);
+        assert!(site_files.rules(dir.path()).redirects.is_empty());
+
+        fs::write(dir.path().join(REDIRECTS_FILE), "/a /b\n").unwrap();
+        assert_eq!(site_files.rules(dir.path()).redirects.len(), 1);
+
+        fs::write(dir.path().join(REDIRECTS_FILE), "/a /b\n/c /d\n").unwrap();
+        assert_eq!(site_files.rules(dir.path()).redirects.len(), 2);
+
+        fs::remove_file(dir.path().join(REDIRECTS_FILE)).unwrap();
+        assert!(site_files.rules(dir.path()).redirects.is_empty());
+    }
+}
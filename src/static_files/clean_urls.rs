@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// This file is part of Static Web Server.
+// See https://static-web-server.net/ for more information
+// Copyright (C) 2019-present Jose Quintana <joseluisq.net>
+
+//! Clean URLs for the HTML files served by the static-files handler.
+//!
+//! HTML files are reachable through their extensionless path (`/about` for
+//! `about.html`) and directory index pages through the directory path
+//! (`/about/` for `about/index.html`). When enabled, the explicit forms are
+//! redirected permanently to those canonical paths, which responses also
+//! announce with a `Link: <path>; rel="canonical"` header. Directory paths
+//! keep their trailing slash only if `redirect_trailing_slash` is enabled.
+//! Paths rewritten internally (e.g. a single-page app fallback) are served
+//! as is, since the rewritten path is not the one the client requested.
+
+use headers::HeaderValue;
+use hyper::{Response, StatusCode, header::LINK};
+use std::path::Path;
+
+use crate::body::Body;
+use crate::exts::http::MethodExt;
+
+use super::opts::{DEFAULT_INDEX_FILES, HandleOpts};
+
+/// Returns the canonical request path of a resolved HTML file, or `None`
+/// when clean URLs are disabled or the file is not an HTML one.
+pub(super) fn canonical_path(
+    opts: &HandleOpts<'_>,
+    file_path: &Path,
+    is_dir: bool,
+) -> Option<String> {
+    if !opts.clean_urls || file_path.extension().is_none_or(|ext| ext != "html") {
+        return None;
+    }
+    if is_dir {
+        return Some(dir_path(opts.uri_path, opts.redirect_trailing_slash));
+    }
+
+    // A file is never reached through a trailing slash
+    let uri_path = opts.uri_path.trim_end_matches('/');
+    let file_name = file_path.file_name()?.to_str()?;
+    if let Some(dir) = uri_path.strip_suffix(file_name)
+        && dir.ends_with('/')
+        && is_dir_index(opts, file_path, file_name)
+    {
+        return Some(dir_path(dir, opts.redirect_trailing_slash));
+    }
+    // The `.html` suffix is only dropped if the extensionless path does not
+    // resolve to another file or directory
+    if let Some(stem) = uri_path.strip_suffix(".html")
+        && !stem.ends_with('/')
+        && !file_path.with_extension("").exists()
+    {
+        return Some(stem.to_owned());
+    }
+    match uri_path {
+        "" => Some("/".to_owned()),
+        path => Some(path.to_owned()),
+    }
+}
+
+/// Produces a `301 Moved Permanently` to the canonical path when the
+/// request used another one. Returns `Ok(None)` otherwise.
+pub(super) fn redirect(
+    opts: &HandleOpts<'_>,
+    canonical: Option<&str>,
+) -> Result<Option<Response<Body>>, StatusCode> {
+    let Some(canonical) = canonical else {
+        return Ok(None);
+    };
+    if opts.method.is_options() || canonical == opts.uri_path {
+        return Ok(None);
+    }
+    // A missing or extra trailing slash is left alone unless redirected
+    if !opts.redirect_trailing_slash && canonical == opts.uri_path.trim_end_matches('/') {
+        return Ok(None);
+    }
+
+    let query = opts.uri_query.map_or(String::new(), |s| ["?", s].concat());
+    let uri = [canonical, query.as_str()].concat();
+    let loc = HeaderValue::from_str(uri.as_str()).map_err(|err| {
+        tracing::error!("invalid header value from canonical uri: {:?}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut resp = Response::new(crate::body::empty());
+    resp.headers_mut().insert(hyper::header::LOCATION, loc);
+    *resp.status_mut() = StatusCode::MOVED_PERMANENTLY;
+    tracing::trace!("uri is not the canonical one so redirecting permanently");
+
+    Ok(Some(resp))
+}
+
+/// Appends the `Link: <path>; rel="canonical"` header to a file response.
+pub(super) fn append_link(resp: &mut Response<Body>, canonical: Option<&str>) {
+    let Some(canonical) = canonical else {
+        return;
+    };
+    if !(resp.status().is_success() || resp.status() == StatusCode::NOT_MODIFIED) {
+        return;
+    }
+    if let Ok(link) = HeaderValue::from_str(&format!("<{canonical}>; rel=\"canonical\"")) {
+        resp.headers_mut().append(LINK, link);
+    }
+}
+
+/// Returns the path of a directory, with a trailing slash if required.
+fn dir_path(path: &str, trailing_slash: bool) -> String {
+    let path = path.trim_end_matches('/');
+    if trailing_slash || path.is_empty() {
+        [path, "/"].concat()
+    } else {
+        path.to_owned()
+    }
+}
+
+/// Checks whether a file is the index page served for its directory.
+fn is_dir_index(opts: &HandleOpts<'_>, file_path: &Path, file_name: &str) -> bool {
+    let Some(dir) = file_path.parent() else {
+        return false;
+    };
+    let index_files = match opts.index_files {
+        [] => DEFAULT_INDEX_FILES.as_slice(),
+        index_files => index_files,
+    };
+    index_files
+        .iter()
+        .find(|index| dir.join(index).is_file())
+        .is_some_and(|index| *index == file_name)
+}
@@ -55,6 +55,7 @@ pub(super) fn try_listing(
         accept: opts.headers.typed_get::<Accept>().as_ref(),
         include_hidden: opts.include_hidden,
         follow_symlinks: opts.follow_symlinks,
+        site_files: opts.site_files,
         #[cfg(feature = "directory-listing-download")]
         dir_listing_download: opts.dir_listing_download,
     })?;
@@ -96,6 +97,7 @@ fn try_archive_download(
             method: opts.method,
             follow_symlinks: opts.follow_symlinks,
             include_hidden: opts.include_hidden,
+            site_files: opts.site_files && dir_path == *opts.base_path,
         },
     );
     Ok(Some(resp))
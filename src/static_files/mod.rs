@@ -9,20 +9,22 @@
 //! [`handle`]:
 //!
 //! 1. **Method check** — `GET`, `HEAD` and `OPTIONS` only.
-//! 2. **Path sanitization** — strip traversal components from the URI path.
+//! 2. **Path sanitization** — pick the root of the longest matching mount
+//!    prefix and strip traversal components from the rest of the URI path.
 //! 3. **In-memory cache lookup** — short-circuit hot files.
-//! 4. **File resolution** — directory → index, `.html` fallback,
-//!    pre-compressed variant detection (see [`resolve`]).
+//! 4. **File resolution** — directory → index, `.html` fallback, image
+//!    and pre-compressed variant detection (see [`resolve`]).
 //! 5. **Security checks** — containment, symlink and hidden-file policy
 //!    (see [`security`]).
-//! 6. **Short-circuit responses** — trailing-slash redirect, `OPTIONS`,
-//!    directory listing or archive download.
+//! 6. **Short-circuit responses** — trailing-slash and clean URL redirects,
+//!    `OPTIONS`, directory listing or archive download.
 //! 7. **File reply** — stream the resolved file or its pre-compressed
 //!    variant (see [`reply`]).
 
 // Part of the module is borrowed and adapted at a convenience from
 // https://github.com/seanmonstar/warp/blob/master/src/filters/fs.rs
 
+mod clean_urls;
 mod opts;
 mod reply;
 mod resolve;
@@ -54,7 +56,32 @@ pub async fn handle(opts: &HandleOpts<'_>) -> Result<StaticFileResponse, StatusC
         return Err(StatusCode::METHOD_NOT_ALLOWED);
     }
 
-    let mut file_path = sanitize_path(opts.base_path, opts.uri_path)?;
+    // Resolve against the root and policies of the matching mount if any
+    let index_files: Vec<&str>;
+    let mounted: HandleOpts<'_>;
+    let (opts, tail) = match opts.mounts.and_then(|mounts| mounts.find(opts.uri_path)) {
+        Some((mount, tail)) => {
+            index_files = mount.index_files.iter().map(|s| s.as_str()).collect();
+            mounted = HandleOpts {
+                base_path: &mount.root,
+                index_files: &index_files,
+                #[cfg(feature = "directory-listing")]
+                dir_listing: mount.dir_listing,
+                #[cfg(feature = "directory-listing")]
+                dir_listing_order: mount.dir_listing_order,
+                #[cfg(feature = "directory-listing")]
+                dir_listing_format: &mount.dir_listing_format,
+                include_hidden: mount.include_hidden,
+                follow_symlinks: mount.follow_symlinks,
+                site_files: false,
+                ..*opts
+            };
+            (&mounted, tail)
+        }
+        None => (opts, opts.uri_path),
+    };
+
+    let mut file_path = sanitize_path(opts.base_path, tail)?;
 
     // In-memory file cache lookup. A hit short-circuits the pipeline.
     // On miss, the file is read from disk and the streaming pipeline
@@ -74,6 +101,7 @@ pub async fn handle(opts: &HandleOpts<'_>) -> Result<StaticFileResponse, StatusC
         &mut file_path,
         opts.headers,
         opts.compression_static,
+        opts.image_negotiation,
         opts.index_files,
     )?;
 
@@ -85,6 +113,11 @@ pub async fn handle(opts: &HandleOpts<'_>) -> Result<StaticFileResponse, StatusC
         return Ok(StaticFileResponse::new(resp, resp_file_path));
     }
 
+    let canonical = clean_urls::canonical_path(opts, file_path, is_dir);
+    if let Some(resp) = clean_urls::redirect(opts, canonical.as_deref())? {
+        return Ok(StaticFileResponse::new(resp, resp_file_path));
+    }
+
     if opts.method.is_options() {
         return Ok(StaticFileResponse::new(
             reply::options_reply(),
@@ -97,8 +130,10 @@ pub async fn handle(opts: &HandleOpts<'_>) -> Result<StaticFileResponse, StatusC
         return Ok(StaticFileResponse::new(resp, resp_file_path));
     }
 
-    let resp =
-        reply::file_or_precompressed(opts, file_path, &metadata, precompressed_variant, file)?;
+    let mut resp =
+        reply::file_or_precompressed(opts, file_path, &metadata, precompressed_variant, file)
+            .await?;
+    clean_urls::append_link(&mut resp, canonical.as_deref());
     Ok(StaticFileResponse::new(resp, resp_file_path))
 }
 
@@ -118,17 +153,45 @@ fn try_memory_cache(
 ) -> Option<StaticFileResponse> {
     // Runtime gate: if `[advanced.memory-cache]` is not configured in TOML,
     // `opts.memory_cache` is `None` and we skip the lookup entirely.
-    opts.memory_cache.as_ref()?;
+    let mem_opts = opts.memory_cache?;
+
+    // Explicit `.html` paths may need a clean URL redirect
+    if opts.clean_urls && opts.uri_path.ends_with(".html") {
+        return None;
+    }
 
     // NOTE: only the default auto-index is supported for directory
     // requests inside the memory-cache context.
-    if opts.redirect_trailing_slash && opts.uri_path.ends_with('/') {
+    let is_dir = opts.redirect_trailing_slash && opts.uri_path.ends_with('/');
+    if is_dir {
         file_path.push("index.html");
     }
 
-    let result = cache::lookup(file_path.as_path(), opts.headers)?;
+    // Images are cached per variant, so look up the negotiated one
+    if opts.image_negotiation
+        && crate::image_negotiation::is_image(file_path)
+        && let Ok((metadata, false)) = crate::fs::meta::try_metadata(file_path)
+        && let Some(variant) =
+            crate::image_negotiation::image_variant(file_path, &metadata, opts.headers)
+    {
+        *file_path = variant.file_path;
+    }
+
+    // The entry may have been stored for another root or policy
+    let result = cache::lookup(
+        file_path.as_path(),
+        opts.uri_path,
+        opts.method,
+        opts.headers,
+        mem_opts,
+        |followed_symlinks| security::enforce_cached(file_path, is_dir, followed_symlinks, opts),
+    )?;
     match result {
-        Ok(resp) => Some(StaticFileResponse::new(resp, file_path.clone())),
+        Ok(mut resp) => {
+            let canonical = clean_urls::canonical_path(opts, file_path, is_dir);
+            clean_urls::append_link(&mut resp, canonical.as_deref());
+            Some(StaticFileResponse::new(resp, file_path.clone()))
+        }
         // Hit, but the cached entry returned an error status (e.g. malformed Range).
         // Fall through to the regular pipeline so the error path is consistent.
         Err(_) => None,
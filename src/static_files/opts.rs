@@ -10,6 +10,7 @@ use hyper::{Method, Response};
 use std::path::PathBuf;
 
 use crate::body::Body;
+use crate::mounts::Mounts;
 
 #[cfg(feature = "mem-cache")]
 use crate::mem_cache::cache::MemCacheOpts;
@@ -58,10 +59,21 @@ pub struct HandleOpts<'a> {
     pub dir_listing_download: &'a [DirDownloadFmt],
     /// Redirect trailing slash feature.
     pub redirect_trailing_slash: bool,
+    /// Clean URLs feature.
+    pub clean_urls: bool,
     /// Compression static feature.
     pub compression_static: bool,
+    /// AVIF and WebP image variants feature.
+    pub image_negotiation: bool,
+    /// Directories served under URL path prefixes.
+    pub mounts: Option<&'a Mounts>,
+    /// Netlify-style site files feature, hiding them from the listings of
+    /// the root directory.
+    pub site_files: bool,
     /// Weak ETag header feature.
     pub etag: bool,
+    /// Strong content-hash ETag header feature.
+    pub etag_strong: bool,
     /// Ignore hidden files feature.
     pub include_hidden: bool,
     /// Prevent following symlinks for files and directories.
@@ -19,9 +19,10 @@ use std::path::{Path, PathBuf};
 
 use crate::body::Body;
 use crate::conditional_headers::ConditionalHeaders;
+use crate::digest::{self, Representation};
 use crate::exts::headers::ContentCoding;
 use crate::exts::http::HTTP_SUPPORTED_METHODS;
-use crate::response::response_body;
+use crate::response::{MAX_RANGES, response_body};
 
 use super::opts::HandleOpts;
 
@@ -68,7 +69,7 @@ pub(super) fn options_reply() -> Response<Body> {
 /// that was opened by the resolver to avoid a redundant `open(2)` syscall
 /// on the hot path. It is ignored when a precompressed variant is being
 /// served (the precomp file is opened on demand).
-pub(super) fn file_or_precompressed(
+pub(super) async fn file_or_precompressed(
     opts: &HandleOpts<'_>,
     file_path: &Path,
     metadata: &Metadata,
@@ -79,22 +80,26 @@ pub(super) fn file_or_precompressed(
         // Pre-opened handle (if any) refers to the original file we are
         // about to replace with the precompressed variant; just drop it.
         drop(pre_opened);
-        return precompressed_reply(opts, file_path, metadata, precomp_path, precomp_encoding);
+        return precompressed_reply(opts, file_path, metadata, precomp_path, precomp_encoding)
+            .await;
     }
 
-    file_reply(opts, file_path, metadata, None, pre_opened)
+    file_reply(opts, file_path, metadata, None, pre_opened).await
 }
 
 /// Serves a pre-compressed variant and adjusts headers (`Content-Length`
 /// removed, `Content-Encoding` set) accordingly.
-fn precompressed_reply(
+async fn precompressed_reply(
     opts: &HandleOpts<'_>,
     file_path: &Path,
     metadata: &Metadata,
     precomp_path: PathBuf,
     precomp_encoding: ContentCoding,
 ) -> Result<Response<Body>, StatusCode> {
-    let mut resp = file_reply(opts, file_path, metadata, Some(precomp_path), None)?;
+    let mut resp = file_reply(opts, file_path, metadata, Some(precomp_path.clone()), None).await?;
+    digest::attach(&mut resp, opts.headers, || {
+        Representation::File(precomp_path)
+    });
 
     resp.headers_mut().remove(CONTENT_LENGTH);
     let encoding = HeaderValue::from_str(precomp_encoding.as_str()).map_err(|err| {
@@ -114,7 +119,7 @@ fn precompressed_reply(
 ///
 /// When `pre_opened` is `Some(file)` and no precompressed variant is being
 /// served, the existing handle is reused instead of re-opening the file.
-fn file_reply(
+async fn file_reply(
     opts: &HandleOpts<'_>,
     path: &Path,
     meta: &Metadata,
@@ -135,16 +140,59 @@ fn file_reply(
 
     match file_result {
         Ok(file) => {
+            let (file, etag) = if opts.etag {
+                // Precompressed variants are hashed as the distinct files they are
+                let precomp_meta;
+                let hash_meta = match path_precompressed {
+                    Some(_) if opts.etag_strong => {
+                        precomp_meta = file
+                            .metadata()
+                            .map_err(|err| open_error_to_status(err, open_path))?;
+                        &precomp_meta
+                    }
+                    _ => meta,
+                };
+                crate::etag::build_for_file(opts.etag_strong, file, open_path, hash_meta)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!("unable to build the etag of `{}`: {err}", path.display());
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    })?
+            } else {
+                (file, None)
+            };
+
+            // Requests for several ranges of an encoded representation get
+            // the full content: `Content-Encoding` would otherwise apply to
+            // the `multipart/byteranges` body rather than to its parts.
+            let max_ranges = if path_precompressed.is_some() {
+                1
+            } else {
+                MAX_RANGES
+            };
+
+            // Precompressed bodies must never be cached under the path of
+            // the original file.
             #[cfg(feature = "mem-cache")]
             {
-                let _ = open_path;
-                response_body(file, path, meta, conditionals, opts.etag, opts.memory_cache)
+                let memory_cache = opts
+                    .memory_cache
+                    .filter(|_| path_precompressed.is_none())
+                    .map(|mem_opts| (mem_opts, opts.uri_path, opts.follow_symlinks));
+                response_body(
+                    file,
+                    path,
+                    meta,
+                    conditionals,
+                    etag,
+                    max_ranges,
+                    memory_cache,
+                )
             }
 
             #[cfg(not(feature = "mem-cache"))]
             {
-                let _ = open_path;
-                response_body(file, path, meta, conditionals, opts.etag)
+                response_body(file, path, meta, conditionals, etag, max_ranges)
             }
         }
         Err(err) => Err(open_error_to_status(err, path)),
@@ -7,7 +7,7 @@
 //!
 //! This module owns the logic that, given a sanitized request path, picks
 //! the actual file to serve: directory → index-file resolution,
-//! `.html`-suffix fallback, and pre-compressed variant lookup.
+//! `.html`-suffix fallback, and image and pre-compressed variant lookup.
 
 use headers::{HeaderMap, HeaderValue};
 use hyper::StatusCode;
@@ -15,8 +15,8 @@ use std::fs::Metadata;
 use std::path::PathBuf;
 
 use crate::Result;
-use crate::compression_static;
 use crate::fs::meta::{FileMetadata, try_file_open, try_metadata, try_metadata_with_html_suffix};
+use crate::{compression_static, image_negotiation};
 
 use super::opts::DEFAULT_INDEX_FILES;
 
@@ -28,6 +28,7 @@ pub(super) fn file_metadata<'a>(
     mut file_path: &'a mut PathBuf,
     headers: &'a HeaderMap<HeaderValue>,
     compression_static: bool,
+    image_negotiation: bool,
     mut index_files: &'a [&'a str],
 ) -> Result<FileMetadata<'a>, StatusCode> {
     tracing::trace!("getting metadata for file {}", file_path.display());
@@ -87,6 +88,18 @@ pub(super) fn file_metadata<'a>(
                 }
             }
 
+            // Serve the smallest AVIF or WebP variant of an image in place of
+            // the original one, so the validators, ranges and cache entries
+            // all relate to the variant file.
+            if let Some(variant) = (image_negotiation && resolved_exists)
+                .then(|| image_negotiation::image_variant(file_path, &metadata, headers))
+                .flatten()
+            {
+                *file_path = variant.file_path;
+                metadata = variant.metadata;
+                opened_file = None;
+            }
+
             // Only probe for pre-compressed siblings when the resolved file
             // actually exists. Probing for `.br`/`.gz`/`.zst` of a path that
             // was never confirmed on disk wastes one `stat(2)` per
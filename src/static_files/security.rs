@@ -150,6 +150,27 @@ pub(super) fn enforce(
     Ok(())
 }
 
+/// Verifies that a file found in the in-memory cache is safe to serve under
+/// the current `opts`.
+///
+/// Entries stored without following symbolic links have no symlink in their
+/// path below the canonical root, so only the cheap hidden file check is
+/// needed. Others go through every check of [`enforce`].
+#[cfg(feature = "mem-cache")]
+pub(super) fn enforce_cached(
+    file_path: &Path,
+    is_dir: bool,
+    followed_symlinks: bool,
+    opts: &HandleOpts<'_>,
+) -> bool {
+    if followed_symlinks {
+        return enforce(file_path, is_dir, opts).is_ok();
+    }
+    file_path
+        .strip_prefix(opts.base_path)
+        .is_ok_and(|relative| opts.include_hidden || !relative.is_hidden())
+}
+
 /// Canonicalizes the requested file path and ensures it lives inside
 /// the base directory.
 ///
@@ -9,11 +9,15 @@
 /// SWS fixtures module.
 #[doc(hidden)]
 pub mod fixtures {
-    use std::{path::PathBuf, sync::Arc};
+    use bytes::Bytes;
+    use http_body_util::BodyExt;
+    use hyper::{HeaderMap, Request, StatusCode};
+    use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 
     use crate::{
         Settings,
         handler::{RequestHandler, RequestHandlerOpts},
+        proxy::RequestBody,
         settings::Advanced,
         settings::cli::General,
     };
@@ -54,6 +58,7 @@ pub mod fixtures {
         ))]
         let compression = general.compression;
         let compression_static = general.compression_static;
+        let image_negotiation = general.image_negotiation;
 
         // Mirror the production startup logic (see `server::opts::init`): when
         // `use_relative_root` is enabled, keep the root as-is so the
@@ -69,6 +74,7 @@ pub mod fixtures {
             root_dir,
             compression,
             compression_static,
+            image_negotiation,
             #[cfg(any(
                 feature = "compression",
                 feature = "compression-gzip",
@@ -77,6 +83,28 @@ pub mod fixtures {
                 feature = "compression-deflate"
             ))]
             compression_level: general.compression_level,
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-gzip",
+                feature = "compression-brotli",
+                feature = "compression-zstd",
+                feature = "compression-deflate"
+            ))]
+            compression_cache: None,
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-gzip",
+                feature = "compression-brotli",
+                feature = "compression-zstd",
+                feature = "compression-deflate"
+            ))]
+            compression_policy: None,
+            #[cfg(any(
+                feature = "compression",
+                feature = "compression-brotli",
+                feature = "compression-zstd"
+            ))]
+            compression_dictionaries: None,
             #[cfg(feature = "directory-listing")]
             dir_listing: general.directory_listing,
             #[cfg(feature = "directory-listing")]
@@ -90,6 +118,13 @@ pub mod fixtures {
             security_headers: general.security_headers,
             cache_control_headers: general.cache_control_headers,
             etag: general.etag,
+            etag_strong: general.etag_strong,
+            repr_digest: general.repr_digest,
+            site_files: None,
+            redirect_maps: None,
+            proxy: None,
+            language_negotiation: None,
+            mounts: None,
             page404: general.page404,
             page50x: general.page50x,
             // TODO: add support or `page_fallback` when required
@@ -102,6 +137,7 @@ pub mod fixtures {
             log_forwarded_for: general.log_forwarded_for,
             trusted_proxies: general.trusted_proxies,
             redirect_trailing_slash: general.redirect_trailing_slash,
+            clean_urls: general.clean_urls,
             include_hidden: general.include_hidden,
             follow_symlinks: general.follow_symlinks,
             use_relative_root: general.use_relative_root,
@@ -121,6 +157,7 @@ pub mod fixtures {
             #[cfg(feature = "mem-cache")]
             memory_cache: None,
             advanced_opts: advanced,
+            virtual_hosts: None,
         }
     }
 
@@ -130,4 +167,60 @@ pub mod fixtures {
             opts: Arc::from(req_handler_opts),
         }
     }
+
+    /// A response of the request handler along with its collected body.
+    pub struct FixtureResponse {
+        /// Response status.
+        pub status: StatusCode,
+        /// Response headers.
+        pub headers: HeaderMap,
+        /// Response body.
+        pub body: Bytes,
+    }
+
+    impl FixtureResponse {
+        /// Returns the value of a response header if present.
+        pub fn header(&self, name: &str) -> Option<String> {
+            self.headers
+                .get(name)
+                .map(|value| value.to_str().unwrap().to_owned())
+        }
+
+        /// Returns the response body as text.
+        pub fn text(&self) -> String {
+            String::from_utf8_lossy(&self.body).into_owned()
+        }
+    }
+
+    /// Handle a request and collect its response (fixture).
+    pub async fn fixture_response<B>(
+        handler: &RequestHandler,
+        mut req: Request<B>,
+        remote_addr: Option<SocketAddr>,
+    ) -> FixtureResponse
+    where
+        B: RequestBody + Send,
+    {
+        let resp = handler.handle(&mut req, remote_addr).await.unwrap();
+        let (parts, body) = resp.into_parts();
+        FixtureResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: body.collect().await.unwrap().to_bytes(),
+        }
+    }
+
+    /// Handle a `GET` request with the given headers and collect its
+    /// response (fixture).
+    pub async fn fixture_get(
+        handler: &RequestHandler,
+        uri: &str,
+        headers: &[(&str, &str)],
+    ) -> FixtureResponse {
+        let mut req = Request::builder().uri(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        fixture_response(handler, req.body(()).unwrap(), None).await
+    }
 }
@@ -5,149 +5,699 @@
 
 //! Module that allows to determine a virtual hostname.
 //!
+//! Every `[[advanced.virtual-hosts]]` entry gets its own request handler
+//! options, cloned from the general ones and then overridden by the entry
+//! settings. The `host` is either an exact hostname or a `*.example.com`
+//! wildcard matching any subdomain of `example.com` but not the domain itself.
+//!
+//! ```toml
+//! [advanced]
+//! unknown-host-status = 421
+//!
+//! [[advanced.virtual-hosts]]
+//! host = "*.preview.example.com"
+//! root = "/var/www/preview"
+//! index-files = "index.html, index.htm"
+//! basic-auth = "preview:$2y$05$..."
+//! ```
+//!
+//! Exact hosts take precedence over wildcards, and longer wildcards over
+//! shorter ones. Requests matching no virtual host are served by the
+//! `default = true` one if any, otherwise answered with the
+//! `unknown-host-status` code if set, otherwise served by the general settings.
+//!
+//! Mass virtual hosting serves every hostname from its own directory below a
+//! common root, e.g. `/srv/sites/pr-1.preview.example.com/`, with the general
+//! settings. It applies to the hosts not matching a configured virtual host.
+//!
+//! ```toml
+//! [advanced.mass-virtual-hosts]
+//! root = "/srv/sites"
+//! fallback = "/srv/sites/_default"
+//! cache-ttl = 10
+//! ```
 
-use hyper::Request;
 use hyper::header::HOST;
-use std::path::PathBuf;
+use hyper::{Request, StatusCode};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use crate::fs::meta::try_metadata;
+use crate::fs::path::sanitize_path;
+use crate::handler::RequestHandlerOpts;
 use crate::settings::VirtualHosts;
+use crate::settings::file::MassVirtualHosts;
+use crate::{Context, Result, bail, error_page, helpers};
 
-/// It returns different root directory if the "Host" header matches a virtual hostname.
-pub(crate) fn get_real_root<'a, T>(
-    req: &mut Request<T>,
-    vhosts_opts: Option<&'a [VirtualHosts]>,
-) -> Option<&'a PathBuf> {
-    let vhosts = vhosts_opts?;
-    if vhosts.is_empty() {
-        return None;
-    }
+#[cfg(feature = "basic-auth")]
+use crate::basic_auth;
 
-    let request_host_str = if let Some(authority) = req.uri().authority() {
-        // HTTP2
-        authority.host()
-    } else {
-        // HTTP1 - fall back to host header
-        let host_header = req.headers().get(HOST)?.to_str().ok()?;
-
-        // host header can include the port -> remove it
-        host_header
-            .rsplit_once(":")
-            .and_then(|(potential_host, potential_port)| {
-                potential_port
-                    .parse::<u16>()
-                    .is_ok()
-                    .then_some(potential_host)
-            })
-            .unwrap_or(host_header)
+#[cfg(feature = "directory-listing")]
+use crate::directory_listing;
+
+#[cfg(feature = "fallback-page")]
+use crate::fallback_page;
+
+/// Default number of seconds a host directory lookup is cached.
+const DEFAULT_CACHE_TTL: u64 = 10;
+
+/// Maximum number of cached host directory lookups.
+const MAX_CACHED_HOSTS: usize = 4096;
+
+/// Maximum length of a hostname.
+const MAX_HOST_LEN: usize = 253;
+
+/// Maximum length of a hostname label.
+const MAX_LABEL_LEN: usize = 63;
+
+/// The virtual hosts along with their request handler options.
+#[derive(Default)]
+pub struct VirtualHostsOpts {
+    /// Exact hostnames.
+    exact: HashMap<String, Arc<RequestHandlerOpts>>,
+    /// Wildcard hostnames as `.example.com` suffixes, the longest first.
+    wildcards: Vec<(String, Arc<RequestHandlerOpts>)>,
+    /// Virtual host serving the requests not matching any other one.
+    default: Option<Arc<RequestHandlerOpts>>,
+    /// Status code of the requests not matching any virtual host.
+    unknown_host_status: Option<StatusCode>,
+    /// Hosts served from a directory per hostname.
+    mass: Option<MassHosts>,
+}
+
+/// Mass virtual hosting from a `<root>/<host>/` directory layout.
+struct MassHosts {
+    /// Directory containing a root directory per hostname.
+    root: PathBuf,
+    /// General options the options of each host are cloned from.
+    template: RequestHandlerOpts,
+    /// Site serving the hosts without a directory.
+    fallback: Option<Arc<RequestHandlerOpts>>,
+    /// Time a host directory lookup is cached.
+    cache_ttl: Duration,
+    /// Host directory lookups keyed by hostname.
+    hosts: Mutex<HashMap<String, CachedHost>>,
+}
+
+struct CachedHost {
+    checked: Instant,
+    opts: Option<Arc<RequestHandlerOpts>>,
+}
+
+/// Initializes the virtual hosts.
+///
+/// It must run after every other feature so the per-host options
+/// inherit the fully initialized general ones.
+pub fn init(handler_opts: &mut RequestHandlerOpts) -> Result {
+    let Some(advanced) = handler_opts.advanced_opts.as_ref() else {
+        return Ok(());
     };
+    let vhosts = advanced.virtual_hosts.as_deref().unwrap_or_default();
+    let unknown_host_status = advanced.unknown_host_status;
+    let mass = advanced.mass_virtual_hosts.as_ref();
+    if vhosts.is_empty() && unknown_host_status.is_none() && mass.is_none() {
+        return Ok(());
+    }
 
+    let mut vhosts_opts = VirtualHostsOpts {
+        unknown_host_status,
+        mass: mass
+            .map(|config| MassHosts::new(handler_opts, config))
+            .transpose()?,
+        ..Default::default()
+    };
     for vhost in vhosts {
-        if vhost.host == request_host_str {
+        let opts = Arc::new(vhost_opts(handler_opts, vhost)?);
+        if vhost.default {
+            vhosts_opts.default = Some(opts.clone());
+        }
+
+        let host = vhost.host.trim_end_matches('.');
+        if let Some(domain) = host.strip_prefix('*') {
+            if !domain.starts_with('.') || domain.len() < 2 || domain.contains('*') {
+                bail!("invalid virtual host wildcard `{}`", vhost.host);
+            }
+            vhosts_opts.wildcards.push((domain.to_owned(), opts));
+        } else if host.is_empty() || host.contains('*') {
+            bail!("invalid virtual host `{}`", vhost.host);
+        } else if vhosts_opts.exact.contains_key(host) {
+            tracing::warn!("duplicate virtual host `{host}` ignored");
+        } else {
+            vhosts_opts.exact.insert(host.to_owned(), opts);
+        }
+    }
+    // A stable sort keeps the configuration order of equally long wildcards
+    vhosts_opts
+        .wildcards
+        .sort_by_key(|(domain, _)| std::cmp::Reverse(domain.len()));
+
+    tracing::info!(
+        "virtual hosts: enabled=true, exact={}, wildcards={}, default={}, unknown_host_status={:?}, mass_root={:?}",
+        vhosts_opts.exact.len(),
+        vhosts_opts.wildcards.len(),
+        vhosts_opts.default.is_some(),
+        unknown_host_status.map(|status| status.as_u16()),
+        vhosts_opts.mass.as_ref().map(|mass| &mass.root)
+    );
+    handler_opts.virtual_hosts = Some(Arc::new(vhosts_opts));
+
+    Ok(())
+}
+
+/// Builds the request handler options of a virtual host.
+fn vhost_opts(base: &RequestHandlerOpts, vhost: &VirtualHosts) -> Result<RequestHandlerOpts> {
+    let mut opts = base.clone();
+    opts.root_dir = vhost.root.clone();
+
+    if let Some(index_files) = &vhost.index_files {
+        opts.index_files = index_files
+            .split(',')
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if opts.index_files.is_empty() {
+            bail!("index files list of virtual host `{}` is empty", vhost.host);
+        }
+    }
+
+    // Error pages are relative to the virtual host root
+    if let Some(page404) = &vhost.page404 {
+        opts.page404 = vhost.root.join(page404);
+        error_page::cache_page(&opts.page404);
+    }
+    if let Some(page50x) = &vhost.page50x {
+        opts.page50x = vhost.root.join(page50x);
+        error_page::cache_page(&opts.page50x);
+    }
+
+    #[cfg(feature = "fallback-page")]
+    if let Some(page_fallback) = &vhost.page_fallback {
+        opts.page_fallback = Vec::new();
+        fallback_page::init(&vhost.root.join(page_fallback), &mut opts);
+    }
+
+    #[cfg(feature = "basic-auth")]
+    if let Some(credentials) = &vhost.basic_auth {
+        basic_auth::init(credentials, &mut opts);
+    }
+
+    #[cfg(feature = "directory-listing")]
+    if vhost.directory_listing.is_some()
+        || vhost.directory_listing_order.is_some()
+        || vhost.directory_listing_format.is_some()
+    {
+        directory_listing::init(
+            vhost.directory_listing.unwrap_or(base.dir_listing),
+            vhost
+                .directory_listing_order
+                .unwrap_or(base.dir_listing_order),
+            vhost
+                .directory_listing_format
+                .clone()
+                .unwrap_or_else(|| base.dir_listing_format.clone()),
+            &mut opts,
+        );
+    }
+
+    if let Some(v) = vhost.redirect_trailing_slash {
+        opts.redirect_trailing_slash = v
+    }
+    if let Some(v) = vhost.clean_urls {
+        opts.clean_urls = v
+    }
+    if let Some(v) = vhost.include_hidden {
+        opts.include_hidden = v
+    }
+    if let Some(v) = vhost.follow_symlinks {
+        opts.follow_symlinks = v
+    }
+
+    if let Some(advanced) = opts.advanced_opts.as_mut() {
+        if vhost.headers.is_some() {
+            advanced.headers = vhost.headers.clone();
+        }
+        if vhost.rewrites.is_some() {
+            advanced.rewrites = vhost.rewrites.clone();
+        }
+        if vhost.redirects.is_some() {
+            advanced.redirects = vhost.redirects.clone();
+        }
+    }
+
+    tracing::debug!(
+        "virtual host options: host={} root={}",
+        vhost.host,
+        opts.root_dir.display()
+    );
+    Ok(opts)
+}
+
+/// It returns the options of the virtual host matching the request "Host" header,
+/// or the status code to reply with when the request matches no virtual host.
+pub(crate) fn select<T>(
+    opts: &Arc<RequestHandlerOpts>,
+    req: &Request<T>,
+) -> Result<Arc<RequestHandlerOpts>, StatusCode> {
+    let Some(vhosts) = opts.virtual_hosts.as_deref() else {
+        return Ok(opts.clone());
+    };
+
+    let matched = request_host(req).and_then(|host| vhosts.find(&normalize_host(host)));
+    let fallback = || {
+        vhosts
+            .default
+            .clone()
+            .or_else(|| vhosts.mass.as_ref().and_then(|mass| mass.fallback.clone()))
+    };
+    match matched.or_else(fallback) {
+        Some(vhost) => {
             // PERF/LOGGING: This fires on every matched request (one per
             // HTTP exchange that targets a configured vhost), so it must
-            // not run at `info` level \u2014 it would amplify access-log
+            // not run at `info` level — it would amplify access-log
             // volume by an order of magnitude and risk leaking request
             // URIs into operator-facing logs. Use `debug` instead.
             tracing::debug!(
-                "virtual host matched: vhost={} vhost_root={} method={} uri={}",
-                vhost.host,
-                vhost.root.display(),
+                "virtual host matched: vhost_root={} method={} uri={}",
+                vhost.root_dir.display(),
                 req.method(),
                 req.uri(),
             );
-            return Some(&vhost.root);
+            Ok(vhost)
         }
+        None => match vhosts.unknown_host_status {
+            Some(status) => Err(status),
+            None => Ok(opts.clone()),
+        },
     }
-    None
+}
+
+impl VirtualHostsOpts {
+    /// Finds the virtual host of a normalized hostname, exact hosts first.
+    fn find(&self, host: &str) -> Option<Arc<RequestHandlerOpts>> {
+        if let Some(opts) = self.exact.get(host) {
+            return Some(opts.clone());
+        }
+        let wildcard = self
+            .wildcards
+            .iter()
+            .find(|(domain, _)| host.len() > domain.len() && host.ends_with(domain.as_str()));
+        if let Some((_, opts)) = wildcard {
+            return Some(opts.clone());
+        }
+        self.mass.as_ref().and_then(|mass| mass.find(host))
+    }
+}
+
+impl MassHosts {
+    fn new(handler_opts: &RequestHandlerOpts, config: &MassVirtualHosts) -> Result<Self> {
+        let canonical = |dir: PathBuf| {
+            if handler_opts.use_relative_root {
+                dir
+            } else {
+                dir.canonicalize().unwrap_or(dir)
+            }
+        };
+
+        let root = helpers::get_valid_dirpath(&config.root).with_context(
+            || "root directory for mass virtual hosts was not found or inaccessible",
+        )?;
+        let fallback = match &config.fallback {
+            Some(fallback) => {
+                let root_dir = helpers::get_valid_dirpath(fallback).with_context(
+                    || "fallback directory for mass virtual hosts was not found or inaccessible",
+                )?;
+                let mut opts = handler_opts.clone();
+                opts.root_dir = canonical(root_dir);
+                Some(Arc::new(opts))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            root: canonical(root),
+            template: handler_opts.clone(),
+            fallback,
+            cache_ttl: Duration::from_secs(config.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL)),
+            hosts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the options of a host having its own directory.
+    fn find(&self, host: &str) -> Option<Arc<RequestHandlerOpts>> {
+        if !is_valid_hostname(host) {
+            tracing::debug!("mass virtual hosts: skipping invalid hostname");
+            return None;
+        }
+
+        {
+            let hosts = self.hosts.lock().unwrap_or_else(|err| err.into_inner());
+            if let Some(cached) = hosts.get(host)
+                && cached.checked.elapsed() < self.cache_ttl
+            {
+                return cached.opts.clone();
+            }
+        }
+
+        // The directory is looked up without holding the lock
+        let opts = self.host_dir(host).map(|root_dir| {
+            let mut opts = self.template.clone();
+            opts.root_dir = root_dir;
+            Arc::new(opts)
+        });
+
+        let mut hosts = self.hosts.lock().unwrap_or_else(|err| err.into_inner());
+        if hosts.len() >= MAX_CACHED_HOSTS {
+            hosts.retain(|_, cached| cached.checked.elapsed() < self.cache_ttl);
+            if hosts.len() >= MAX_CACHED_HOSTS {
+                hosts.clear();
+            }
+        }
+
+        hosts.insert(
+            host.to_owned(),
+            CachedHost {
+                checked: Instant::now(),
+                opts: opts.clone(),
+            },
+        );
+        opts
+    }
+
+    /// Returns the root directory of a validated hostname if it exists.
+    fn host_dir(&self, host: &str) -> Option<PathBuf> {
+        // SECURITY: on top of the hostname validation, the directory must
+        // be a direct child of the root once sanitized like request paths.
+        let dir = sanitize_path(&self.root, host).ok()?;
+        if dir.parent() != Some(self.root.as_path()) {
+            return None;
+        }
+        if !self.template.follow_symlinks && is_symlink(&dir) {
+            tracing::debug!("mass virtual hosts: skipping symlinked host directory");
+            return None;
+        }
+        if !matches!(try_metadata(&dir), Ok((_, true))) {
+            return None;
+        }
+
+        tracing::debug!("mass virtual hosts: found host directory {}", dir.display());
+        if self.template.use_relative_root {
+            Some(dir)
+        } else {
+            Some(dir.canonicalize().unwrap_or(dir))
+        }
+    }
+}
+
+/// Lowercase hostname without its trailing dot.
+fn normalize_host(host: &str) -> Cow<'_, str> {
+    let host = host.trim_end_matches('.');
+    if host.bytes().any(|b| b.is_ascii_uppercase()) {
+        Cow::Owned(host.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(host)
+    }
+}
+
+/// Checks whether a normalized hostname is made of valid DNS labels,
+/// which rules out any path separator, dot segment or percent-encoding.
+fn is_valid_hostname(host: &str) -> bool {
+    !host.is_empty()
+        && host.len() <= MAX_HOST_LEN
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= MAX_LABEL_LEN
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        })
+}
+
+fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+/// Hostname of the request without its port.
+fn request_host<T>(req: &Request<T>) -> Option<&str> {
+    if let Some(authority) = req.uri().authority() {
+        // HTTP2
+        return Some(authority.host());
+    }
+
+    // HTTP1 - fall back to host header
+    let host_header = req.headers().get(HOST)?.to_str().ok()?;
+
+    // host header can include the port -> remove it
+    let host = host_header
+        .rsplit_once(":")
+        .and_then(|(potential_host, potential_port)| {
+            potential_port
+                .parse::<u16>()
+                .is_ok()
+                .then_some(potential_host)
+        })
+        .unwrap_or(host_header);
+    Some(host)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use hyper::{Request, Uri};
+    use hyper::Uri;
+    use std::path::PathBuf;
+
+    use crate::settings::Advanced;
 
-    fn create_vhost(host: &str, root: &str) -> VirtualHosts {
+    fn vhost(host: &str, root: &str) -> VirtualHosts {
         VirtualHosts {
-            host: host.to_string(),
+            host: host.to_owned(),
             root: PathBuf::from(root),
+            default: false,
+            index_files: None,
+            page404: None,
+            page50x: None,
+            #[cfg(feature = "fallback-page")]
+            page_fallback: None,
+            #[cfg(feature = "basic-auth")]
+            basic_auth: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing_order: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing_format: None,
+            redirect_trailing_slash: None,
+            clean_urls: None,
+            include_hidden: None,
+            follow_symlinks: None,
+            headers: None,
+            rewrites: None,
+            redirects: None,
         }
     }
 
-    #[test]
-    fn test_get_real_root_match_http1() {
-        let vhosts = [
-            create_vhost("example.com", "/var/www/example"),
-            create_vhost("test.com", "/var/www/test"),
-        ];
-        let mut req = Request::builder()
-            .uri("http://example.com/")
-            .header(HOST, "example.com")
-            .body(crate::body::empty())
-            .unwrap();
-
-        let result = get_real_root(&mut req, Some(&vhosts));
-        assert_eq!(result, Some(&PathBuf::from("/var/www/example")));
+    fn opts(
+        vhosts: Vec<VirtualHosts>,
+        unknown_host_status: Option<StatusCode>,
+    ) -> Arc<RequestHandlerOpts> {
+        let mut opts = RequestHandlerOpts {
+            root_dir: PathBuf::from("/var/www/general"),
+            advanced_opts: Some(Advanced {
+                virtual_hosts: Some(vhosts),
+                unknown_host_status,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        init(&mut opts).unwrap();
+        Arc::new(opts)
     }
 
-    #[test]
-    fn test_get_real_root_match_http1_with_port() {
-        let vhosts = [create_vhost("example.com", "/var/www/example")];
-        let mut req = Request::builder()
-            .uri("http://example.com:8080/")
-            .header(HOST, "example.com:8080")
-            .body(crate::body::empty())
+    fn root_of(opts: &Arc<RequestHandlerOpts>, host: &str) -> Result<PathBuf, StatusCode> {
+        let req = Request::builder()
+            .uri("/")
+            .header(HOST, host)
+            .body(())
             .unwrap();
-
-        let result = get_real_root(&mut req, Some(&vhosts));
-        assert_eq!(result, Some(&PathBuf::from("/var/www/example")));
+        select(opts, &req).map(|opts| opts.root_dir.clone())
     }
 
     #[test]
-    fn test_get_real_root_match_http2_authority() {
-        let vhosts = [create_vhost("example.com", "/var/www/example")];
-        let mut req = Request::builder()
-            .uri(Uri::builder().authority("example.com").build().unwrap())
-            .body(crate::body::empty())
+    fn selects_exact_hosts() {
+        let opts = opts(
+            vec![
+                vhost("example.com", "/var/www/example"),
+                vhost("test.com", "/var/www/test"),
+            ],
+            None,
+        );
+        let root = |host| root_of(&opts, host);
+        assert_eq!(root("example.com"), Ok(PathBuf::from("/var/www/example")));
+        assert_eq!(root("test.com:8080"), Ok(PathBuf::from("/var/www/test")));
+        assert_eq!(root("Example.COM."), Ok(PathBuf::from("/var/www/example")));
+        assert_eq!(root("example2.com"), Ok(PathBuf::from("/var/www/general")));
+
+        let req = Request::builder()
+            .uri(Uri::builder().authority("test.com").build().unwrap())
+            .body(())
             .unwrap();
+        let selected = select(&opts, &req).unwrap();
+        assert_eq!(selected.root_dir, PathBuf::from("/var/www/test"));
+    }
 
-        let result = get_real_root(&mut req, Some(&vhosts));
-        assert_eq!(result, Some(&PathBuf::from("/var/www/example")));
+    #[test]
+    fn selects_wildcard_hosts() {
+        let opts = opts(
+            vec![
+                vhost("*.example.com", "/var/www/any"),
+                vhost("*.preview.example.com", "/var/www/preview"),
+                vhost("www.preview.example.com", "/var/www/www"),
+            ],
+            None,
+        );
+        let root = |host| root_of(&opts, host).unwrap();
+        assert_eq!(root("a.example.com"), PathBuf::from("/var/www/any"));
+        assert_eq!(
+            root("pr-1.preview.example.com"),
+            PathBuf::from("/var/www/preview")
+        );
+        assert_eq!(
+            root("a.b.preview.example.com"),
+            PathBuf::from("/var/www/preview")
+        );
+        assert_eq!(
+            root("www.preview.example.com"),
+            PathBuf::from("/var/www/www")
+        );
+        assert_eq!(root("preview.example.com"), PathBuf::from("/var/www/any"));
+        assert_eq!(root("example.com"), PathBuf::from("/var/www/general"));
+        assert_eq!(root("badexample.com"), PathBuf::from("/var/www/general"));
     }
 
     #[test]
-    fn test_get_real_root_no_match() {
-        let vhosts = [create_vhost("example.com", "/var/www/example")];
-        let mut req = Request::builder()
-            .uri("http://example2.com/")
-            .header(HOST, "example2.com")
-            .body(crate::body::empty())
-            .unwrap();
+    fn unknown_hosts() {
+        let mut fallback = vhost("fallback.com", "/var/www/fallback");
+        fallback.default = true;
+        let opts_default = opts(
+            vec![vhost("example.com", "/var/www/example"), fallback],
+            None,
+        );
+        assert_eq!(
+            root_of(&opts_default, "other.com"),
+            Ok(PathBuf::from("/var/www/fallback"))
+        );
 
-        let result = get_real_root(&mut req, Some(&vhosts));
-        assert_eq!(result, None);
+        let opts_status = opts(
+            vec![vhost("example.com", "/var/www/example")],
+            Some(StatusCode::MISDIRECTED_REQUEST),
+        );
+        assert_eq!(
+            root_of(&opts_status, "other.com"),
+            Err(StatusCode::MISDIRECTED_REQUEST)
+        );
+        let req = Request::builder().uri("/").body(()).unwrap();
+        assert_eq!(
+            select(&opts_status, &req).err(),
+            Some(StatusCode::MISDIRECTED_REQUEST)
+        );
     }
 
     #[test]
-    fn test_get_real_root_no_vhosts() {
-        let mut req = Request::builder()
-            .uri("http://example.com/")
+    fn overrides_general_options() {
+        let mut example = vhost("example.com", "/var/www/example");
+        example.index_files = Some("home.html, index.htm".to_owned());
+        example.page404 = Some(PathBuf::from("errors/404.html"));
+        example.include_hidden = Some(false);
+        let opts = opts(vec![example], None);
+
+        let req = Request::builder()
+            .uri("/")
             .header(HOST, "example.com")
-            .body(crate::body::empty())
+            .body(())
             .unwrap();
+        let selected = select(&opts, &req).unwrap();
+        assert_eq!(selected.index_files, ["home.html", "index.htm"]);
+        assert_eq!(
+            selected.page404,
+            PathBuf::from("/var/www/example/errors/404.html")
+        );
+        assert!(!selected.include_hidden);
+        assert_eq!(selected.page50x, opts.page50x);
+        assert!(selected.virtual_hosts.is_none());
+    }
 
-        let result = get_real_root(&mut req, None);
-        assert_eq!(result, None);
+    #[test]
+    fn validates_hostnames() {
+        for host in [
+            "example.com",
+            "pr-1.preview.example.com",
+            "127.0.0.1",
+            "localhost",
+        ] {
+            assert!(is_valid_hostname(host), "{host}");
+        }
+        let long_label = "a".repeat(MAX_LABEL_LEN + 1);
+        for host in [
+            "",
+            ".",
+            "..",
+            "a..com",
+            ".hidden",
+            "-a.com",
+            "a-.com",
+            "a/b",
+            "a\\b",
+            "a%2fb",
+            "a_b.com",
+            "[::1]",
+            long_label.as_str(),
+        ] {
+            assert!(!is_valid_hostname(host), "{host}");
+        }
     }
 
     #[test]
-    fn test_get_real_root_empty_vhosts() {
-        let mut req = Request::builder()
-            .uri("http://example.com/")
-            .header(HOST, "example.com")
-            .body(crate::body::empty())
-            .unwrap();
+    fn mass_hosts_directories() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("sites");
+        std::fs::create_dir_all(root.join("a.com")).unwrap();
+        std::fs::write(root.join("b.com"), "not a directory").unwrap();
+        let config = |cache_ttl| MassVirtualHosts {
+            root: root.clone(),
+            fallback: None,
+            cache_ttl: Some(cache_ttl),
+        };
+        let mass = MassHosts::new(&RequestHandlerOpts::default(), &config(60)).unwrap();
+        let root_of = |mass: &MassHosts, host| mass.find(host).map(|opts| opts.root_dir.clone());
 
-        let result = get_real_root(&mut req, Some(&[]));
-        assert_eq!(result, None);
+        assert_eq!(root_of(&mass, "a.com"), Some(mass.root.join("a.com")));
+        assert_eq!(root_of(&mass, "b.com"), None);
+        assert_eq!(root_of(&mass, ".."), None);
+        assert_eq!(root_of(&mass, "a.com/.."), None);
+
+        // Lookups are cached
+        assert_eq!(root_of(&mass, "c.com"), None);
+        std::fs::create_dir(root.join("c.com")).unwrap();
+        assert_eq!(root_of(&mass, "c.com"), None);
+        let mass = MassHosts::new(&RequestHandlerOpts::default(), &config(0)).unwrap();
+        assert_eq!(root_of(&mass, "c.com"), Some(mass.root.join("c.com")));
+    }
+
+    #[test]
+    fn invalid_wildcards() {
+        for host in ["*", "*.", "a.*.com", "*example.com", "*.*.com"] {
+            let mut opts = RequestHandlerOpts {
+                advanced_opts: Some(Advanced {
+                    virtual_hosts: Some(vec![vhost(host, "/var/www")]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            assert!(init(&mut opts).is_err(), "{host}");
+        }
     }
 }
@@ -0,0 +1,117 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use static_web_server::handler::{RequestHandler, RequestHandlerOpts};
+    use static_web_server::testing::fixtures::{
+        fixture_get, fixture_req_handler, fixture_req_handler_opts, fixture_settings,
+    };
+
+    fn site(root: &Path) {
+        fs::write(root.join("index.html"), "home").unwrap();
+        fs::write(root.join("about.html"), "about").unwrap();
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/index.html"), "docs").unwrap();
+        // Both `/blog` and `/blog.html` exist so the latter stays explicit
+        fs::create_dir_all(root.join("blog")).unwrap();
+        fs::write(root.join("blog/index.html"), "blog").unwrap();
+        fs::write(root.join("blog.html"), "blog page").unwrap();
+    }
+
+    fn handler(root: &Path, redirect_trailing_slash: bool) -> RequestHandler {
+        site(root);
+        fixture_req_handler(RequestHandlerOpts {
+            root_dir: root.to_path_buf(),
+            clean_urls: true,
+            redirect_trailing_slash,
+            ..Default::default()
+        })
+    }
+
+    /// Status, `Location` and `Link` headers of the response for `uri`.
+    async fn fetch(handler: &RequestHandler, uri: &str) -> (u16, Option<String>, Option<String>) {
+        let res = fixture_get(handler, uri, &[]).await;
+        (
+            res.status.as_u16(),
+            res.header("location"),
+            res.header("link"),
+        )
+    }
+
+    #[tokio::test]
+    async fn clean_urls_redirect_to_canonical_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = handler(dir.path(), true);
+
+        let redirect = |location: &str| (301, Some(location.to_owned()), None);
+        assert_eq!(fetch(&handler, "/about.html").await, redirect("/about"));
+        assert_eq!(
+            fetch(&handler, "/about.html?lang=en").await,
+            redirect("/about?lang=en")
+        );
+        assert_eq!(fetch(&handler, "/about/").await, redirect("/about"));
+        assert_eq!(fetch(&handler, "/index.html").await, redirect("/"));
+        assert_eq!(
+            fetch(&handler, "/docs/index.html?page=2").await,
+            redirect("/docs/?page=2")
+        );
+        assert_eq!(
+            fetch(&handler, "/docs").await,
+            (308, Some("/docs/".to_owned()), None)
+        );
+    }
+
+    #[tokio::test]
+    async fn clean_urls_canonical_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = handler(dir.path(), true);
+
+        let ok = |path: &str| (200, None, Some(format!("<{path}>; rel=\"canonical\"")));
+        assert_eq!(fetch(&handler, "/").await, ok("/"));
+        assert_eq!(fetch(&handler, "/about").await, ok("/about"));
+        assert_eq!(fetch(&handler, "/docs/").await, ok("/docs/"));
+        assert_eq!(fetch(&handler, "/blog/").await, ok("/blog/"));
+        assert_eq!(fetch(&handler, "/blog.html").await, ok("/blog.html"));
+    }
+
+    #[tokio::test]
+    async fn clean_urls_without_trailing_slash_redirect() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = handler(dir.path(), false);
+
+        assert_eq!(
+            fetch(&handler, "/docs/index.html").await,
+            (301, Some("/docs".to_owned()), None)
+        );
+        let canonical = Some("</docs>; rel=\"canonical\"".to_owned());
+        assert_eq!(
+            fetch(&handler, "/docs").await,
+            (200, None, canonical.clone())
+        );
+        assert_eq!(fetch(&handler, "/docs/").await, (200, None, canonical));
+        assert_eq!(
+            fetch(&handler, "/about/").await,
+            (200, None, Some("</about>; rel=\"canonical\"".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn clean_urls_skip_rewritten_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        site(dir.path());
+        let mut opts = fixture_settings("toml/clean_urls_spa.toml");
+        opts.general.root = dir.path().to_path_buf();
+        let handler = fixture_req_handler(fixture_req_handler_opts(opts.general, opts.advanced));
+
+        // Rewritten to `/index.html` and `/docs/index.html`, served as is
+        for uri in ["/", "/app/settings", "/about.html", "/docs/guide", "/docs/"] {
+            assert_eq!(fetch(&handler, uri).await, (200, None, None), "{uri}");
+        }
+    }
+}
@@ -30,6 +30,7 @@ pub mod tests {
             compression: true,
             compression_static: true,
             etag: true,
+            etag_strong: false,
             index_files: "index.htm, index.html".to_owned(),
             ..opts.general
         };
@@ -84,4 +85,57 @@ pub mod tests {
             Err(err) => panic!("unexpected error: {err}"),
         };
     }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn compression_policy() {
+        use static_web_server::compression;
+        use static_web_server::handler::RequestHandlerOpts;
+        use static_web_server::settings::Advanced;
+        use static_web_server::settings::file::{CompressionPolicy, CompressionRule};
+
+        let mut opts = RequestHandlerOpts {
+            root_dir: "tests/fixtures/public".into(),
+            compression: true,
+            advanced_opts: Some(Advanced {
+                compression: Some(CompressionPolicy {
+                    min_size: Some(100),
+                    encodings: Some(vec!["gzip".to_owned(), "zstd".to_owned()]),
+                    levels: None,
+                    rules: Some(vec![CompressionRule {
+                        source: Some("/assets/*.html".to_owned()),
+                        mime: None,
+                        enabled: Some(false),
+                        encodings: None,
+                    }]),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        compression::init_policy(&mut opts).unwrap();
+        let req_handler = fixture_req_handler(opts);
+
+        for (uri, expected) in [
+            ("/assets/main.css", Some("gzip")),
+            ("/assets/main.js", None),
+            ("/assets/index.html", None),
+        ] {
+            let mut req = Request::new(());
+            *req.uri_mut() = format!("http://localhost{uri}").parse().unwrap();
+            req.headers_mut().insert(
+                http::header::ACCEPT_ENCODING,
+                "br, zstd, gzip".parse().unwrap(),
+            );
+            let res = req_handler.handle(&mut req, None).await.unwrap();
+            assert_eq!(res.status(), 200, "{uri}");
+            assert_eq!(
+                res.headers()
+                    .get("content-encoding")
+                    .map(|v| v.to_str().unwrap()),
+                expected,
+                "{uri}"
+            );
+        }
+    }
 }
@@ -0,0 +1,159 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(feature = "compression-brotli")]
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http_body_util::BodyExt;
+    use hyper::Request;
+    use std::path::{Path, PathBuf};
+
+    use static_web_server::compression_cache;
+    use static_web_server::handler::{RequestHandler, RequestHandlerOpts};
+    use static_web_server::settings::Advanced;
+    use static_web_server::settings::file::CompressionCache;
+    use static_web_server::testing::fixtures::fixture_req_handler;
+
+    fn handler(root_dir: &Path, cache_dir: &Path) -> RequestHandler {
+        let mut opts = RequestHandlerOpts {
+            root_dir: root_dir.to_owned(),
+            compression: true,
+            advanced_opts: Some(Advanced {
+                compression_cache: Some(CompressionCache {
+                    dir: cache_dir.to_owned(),
+                    max_size: None,
+                    max_file_size: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        compression_cache::init(&mut opts).unwrap();
+        fixture_req_handler(opts)
+    }
+
+    async fn fetch_br(handler: &RequestHandler) -> (http::HeaderMap, Bytes) {
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/style.css".parse().unwrap();
+        req.headers_mut()
+            .insert("accept-encoding", "br".parse().unwrap());
+        let resp = handler.handle(&mut req, None).await.unwrap();
+        let (parts, body) = resp.into_parts();
+        (parts.headers, body.collect().await.unwrap().to_bytes())
+    }
+
+    fn cached_entries(cache_dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(cache_dir)
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn compressed_output_is_persisted_and_served_after_restart() {
+        let root = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let root_dir = root.path().canonicalize().unwrap();
+        std::fs::write(
+            root_dir.join("style.css"),
+            "body { color: red; }\n".repeat(50),
+        )
+        .unwrap();
+
+        let sws = handler(&root_dir, cache_dir.path());
+        let (headers, compressed) = fetch_br(&sws).await;
+        assert_eq!(headers["content-encoding"], "br");
+
+        // The entry is written off the request path
+        let mut entries = cached_entries(cache_dir.path());
+        for _ in 0..100 {
+            if !entries.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            entries = cached_entries(cache_dir.path());
+        }
+        assert_eq!(entries.len(), 1, "one entry per file and encoding");
+        assert!(entries[0].to_str().unwrap().ends_with(".br"));
+        assert_eq!(std::fs::read(&entries[0]).unwrap(), compressed);
+
+        let (headers, body) = fetch_br(&sws).await;
+        assert_eq!(headers["content-encoding"], "br");
+        assert_eq!(headers["vary"], "accept-encoding");
+        assert_eq!(headers["content-length"], body.len().to_string().as_str());
+        assert_eq!(body, compressed);
+
+        // A restart indexes the existing entries and serves them as-is
+        std::fs::write(&entries[0], "cached!").unwrap();
+        let sws = handler(&root_dir, cache_dir.path());
+        let (headers, body) = fetch_br(&sws).await;
+        assert_eq!(headers["content-encoding"], "br");
+        assert_eq!(headers["content-length"], "7");
+        assert_eq!(body, "cached!");
+
+        // A changed file gets a new key and is never served stale
+        std::fs::write(
+            root_dir.join("style.css"),
+            "body { color: blue; }\n".repeat(50),
+        )
+        .unwrap();
+        let (headers, body) = fetch_br(&sws).await;
+        assert_eq!(headers["content-encoding"], "br");
+        assert_ne!(body, "cached!");
+    }
+
+    #[tokio::test]
+    async fn cache_is_disabled_without_dynamic_compression() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut opts = RequestHandlerOpts {
+            compression: false,
+            advanced_opts: Some(Advanced {
+                compression_cache: Some(CompressionCache {
+                    dir: cache_dir.path().to_owned(),
+                    max_size: None,
+                    max_file_size: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        compression_cache::init(&mut opts).unwrap();
+        assert!(opts.compression_cache.is_none());
+    }
+
+    #[test]
+    fn cache_dir_inside_a_served_directory_is_rejected() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        for (dir, ok) in [
+            (root.path().join("cache"), false),
+            (root.path().join("a/../cache"), false),
+            (outside.path().join("a/../cache"), true),
+            (root.path().to_owned(), false),
+            (outside.path().join("cache"), true),
+        ] {
+            let mut opts = RequestHandlerOpts {
+                root_dir: root.path().to_owned(),
+                compression: true,
+                advanced_opts: Some(Advanced {
+                    compression_cache: Some(CompressionCache {
+                        dir: dir.clone(),
+                        max_size: Some(u64::MAX),
+                        max_file_size: None,
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let result = compression_cache::init(&mut opts);
+            assert_eq!(result.is_ok(), ok, "{}", dir.display());
+            // Nothing is created in a served directory
+            assert_eq!(dir.join(".tmp").exists(), ok, "{}", dir.display());
+        }
+    }
+}
@@ -0,0 +1,166 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use base64::Engine as _;
+    use sha2::Digest as _;
+    use std::fs;
+    use std::path::Path;
+
+    use static_web_server::compression_dictionary;
+    use static_web_server::handler::{RequestHandler, RequestHandlerOpts};
+    use static_web_server::settings::{Advanced, file::CompressionDictionary};
+    use static_web_server::testing::fixtures::{fixture_get, fixture_req_handler};
+
+    const DCZ_MAGIC: [u8; 8] = [0x5e, 0x2a, 0x4d, 0x18, 0x20, 0x00, 0x00, 0x00];
+
+    fn app_js(version: u32) -> Vec<u8> {
+        let mut js = format!("const version = {version};\n").into_bytes();
+        for i in 0..50 {
+            js.extend_from_slice(format!("export function f{i}() {{ return {i}; }}\n").as_bytes());
+        }
+        js
+    }
+
+    fn handler(root: &Path) -> RequestHandler {
+        let mut opts = RequestHandlerOpts {
+            root_dir: root.to_owned(),
+            compression: true,
+            advanced_opts: Some(Advanced {
+                headers: None,
+                rewrites: None,
+                redirects: None,
+                virtual_hosts: None,
+                unknown_host_status: None,
+                mass_virtual_hosts: None,
+                mounts: None,
+                memory_cache: None,
+                compression_cache: None,
+                cache_control: None,
+                compression_dictionaries: Some(vec![CompressionDictionary {
+                    source: "/v1/app.js".to_owned(),
+                    url_match: "/v*/app.js".to_owned(),
+                    id: Some("app".to_owned()),
+                }]),
+                compression: None,
+                redirect_maps: None,
+                proxy: None,
+                language_negotiation: None,
+            }),
+            ..Default::default()
+        };
+        compression_dictionary::init(&mut opts).unwrap();
+        assert!(opts.compression_dictionaries.is_some());
+        fixture_req_handler(opts)
+    }
+
+    async fn fetch(
+        handler: &RequestHandler,
+        uri: &str,
+        headers: &[(&str, &str)],
+    ) -> (http::HeaderMap, Vec<u8>) {
+        let res = fixture_get(handler, uri, headers).await;
+        assert!(res.status.is_success(), "{uri}: {}", res.status);
+        (res.headers, res.body.to_vec())
+    }
+
+    fn setup() -> (tempfile::TempDir, Vec<u8>, [u8; 32]) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("v1")).unwrap();
+        fs::create_dir(dir.path().join("v2")).unwrap();
+        let dictionary = app_js(1);
+        fs::write(dir.path().join("v1/app.js"), &dictionary).unwrap();
+        fs::write(dir.path().join("v2/app.js"), app_js(2)).unwrap();
+        let hash = sha2::Sha256::digest(&dictionary).into();
+        (dir, dictionary, hash)
+    }
+
+    fn available(hash: &[u8; 32]) -> String {
+        format!(
+            ":{}:",
+            base64::engine::general_purpose::STANDARD.encode(hash)
+        )
+    }
+
+    #[tokio::test]
+    async fn dictionaries_are_announced() {
+        let (dir, _, _) = setup();
+        let handler = handler(dir.path());
+        let (headers, _) = fetch(&handler, "/v1/app.js", &[]).await;
+        assert_eq!(
+            headers["use-as-dictionary"],
+            r#"match="/v*/app.js", id="app""#
+        );
+        let (headers, _) = fetch(&handler, "/v2/app.js", &[]).await;
+        assert!(headers.get("use-as-dictionary").is_none());
+    }
+
+    #[tokio::test]
+    async fn responses_are_compressed_with_the_available_dictionary() {
+        let (dir, dictionary, hash) = setup();
+        let handler = handler(dir.path());
+        let available = available(&hash);
+        let (headers, body) = fetch(
+            &handler,
+            "/v2/app.js",
+            &[
+                ("accept-encoding", "gzip, dcz"),
+                ("available-dictionary", &available),
+            ],
+        )
+        .await;
+        assert_eq!(headers["content-encoding"], "dcz");
+        let vary = headers["vary"].to_str().unwrap();
+        assert!(vary.contains("available-dictionary"), "{vary}");
+        assert_eq!(&body[..8], DCZ_MAGIC);
+        assert_eq!(&body[8..40], hash);
+
+        let decoded = zstd::bulk::Decompressor::with_dictionary(&dictionary)
+            .unwrap()
+            .decompress(&body, 1 << 16)
+            .unwrap();
+        assert_eq!(decoded, app_js(2));
+
+        // Unknown dictionaries fall back to the regular compression
+        let unknown = self::available(&[7; 32]);
+        let (headers, _) = fetch(
+            &handler,
+            "/v2/app.js",
+            &[
+                ("accept-encoding", "gzip, dcz"),
+                ("available-dictionary", &unknown),
+            ],
+        )
+        .await;
+        assert_eq!(headers["content-encoding"], "gzip");
+    }
+
+    #[tokio::test]
+    async fn precomputed_sidecars_are_served() {
+        let (dir, _, hash) = setup();
+        let hex: String = hash.iter().map(|b| format!("{b:02x}")).collect();
+        let sidecar = [&[0xff, 0x44, 0x43, 0x42][..], &hash, b"precomputed"].concat();
+        fs::write(dir.path().join(format!("v2/app.js.{hex}.dcb")), &sidecar).unwrap();
+
+        let handler = handler(dir.path());
+        let available = available(&hash);
+        let (headers, body) = fetch(
+            &handler,
+            "/v2/app.js",
+            &[
+                ("accept-encoding", "dcb"),
+                ("available-dictionary", &available),
+            ],
+        )
+        .await;
+        assert_eq!(headers["content-encoding"], "dcb");
+        assert_eq!(
+            headers["content-length"],
+            sidecar.len().to_string().as_str()
+        );
+        assert_eq!(body, sidecar);
+    }
+}
@@ -31,6 +31,7 @@ mod tests {
         let general = General {
             compression_static: true,
             etag: true,
+            etag_strong: false,
             index_files: "index.htm, index.html".to_owned(),
             ..opts.general
         };
@@ -88,6 +89,7 @@ mod tests {
         let general = General {
             compression_static: true,
             etag: true,
+            etag_strong: false,
             ..opts.general
         };
         let req_handler_opts = fixture_req_handler_opts(general, opts.advanced);
@@ -147,6 +149,7 @@ mod tests {
             compression: false,
             compression_static: true,
             etag: true,
+            etag_strong: false,
             ..opts.general
         };
         let req_handler_opts = fixture_req_handler_opts(general, opts.advanced);
@@ -192,6 +195,7 @@ mod tests {
             compression: false,
             compression_static: true,
             etag: true,
+            etag_strong: false,
             directory_listing: true,
             directory_listing_format: DirListFmt::Html,
             include_hidden: true,
@@ -246,6 +250,7 @@ mod tests {
             compression: false,
             compression_static: true,
             etag: true,
+            etag_strong: false,
             ..opts.general
         };
         let req_handler_opts = fixture_req_handler_opts(general, opts.advanced);
@@ -309,6 +314,7 @@ mod tests {
             compression: false,
             compression_static: true,
             etag: true,
+            etag_strong: false,
             ..opts.general
         };
         let req_handler_opts = fixture_req_handler_opts(general, opts.advanced);
@@ -357,6 +363,7 @@ mod tests {
             compression: false,
             compression_static: true,
             etag: true,
+            etag_strong: false,
             ..opts.general
         };
         let req_handler_opts = fixture_req_handler_opts(general, opts.advanced);
@@ -420,6 +427,7 @@ mod tests {
             compression: false,
             compression_static: true,
             etag: true,
+            etag_strong: false,
             // Use only an index name that does not exist in `assets/` so
             // SWS exercises the no-index-found branch.
             index_files: "missing-index.html".to_owned(),
@@ -473,6 +481,7 @@ mod tests {
         let general = General {
             compression_static: true,
             etag: true,
+            etag_strong: false,
             ..opts.general
         };
         let req_handler_opts = fixture_req_handler_opts(general, opts.advanced);
@@ -507,4 +516,44 @@ mod tests {
             Err(err) => panic!("unexpected error: {err}"),
         };
     }
+
+    #[cfg(feature = "compression-brotli")]
+    #[tokio::test]
+    async fn compress_subcommand_output_is_served_as_precompressed_variant() {
+        use static_web_server::handler::RequestHandlerOpts;
+        use static_web_server::precompress::{self, PrecompressOpts, SidecarFormat};
+
+        let root = tempfile::tempdir().unwrap();
+        let root_dir = root.path().canonicalize().unwrap();
+        std::fs::write(root_dir.join("app.js"), "console.log(1);\n".repeat(40)).unwrap();
+
+        let summary = precompress::run(&PrecompressOpts {
+            root: root_dir.clone(),
+            formats: vec![SidecarFormat::Br],
+            level: static_web_server::settings::CompressionLevel::Default,
+            jobs: 1,
+            force: false,
+            include_hidden: false,
+        })
+        .unwrap();
+        assert_eq!(summary.written, 1);
+        let sidecar = Bytes::from(std::fs::read(root_dir.join("app.js.br")).unwrap());
+
+        let req_handler = fixture_req_handler(RequestHandlerOpts {
+            root_dir,
+            compression: false,
+            compression_static: true,
+            ..Default::default()
+        });
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/app.js".parse().unwrap();
+        req.headers_mut()
+            .insert(http::header::ACCEPT_ENCODING, "br".parse().unwrap());
+
+        let res = req_handler.handle(&mut req, None).await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["content-encoding"], "br");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, sidecar);
+    }
 }
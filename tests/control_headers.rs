@@ -0,0 +1,98 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use headers::{Expires, HeaderMapExt};
+    use hyper::Request;
+    use std::time::{Duration, SystemTime};
+
+    use static_web_server::handler::RequestHandler;
+    use static_web_server::testing::fixtures::{
+        fixture_req_handler, fixture_req_handler_opts, fixture_settings,
+    };
+
+    fn handler() -> (RequestHandler, tempfile::TempDir) {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("api")).unwrap();
+        for file in ["app.3f9a1c.js", "app.js", "index.html", "api/users.json"] {
+            std::fs::write(root.path().join(file), "content").unwrap();
+        }
+
+        let opts = fixture_settings("toml/cache_control.toml");
+        let mut req_handler_opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        req_handler_opts.root_dir = root.path().to_owned();
+        (fixture_req_handler(req_handler_opts), root)
+    }
+
+    async fn fetch(handler: &RequestHandler, uri: &str) -> http::HeaderMap {
+        let mut req = Request::new(());
+        *req.uri_mut() = format!("http://localhost{uri}").parse().unwrap();
+        let res = handler.handle(&mut req, None).await.unwrap();
+        assert_eq!(res.status(), 200, "{uri}");
+        res.headers().clone()
+    }
+
+    #[tokio::test]
+    async fn rules_take_precedence_over_built_in_table() {
+        let (handler, _root) = handler();
+
+        let headers = fetch(&handler, "/app.3f9a1c.js").await;
+        assert_eq!(
+            headers["cache-control"],
+            "public, max-age=31536000, immutable"
+        );
+        assert!(headers.get("expires").is_none());
+
+        let headers = fetch(&handler, "/").await;
+        assert_eq!(headers["cache-control"], "no-cache");
+
+        let headers = fetch(&handler, "/api/users.json").await;
+        assert_eq!(
+            headers["cache-control"],
+            "max-age=60, stale-while-revalidate=600"
+        );
+        let expires = SystemTime::from(headers.typed_get::<Expires>().unwrap());
+        assert!(expires > SystemTime::now() + Duration::from_secs(30));
+        assert!(expires <= SystemTime::now() + Duration::from_secs(61));
+    }
+
+    #[tokio::test]
+    async fn unmatched_paths_fall_back_to_built_in_table() {
+        let (handler, _root) = handler();
+        let headers = fetch(&handler, "/app.js").await;
+        assert_eq!(headers["cache-control"], "max-age=31536000");
+    }
+
+    #[test]
+    fn invalid_rules_fail_at_startup() {
+        let dir = tempfile::tempdir().unwrap();
+        for (rule, error) in [
+            ("value = \"no-cache\"", "requires a `source`"),
+            (
+                "source-regex = \"(\"\nvalue = \"no-cache\"",
+                "can not compile regex",
+            ),
+            (
+                "source = \"/\"\nvalue = \"no\\ncache\"",
+                "invalid cache-control",
+            ),
+        ] {
+            let config = dir.path().join("sws.toml");
+            std::fs::write(
+                &config,
+                format!("[general]\nroot = \"tests/fixtures/public\"\n\n[[advanced.cache-control]]\n{rule}\n"),
+            )
+            .unwrap();
+            let err = static_web_server::Settings::get_unparsed(
+                false,
+                &["static-web-server", "-w", config.to_str().unwrap()],
+            )
+            .err()
+            .expect("invalid rule must be rejected");
+            assert!(format!("{err:#}").contains(error), "{err:#}");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+
+    use static_web_server::handler::RequestHandler;
+    use static_web_server::testing::fixtures::{
+        fixture_get, fixture_req_handler, fixture_req_handler_opts, fixture_settings,
+    };
+
+    fn handler(compression: bool) -> RequestHandler {
+        let opts = fixture_settings("toml/digest.toml");
+        let mut opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        opts.compression = compression;
+        fixture_req_handler(opts)
+    }
+
+    async fn fetch(handler: &RequestHandler, uri: &str, headers: &[(&str, &str)]) -> HeaderMap {
+        let res = fixture_get(handler, uri, headers).await;
+        assert!(res.status.is_success(), "{uri}: {}", res.status);
+        res.headers
+    }
+
+    #[tokio::test]
+    async fn repr_digest_is_sent_when_wanted() {
+        let handler = handler(false);
+        let headers = fetch(&handler, "/index.htm", &[]).await;
+        assert!(headers.get("repr-digest").is_none());
+
+        let want = [("want-repr-digest", "sha-256=1, sha-512=3")];
+        let headers = fetch(&handler, "/index.htm", &want).await;
+        let repr_digest = headers["repr-digest"].to_str().unwrap().to_owned();
+        assert!(repr_digest.starts_with("sha-512=:"), "{repr_digest}");
+        assert!(headers.get("content-digest").is_none());
+
+        // Ranges carry the digest of the whole representation
+        let headers = fetch(&handler, "/index.htm", &[want[0], ("range", "bytes=0-3")]).await;
+        assert_eq!(headers["repr-digest"], repr_digest.as_str());
+
+        let headers = fetch(
+            &handler,
+            "/index.htm",
+            &[("want-content-digest", "sha-256=1")],
+        )
+        .await;
+        assert!(
+            headers["content-digest"]
+                .to_str()
+                .unwrap()
+                .starts_with("sha-256=:")
+        );
+    }
+
+    #[tokio::test]
+    async fn precompressed_variants_have_their_own_digest() {
+        let handler = handler(false);
+        let want = ("want-repr-digest", "sha-256=1");
+        let identity = fetch(&handler, "/index.htm", &[want]).await;
+        let brotli = fetch(&handler, "/index.htm", &[want, ("accept-encoding", "br")]).await;
+        assert_eq!(brotli["content-encoding"], "br");
+        assert!(
+            brotli["repr-digest"]
+                .to_str()
+                .unwrap()
+                .starts_with("sha-256=:")
+        );
+        assert_ne!(identity["repr-digest"], brotli["repr-digest"]);
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[tokio::test]
+    async fn responses_compressed_on_the_fly_carry_no_digest() {
+        let handler = handler(true);
+        let headers = fetch(
+            &handler,
+            "/assets/index.html",
+            &[
+                ("want-repr-digest", "sha-256=1"),
+                ("accept-encoding", "gzip"),
+            ],
+        )
+        .await;
+        assert_eq!(headers["content-encoding"], "gzip");
+        assert!(headers.get("repr-digest").is_none());
+    }
+
+    #[cfg(all(feature = "mem-cache", feature = "compression-gzip"))]
+    #[tokio::test]
+    async fn cached_representations_have_their_own_digest() {
+        use base64::Engine as _;
+        use sha2::Digest as _;
+        use static_web_server::mem_cache::cache;
+
+        let opts = fixture_settings("toml/digest_mem_cache.toml");
+        let mut handler_opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        cache::init(&mut handler_opts).unwrap();
+        let handler = fixture_req_handler(handler_opts);
+
+        // Served from disk, then from memory. The first gzip response is
+        // compressed on the fly and fills the cached variant.
+        let requests = [
+            ("identity", None),
+            ("identity", Some("HIT")),
+            ("gzip", Some("HIT")),
+            ("gzip", Some("HIT")),
+        ];
+        for (i, (accept_encoding, x_cache)) in requests.into_iter().enumerate() {
+            let res = fixture_get(
+                &handler,
+                "/assets/index.html",
+                &[
+                    ("want-repr-digest", "sha-256=1"),
+                    ("accept-encoding", accept_encoding),
+                ],
+            )
+            .await;
+            let (headers, body) = (res.headers, res.body);
+
+            assert_eq!(
+                headers.get("x-cache").map(|v| v.to_str().unwrap()),
+                x_cache,
+                "{i}"
+            );
+            if i == 2 {
+                assert!(headers.get("repr-digest").is_none());
+                continue;
+            }
+            if i == 3 {
+                assert_eq!(headers["content-encoding"], "gzip");
+            }
+            let hash =
+                base64::engine::general_purpose::STANDARD.encode(sha2::Sha256::digest(&body));
+            assert_eq!(headers["repr-digest"], format!("sha-256=:{hash}:"), "{i}");
+        }
+    }
+}
@@ -53,8 +53,13 @@ mod tests {
                 dir_listing_order: 6,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -91,8 +96,13 @@ mod tests {
                 dir_listing_order: 6,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -142,8 +152,13 @@ mod tests {
                 dir_listing_order: 6,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: false,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -193,8 +208,13 @@ mod tests {
                 dir_listing_order: 6,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: false,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -231,8 +251,13 @@ mod tests {
                 dir_listing_order: 6,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -293,8 +318,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Json,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &[],
@@ -373,8 +403,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Json,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -426,8 +461,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &[],
@@ -479,8 +519,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &[],
@@ -531,8 +576,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &[],
@@ -606,8 +656,13 @@ mod tests {
             dir_listing_order: 6,
             dir_listing_format: &DirListFmt::Html,
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &[],
@@ -657,8 +712,13 @@ mod tests {
             dir_listing_order: 6,
             dir_listing_format: &DirListFmt::Auto,
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &[],
@@ -690,8 +750,13 @@ mod tests {
             dir_listing_order: 6,
             dir_listing_format: &DirListFmt::Auto,
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &[],
@@ -728,8 +793,13 @@ mod tests {
                 dir_listing_order: 6,
                 dir_listing_format: &DirListFmt::Auto,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -137,8 +137,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks,
                 index_files: &[],
@@ -175,6 +180,7 @@ mod tests {
                                 method: &method,
                                 follow_symlinks,
                                 include_hidden: true,
+                                site_files: false,
                             },
                         )
                         .await;
@@ -213,8 +219,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &[],
@@ -278,8 +289,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks,
                 index_files: &[],
@@ -316,6 +332,7 @@ mod tests {
                                 method: &method,
                                 follow_symlinks,
                                 include_hidden: true,
+                                site_files: false,
                             },
                         )
                         .await;
@@ -353,8 +370,13 @@ mod tests {
                 dir_listing_order: 1,
                 dir_listing_format: &DirListFmt::Html,
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -3,7 +3,7 @@
 #![deny(rust_2018_idioms)]
 #![deny(dead_code)]
 
-//! Integration tests for weak `ETag` (`--etag`) support.
+//! Integration tests for `ETag` (`--etag` and `--etag-strong`) support.
 //!
 //! Exercises RFC 7232 semantics on the public file handler:
 //!
@@ -14,6 +14,8 @@
 //! * `If-Range` with a weak ETag falls back to a full 200.
 //! * `--etag false` disables the header entirely.
 //! * `ETag` and `Cache-Control` coexist on the full request pipeline.
+//! * Strong content-hash `ETag`s satisfy `If-Match` and `If-Range`, and
+//!   differ across precompressed and compressed representations.
 
 #[cfg(test)]
 mod tests {
@@ -26,6 +28,8 @@ mod tests {
 
     #[cfg(feature = "directory-listing")]
     use static_web_server::directory_listing::DirListFmt;
+    #[cfg(feature = "compression-gzip")]
+    use static_web_server::handler::RequestHandlerOpts;
     use static_web_server::static_files::{self, HandleOpts};
     use static_web_server::testing::fixtures::{
         REMOTE_ADDR, fixture_req_handler, fixture_req_handler_opts, fixture_settings,
@@ -61,8 +65,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &["index.htm"],
@@ -247,4 +256,106 @@ mod tests {
             "Cache-Control must coexist with ETag"
         );
     }
+
+    async fn fetch_strong(
+        uri: &str,
+        headers: &HeaderMap,
+    ) -> http::Response<static_web_server::body::Body> {
+        let base = root_dir();
+        let mut opts = opts(&Method::GET, headers, &base, uri, true);
+        opts.etag_strong = true;
+        opts.compression_static = true;
+        static_files::handle(&opts)
+            .await
+            .expect("handler should succeed")
+            .resp
+    }
+
+    #[tokio::test]
+    async fn strong_etag_satisfies_if_match_and_if_range() {
+        let res = fetch_strong("index.htm", &HeaderMap::new()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let etag = res.headers()[http::header::ETAG].clone();
+        let v = etag.to_str().unwrap();
+        assert!(!v.starts_with("W/"), "expected strong ETag, got `{v}`");
+        assert_eq!(v.len(), 34, "expected a quoted 128-bit hash, got `{v}`");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::IF_MATCH, etag.clone());
+        let res = fetch_strong("index.htm", &headers).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::IF_RANGE, etag.clone());
+        headers.insert(
+            http::header::RANGE,
+            http::HeaderValue::from_static("bytes=0-3"),
+        );
+        let res = fetch_strong("index.htm", &headers).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::IF_NONE_MATCH, etag);
+        let res = fetch_strong("index.htm", &headers).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn strong_etag_differs_for_precompressed_variant() {
+        let identity = fetch_strong("index.htm", &HeaderMap::new()).await;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            http::header::ACCEPT_ENCODING,
+            http::HeaderValue::from_static("br"),
+        );
+        let brotli = fetch_strong("index.htm", &headers).await;
+        assert_eq!(brotli.headers()[http::header::CONTENT_ENCODING], "br");
+        assert_ne!(
+            identity.headers()[http::header::ETAG],
+            brotli.headers()[http::header::ETAG]
+        );
+        assert!(
+            !brotli.headers()[http::header::ETAG]
+                .to_str()
+                .unwrap()
+                .starts_with("W/")
+        );
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[tokio::test]
+    async fn strong_etag_of_compressed_response_revalidates() {
+        let req_handler = fixture_req_handler(RequestHandlerOpts {
+            root_dir: root_dir(),
+            compression: true,
+            etag: true,
+            etag_strong: true,
+            ..Default::default()
+        });
+        let fetch = |if_none_match: Option<http::HeaderValue>| {
+            let mut req = Request::new(());
+            *req.uri_mut() = "http://localhost/assets/index.html".parse().unwrap();
+            req.headers_mut().insert(
+                http::header::ACCEPT_ENCODING,
+                http::HeaderValue::from_static("gzip"),
+            );
+            if let Some(etag) = if_none_match {
+                req.headers_mut().insert(http::header::IF_NONE_MATCH, etag);
+            }
+            req
+        };
+
+        let res = req_handler.handle(&mut fetch(None), None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[http::header::CONTENT_ENCODING], "gzip");
+        let etag = res.headers()[http::header::ETAG].clone();
+        assert!(etag.to_str().unwrap().ends_with("-gzip\""), "{etag:?}");
+
+        let res = req_handler
+            .handle(&mut fetch(Some(etag.clone())), None)
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[http::header::ETAG], etag);
+    }
 }
@@ -0,0 +1 @@
+app
\ No newline at end of file
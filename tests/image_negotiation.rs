@@ -0,0 +1,86 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use static_web_server::handler::{RequestHandler, RequestHandlerOpts};
+    use static_web_server::testing::fixtures::{FixtureResponse, fixture_get, fixture_req_handler};
+
+    fn handler(root: &Path) -> RequestHandler {
+        fs::write(root.join("hero.jpg"), "original jpeg image").unwrap();
+        fs::write(root.join("hero.jpg.avif"), "avif image").unwrap();
+        fs::write(root.join("hero.jpg.webp"), "webp image!").unwrap();
+        fs::write(root.join("logo.png"), "png").unwrap();
+        fs::write(root.join("logo.png.webp"), "larger webp").unwrap();
+
+        fixture_req_handler(RequestHandlerOpts {
+            root_dir: root.to_path_buf(),
+            image_negotiation: true,
+            compression_static: false,
+            ..Default::default()
+        })
+    }
+
+    fn varies_by_accept(resp: &FixtureResponse) -> bool {
+        let vary = resp.header("vary").unwrap();
+        vary.split(',').any(|name| name.trim() == "accept")
+    }
+
+    #[tokio::test]
+    async fn image_negotiation_serves_smallest_accepted_variant() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = handler(dir.path());
+
+        let cases = [
+            ("image/avif,image/webp,*/*", "image/avif", "avif image"),
+            ("image/webp,image/*;q=0.8", "image/webp", "webp image!"),
+            ("image/avif;q=0,*/*", "image/jpeg", "original jpeg image"),
+            ("*/*", "image/jpeg", "original jpeg image"),
+        ];
+        let mut etags = Vec::new();
+        for (accept, content_type, expected) in cases {
+            let resp = fixture_get(&handler, "/hero.jpg", &[("accept", accept)]).await;
+            assert_eq!(resp.status, 200);
+            assert_eq!(resp.headers["content-type"], content_type);
+            assert!(varies_by_accept(&resp));
+            etags.push(resp.headers["etag"].clone());
+            assert_eq!(resp.text(), expected);
+        }
+        assert_ne!(etags[0], etags[1]);
+        assert_ne!(etags[0], etags[2]);
+        assert_eq!(etags[2], etags[3]);
+
+        // A variant larger than its original is never served
+        let resp = fixture_get(&handler, "/logo.png", &[("accept", "image/webp")]).await;
+        assert_eq!(resp.headers["content-type"], "image/png");
+        assert_eq!(resp.text(), "png");
+    }
+
+    #[tokio::test]
+    async fn image_negotiation_keeps_validators_and_ranges_per_variant() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = handler(dir.path());
+        let accept = ("accept", "image/avif");
+
+        let resp = fixture_get(&handler, "/hero.jpg", &[accept]).await;
+        let etag = resp.headers["etag"].to_str().unwrap().to_owned();
+
+        let resp = fixture_get(&handler, "/hero.jpg", &[accept, ("if-none-match", &etag)]).await;
+        assert_eq!(resp.status, 304);
+        assert!(varies_by_accept(&resp));
+
+        // The validator of a variant does not match the original
+        let resp = fixture_get(&handler, "/hero.jpg", &[("if-none-match", &etag)]).await;
+        assert_eq!(resp.status, 200);
+
+        let resp = fixture_get(&handler, "/hero.jpg", &[accept, ("range", "bytes=0-3")]).await;
+        assert_eq!(resp.status, 206);
+        assert_eq!(resp.headers["content-range"], "bytes 0-3/10");
+        assert_eq!(resp.text(), "avif");
+    }
+}
@@ -0,0 +1,71 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use static_web_server::handler::RequestHandler;
+    use static_web_server::testing::fixtures::{
+        FixtureResponse, fixture_get, fixture_req_handler, fixture_req_handler_opts,
+        fixture_settings,
+    };
+
+    fn handler() -> RequestHandler {
+        let opts = fixture_settings("toml/language_negotiation.toml");
+        let mut opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        static_web_server::language_negotiation::init(&mut opts).unwrap();
+        fixture_req_handler(opts)
+    }
+
+    fn assert_negotiated(res: &FixtureResponse, language: Option<&str>, body: &str) {
+        assert_eq!(res.status, 200);
+        assert_eq!(res.header("content-language").as_deref(), language);
+        assert_eq!(
+            res.header("vary").as_deref(),
+            Some("accept-language, cookie")
+        );
+        assert_eq!(res.text(), body);
+    }
+
+    #[tokio::test]
+    async fn language_negotiation_serves_variants() {
+        let handler = handler();
+
+        let accept = |value| [("accept-language", value)];
+        let res = fixture_get(&handler, "/", &accept("de-AT, en;q=0.5")).await;
+        assert_negotiated(&res, Some("de"), "startseite");
+        let res = fixture_get(&handler, "/about", &accept("pt-BR, de;q=0.9")).await;
+        assert_negotiated(&res, Some("pt-br"), "sobre");
+        let res = fixture_get(&handler, "/about", &accept("de, fr;q=0.9")).await;
+        assert_negotiated(&res, Some("en"), "about");
+        let res = fixture_get(&handler, "/", &[]).await;
+        assert_negotiated(&res, Some("en"), "home");
+    }
+
+    #[tokio::test]
+    async fn language_negotiation_overrides() {
+        let handler = handler();
+
+        let headers = [("accept-language", "en"), ("cookie", "lang=de")];
+        let res = fixture_get(&handler, "/", &headers).await;
+        assert_negotiated(&res, Some("de"), "startseite");
+
+        let res = fixture_get(&handler, "/?lang=en", &headers).await;
+        assert_negotiated(&res, Some("en"), "home");
+
+        // Pages without variants are served as usual, though they may get
+        // some later on
+        let res = fixture_get(&handler, "/plain.html", &headers).await;
+        assert_negotiated(&res, None, "plain");
+
+        // Neither the requested nor the default language has a variant
+        let res = fixture_get(&handler, "/contact.html", &[("accept-language", "fr")]).await;
+        assert_negotiated(&res, None, "contact");
+
+        // Other files are not negotiated
+        let res = fixture_get(&handler, "/app.js", &headers).await;
+        assert_eq!(res.header("content-language"), None);
+        assert_eq!(res.header("vary"), None);
+    }
+}
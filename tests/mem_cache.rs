@@ -20,7 +20,10 @@ mod tests {
         self, DEFAULT_CAPACITY, DEFAULT_MAX_FILE_SIZE, DEFAULT_TTI, DEFAULT_TTL, MemCacheOpts,
     };
     use static_web_server::settings::Advanced;
-    use static_web_server::settings::file::MemoryCache;
+    use static_web_server::settings::file::{MemoryCache, MemoryCacheInvalidation, Mount};
+    use static_web_server::testing::fixtures::{
+        fixture_req_handler, fixture_req_handler_opts, fixture_settings,
+    };
 
     fn root_dir() -> PathBuf {
         PathBuf::from("tests/fixtures/public/")
@@ -48,8 +51,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &["index.htm"],
@@ -60,9 +68,16 @@ mod tests {
     fn memory_cache_config_defaults() {
         let cfg = MemoryCache {
             capacity: None,
+            max_size: None,
             ttl: None,
             tti: None,
             max_file_size: None,
+            invalidation: None,
+            include: None,
+            exclude: None,
+            preload: None,
+            preload_wait: None,
+            purge_token: None,
         };
         assert_eq!(cfg.capacity.unwrap_or(DEFAULT_CAPACITY), 100);
         assert_eq!(cfg.ttl.unwrap_or(DEFAULT_TTL), 1800);
@@ -74,9 +89,16 @@ mod tests {
     fn memory_cache_config_custom_values() {
         let cfg = MemoryCache {
             capacity: Some(50),
+            max_size: None,
             ttl: Some(600),
             tti: Some(120),
             max_file_size: Some(4096),
+            invalidation: None,
+            include: None,
+            exclude: None,
+            preload: None,
+            preload_wait: None,
+            purge_token: None,
         };
         assert_eq!(cfg.capacity.unwrap(), 50);
         assert_eq!(cfg.ttl.unwrap(), 600);
@@ -97,6 +119,42 @@ mod tests {
         assert_eq!(cfg.ttl, Some(900));
         assert_eq!(cfg.tti, Some(60));
         assert_eq!(cfg.max_file_size, Some(16384));
+        assert_eq!(cfg.invalidation, None);
+    }
+
+    #[test]
+    fn memory_cache_config_deserializes_invalidation_from_toml() {
+        for (value, expected) in [
+            ("none", MemoryCacheInvalidation::None),
+            ("watch", MemoryCacheInvalidation::Watch),
+            ("revalidate", MemoryCacheInvalidation::Revalidate),
+        ] {
+            let cfg: MemoryCache = toml::from_str(&format!("invalidation = \"{value}\"")).unwrap();
+            assert_eq!(cfg.invalidation, Some(expected));
+        }
+        assert_eq!(
+            MemoryCacheInvalidation::default(),
+            MemoryCacheInvalidation::None
+        );
+    }
+
+    #[test]
+    fn memory_cache_config_deserializes_size_budget_and_rules_from_toml() {
+        let toml_str = r#"
+            max-size = 256
+            max-file-size = 65536
+            include = ["/assets/**", "*.html"]
+            exclude = ["/assets/video/**"]
+            preload = ["/index.html"]
+            preload-wait = true
+        "#;
+        let cfg: MemoryCache = toml::from_str(toml_str).unwrap();
+        assert_eq!(cfg.max_size, Some(256));
+        assert_eq!(cfg.max_file_size, Some(65536));
+        assert_eq!(cfg.include.unwrap(), ["/assets/**", "*.html"]);
+        assert_eq!(cfg.exclude.unwrap(), ["/assets/video/**"]);
+        assert_eq!(cfg.preload.unwrap(), ["/index.html"]);
+        assert_eq!(cfg.preload_wait, Some(true));
     }
 
     #[test]
@@ -136,9 +194,16 @@ mod tests {
 
         let memory_cache = Some(MemoryCache {
             capacity: Some(10),
+            max_size: None,
             ttl: Some(60),
             tti: Some(30),
             max_file_size: Some(1024),
+            invalidation: None,
+            include: None,
+            exclude: None,
+            preload: None,
+            preload_wait: None,
+            purge_token: None,
         });
         let mut handler_opts = RequestHandlerOpts {
             advanced_opts: Some(Advanced {
@@ -146,7 +211,17 @@ mod tests {
                 rewrites: None,
                 redirects: None,
                 virtual_hosts: None,
+                unknown_host_status: None,
+                mass_virtual_hosts: None,
+                mounts: None,
                 memory_cache,
+                compression_cache: None,
+                cache_control: None,
+                compression_dictionaries: None,
+                compression: None,
+                redirect_maps: None,
+                proxy: None,
+                language_negotiation: None,
             }),
             ..Default::default()
         };
@@ -204,8 +279,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &["index.htm"],
@@ -239,8 +319,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &["index.htm"],
@@ -311,9 +396,16 @@ mod tests {
             advanced_opts: Some(Advanced {
                 memory_cache: Some(MemoryCache {
                     capacity: Some(DEFAULT_CAPACITY),
+                    max_size: None,
                     ttl: Some(DEFAULT_TTL),
                     tti: Some(DEFAULT_TTI),
                     max_file_size: Some(DEFAULT_MAX_FILE_SIZE),
+                    invalidation: None,
+                    include: None,
+                    exclude: None,
+                    preload: None,
+                    preload_wait: None,
+                    purge_token: None,
                 }),
                 ..Default::default()
             }),
@@ -344,8 +436,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &[],
@@ -399,9 +496,16 @@ mod tests {
             advanced_opts: Some(Advanced {
                 memory_cache: Some(MemoryCache {
                     capacity: Some(DEFAULT_CAPACITY),
+                    max_size: None,
                     ttl: Some(DEFAULT_TTL),
                     tti: Some(DEFAULT_TTI),
                     max_file_size: Some(DEFAULT_MAX_FILE_SIZE),
+                    invalidation: None,
+                    include: None,
+                    exclude: None,
+                    preload: None,
+                    preload_wait: None,
+                    purge_token: None,
                 }),
                 ..Default::default()
             }),
@@ -430,8 +534,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &[],
@@ -482,4 +591,615 @@ mod tests {
             "304 from cache must echo the ETag (RFC 7232 §4.1)"
         );
     }
+
+    // Invalidation of entries whose files change on disk.
+    //
+    // Both strategies must stop serving the stale body once the file is
+    // rewritten: `revalidate` on the very next hit, `watch` as soon as the
+    // watcher delivers the change event.
+
+    fn temp_handle_opts(
+        base_path: &'static PathBuf,
+        uri_path: &'static str,
+        revalidate: bool,
+    ) -> HandleOpts<'static> {
+        let mut mem_opts = MemCacheOpts::new(DEFAULT_MAX_FILE_SIZE);
+        mem_opts.revalidate = revalidate;
+        HandleOpts {
+            method: &Method::GET,
+            headers: Box::leak(Box::new(HeaderMap::new())),
+            base_path,
+            uri_path,
+            uri_query: None,
+            memory_cache: Some(Box::leak(Box::new(mem_opts))),
+            #[cfg(feature = "directory-listing")]
+            dir_listing: false,
+            #[cfg(feature = "directory-listing")]
+            dir_listing_order: 6,
+            #[cfg(feature = "directory-listing")]
+            dir_listing_format: Box::leak(Box::new(DirListFmt::Html)),
+            #[cfg(feature = "directory-listing-download")]
+            dir_listing_download: &[],
+            redirect_trailing_slash: true,
+            clean_urls: false,
+            compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
+            etag: true,
+            etag_strong: false,
+            include_hidden: true,
+            follow_symlinks: true,
+            index_files: &[],
+        }
+    }
+
+    async fn fetch(opts: &HandleOpts<'_>) -> (bool, Bytes) {
+        let resp = static_files::handle(opts)
+            .await
+            .expect("request should succeed")
+            .resp;
+        let hit = resp.headers().get("x-cache").is_some();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        (hit, body)
+    }
+
+    fn init_store(root_dir: PathBuf, invalidation: MemoryCacheInvalidation) {
+        let mut handler_opts = RequestHandlerOpts {
+            root_dir,
+            advanced_opts: Some(Advanced {
+                memory_cache: Some(MemoryCache {
+                    capacity: Some(DEFAULT_CAPACITY),
+                    max_size: None,
+                    ttl: Some(DEFAULT_TTL),
+                    tti: Some(DEFAULT_TTI),
+                    max_file_size: Some(DEFAULT_MAX_FILE_SIZE),
+                    invalidation: Some(invalidation),
+                    include: None,
+                    exclude: None,
+                    preload: None,
+                    preload_wait: None,
+                    purge_token: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        cache::init(&mut handler_opts).unwrap();
+    }
+
+    #[tokio::test]
+    async fn revalidate_evicts_entry_when_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        init_store(base_path.clone(), MemoryCacheInvalidation::Revalidate);
+        std::fs::write(base_path.join("page.html"), "first version").unwrap();
+
+        let opts = temp_handle_opts(base_path, "page.html", true);
+        let (hit, body) = fetch(&opts).await;
+        assert!(!hit);
+        assert_eq!(body, "first version");
+        let (hit, _) = fetch(&opts).await;
+        assert!(hit, "unchanged file must be served from the cache");
+
+        std::fs::write(base_path.join("page.html"), "second version!").unwrap();
+        let (hit, body) = fetch(&opts).await;
+        assert!(!hit, "a changed file must not be served from the cache");
+        assert_eq!(body, "second version!");
+    }
+
+    #[tokio::test]
+    async fn cached_entries_keep_strong_etags() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        init_store(base_path.clone(), MemoryCacheInvalidation::Revalidate);
+        std::fs::write(base_path.join("app.js"), "console.log('strong');").unwrap();
+
+        let mut opts = temp_handle_opts(base_path, "app.js", true);
+        opts.etag_strong = true;
+        let etag_of = |resp: &http::Response<_>| resp.headers()[http::header::ETAG].clone();
+        let miss = static_files::handle(&opts).await.unwrap().resp;
+        let etag = etag_of(&miss);
+        assert!(!etag.to_str().unwrap().starts_with("W/"), "{etag:?}");
+        miss.into_body().collect().await.unwrap();
+
+        let hit = static_files::handle(&opts).await.unwrap().resp;
+        assert!(hit.headers().get("x-cache").is_some());
+        assert_eq!(etag_of(&hit), etag);
+
+        // Strong validators satisfy `If-Match` on cache hits
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::IF_MATCH, etag);
+        opts.headers = Box::leak(Box::new(headers));
+        let resp = static_files::handle(&opts).await.unwrap().resp;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get("x-cache").is_some());
+    }
+
+    #[tokio::test]
+    async fn cache_hits_serve_multipart_byteranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        init_store(base_path.clone(), MemoryCacheInvalidation::None);
+        std::fs::write(base_path.join("ranges.txt"), "0123456789abcdefghij").unwrap();
+
+        let mut opts = temp_handle_opts(base_path, "ranges.txt", false);
+        let (hit, _) = fetch(&opts).await;
+        assert!(!hit);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::RANGE, "bytes=10-11,0-1".parse().unwrap());
+        opts.headers = Box::leak(Box::new(headers));
+        let resp = static_files::handle(&opts).await.unwrap().resp;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert!(resp.headers().get("x-cache").is_some());
+        assert!(resp.headers().get(http::header::CONTENT_RANGE).is_none());
+        let content_type = resp.headers()[http::header::CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_owned();
+        let content_length = resp.headers()[http::header::CONTENT_LENGTH].clone();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(content_length, body.len().to_string().as_str());
+        assert_eq!(
+            body,
+            format!(
+                "--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/20\r\n\r\n01\r\n\
+                 --{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 10-11/20\r\n\r\nab\r\n\
+                 --{boundary}--\r\n"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn image_variants_are_cached_separately() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        init_store(base_path.clone(), MemoryCacheInvalidation::None);
+        std::fs::write(base_path.join("hero.png"), "original png image").unwrap();
+        std::fs::write(base_path.join("hero.png.webp"), "webp image").unwrap();
+
+        let mut opts = temp_handle_opts(base_path, "hero.png", false);
+        opts.image_negotiation = true;
+        assert_eq!(
+            fetch(&opts).await,
+            (false, Bytes::from("original png image"))
+        );
+        assert_eq!(
+            fetch(&opts).await,
+            (true, Bytes::from("original png image"))
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::ACCEPT, "image/webp,*/*".parse().unwrap());
+        opts.headers = Box::leak(Box::new(headers));
+        assert_eq!(fetch(&opts).await, (false, Bytes::from("webp image")));
+        let hit = static_files::handle(&opts).await.unwrap().resp;
+        assert!(hit.headers().get("x-cache").is_some());
+        assert_eq!(hit.headers()[http::header::CONTENT_TYPE], "image/webp");
+    }
+
+    #[tokio::test]
+    async fn excluded_paths_are_never_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        init_store(base_path.clone(), MemoryCacheInvalidation::None);
+        std::fs::write(base_path.join("report.pdf"), "not cached").unwrap();
+        std::fs::write(base_path.join("notes.txt"), "cached").unwrap();
+        std::fs::create_dir(base_path.join("docs")).unwrap();
+        std::fs::write(base_path.join("docs/index.html"), "docs").unwrap();
+
+        let mut handler_opts = RequestHandlerOpts {
+            root_dir: base_path.clone(),
+            advanced_opts: Some(Advanced {
+                memory_cache: Some(MemoryCache {
+                    capacity: None,
+                    max_size: None,
+                    ttl: None,
+                    tti: None,
+                    max_file_size: None,
+                    invalidation: Some(MemoryCacheInvalidation::None),
+                    include: Some(vec![
+                        "/*.{txt,pdf}".to_owned(),
+                        "/docs/index.html".to_owned(),
+                    ]),
+                    exclude: Some(vec!["*.pdf".to_owned()]),
+                    preload: None,
+                    preload_wait: None,
+                    purge_token: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        cache::init(&mut handler_opts).unwrap();
+        let mem_opts: &'static MemCacheOpts =
+            Box::leak(Box::new(handler_opts.memory_cache.unwrap()));
+
+        let mut opts = temp_handle_opts(base_path, "/report.pdf", false);
+        opts.memory_cache = Some(mem_opts);
+        let _ = fetch(&opts).await;
+        let (hit, body) = fetch(&opts).await;
+        assert!(!hit, "excluded paths must not be cached");
+        assert_eq!(body, "not cached");
+
+        let mut opts = temp_handle_opts(base_path, "/notes.txt", false);
+        opts.memory_cache = Some(mem_opts);
+        let _ = fetch(&opts).await;
+        let (hit, _) = fetch(&opts).await;
+        assert!(hit, "included paths must be cached");
+
+        // The file cached for an included path is not served to other ones
+        let mut opts = temp_handle_opts(base_path, "/docs/index.html", false);
+        opts.memory_cache = Some(mem_opts);
+        let _ = fetch(&opts).await;
+        assert!(fetch(&opts).await.0, "included paths must be cached");
+        let mut opts = temp_handle_opts(base_path, "/docs/", false);
+        opts.memory_cache = Some(mem_opts);
+        let (hit, body) = fetch(&opts).await;
+        assert!(!hit, "paths not included must not be served from the cache");
+        assert_eq!(body, "docs");
+    }
+
+    #[tokio::test]
+    async fn preload_warms_up_matching_files_before_ready() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        std::fs::create_dir(base_path.join("landing")).unwrap();
+        std::fs::write(base_path.join("landing/index.html"), "<h1>landing</h1>").unwrap();
+        std::fs::write(base_path.join("landing/.draft.html"), "hidden").unwrap();
+        std::fs::write(base_path.join("other.html"), "other").unwrap();
+
+        let mut handler_opts = RequestHandlerOpts {
+            root_dir: base_path.clone(),
+            health: true,
+            include_hidden: false,
+            advanced_opts: Some(Advanced {
+                memory_cache: Some(MemoryCache {
+                    capacity: None,
+                    max_size: None,
+                    ttl: None,
+                    tti: None,
+                    max_file_size: None,
+                    invalidation: Some(MemoryCacheInvalidation::None),
+                    include: None,
+                    exclude: None,
+                    preload: Some(vec!["/landing/**".to_owned()]),
+                    preload_wait: Some(true),
+                    purge_token: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        cache::init(&mut handler_opts).unwrap();
+        let handler = static_web_server::testing::fixtures::fixture_req_handler(handler_opts);
+
+        // Readiness turns green once the warm-up finished.
+        let mut ready = false;
+        for _ in 0..100 {
+            let mut req = http::Request::new(());
+            *req.uri_mut() = "http://localhost/health".parse().unwrap();
+            let resp = handler.handle(&mut req, None).await.unwrap();
+            if resp.status() == StatusCode::OK {
+                ready = true;
+                break;
+            }
+            assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(ready, "the preload must finish");
+
+        let (hit, body) = fetch(&temp_handle_opts(base_path, "/landing/index.html", false)).await;
+        assert!(
+            hit,
+            "preloaded files must be served from the cache on first request"
+        );
+        assert_eq!(body, "<h1>landing</h1>");
+
+        let (hit, _) = fetch(&temp_handle_opts(base_path, "/other.html", false)).await;
+        assert!(
+            !hit,
+            "files not matching the preload patterns are not preloaded"
+        );
+        let (hit, _) = fetch(&temp_handle_opts(base_path, "/landing/.draft.html", false)).await;
+        assert!(!hit, "hidden files are not preloaded unless included");
+    }
+
+    #[tokio::test]
+    async fn preload_and_purge_apply_to_mounts() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(base_path.join("app")).unwrap();
+        std::fs::create_dir_all(base_path.join("downloads")).unwrap();
+        std::fs::write(base_path.join("downloads/file.txt"), "file").unwrap();
+        std::fs::write(base_path.join("downloads/.secret"), "secret").unwrap();
+
+        let downloads = Mount {
+            prefix: "/downloads".to_owned(),
+            root: base_path.join("downloads"),
+            index_files: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing_order: None,
+            #[cfg(feature = "directory-listing")]
+            directory_listing_format: None,
+            include_hidden: Some(false),
+            follow_symlinks: None,
+        };
+        let mut handler_opts = RequestHandlerOpts {
+            root_dir: base_path.join("app"),
+            health: true,
+            include_hidden: true,
+            advanced_opts: Some(Advanced {
+                memory_cache: Some(MemoryCache {
+                    capacity: None,
+                    max_size: None,
+                    ttl: None,
+                    tti: None,
+                    max_file_size: None,
+                    invalidation: Some(MemoryCacheInvalidation::None),
+                    include: None,
+                    exclude: None,
+                    preload: Some(vec!["/downloads/**".to_owned()]),
+                    preload_wait: Some(true),
+                    purge_token: Some("s3cr3t".to_owned()),
+                }),
+                mounts: Some(vec![downloads]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        static_web_server::mounts::init(&mut handler_opts).unwrap();
+        cache::init(&mut handler_opts).unwrap();
+        let handler = static_web_server::testing::fixtures::fixture_req_handler(handler_opts);
+
+        let request = async |method: Method, uri: &str| {
+            let mut req = http::Request::new(());
+            *req.method_mut() = method;
+            *req.uri_mut() = uri.parse().unwrap();
+            req.headers_mut()
+                .insert("authorization", "Bearer s3cr3t".parse().unwrap());
+            handler.handle(&mut req, None).await.unwrap()
+        };
+        for _ in 0..100 {
+            if request(Method::GET, "http://localhost/health")
+                .await
+                .status()
+                == StatusCode::OK
+            {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let resp = request(Method::GET, "http://localhost/downloads/file.txt").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(
+            resp.headers().contains_key("x-cache"),
+            "files are preloaded under the mount prefix"
+        );
+        let resp = request(Method::GET, "http://localhost/downloads/.secret").await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::NOT_FOUND,
+            "hidden files of the mount are not served"
+        );
+
+        let resp = request(
+            Method::POST,
+            "http://localhost/_sws/cache/purge?prefix=/downloads/",
+        )
+        .await;
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"purged":1}"#);
+        let resp = request(Method::GET, "http://localhost/downloads/file.txt").await;
+        assert!(!resp.headers().contains_key("x-cache"));
+    }
+
+    #[tokio::test]
+    async fn cached_files_apply_virtual_host_policies() {
+        let settings = fixture_settings("toml/mem_cache_vhosts.toml");
+        let mut handler_opts = fixture_req_handler_opts(settings.general, settings.advanced);
+        cache::init(&mut handler_opts).unwrap();
+        static_web_server::virtual_hosts::init(&mut handler_opts).unwrap();
+        let handler = fixture_req_handler(handler_opts);
+
+        let get = async |uri: &str| {
+            let mut req = http::Request::new(());
+            *req.uri_mut() = uri.parse().unwrap();
+            handler.handle(&mut req, None).await.unwrap()
+        };
+        for _ in 0..100 {
+            if get("http://localhost/health").await.status() == StatusCode::OK {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let resp = get("http://localhost/.dotfile").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().contains_key("x-cache"));
+        let resp = get("http://strict.example.com/.dotfile").await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::NOT_FOUND,
+            "cached hidden files are not served to a virtual host excluding them"
+        );
+        let resp = get("http://strict.example.com/index.htm").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().contains_key("x-cache"));
+    }
+
+    #[tokio::test]
+    async fn purge_endpoint_evicts_matching_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        init_store(base_path.clone(), MemoryCacheInvalidation::None);
+        std::fs::create_dir(base_path.join("assets")).unwrap();
+        std::fs::write(base_path.join("assets/app.js"), "app").unwrap();
+        std::fs::write(base_path.join("index.html"), "index").unwrap();
+
+        let mut mem_opts = MemCacheOpts::new(DEFAULT_MAX_FILE_SIZE);
+        mem_opts.purge_token = Some("s3cr3t".to_owned());
+        let handler =
+            static_web_server::testing::fixtures::fixture_req_handler(RequestHandlerOpts {
+                root_dir: base_path.clone(),
+                memory_cache: Some(mem_opts),
+                ..Default::default()
+            });
+        let purge = async |uri: &str, token: &str| {
+            let mut req = http::Request::new(());
+            *req.method_mut() = Method::POST;
+            *req.uri_mut() = uri.parse().unwrap();
+            req.headers_mut()
+                .insert("authorization", format!("Bearer {token}").parse().unwrap());
+            let resp = handler.handle(&mut req, None).await.unwrap();
+            let status = resp.status();
+            (status, resp.into_body().collect().await.unwrap().to_bytes())
+        };
+
+        let app = temp_handle_opts(base_path, "/assets/app.js", false);
+        let index = temp_handle_opts(base_path, "/index.html", false);
+        let _ = fetch(&app).await;
+        let _ = fetch(&index).await;
+        assert!(fetch(&app).await.0 && fetch(&index).await.0);
+
+        let (status, _) = purge("http://localhost/_sws/cache/purge", "wrong").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(fetch(&app).await.0, "unauthorized purges must not evict");
+
+        let (status, body) = purge(
+            "http://localhost/_sws/cache/purge?prefix=/assets/",
+            "s3cr3t",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"purged":1}"#);
+        assert!(!fetch(&app).await.0, "purged entries must be reloaded");
+        assert!(fetch(&index).await.0, "other entries must be kept");
+    }
+
+    #[tokio::test]
+    async fn revalidate_evicts_entry_when_file_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        init_store(base_path.clone(), MemoryCacheInvalidation::Revalidate);
+        std::fs::write(base_path.join("gone.html"), "soon removed").unwrap();
+
+        let opts = temp_handle_opts(base_path, "gone.html", true);
+        let _ = fetch(&opts).await;
+        let (hit, _) = fetch(&opts).await;
+        assert!(hit);
+
+        std::fs::remove_file(base_path.join("gone.html")).unwrap();
+        let result = static_files::handle(&opts).await;
+        assert_eq!(result.err(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn watcher_evicts_entry_when_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path: &'static PathBuf = Box::leak(Box::new(dir.path().canonicalize().unwrap()));
+        std::fs::create_dir(base_path.join("assets")).unwrap();
+        std::fs::write(base_path.join("assets/app.js"), "let a = 1;").unwrap();
+        init_store(base_path.clone(), MemoryCacheInvalidation::Watch);
+
+        let opts = temp_handle_opts(base_path, "assets/app.js", false);
+        let _ = fetch(&opts).await;
+        let (hit, _) = fetch(&opts).await;
+        assert!(hit);
+
+        std::fs::write(base_path.join("assets/app.js"), "let a = 2;").unwrap();
+
+        // The watcher delivers events asynchronously, poll until evicted.
+        let mut body = Bytes::new();
+        for _ in 0..100 {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            let (hit, b) = fetch(&opts).await;
+            if !hit {
+                body = b;
+                break;
+            }
+        }
+        assert_eq!(body, "let a = 2;");
+    }
+
+    // Content-encoded variants of cached entries.
+    //
+    // The first hit negotiating an encoding is compressed on the fly and
+    // fills the variant, later hits are served from memory as-is.
+
+    #[cfg(feature = "compression-brotli")]
+    async fn fetch_encoded(
+        handler: &static_web_server::handler::RequestHandler,
+        uri: &str,
+    ) -> (http::HeaderMap, Bytes) {
+        let mut req = http::Request::new(());
+        *req.uri_mut() = uri.parse().unwrap();
+        req.headers_mut()
+            .insert("accept-encoding", "br".parse().unwrap());
+        let resp = handler.handle(&mut req, None).await.unwrap();
+        let (parts, body) = resp.into_parts();
+        (parts.headers, body.collect().await.unwrap().to_bytes())
+    }
+
+    #[cfg(feature = "compression-brotli")]
+    #[tokio::test]
+    async fn encoded_variant_is_cached_and_served_from_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root_dir = dir.path().canonicalize().unwrap();
+        init_store(root_dir.clone(), MemoryCacheInvalidation::None);
+        std::fs::write(
+            root_dir.join("style.css"),
+            "body { color: red; }\n".repeat(50),
+        )
+        .unwrap();
+
+        let mut mem_opts = MemCacheOpts::new(DEFAULT_MAX_FILE_SIZE);
+        mem_opts.compression = true;
+        let handler =
+            static_web_server::testing::fixtures::fixture_req_handler(RequestHandlerOpts {
+                root_dir,
+                compression: true,
+                memory_cache: Some(mem_opts),
+                ..Default::default()
+            });
+        let uri = "http://localhost/style.css";
+
+        // Miss, then a hit compressed on the fly which fills the variant.
+        let (headers, _) = fetch_encoded(&handler, uri).await;
+        assert!(headers.get("x-cache").is_none());
+        let (headers, filled) = fetch_encoded(&handler, uri).await;
+        assert_eq!(headers["x-cache"], "HIT");
+        assert_eq!(headers["content-encoding"], "br");
+
+        let (headers, body) = fetch_encoded(&handler, uri).await;
+        assert_eq!(headers["x-cache"], "HIT");
+        assert_eq!(headers["content-encoding"], "br");
+        assert_eq!(headers["vary"], "accept-encoding");
+        assert_eq!(headers["content-length"], body.len().to_string().as_str());
+        assert!(headers["etag"].to_str().unwrap().ends_with("-br\""));
+        assert_eq!(body, filled);
+
+        // The variant validator is honored on conditional requests.
+        let mut req = http::Request::new(());
+        *req.uri_mut() = uri.parse().unwrap();
+        req.headers_mut()
+            .insert("accept-encoding", "br".parse().unwrap());
+        req.headers_mut()
+            .insert("if-none-match", headers["etag"].clone());
+        let resp = handler.handle(&mut req, None).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+        // Clients without a matching encoding still get the identity body.
+        let mut req = http::Request::new(());
+        *req.uri_mut() = uri.parse().unwrap();
+        let resp = handler.handle(&mut req, None).await.unwrap();
+        assert!(resp.headers().get("content-encoding").is_none());
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.len(), 21 * 50);
+    }
 }
@@ -0,0 +1,72 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use static_web_server::handler::RequestHandler;
+    use static_web_server::testing::fixtures::{
+        fixture_get, fixture_req_handler, fixture_req_handler_opts, fixture_settings,
+    };
+
+    fn handler() -> RequestHandler {
+        let opts = fixture_settings("toml/mounts.toml");
+        let mut opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        static_web_server::mounts::init(&mut opts).unwrap();
+        fixture_req_handler(opts)
+    }
+
+    #[tokio::test]
+    async fn mounts_serve_longest_prefix() {
+        let handler = handler();
+
+        for (uri, body) in [
+            ("/", "app"),
+            ("/secret.txt", "secret"),
+            ("/docs/", "docs"),
+            ("/docs/guide/", "guide"),
+            ("/downloads/file.txt", "file"),
+        ] {
+            let res = fixture_get(&handler, uri, &[]).await;
+            assert_eq!(res.status, 200, "{uri}");
+            assert_eq!(res.text(), body, "{uri}");
+        }
+
+        let res = fixture_get(&handler, "/docs", &[]).await;
+        assert_eq!(res.status, 308);
+        assert_eq!(res.header("location").as_deref(), Some("/docs/"));
+
+        let res = fixture_get(&handler, "/docsearch", &[]).await;
+        assert_eq!(res.status, 404);
+    }
+
+    #[tokio::test]
+    async fn mounts_enforce_their_own_policies() {
+        let handler = handler();
+
+        // Traversal stays inside the mount root
+        for uri in [
+            "/docs/../secret.txt",
+            "/docs/%2e%2e/secret.txt",
+            "/downloads/.hidden",
+        ] {
+            let res = fixture_get(&handler, uri, &[]).await;
+            assert_eq!(res.status, 404, "{uri}");
+        }
+        #[cfg(unix)]
+        {
+            let res = fixture_get(&handler, "/docs/latest/home.html", &[]).await;
+            assert_eq!(res.status, 403);
+        }
+
+        #[cfg(feature = "directory-listing")]
+        {
+            let res = fixture_get(&handler, "/downloads/", &[]).await;
+            assert_eq!(res.status, 200);
+            let body = res.text();
+            assert!(body.contains("file.txt"), "{body}");
+            assert!(!body.contains(".hidden"), "{body}");
+        }
+    }
+}
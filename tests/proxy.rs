@@ -0,0 +1,328 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use globset::GlobBuilder;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Incoming;
+    use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+    use hyper::service::service_fn;
+    use hyper::{Method, Request, Response};
+    use hyper_util::rt::TokioIo;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+
+    use static_web_server::body;
+    use static_web_server::handler::{RequestHandler, RequestHandlerOpts};
+    use static_web_server::settings::{Advanced, Headers, file::Proxy};
+    use static_web_server::testing::fixtures::{
+        REMOTE_ADDR, fixture_req_handler, fixture_response,
+    };
+
+    /// Echoes the request line, the forwarded headers and the body back.
+    async fn echo(req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .map(|v| v.to_str().unwrap().to_owned())
+                .unwrap_or_default()
+        };
+        let head = format!(
+            "{} {}\nhost: {}\nx-forwarded-for: {}\nx-forwarded-proto: {}\nx-forwarded-host: {}\nx-secret: {}\n",
+            req.method(),
+            req.uri(),
+            header("host"),
+            header("x-forwarded-for"),
+            header("x-forwarded-proto"),
+            header("x-forwarded-host"),
+            header("x-secret"),
+        );
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        let mut resp = Response::new(Full::new(Bytes::from(format!(
+            "{head}\n{}",
+            String::from_utf8_lossy(&body)
+        ))));
+        resp.headers_mut()
+            .insert("keep-alive", "timeout=5".parse().unwrap());
+        Ok(resp)
+    }
+
+    async fn spawn_upstream() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service_fn(echo)),
+                );
+            }
+        });
+        addr
+    }
+
+    fn rule(source: &str, upstream: String) -> Proxy {
+        Proxy {
+            source: source.to_owned(),
+            upstream,
+            host: None,
+            strip_prefix: None,
+            preserve_host: None,
+            connect_timeout: None,
+            timeout: None,
+        }
+    }
+
+    fn opts(rules: Vec<Proxy>) -> RequestHandlerOpts {
+        let mut opts = RequestHandlerOpts {
+            root_dir: PathBuf::from("docker/public"),
+            advanced_opts: Some(Advanced {
+                proxy: Some(rules),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        static_web_server::proxy::init(false, &mut opts).unwrap();
+        opts
+    }
+
+    fn handler(rules: Vec<Proxy>) -> RequestHandler {
+        fixture_req_handler(opts(rules))
+    }
+
+    async fn fetch(handler: &RequestHandler, req: Request<body::Body>) -> (u16, String) {
+        let remote_addr = Some(REMOTE_ADDR.parse::<SocketAddr>().unwrap());
+        let res = fixture_response(handler, req, remote_addr).await;
+        assert!(!res.headers.contains_key("keep-alive"));
+        (res.status.as_u16(), res.text())
+    }
+
+    #[tokio::test]
+    async fn proxy_forwards_matching_requests() {
+        let addr = spawn_upstream().await;
+        let handler = handler(vec![rule("/api/**", format!("http://{addr}"))]);
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/api/users?page=2")
+            .header("host", "example.com")
+            .header("connection", "x-secret")
+            .header("x-secret", "1")
+            .header("x-forwarded-for", "10.0.0.1")
+            .body(body::full("name=sws"))
+            .unwrap();
+        let (status, body) = fetch(&handler, req).await;
+        assert_eq!(status, 200);
+        let remote_ip = REMOTE_ADDR.parse::<SocketAddr>().unwrap().ip();
+        assert_eq!(
+            body,
+            format!(
+                "POST /api/users?page=2\nhost: {addr}\nx-forwarded-for: {remote_ip}\n\
+                 x-forwarded-proto: http\nx-forwarded-host: example.com\nx-secret: \n\nname=sws"
+            )
+        );
+
+        // Other paths are still served from the root directory
+        let req = Request::builder().uri("/").body(body::empty()).unwrap();
+        let (status, body) = fetch(&handler, req).await;
+        assert_eq!(status, 200);
+        assert!(body.contains("<html"));
+    }
+
+    #[tokio::test]
+    async fn proxy_extends_forwarded_for_of_trusted_proxies() {
+        let addr = spawn_upstream().await;
+        let remote_ip = REMOTE_ADDR.parse::<SocketAddr>().unwrap().ip();
+        let mut opts = opts(vec![rule("/api/**", format!("http://{addr}"))]);
+        opts.trusted_proxies = vec![remote_ip];
+        let handler = fixture_req_handler(opts);
+
+        let req = Request::builder()
+            .uri("/api/users")
+            .header("x-forwarded-for", "10.0.0.1")
+            .body(body::empty())
+            .unwrap();
+        let (status, body) = fetch(&handler, req).await;
+        assert_eq!(status, 200);
+        assert!(body.contains(&format!("\nx-forwarded-for: 10.0.0.1, {remote_ip}\n")));
+    }
+
+    #[tokio::test]
+    async fn proxy_normalizes_dot_segments() {
+        let addr = spawn_upstream().await;
+        let handler = handler(vec![rule("/api/**", format!("http://{addr}"))]);
+
+        for (uri, forwarded) in [
+            ("/api/v1/../users", "GET /api/users\n"),
+            ("/api/./v1/%2e%2E/users", "GET /api/users\n"),
+        ] {
+            let req = Request::builder().uri(uri).body(body::empty()).unwrap();
+            let (status, body) = fetch(&handler, req).await;
+            assert_eq!(status, 200, "{uri}");
+            assert!(body.starts_with(forwarded), "{uri}");
+        }
+
+        // Escaping the source is not forwarded
+        for uri in ["/api/../admin", "/api/%2e%2e/admin"] {
+            let req = Request::builder().uri(uri).body(body::empty()).unwrap();
+            let (status, body) = fetch(&handler, req).await;
+            assert_eq!(status, 404, "{uri}");
+            assert!(!body.contains("GET /admin"), "{uri}");
+        }
+
+        for uri in ["/api/..%2f..%2fadmin", "/api/%ff"] {
+            let req = Request::builder().uri(uri).body(body::empty()).unwrap();
+            let (status, _) = fetch(&handler, req).await;
+            assert_eq!(status, 400, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn proxy_appends_security_and_custom_headers() {
+        let addr = spawn_upstream().await;
+        let mut opts = opts(vec![rule("/api/**", format!("http://{addr}"))]);
+        opts.security_headers = true;
+        opts.advanced_opts.as_mut().unwrap().headers = Some(vec![Headers {
+            source: GlobBuilder::new("/api/**")
+                .literal_separator(true)
+                .build()
+                .unwrap()
+                .compile_matcher(),
+            headers: HeaderMap::from_iter([(
+                HeaderName::from_static("x-app"),
+                HeaderValue::from_static("sws"),
+            )]),
+        }]);
+        let handler = fixture_req_handler(opts);
+
+        let req = Request::builder()
+            .uri("/api/users")
+            .body(body::empty())
+            .unwrap();
+        let remote_addr = Some(REMOTE_ADDR.parse::<SocketAddr>().unwrap());
+        let res = fixture_response(&handler, req, remote_addr).await;
+        assert_eq!(res.status, 200);
+        assert_eq!(res.headers["x-app"], "sws");
+        assert_eq!(res.headers["x-content-type-options"], "nosniff");
+    }
+
+    #[tokio::test]
+    async fn proxy_reuses_upstream_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service_fn(echo)),
+                );
+            }
+        });
+        let handler = handler(vec![rule("/api/**", format!("http://{addr}"))]);
+
+        for _ in 0..3 {
+            let req = Request::builder()
+                .uri("/api/users")
+                .body(body::empty())
+                .unwrap();
+            let (status, _) = fetch(&handler, req).await;
+            assert_eq!(status, 200);
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn proxy_strips_prefix_and_preserves_host() {
+        let addr = spawn_upstream().await;
+        let mut api = rule("/api/**", format!("http://{addr}/v1/"));
+        api.strip_prefix = Some("/api".to_owned());
+        api.preserve_host = Some(true);
+        let handler = handler(vec![api]);
+
+        let req = Request::builder()
+            .uri("/api/users")
+            .header("host", "example.com")
+            .body(body::empty())
+            .unwrap();
+        let (status, body) = fetch(&handler, req).await;
+        assert_eq!(status, 200);
+        assert!(body.starts_with("GET /v1/users\nhost: example.com\n"));
+    }
+
+    #[tokio::test]
+    async fn proxy_unreachable_upstream() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let handler = handler(vec![rule("/api/**", format!("http://{addr}"))]);
+
+        let req = Request::builder()
+            .uri("/api/users")
+            .body(body::empty())
+            .unwrap();
+        let (status, _) = fetch(&handler, req).await;
+        assert_eq!(status, 502);
+    }
+
+    #[tokio::test]
+    async fn proxy_upstream_timeout() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            loop {
+                streams.push(listener.accept().await.unwrap());
+            }
+        });
+        let mut api = rule("/api/**", format!("http://{addr}"));
+        api.timeout = Some(1);
+        let handler = handler(vec![api]);
+
+        let req = Request::builder()
+            .uri("/api/slow")
+            .body(body::empty())
+            .unwrap();
+        let (status, _) = fetch(&handler, req).await;
+        assert_eq!(status, 504);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn proxy_unix_socket_upstream() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("upstream.sock");
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service_fn(echo)),
+                );
+            }
+        });
+        let handler = handler(vec![rule("/app/**", format!("unix:{}", path.display()))]);
+
+        let req = Request::builder()
+            .uri("/app/status")
+            .body(body::empty())
+            .unwrap();
+        let (status, body) = fetch(&handler, req).await;
+        assert_eq!(status, 200);
+        assert!(body.starts_with("GET /app/status\nhost: localhost\n"));
+    }
+}
@@ -0,0 +1,89 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::Duration};
+
+    use static_web_server::Settings;
+    use static_web_server::handler::RequestHandler;
+    use static_web_server::redirect_maps;
+    use static_web_server::testing::fixtures::{
+        fixture_get, fixture_req_handler, fixture_req_handler_opts, fixture_settings,
+    };
+
+    fn handler(opts: Settings) -> RequestHandler {
+        let mut opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        redirect_maps::init(&mut opts).unwrap();
+        fixture_req_handler(opts)
+    }
+
+    #[tokio::test]
+    async fn redirect_maps_match_exact_paths_per_host() {
+        let handler = handler(fixture_settings("toml/redirect_maps.toml"));
+
+        let res = fixture_get(&handler, "http://localhost/old-page/?a=1", &[]).await;
+        assert_eq!(res.status, 301);
+        assert_eq!(res.header("location").as_deref(), Some("/new-page?a=1"));
+
+        let res = fixture_get(&handler, "http://example.com/old-page", &[]).await;
+        assert_eq!(res.status, 301);
+        assert_eq!(
+            res.header("location").as_deref(),
+            Some("https://example.com/new")
+        );
+
+        let res = fixture_get(&handler, "http://example.com/about.php", &[]).await;
+        assert_eq!(res.status, 302);
+        assert_eq!(res.header("location").as_deref(), Some("/about"));
+
+        // Request paths are percent-decoded like static file paths
+        let res = fixture_get(&handler, "http://localhost/%61bout.php", &[]).await;
+        assert_eq!(res.status, 302);
+        assert_eq!(res.header("location").as_deref(), Some("/about"));
+
+        let res = fixture_get(&handler, "http://localhost/old-page/extra", &[]).await;
+        assert_eq!(res.status, 404);
+    }
+
+    #[tokio::test]
+    async fn redirect_maps_are_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("redirects.csv");
+        fs::write(&csv, "/a,/b\n").unwrap();
+        let mut opts = fixture_settings("toml/redirect_maps.toml");
+        let maps = opts
+            .advanced
+            .as_mut()
+            .unwrap()
+            .redirect_maps
+            .as_mut()
+            .unwrap();
+        maps.truncate(1);
+        maps[0].path = csv.clone();
+        maps[0].host = None;
+        let handler = handler(opts);
+
+        assert_eq!(fixture_get(&handler, "/a", &[]).await.status, 301);
+
+        // Files are reloaded in the background, wait for the new entries
+        thread::sleep(Duration::from_millis(1100));
+        fs::write(&csv, "/c,/d,308\n").unwrap();
+        for _ in 0..30 {
+            if fixture_get(&handler, "/c", &[]).await.status != 404 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!(fixture_get(&handler, "/c", &[]).await.status, 308);
+        assert_eq!(fixture_get(&handler, "/a", &[]).await.status, 404);
+
+        // Invalid files keep the previous entries
+        thread::sleep(Duration::from_millis(1100));
+        fs::write(&csv, "/c\n").unwrap();
+        thread::sleep(Duration::from_millis(2100));
+        assert_eq!(fixture_get(&handler, "/c", &[]).await.status, 308);
+    }
+}
@@ -80,6 +80,94 @@ pub mod tests {
         };
     }
 
+    #[tokio::test]
+    async fn redirects_conditions() {
+        let opts = fixture_settings("toml/redirects.toml");
+        let req_handler_opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        let req_handler = fixture_req_handler(req_handler_opts);
+        let remote_addr = Some(REMOTE_ADDR.parse::<SocketAddr>().unwrap());
+
+        // Responses depend on the conditions whether they are met or not
+        let cases = [
+            ("de-DE,en;q=0.5", None, 302, "accept-language, cookie"),
+            (
+                "en-US",
+                None,
+                404,
+                "accept-language, cookie, accept-encoding",
+            ),
+            (
+                "fr",
+                Some("lang=en"),
+                404,
+                "accept-language, cookie, accept-encoding",
+            ),
+        ];
+        for (language, cookie, status, vary) in cases {
+            let mut req = Request::new(());
+            *req.uri_mut() = "http://localhost/conditional/page?v=2".parse().unwrap();
+            req.headers_mut()
+                .insert("accept-language", language.parse().unwrap());
+            if let Some(cookie) = cookie {
+                req.headers_mut().insert("cookie", cookie.parse().unwrap());
+            }
+            let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+            assert_eq!(res.status(), status, "{language}");
+            assert_eq!(res.headers()["vary"], vary, "{language}");
+            if status == 302 {
+                assert_eq!(res.headers()["location"], "http://localhost/de/page-v2?v=2");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn redirects_regex_sources() {
+        let opts = fixture_settings("toml/redirects.toml");
+        let req_handler_opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        let req_handler = fixture_req_handler(req_handler_opts);
+        let remote_addr = Some(REMOTE_ADDR.parse::<SocketAddr>().unwrap());
+
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/POSTS/2024/Hello-World".parse().unwrap();
+        let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+        assert_eq!(res.status(), 301);
+        assert_eq!(res.headers()["location"], "/blog/Hello-World?year=2024");
+
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/search?q=rust".parse().unwrap();
+        let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+        assert_eq!(res.status(), 302);
+        assert_eq!(res.headers()["location"], "/find/rust");
+
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/search?q=rust&page=2".parse().unwrap();
+        let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+        assert_ne!(res.status(), 302);
+    }
+
+    #[tokio::test]
+    async fn redirects_statuses() {
+        let opts = fixture_settings("toml/redirects.toml");
+        let req_handler_opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        let req_handler = fixture_req_handler(req_handler_opts);
+        let remote_addr = Some(REMOTE_ADDR.parse::<SocketAddr>().unwrap());
+
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/moved/file".parse().unwrap();
+        let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+        assert_eq!(res.status(), 308);
+        assert_eq!(res.headers()["location"], "/new/file");
+
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/blocked/file".parse().unwrap();
+        let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+        assert_eq!(res.status(), 451);
+        assert_eq!(
+            res.headers()["link"],
+            "<https://authority.example/>; rel=\"blocked-by\""
+        );
+    }
+
     #[tokio::test]
     async fn redirects_glob_groups_1() {
         let opts = fixture_settings("toml/redirects.toml");
@@ -155,6 +155,29 @@ pub mod tests {
         };
     }
 
+    #[tokio::test]
+    async fn rewrites_regex_source_with_query() {
+        let opts = fixture_settings("toml/rewrites.toml");
+        let req_handler_opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        let req_handler = fixture_req_handler(req_handler_opts);
+        let remote_addr = Some(REMOTE_ADDR.parse::<SocketAddr>().unwrap());
+
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/docs?page=index".parse().unwrap();
+
+        match req_handler.handle(&mut req, remote_addr).await {
+            Ok(res) => {
+                assert_eq!(res.status(), 200);
+                assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+            }
+            Err(err) => {
+                panic!("unexpected error: {err}")
+            }
+        };
+        assert_eq!(req.uri().path(), "/assets/index.html");
+        assert_eq!(req.uri().query(), None);
+    }
+
     #[tokio::test]
     async fn rewrites_glob_groups_recursive_1() {
         let opts = fixture_settings("toml/rewrites.toml");
@@ -246,4 +269,43 @@ pub mod tests {
             }
         };
     }
+
+    #[tokio::test]
+    async fn rewrites_redirect_statuses() {
+        let opts = fixture_settings("toml/rewrites.toml");
+        let req_handler_opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        let req_handler = fixture_req_handler(req_handler_opts);
+        let remote_addr = Some(REMOTE_ADDR.parse::<SocketAddr>().unwrap());
+
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/downloads/app.zip".parse().unwrap();
+        let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+        assert_eq!(res.status(), 307);
+        assert_eq!(res.headers()["location"], "/files/app.zip");
+
+        let mut req = Request::new(());
+        *req.uri_mut() = "http://localhost/removed/page.html".parse().unwrap();
+        let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+        assert_eq!(res.status(), 410);
+        assert!(res.headers().get("location").is_none());
+        assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+    }
+
+    #[tokio::test]
+    async fn rewrites_conditions_vary_responses() {
+        let opts = fixture_settings("toml/rewrites.toml");
+        let req_handler_opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        let req_handler = fixture_req_handler(req_handler_opts);
+        let remote_addr = Some(REMOTE_ADDR.parse::<SocketAddr>().unwrap());
+
+        // Both the rewritten and the unmatched responses depend on the cookie
+        for (cookie, status) in [("beta=on", 200), ("beta=off", 404)] {
+            let mut req = Request::new(());
+            *req.uri_mut() = "http://localhost/beta".parse().unwrap();
+            req.headers_mut().insert("cookie", cookie.parse().unwrap());
+            let res = req_handler.handle(&mut req, remote_addr).await.unwrap();
+            assert_eq!(res.status(), status, "{cookie}");
+            assert_eq!(res.headers()["vary"], "cookie, accept-encoding", "{cookie}");
+        }
+    }
 }
@@ -0,0 +1,94 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+
+    use static_web_server::Settings;
+    use static_web_server::handler::RequestHandler;
+    use static_web_server::site_files;
+    use static_web_server::testing::fixtures::{
+        fixture_get, fixture_req_handler, fixture_req_handler_opts, fixture_settings,
+    };
+
+    fn handler(opts: Settings) -> RequestHandler {
+        let enabled = opts.general.site_files;
+        let mut opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        site_files::init(enabled, &mut opts);
+        assert!(opts.site_files.is_some());
+        fixture_req_handler(opts)
+    }
+
+    #[tokio::test]
+    async fn redirects_with_placeholders_and_splats() {
+        let handler = handler(fixture_settings("toml/site_files.toml"));
+
+        let res = fixture_get(&handler, "/blog/2024/hello", &[]).await;
+        assert_eq!(res.status, StatusCode::FOUND);
+        assert_eq!(res.headers["location"], "/posts/2024-hello");
+
+        let res = fixture_get(&handler, "/docs/a/b.html?v=1", &[]).await;
+        assert_eq!(res.status, StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers["location"], "/guide/a/b.html?v=1");
+    }
+
+    #[tokio::test]
+    async fn rewrites_apply_unless_shadowed_by_a_file() {
+        let handler = handler(fixture_settings("toml/site_files.toml"));
+
+        let res = fixture_get(&handler, "/app/users/42", &[]).await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert_eq!(res.text(), "<h1>index</h1>");
+        assert!(res.headers.get("x-frame-options").is_none());
+
+        let res = fixture_get(&handler, "/app/real.txt", &[]).await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert_eq!(res.text(), "real");
+        assert_eq!(res.headers["x-frame-options"], "DENY");
+        assert_eq!(res.headers["x-custom"], "a, b");
+
+        // Forced rules apply even if the file exists
+        let res = fixture_get(&handler, "/legacy.txt", &[]).await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert_eq!(res.text(), "<h1>index</h1>");
+    }
+
+    #[tokio::test]
+    async fn site_files_are_not_served() {
+        let handler = handler(fixture_settings("toml/site_files.toml"));
+        for uri in [
+            "/_redirects",
+            "/_headers",
+            "/%5Fredirects",
+            "//_headers",
+            "/_REDIRECTS",
+            "/_Headers",
+        ] {
+            let res = fixture_get(&handler, uri, &[]).await;
+            assert_eq!(res.status, StatusCode::NOT_FOUND, "{uri}");
+        }
+    }
+
+    #[cfg(feature = "directory-listing")]
+    #[tokio::test]
+    async fn site_files_are_not_listed() {
+        let mut opts = fixture_settings("toml/site_files.toml");
+        opts.general.root = "tests/fixtures/site_files_listing".into();
+        opts.general.directory_listing = true;
+        let handler = handler(opts);
+
+        let res = fixture_get(&handler, "/", &[]).await;
+        assert_eq!(res.status, StatusCode::OK);
+        let body = res.text();
+        assert!(body.contains("a.txt"), "{body}");
+        assert!(!body.to_lowercase().contains("_redirects"), "{body}");
+        assert!(!body.to_lowercase().contains("_headers"), "{body}");
+
+        let res = fixture_get(&handler, "/docs/", &[]).await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert!(res.text().contains("_redirects"), "{}", res.text());
+    }
+}
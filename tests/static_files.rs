@@ -73,8 +73,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &["index.htm"],
@@ -125,8 +130,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &["index.htm"],
@@ -178,8 +188,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -215,8 +230,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &["index.htm"],
@@ -257,8 +277,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: true,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &["index.htm"],
@@ -295,8 +320,13 @@ mod tests {
             #[cfg(feature = "directory-listing-download")]
             dir_listing_download: &[],
             redirect_trailing_slash: false,
+            clean_urls: false,
             compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
             etag: true,
+            etag_strong: false,
             include_hidden: true,
             follow_symlinks: true,
             index_files: &[],
@@ -338,8 +368,13 @@ mod tests {
                     #[cfg(feature = "directory-listing-download")]
                     dir_listing_download: &[],
                     redirect_trailing_slash: true,
+                    clean_urls: false,
                     compression_static: false,
+                    image_negotiation: false,
+                    mounts: None,
+                    site_files: false,
                     etag: true,
+                    etag_strong: false,
                     include_hidden: true,
                     follow_symlinks: true,
                     index_files: &[],
@@ -399,8 +434,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -439,8 +479,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -481,8 +526,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -524,8 +574,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -573,8 +628,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -620,8 +680,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -662,8 +727,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -703,8 +773,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -751,8 +826,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -842,8 +922,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["large-test.html"],
@@ -918,8 +1003,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -976,8 +1066,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -1007,6 +1102,135 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn handle_byte_ranges_multipart() {
+        let mut headers = HeaderMap::new();
+        headers.insert("range", "bytes=100-200,0-9,5-19".parse().unwrap());
+
+        let buf = fs::read(root_dir().join("assets/index.html"))
+            .expect("unexpected error during index.html reading");
+
+        let result = static_files::handle(&HandleOpts {
+            method: &Method::GET,
+            headers: &headers,
+            base_path: &root_dir(),
+            uri_path: "assets/index.html",
+            uri_query: None,
+            #[cfg(feature = "mem-cache")]
+            memory_cache: None,
+            #[cfg(feature = "directory-listing")]
+            dir_listing: false,
+            #[cfg(feature = "directory-listing")]
+            dir_listing_order: 6,
+            #[cfg(feature = "directory-listing")]
+            dir_listing_format: &DirListFmt::Html,
+            #[cfg(feature = "directory-listing-download")]
+            dir_listing_download: &[],
+            redirect_trailing_slash: true,
+            clean_urls: false,
+            compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
+            etag: true,
+            etag_strong: false,
+            include_hidden: true,
+            follow_symlinks: true,
+            index_files: &[],
+        })
+        .await
+        .expect("expected a normal response rather than a status error");
+
+        let res = result.resp;
+        assert_eq!(res.status(), 206);
+        assert!(res.headers().get("content-range").is_none());
+        assert!(res.headers().get("etag").is_some());
+        let boundary = res.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .strip_prefix("multipart/byteranges; boundary=")
+            .expect("unexpected content type")
+            .to_owned();
+        let content_length = res.headers()["content-length"].clone();
+        let body = res
+            .into_body()
+            .collect()
+            .await
+            .expect("unexpected bytes error during `body` conversion")
+            .to_bytes();
+        assert_eq!(content_length, body.len().to_string().as_str());
+
+        // Overlapping ranges are coalesced, parts are sorted
+        let mut expected = Vec::new();
+        for (start, end) in [(0, 19), (100, 200)] {
+            if start > 0 {
+                expected.extend_from_slice(b"\r\n");
+            }
+            expected.extend_from_slice(
+                format!(
+                    "--{boundary}\r\nContent-Type: text/html\r\nContent-Range: bytes {start}-{end}/{}\r\n\r\n",
+                    buf.len()
+                )
+                .as_bytes(),
+            );
+            expected.extend_from_slice(&buf[start..=end]);
+        }
+        expected.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+        assert_eq!(body, expected);
+    }
+
+    #[tokio::test]
+    async fn handle_byte_ranges_too_many() {
+        let mut headers = HeaderMap::new();
+        let ranges = (0..100)
+            .map(|i| format!("{0}-{0}", i * 2))
+            .collect::<Vec<_>>()
+            .join(",");
+        headers.insert("range", format!("bytes={ranges}").parse().unwrap());
+
+        let buf = fs::read(root_dir().join("assets/index.html"))
+            .expect("unexpected error during index.html reading");
+
+        let result = static_files::handle(&HandleOpts {
+            method: &Method::GET,
+            headers: &headers,
+            base_path: &root_dir(),
+            uri_path: "assets/index.html",
+            uri_query: None,
+            #[cfg(feature = "mem-cache")]
+            memory_cache: None,
+            #[cfg(feature = "directory-listing")]
+            dir_listing: false,
+            #[cfg(feature = "directory-listing")]
+            dir_listing_order: 6,
+            #[cfg(feature = "directory-listing")]
+            dir_listing_format: &DirListFmt::Html,
+            #[cfg(feature = "directory-listing-download")]
+            dir_listing_download: &[],
+            redirect_trailing_slash: true,
+            clean_urls: false,
+            compression_static: false,
+            image_negotiation: false,
+            mounts: None,
+            site_files: false,
+            etag: true,
+            etag_strong: false,
+            include_hidden: true,
+            follow_symlinks: true,
+            index_files: &[],
+        })
+        .await
+        .expect("expected a normal response rather than a status error");
+
+        // Amplification guard: the full content is sent instead
+        let res = result.resp;
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            res.headers()["content-length"],
+            buf.len().to_string().as_str()
+        );
+    }
+
     #[tokio::test]
     async fn handle_byte_ranges_out_of_range() {
         let mut headers = HeaderMap::new();
@@ -1034,8 +1258,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -1093,8 +1322,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -1141,8 +1375,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -1202,8 +1441,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -1260,8 +1504,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -1318,8 +1567,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -1379,8 +1633,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -1430,8 +1689,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -1477,8 +1741,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -1542,8 +1811,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: false,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: true,
                 follow_symlinks: true,
                 index_files: &[],
@@ -1599,8 +1873,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: true,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -1640,8 +1919,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: true,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &[],
@@ -1697,8 +1981,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: true,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &["index.htm"],
@@ -1742,8 +2031,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: true,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &["index.htm", "index.htm"],
@@ -1792,8 +2086,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: true,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: false,
                 index_files: &["index.htm", "index.htm"],
@@ -1829,8 +2128,13 @@ mod tests {
                 #[cfg(feature = "directory-listing-download")]
                 dir_listing_download: &[],
                 redirect_trailing_slash: true,
+                clean_urls: false,
                 compression_static: true,
+                image_negotiation: false,
+                mounts: None,
+                site_files: false,
                 etag: true,
+                etag_strong: false,
                 include_hidden: false,
                 follow_symlinks: true,
                 index_files: &["index.htm", "index.htm"],
@@ -1876,8 +2180,13 @@ mod tests {
                     #[cfg(feature = "directory-listing-download")]
                     dir_listing_download: &[],
                     redirect_trailing_slash: true,
+                    clean_urls: false,
                     compression_static: true,
+                    image_negotiation: false,
+                    mounts: None,
+                    site_files: false,
                     etag: true,
+                    etag_strong: false,
                     include_hidden: false,
                     follow_symlinks: true,
                     index_files: &["index.htm", "index.htm"],
@@ -1924,8 +2233,13 @@ mod tests {
                     #[cfg(feature = "directory-listing-download")]
                     dir_listing_download: &[],
                     redirect_trailing_slash: true,
+                    clean_urls: false,
                     compression_static: true,
+                    image_negotiation: false,
+                    mounts: None,
+                    site_files: false,
                     etag: true,
+                    etag_strong: false,
                     include_hidden: false,
                     follow_symlinks: true,
                     index_files: &["index.htm", "index.htm"],
@@ -0,0 +1,112 @@
+#![forbid(unsafe_code)]
+#![deny(warnings)]
+#![deny(rust_2018_idioms)]
+#![deny(dead_code)]
+
+#[cfg(test)]
+mod tests {
+    use static_web_server::handler::RequestHandler;
+    use static_web_server::testing::fixtures::{
+        FixtureResponse, fixture_get, fixture_req_handler, fixture_req_handler_opts,
+        fixture_settings,
+    };
+
+    fn handler() -> RequestHandler {
+        let opts = fixture_settings("toml/virtual_hosts.toml");
+        let mut opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        static_web_server::virtual_hosts::init(&mut opts).unwrap();
+        fixture_req_handler(opts)
+    }
+
+    /// Whether the response is the test page of the markdown fixtures.
+    fn is_test_page(res: &FixtureResponse) -> bool {
+        res.text().contains("Test Page")
+    }
+
+    #[tokio::test]
+    async fn wildcard_host_with_own_settings() {
+        let handler = handler();
+
+        let res = fixture_get(&handler, "http://pr-1.preview.example.com/", &[]).await;
+        assert_eq!(res.status, 200);
+        assert_eq!(res.header("x-preview").as_deref(), Some("1"));
+        assert!(is_test_page(&res));
+
+        // The bare domain matches no virtual host
+        let res = fixture_get(&handler, "http://preview.example.com/", &[]).await;
+        assert_eq!(res.status, 421);
+        assert_eq!(res.header("x-preview"), None);
+        assert!(!is_test_page(&res));
+    }
+
+    #[tokio::test]
+    async fn exact_hosts_with_own_settings() {
+        let handler = handler();
+
+        let res = fixture_get(&handler, "http://docs.example.com/missing", &[]).await;
+        assert_eq!(res.status, 404);
+        assert_eq!(res.header("x-preview"), None);
+        assert!(is_test_page(&res));
+
+        let res = fixture_get(&handler, "http://example.com/old", &[]).await;
+        assert_eq!(res.status, 301);
+        assert_eq!(res.header("location").as_deref(), Some("/new"));
+
+        // Redirects of another virtual host do not apply
+        let res = fixture_get(&handler, "http://docs.example.com/old", &[]).await;
+        assert_eq!(res.status, 404);
+        assert_eq!(res.header("location"), None);
+        assert!(is_test_page(&res));
+    }
+
+    #[tokio::test]
+    async fn unknown_hosts_are_rejected() {
+        let handler = handler();
+
+        for uri in ["http://unknown.com/", "/"] {
+            let res = fixture_get(&handler, uri, &[]).await;
+            assert_eq!(res.status, 421, "{uri}");
+            assert!(!is_test_page(&res), "{uri}");
+        }
+    }
+
+    fn mass_handler() -> RequestHandler {
+        let opts = fixture_settings("toml/mass_virtual_hosts.toml");
+        let mut opts = fixture_req_handler_opts(opts.general, opts.advanced);
+        static_web_server::virtual_hosts::init(&mut opts).unwrap();
+        fixture_req_handler(opts)
+    }
+
+    #[tokio::test]
+    async fn mass_virtual_hosts_from_host_directories() {
+        let handler = mass_handler();
+
+        for (host, body) in [
+            ("pr-1.example.com", "sites/pr-1.example.com"),
+            ("PR-2.example.com.:8080", "sites/pr-2.example.com"),
+            ("pr-3.example.com", "fallback"),
+        ] {
+            let res = fixture_get(&handler, "/", &[("host", host)]).await;
+            assert_eq!(res.status, 200, "{host}");
+            assert_eq!(res.text(), body, "{host}");
+        }
+    }
+
+    #[tokio::test]
+    async fn mass_virtual_hosts_reject_invalid_hostnames() {
+        let handler = mass_handler();
+
+        for (host, uri) in [
+            ("..", "/"),
+            ("sites", "/"),
+            ("pr-1.example.com/..", "/"),
+            ("%2e%2e", "/"),
+            ("pr-1.example.com%2f..", "/"),
+            ("..", "/index.html"),
+        ] {
+            let res = fixture_get(&handler, uri, &[("host", host)]).await;
+            assert_eq!(res.status, 200, "{host}");
+            assert_eq!(res.text(), "fallback", "{host}");
+        }
+    }
+}